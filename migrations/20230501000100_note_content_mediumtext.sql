@@ -0,0 +1,12 @@
+-- Note content goes up to the configurable size limit, which a TEXT column
+-- can't hold. Only changed while it is still TEXT
+
+SET @widen_note_content = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblNotes' AND COLUMN_NAME = 'content' AND DATA_TYPE = 'text') = 1,
+  'ALTER TABLE tblNotes MODIFY content MEDIUMTEXT NOT NULL',
+  'DO 0'
+);
+PREPARE widen_note_content FROM @widen_note_content;
+EXECUTE widen_note_content;
+DEALLOCATE PREPARE widen_note_content;
@@ -0,0 +1,13 @@
+-- When a note was soft deleted, so it can be restored. MySQL has no
+-- ADD COLUMN IF NOT EXISTS, so the column is only added when missing, leaving
+-- databases set up from the SQL scripts as they are
+
+SET @add_note_deleted_at = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblNotes' AND COLUMN_NAME = 'deleted_at') = 0,
+  'ALTER TABLE tblNotes ADD COLUMN deleted_at DATETIME',
+  'DO 0'
+);
+PREPARE add_note_deleted_at FROM @add_note_deleted_at;
+EXECUTE add_note_deleted_at;
+DEALLOCATE PREPARE add_note_deleted_at;
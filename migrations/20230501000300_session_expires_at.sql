@@ -0,0 +1,33 @@
+-- Sessions expire at a time rather than on a date, and are renewed on use.
+-- The old expiry date carries over to expires_at, and existing sessions count
+-- as used now. Nothing happens once expiry_date is gone
+
+SET @has_expiry_date = (SELECT COUNT(*) FROM information_schema.COLUMNS
+  WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblSessionToken' AND COLUMN_NAME = 'expiry_date');
+
+SET @add_session_expiry = IF(
+  @has_expiry_date = 1,
+  'ALTER TABLE tblSessionToken ADD COLUMN expires_at DATETIME, ADD COLUMN last_active DATETIME',
+  'DO 0'
+);
+PREPARE add_session_expiry FROM @add_session_expiry;
+EXECUTE add_session_expiry;
+DEALLOCATE PREPARE add_session_expiry;
+
+SET @copy_session_expiry = IF(
+  @has_expiry_date = 1,
+  'UPDATE tblSessionToken SET expires_at = expiry_date, last_active = UTC_TIMESTAMP()',
+  'DO 0'
+);
+PREPARE copy_session_expiry FROM @copy_session_expiry;
+EXECUTE copy_session_expiry;
+DEALLOCATE PREPARE copy_session_expiry;
+
+SET @drop_expiry_date = IF(
+  @has_expiry_date = 1,
+  'ALTER TABLE tblSessionToken MODIFY expires_at DATETIME NOT NULL, MODIFY last_active DATETIME NOT NULL, DROP COLUMN expiry_date',
+  'DO 0'
+);
+PREPARE drop_expiry_date FROM @drop_expiry_date;
+EXECUTE drop_expiry_date;
+DEALLOCATE PREPARE drop_expiry_date;
@@ -0,0 +1,33 @@
+-- When each session was started, shown in the sessions list. Sessions from
+-- before this are taken to have started when they were last used. Only done
+-- when the column is missing
+
+SET @has_session_created_at = (SELECT COUNT(*) FROM information_schema.COLUMNS
+  WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblSessionToken' AND COLUMN_NAME = 'created_at');
+
+SET @add_session_created_at = IF(
+  @has_session_created_at = 0,
+  'ALTER TABLE tblSessionToken ADD COLUMN created_at DATETIME AFTER token',
+  'DO 0'
+);
+PREPARE add_session_created_at FROM @add_session_created_at;
+EXECUTE add_session_created_at;
+DEALLOCATE PREPARE add_session_created_at;
+
+SET @fill_session_created_at = IF(
+  @has_session_created_at = 0,
+  'UPDATE tblSessionToken SET created_at = last_active',
+  'DO 0'
+);
+PREPARE fill_session_created_at FROM @fill_session_created_at;
+EXECUTE fill_session_created_at;
+DEALLOCATE PREPARE fill_session_created_at;
+
+SET @require_session_created_at = IF(
+  @has_session_created_at = 0,
+  'ALTER TABLE tblSessionToken MODIFY created_at DATETIME NOT NULL',
+  'DO 0'
+);
+PREPARE require_session_created_at FROM @require_session_created_at;
+EXECUTE require_session_created_at;
+DEALLOCATE PREPARE require_session_created_at;
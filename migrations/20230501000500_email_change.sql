@@ -0,0 +1,10 @@
+-- Email changes waiting to be confirmed from the new address
+
+CREATE TABLE IF NOT EXISTS tblEmailChange (
+  email_change_id INT PRIMARY KEY AUTO_INCREMENT,
+  account_id INT NOT NULL,
+  new_email VARCHAR(255) NOT NULL,
+  token VARCHAR(255) NOT NULL,
+  expires_at DATETIME NOT NULL,
+  FOREIGN KEY (account_id) REFERENCES tblAccount(account_id)
+);
@@ -0,0 +1,12 @@
+-- Uploaded profile pictures can be bigger than a BLOB holds. Only changed
+-- while the column is still a BLOB
+
+SET @widen_profile_photo = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblAccount' AND COLUMN_NAME = 'profile_photo' AND DATA_TYPE = 'blob') = 1,
+  'ALTER TABLE tblAccount MODIFY profile_photo MEDIUMBLOB',
+  'DO 0'
+);
+PREPARE widen_profile_photo FROM @widen_profile_photo;
+EXECUTE widen_profile_photo;
+DEALLOCATE PREPARE widen_profile_photo;
@@ -0,0 +1,12 @@
+-- Role of each account, `admin` for accounts that manage protocols and
+-- events. Existing accounts become regular users. Added only when missing
+
+SET @add_account_role = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblAccount' AND COLUMN_NAME = 'role') = 0,
+  'ALTER TABLE tblAccount ADD COLUMN role VARCHAR(16) NOT NULL DEFAULT ''user''',
+  'DO 0'
+);
+PREPARE add_account_role FROM @add_account_role;
+EXECUTE add_account_role;
+DEALLOCATE PREPARE add_account_role;
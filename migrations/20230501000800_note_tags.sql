@@ -0,0 +1,9 @@
+-- Tags on notes, each at most once per note
+
+CREATE TABLE IF NOT EXISTS tblNoteTags (
+  note_tag_id INT PRIMARY KEY AUTO_INCREMENT,
+  note_id INT NOT NULL,
+  tag VARCHAR(64) NOT NULL,
+  UNIQUE (note_id, tag),
+  FOREIGN KEY (note_id) REFERENCES tblNotes(note_id)
+);
@@ -0,0 +1,12 @@
+-- When each note was created and last changed. Notes from before this get
+-- the time the migration ran. Added only when missing
+
+SET @add_note_timestamps = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblNotes' AND COLUMN_NAME = 'created_at') = 0,
+  'ALTER TABLE tblNotes ADD COLUMN created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP AFTER public, ADD COLUMN updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP AFTER created_at',
+  'DO 0'
+);
+PREPARE add_note_timestamps FROM @add_note_timestamps;
+EXECUTE add_note_timestamps;
+DEALLOCATE PREPARE add_note_timestamps;
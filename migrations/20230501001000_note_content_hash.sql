@@ -0,0 +1,15 @@
+-- SHA-256 of each note's content, checked by the integrity check. Existing
+-- notes get theirs from MySQL's SHA2, the same as the seed data. The column
+-- is only added when missing
+
+SET @add_note_content_hash = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblNotes' AND COLUMN_NAME = 'content_hash') = 0,
+  'ALTER TABLE tblNotes ADD COLUMN content_hash CHAR(64) AFTER content',
+  'DO 0'
+);
+PREPARE add_note_content_hash FROM @add_note_content_hash;
+EXECUTE add_note_content_hash;
+DEALLOCATE PREPARE add_note_content_hash;
+
+UPDATE tblNotes SET content_hash = SHA2(content, 256) WHERE content_hash IS NULL;
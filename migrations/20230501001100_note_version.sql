@@ -0,0 +1,12 @@
+-- Version of each note, bumped on every update so stale updates can be
+-- refused. Added only when missing
+
+SET @add_note_version = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblNotes' AND COLUMN_NAME = 'version') = 0,
+  'ALTER TABLE tblNotes ADD COLUMN version INT NOT NULL DEFAULT 1',
+  'DO 0'
+);
+PREPARE add_note_version FROM @add_note_version;
+EXECUTE add_note_version;
+DEALLOCATE PREPARE add_note_version;
@@ -0,0 +1,12 @@
+-- Idempotency keys sent with add_note, and the note each one added
+
+CREATE TABLE IF NOT EXISTS tblIdempotencyKeys (
+  idempotency_key_id INT PRIMARY KEY AUTO_INCREMENT,
+  account_id INT NOT NULL,
+  idempotency_key VARCHAR(255) NOT NULL,
+  note_id INT NOT NULL,
+  expires_at DATETIME NOT NULL,
+  UNIQUE (account_id, idempotency_key),
+  FOREIGN KEY (account_id) REFERENCES tblAccount(account_id),
+  FOREIGN KEY (note_id) REFERENCES tblNotes(note_id)
+);
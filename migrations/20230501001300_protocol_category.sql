@@ -0,0 +1,11 @@
+-- Optional category protocols can be filtered by. Added only when missing
+
+SET @add_protocol_category = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblProtocol' AND COLUMN_NAME = 'category') = 0,
+  'ALTER TABLE tblProtocol ADD COLUMN category VARCHAR(64)',
+  'DO 0'
+);
+PREPARE add_protocol_category FROM @add_protocol_category;
+EXECUTE add_protocol_category;
+DEALLOCATE PREPARE add_protocol_category;
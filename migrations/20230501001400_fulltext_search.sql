@@ -0,0 +1,23 @@
+-- Full-text indexes that note and protocol searches are ranked with. There
+-- is no ADD INDEX IF NOT EXISTS either, so each index is only added when the
+-- table has no full-text index yet
+
+SET @add_note_fulltext = IF(
+  (SELECT COUNT(*) FROM information_schema.STATISTICS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblNotes' AND INDEX_TYPE = 'FULLTEXT') = 0,
+  'ALTER TABLE tblNotes ADD FULLTEXT (title)',
+  'DO 0'
+);
+PREPARE add_note_fulltext FROM @add_note_fulltext;
+EXECUTE add_note_fulltext;
+DEALLOCATE PREPARE add_note_fulltext;
+
+SET @add_protocol_fulltext = IF(
+  (SELECT COUNT(*) FROM information_schema.STATISTICS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblProtocol' AND INDEX_TYPE = 'FULLTEXT') = 0,
+  'ALTER TABLE tblProtocol ADD FULLTEXT (title, content)',
+  'DO 0'
+);
+PREPARE add_protocol_fulltext FROM @add_protocol_fulltext;
+EXECUTE add_protocol_fulltext;
+DEALLOCATE PREPARE add_protocol_fulltext;
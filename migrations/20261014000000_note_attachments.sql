@@ -0,0 +1,12 @@
+-- Files attached to notes, stored in the database like profile pictures
+
+CREATE TABLE IF NOT EXISTS tblNoteAttachments (
+  attachment_id INT PRIMARY KEY AUTO_INCREMENT,
+  note_id INT NOT NULL,
+  file_name VARCHAR(255) NOT NULL,
+  content_type VARCHAR(100) NOT NULL,
+  size INT NOT NULL,
+  data MEDIUMBLOB NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (note_id) REFERENCES tblNotes(note_id)
+);
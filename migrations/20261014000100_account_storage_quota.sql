@@ -0,0 +1,19 @@
+-- Running total of the bytes each account's notes take up, and an optional
+-- per account quota overriding storage_quota_bytes in config.toml.
+-- MySQL has no ADD COLUMN IF NOT EXISTS, so the columns are only added when
+-- missing, leaving databases set up from the SQL scripts as they are
+
+SET @add_storage_columns = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblAccount' AND COLUMN_NAME = 'bytes_used') = 0,
+  'ALTER TABLE tblAccount ADD COLUMN bytes_used BIGINT NOT NULL DEFAULT 0, ADD COLUMN storage_quota_bytes BIGINT',
+  'DO 0'
+);
+PREPARE add_storage_columns FROM @add_storage_columns;
+EXECUTE add_storage_columns;
+DEALLOCATE PREPARE add_storage_columns;
+
+UPDATE tblAccount SET bytes_used = (
+  SELECT COALESCE(SUM(LENGTH(content)), 0) FROM tblNotes
+  WHERE tblNotes.account_id = tblAccount.account_id AND deleted_at IS NULL
+);
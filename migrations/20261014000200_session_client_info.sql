@@ -0,0 +1,12 @@
+-- Where each session was started from, shown in the sessions list. Added only
+-- when missing, like the storage columns, as MySQL has no ADD COLUMN IF NOT EXISTS
+
+SET @add_session_columns = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblSessionToken' AND COLUMN_NAME = 'ip_address') = 0,
+  'ALTER TABLE tblSessionToken ADD COLUMN ip_address VARCHAR(45), ADD COLUMN user_agent VARCHAR(512)',
+  'DO 0'
+);
+PREPARE add_session_columns FROM @add_session_columns;
+EXECUTE add_session_columns;
+DEALLOCATE PREPARE add_session_columns;
@@ -0,0 +1,9 @@
+-- Single use tokens proving a password reset was asked for
+
+CREATE TABLE IF NOT EXISTS tblPasswordReset (
+  password_reset_id INT PRIMARY KEY AUTO_INCREMENT,
+  account_id INT NOT NULL,
+  token VARCHAR(255) NOT NULL,
+  expires_at DATETIME NOT NULL,
+  FOREIGN KEY (account_id) REFERENCES tblAccount(account_id)
+);
@@ -0,0 +1,12 @@
+-- Custom order for an account's notes, set by reorder_notes. Notes without a
+-- position list after the ones that have one
+
+SET @add_sort_position = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblNotes' AND COLUMN_NAME = 'sort_position') = 0,
+  'ALTER TABLE tblNotes ADD COLUMN sort_position INT',
+  'DO 0'
+);
+PREPARE add_sort_position FROM @add_sort_position;
+EXECUTE add_sort_position;
+DEALLOCATE PREPARE add_sort_position;
@@ -0,0 +1,11 @@
+-- When each protocol last changed, sent as Last-Modified by fetch_protocols
+
+SET @add_protocol_updated_at = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblProtocol' AND COLUMN_NAME = 'updated_at') = 0,
+  'ALTER TABLE tblProtocol ADD COLUMN updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP',
+  'DO 0'
+);
+PREPARE add_protocol_updated_at FROM @add_protocol_updated_at;
+EXECUTE add_protocol_updated_at;
+DEALLOCATE PREPARE add_protocol_updated_at;
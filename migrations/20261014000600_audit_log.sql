@@ -0,0 +1,11 @@
+-- Who changed which account or note, and when
+
+CREATE TABLE IF NOT EXISTS tblAuditLog (
+  audit_log_id INT PRIMARY KEY AUTO_INCREMENT,
+  actor_account_id INT NOT NULL,
+  action VARCHAR(64) NOT NULL,
+  target_type VARCHAR(32) NOT NULL,
+  target_id INT NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX (created_at)
+);
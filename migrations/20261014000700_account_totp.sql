@@ -0,0 +1,14 @@
+-- Two-factor authentication. The TOTP secret is stored encrypted, 2FA is on
+-- once totp_confirmed_at is set, and totp_last_step stops a code being reused.
+-- MySQL has no ADD COLUMN IF NOT EXISTS, so the columns are only added when
+-- missing, leaving databases set up from the SQL scripts as they are
+
+SET @add_totp_columns = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblAccount' AND COLUMN_NAME = 'totp_secret') = 0,
+  'ALTER TABLE tblAccount ADD COLUMN totp_secret VARCHAR(255), ADD COLUMN totp_confirmed_at DATETIME, ADD COLUMN totp_last_step BIGINT',
+  'DO 0'
+);
+PREPARE add_totp_columns FROM @add_totp_columns;
+EXECUTE add_totp_columns;
+DEALLOCATE PREPARE add_totp_columns;
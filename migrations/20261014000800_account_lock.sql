@@ -0,0 +1,14 @@
+-- Bad passwords in a row for each account, and when a temporary lock put on
+-- the account after too many of them runs out.
+-- MySQL has no ADD COLUMN IF NOT EXISTS, so the columns are only added when
+-- missing, leaving databases set up from the SQL scripts as they are
+
+SET @add_lock_columns = IF(
+  (SELECT COUNT(*) FROM information_schema.COLUMNS
+   WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'tblAccount' AND COLUMN_NAME = 'failed_attempts') = 0,
+  'ALTER TABLE tblAccount ADD COLUMN failed_attempts INT NOT NULL DEFAULT 0, ADD COLUMN locked_until DATETIME',
+  'DO 0'
+);
+PREPARE add_lock_columns FROM @add_lock_columns;
+EXECUTE add_lock_columns;
+DEALLOCATE PREPARE add_lock_columns;
@@ -0,0 +1,7 @@
+-- When each removed protocol went, so fetch_protocols can move Last-Modified
+-- on a removal without touching updated_at on the protocols that are left
+
+CREATE TABLE IF NOT EXISTS tblProtocolRemovals (
+  protocol_id INT PRIMARY KEY,
+  removed_at DATETIME NOT NULL
+);
@@ -7,7 +7,15 @@ CREATE TABLE tblAccount (
   hashed_password VARCHAR(255) NOT NULL,
   username VARCHAR(255) NOT NULL,
   cell_number VARCHAR(255),
-  profile_photo BLOB
+  profile_photo MEDIUMBLOB,
+  role VARCHAR(16) NOT NULL DEFAULT 'user',
+  bytes_used BIGINT NOT NULL DEFAULT 0,
+  storage_quota_bytes BIGINT,
+  totp_secret VARCHAR(255),
+  totp_confirmed_at DATETIME,
+  totp_last_step BIGINT,
+  failed_attempts INT NOT NULL DEFAULT 0,
+  locked_until DATETIME
 );
 
 CREATE TABLE tblHospital (
@@ -24,7 +32,28 @@ CREATE TABLE tblSessionToken (
   session_token_id INT PRIMARY KEY AUTO_INCREMENT,
   account_id INT NOT NULL,
   token VARCHAR(255) NOT NULL,
-  expiry_date DATE NOT NULL,
+  created_at DATETIME NOT NULL,
+  expires_at DATETIME NOT NULL,
+  last_active DATETIME NOT NULL,
+  ip_address VARCHAR(45),
+  user_agent VARCHAR(512),
+  FOREIGN KEY (account_id) REFERENCES tblAccount(account_id)
+);
+
+CREATE TABLE tblEmailChange (
+  email_change_id INT PRIMARY KEY AUTO_INCREMENT,
+  account_id INT NOT NULL,
+  new_email VARCHAR(255) NOT NULL,
+  token VARCHAR(255) NOT NULL,
+  expires_at DATETIME NOT NULL,
+  FOREIGN KEY (account_id) REFERENCES tblAccount(account_id)
+);
+
+CREATE TABLE tblPasswordReset (
+  password_reset_id INT PRIMARY KEY AUTO_INCREMENT,
+  account_id INT NOT NULL,
+  token VARCHAR(255) NOT NULL,
+  expires_at DATETIME NOT NULL,
   FOREIGN KEY (account_id) REFERENCES tblAccount(account_id)
 );
 
@@ -32,15 +61,60 @@ CREATE TABLE tblNotes (
   note_id INT PRIMARY KEY AUTO_INCREMENT,
   account_id INT NOT NULL,
   title VARCHAR(255) NOT NULL,
-  content TEXT NOT NULL,
+  content MEDIUMTEXT NOT NULL,
+  content_hash CHAR(64),
   public BOOLEAN NOT NULL DEFAULT 0,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  deleted_at DATETIME,
+  version INT NOT NULL DEFAULT 1,
+  sort_position INT,
+  FULLTEXT (title),
   FOREIGN KEY (account_id) REFERENCES tblAccount(account_id)
 );
 
+CREATE TABLE tblNoteTags (
+  note_tag_id INT PRIMARY KEY AUTO_INCREMENT,
+  note_id INT NOT NULL,
+  tag VARCHAR(64) NOT NULL,
+  UNIQUE (note_id, tag),
+  FOREIGN KEY (note_id) REFERENCES tblNotes(note_id)
+);
+
+CREATE TABLE tblNoteAttachments (
+  attachment_id INT PRIMARY KEY AUTO_INCREMENT,
+  note_id INT NOT NULL,
+  file_name VARCHAR(255) NOT NULL,
+  content_type VARCHAR(100) NOT NULL,
+  size INT NOT NULL,
+  data MEDIUMBLOB NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (note_id) REFERENCES tblNotes(note_id)
+);
+
+CREATE TABLE tblIdempotencyKeys (
+  idempotency_key_id INT PRIMARY KEY AUTO_INCREMENT,
+  account_id INT NOT NULL,
+  idempotency_key VARCHAR(255) NOT NULL,
+  note_id INT NOT NULL,
+  expires_at DATETIME NOT NULL,
+  UNIQUE (account_id, idempotency_key),
+  FOREIGN KEY (account_id) REFERENCES tblAccount(account_id),
+  FOREIGN KEY (note_id) REFERENCES tblNotes(note_id)
+);
+
 CREATE TABLE tblProtocol (
   protocol_id INT PRIMARY KEY AUTO_INCREMENT,
   title VARCHAR(255) NOT NULL,
-  content TEXT
+  content TEXT,
+  category VARCHAR(64),
+  updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FULLTEXT (title, content)
+);
+
+CREATE TABLE tblProtocolRemovals (
+  protocol_id INT PRIMARY KEY,
+  removed_at DATETIME NOT NULL
 );
 
 CREATE TABLE tblEvents (
@@ -83,4 +157,14 @@ CREATE TABLE tblStaff (
   last_name VARCHAR(255) NOT NULL,
   email VARCHAR(255) NOT NULL,
   cell_number VARCHAR(255) NOT NULL
-);
\ No newline at end of file
+);
+
+CREATE TABLE tblAuditLog (
+  audit_log_id INT PRIMARY KEY AUTO_INCREMENT,
+  actor_account_id INT NOT NULL,
+  action VARCHAR(64) NOT NULL,
+  target_type VARCHAR(32) NOT NULL,
+  target_id INT NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX (created_at)
+);
@@ -19,12 +19,12 @@ INSERT INTO tblSecurityQuestions (question) VALUES
 ('What high school did you go to?');
 
 INSERT INTO tblSecurityAnswers (secques_id, account_id, answer) VALUES
-(1, 1, 'a459891617d735655dcfed3e37db66fa07f0175866ebf35f9de8ccc59c0840bb'), /*jeffrey*/
-(2, 1, 'b93b9776163702f1fad6cbaf815326a41b3285d0961f4e838ebdb8ad52e5f16e'), /*manchester united*/
-(3, 2, 'c34ce311989d8bf0a65b9272905811c857e8c975313327c3deca09d3f95409e1'), /*taurus*/
-(4, 2, '1584ecc2592eccae2314eaa81e61cf4ab9674f7508d77c3bd8acebff52acdb53'), /*play videogames*/
-(5, 3, '0b4f17f89076d53c785b72e1ac4c0da8abc1e33ec6215e6806efc1f97696ce09'), /*edenvale high school*/
-(1, 3, '730551f5bad4af0604f661e7f8b82e6a6c364ca19ce140166333d86cdc814ca6'); /*snowball*/
+(1, 1, '$argon2id$v=19$m=19456,t=2,p=1$XcuUBpRyLcbxlNrW3FTDvg$itHXbabF2RIrUjOGHYFMTO0d4Gd6/cCUWJTM5UoIyTM'), /*jeffrey*/
+(2, 1, '$argon2id$v=19$m=19456,t=2,p=1$TyzK2QW97iXP3yB5FQcUzw$AOsrMXCSxG9aTEvQvWpswoxOqIgL9R4k5W8c14BoWm0'), /*manchester united*/
+(3, 2, '$argon2id$v=19$m=19456,t=2,p=1$DiFH5KdJdkkY2r7kgaSUnQ$Msg4v6+zjZ9eRaCqB3JkHvBlzbPkjRc0+vQeONOGf2E'), /*taurus*/
+(4, 2, '$argon2id$v=19$m=19456,t=2,p=1$cSoA/I5fJBq0t07zQe7zrg$CU1E8QgvagRNudSXy4fyZAG9d2BgB4CkFqyitGmDaOA'), /*play videogames*/
+(5, 3, '$argon2id$v=19$m=19456,t=2,p=1$aPoazYdxMwX8AU7zc4xpeg$0pEm2z2ng9d1mWXz7JteIiNSsrIGexMDfK0TRIVqDuI'), /*edenvale high school*/
+(1, 3, '$argon2id$v=19$m=19456,t=2,p=1$xmAbDHdAJ5bxZYbzB1ZMCA$qYRbmNvMCOypqNp5618f/nKc6XZySqdPhWxOHsCTYLk'); /*snowball*/
 
 INSERT INTO tblProtocol (title, content) VALUES
 ('HHHH', 'Hazard - Identify any hazards that can cause harm to both you and the casulty.\nHello - Introduce yourself yourself to the casualty, and ask for consent to administer treatment.\nHistory - Ask the casualty what happened.\nHelp - call emergency services if necessary, else administer first aid.'),
@@ -43,6 +43,8 @@ INSERT INTO tblNotes (account_id, title, content) VALUES
 (9, 'Emergency Room', 'Worked in the ER and saw several cases of trauma. Some of the cases were:\n\n* Motor vehicle accident\n* Gunshot wound\n* Fall from height\n\nI assisted with procedures such as intubation, chest tube placement, and wound care.\n\n---\n\n'),
 (10, 'Surgery Rotation', 'Started my rotation in the surgical ward. Observed several procedures, including:\n\n* Appendectomy\n* Cholecystectomy\n* Hysterectomy\n\nI also learned about postoperative care and wound management.\n\n---\n\n');
 
+UPDATE tblNotes SET content_hash = SHA2(content, 256);
+
 INSERT INTO tblHospital (hospital_name) VALUES
 ('St. Marys Hospital'),
 ('Johns Hopkins Hospital'),
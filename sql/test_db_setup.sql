@@ -6,13 +6,20 @@ DROP TABLE IF EXISTS tblAccount ;
 DROP TABLE IF EXISTS tblHospital ;
 DROP TABLE IF EXISTS tblDiscipline ;
 DROP TABLE IF EXISTS tblSessionToken ;
+DROP TABLE IF EXISTS tblEmailChange ;
+DROP TABLE IF EXISTS tblPasswordReset ;
+DROP TABLE IF EXISTS tblIdempotencyKeys ;
+DROP TABLE IF EXISTS tblNoteAttachments ;
+DROP TABLE IF EXISTS tblNoteTags ;
 DROP TABLE IF EXISTS tblNotes ;
 DROP TABLE IF EXISTS tblProtocol ;
+DROP TABLE IF EXISTS tblProtocolRemovals ;
 DROP TABLE IF EXISTS tblEvents ;
 DROP TABLE IF EXISTS tblRotation ;
 DROP TABLE IF EXISTS tblSecurityQuestions ;
 DROP TABLE IF EXISTS tblSecurityAnswers ;
 DROP TABLE IF EXISTS tblStaff ;
+DROP TABLE IF EXISTS tblAuditLog ;
 
 CREATE TABLE tblAccount (
   account_id INT PRIMARY KEY AUTO_INCREMENT,
@@ -20,7 +27,15 @@ CREATE TABLE tblAccount (
   hashed_password VARCHAR(255) NOT NULL,
   username VARCHAR(255) NOT NULL,
   cell_number VARCHAR(255),
-  profile_photo BLOB
+  profile_photo MEDIUMBLOB,
+  role VARCHAR(16) NOT NULL DEFAULT 'user',
+  bytes_used BIGINT NOT NULL DEFAULT 0,
+  storage_quota_bytes BIGINT,
+  totp_secret VARCHAR(255),
+  totp_confirmed_at DATETIME,
+  totp_last_step BIGINT,
+  failed_attempts INT NOT NULL DEFAULT 0,
+  locked_until DATETIME
 );
 
 CREATE TABLE tblHospital (
@@ -37,7 +52,28 @@ CREATE TABLE tblSessionToken (
   session_token_id INT PRIMARY KEY AUTO_INCREMENT,
   account_id INT NOT NULL,
   token VARCHAR(255) NOT NULL,
-  expiry_date DATE NOT NULL,
+  created_at DATETIME NOT NULL,
+  expires_at DATETIME NOT NULL,
+  last_active DATETIME NOT NULL,
+  ip_address VARCHAR(45),
+  user_agent VARCHAR(512),
+  FOREIGN KEY (account_id) REFERENCES tblAccount(account_id)
+);
+
+CREATE TABLE tblEmailChange (
+  email_change_id INT PRIMARY KEY AUTO_INCREMENT,
+  account_id INT NOT NULL,
+  new_email VARCHAR(255) NOT NULL,
+  token VARCHAR(255) NOT NULL,
+  expires_at DATETIME NOT NULL,
+  FOREIGN KEY (account_id) REFERENCES tblAccount(account_id)
+);
+
+CREATE TABLE tblPasswordReset (
+  password_reset_id INT PRIMARY KEY AUTO_INCREMENT,
+  account_id INT NOT NULL,
+  token VARCHAR(255) NOT NULL,
+  expires_at DATETIME NOT NULL,
   FOREIGN KEY (account_id) REFERENCES tblAccount(account_id)
 );
 
@@ -45,15 +81,60 @@ CREATE TABLE tblNotes (
   note_id INT PRIMARY KEY AUTO_INCREMENT,
   account_id INT NOT NULL,
   title VARCHAR(255) NOT NULL,
-  content TEXT NOT NULL,
+  content MEDIUMTEXT NOT NULL,
+  content_hash CHAR(64),
   public BOOLEAN NOT NULL DEFAULT 0,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  deleted_at DATETIME,
+  version INT NOT NULL DEFAULT 1,
+  sort_position INT,
+  FULLTEXT (title),
   FOREIGN KEY (account_id) REFERENCES tblAccount(account_id)
 );
 
+CREATE TABLE tblNoteTags (
+  note_tag_id INT PRIMARY KEY AUTO_INCREMENT,
+  note_id INT NOT NULL,
+  tag VARCHAR(64) NOT NULL,
+  UNIQUE (note_id, tag),
+  FOREIGN KEY (note_id) REFERENCES tblNotes(note_id)
+);
+
+CREATE TABLE tblNoteAttachments (
+  attachment_id INT PRIMARY KEY AUTO_INCREMENT,
+  note_id INT NOT NULL,
+  file_name VARCHAR(255) NOT NULL,
+  content_type VARCHAR(100) NOT NULL,
+  size INT NOT NULL,
+  data MEDIUMBLOB NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (note_id) REFERENCES tblNotes(note_id)
+);
+
+CREATE TABLE tblIdempotencyKeys (
+  idempotency_key_id INT PRIMARY KEY AUTO_INCREMENT,
+  account_id INT NOT NULL,
+  idempotency_key VARCHAR(255) NOT NULL,
+  note_id INT NOT NULL,
+  expires_at DATETIME NOT NULL,
+  UNIQUE (account_id, idempotency_key),
+  FOREIGN KEY (account_id) REFERENCES tblAccount(account_id),
+  FOREIGN KEY (note_id) REFERENCES tblNotes(note_id)
+);
+
 CREATE TABLE tblProtocol (
   protocol_id INT PRIMARY KEY AUTO_INCREMENT,
   title VARCHAR(255) NOT NULL,
-  content TEXT
+  content TEXT,
+  category VARCHAR(64),
+  updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FULLTEXT (title, content)
+);
+
+CREATE TABLE tblProtocolRemovals (
+  protocol_id INT PRIMARY KEY,
+  removed_at DATETIME NOT NULL
 );
 
 CREATE TABLE tblEvents (
@@ -98,30 +179,48 @@ CREATE TABLE tblStaff (
   cell_number VARCHAR(255) NOT NULL
 );
 
-INSERT INTO tblAccount (email, hashed_password, username, cell_number, profile_photo) VALUES 
-('0000000@students.wits.ac.za', '0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e', 'test_account_has_everything', '1234567890', '0x0123456789ABCDEF'), /* password_1 */
-('1111111@students.wits.ac.za', '0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e', 'test_account_missing_everything', '1234567890', '0x0123456789ABCDEF'); /* password_1 */
+CREATE TABLE tblAuditLog (
+  audit_log_id INT PRIMARY KEY AUTO_INCREMENT,
+  actor_account_id INT NOT NULL,
+  action VARCHAR(64) NOT NULL,
+  target_type VARCHAR(32) NOT NULL,
+  target_id INT NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX (created_at)
+);
+
+INSERT INTO tblAccount (email, hashed_password, username, cell_number, profile_photo, role) VALUES 
+('0000000@students.wits.ac.za', '0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e', 'test_account_has_everything', '1234567890', '0x0123456789ABCDEF', 'admin'), /* password_1 */
+('1111111@students.wits.ac.za', '0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e', 'test_account_missing_everything', '1234567890', '0x0123456789ABCDEF', 'user'), /* password_1 */
+('2222222@students.wits.ac.za', '0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e', 'test_account_regular_user', '1234567890', NULL, 'user'); /* password_1 */
 
 INSERT INTO tblSecurityQuestions (question) VALUES
 ('What was the name of your first pet?'),
 ('What is the name of your favourite sports team?');
 
 INSERT INTO tblSecurityAnswers (secques_id, account_id, answer) VALUES
-(1, 1, 'a459891617d735655dcfed3e37db66fa07f0175866ebf35f9de8ccc59c0840bb'), /*jeffrey*/
-(2, 1, 'b93b9776163702f1fad6cbaf815326a41b3285d0961f4e838ebdb8ad52e5f16e'); /*manchester united*/
+(1, 1, '$argon2id$v=19$m=19456,t=2,p=1$XcuUBpRyLcbxlNrW3FTDvg$itHXbabF2RIrUjOGHYFMTO0d4Gd6/cCUWJTM5UoIyTM'), /*jeffrey*/
+(2, 1, '$argon2id$v=19$m=19456,t=2,p=1$TyzK2QW97iXP3yB5FQcUzw$AOsrMXCSxG9aTEvQvWpswoxOqIgL9R4k5W8c14BoWm0'); /*manchester united*/
 
-INSERT INTO tblSessionToken (account_id, token, expiry_date)
-VALUES (1, '4a5c5b7f0e0f70eabfe5e2d3fb8ae19de7427f8d48f2e502ee4e61c9af174620', '2023-06-30');
+INSERT INTO tblSessionToken (account_id, token, created_at, expires_at, last_active) VALUES
+(1, '4a5c5b7f0e0f70eabfe5e2d3fb8ae19de7427f8d48f2e502ee4e61c9af174620', NOW(), '2099-06-30 00:00:00', NOW()),
+(1, 'expired_session_token', '2023-01-01 00:00:00', '2023-06-30 00:00:00', '2023-01-01 00:00:00'),
+(1, 'expiring_session_token', NOW(), DATE_ADD(NOW(), INTERVAL 1 DAY), NOW()),
+(1, 'admin_session_token', NOW(), '2099-06-30 00:00:00', NOW()),
+(3, 'user_session_token', NOW(), '2099-06-30 00:00:00', NOW());
 
-INSERT INTO tblProtocol (title, content) VALUES
-('HHHH', 'Hazard - Identify any hazards that can cause harm to both you and the casulty.\nHello - Introduce yourself yourself to the casualty, and ask for consent to administer treatment.\nHistory - Ask the casualty what happened.\nHelp - call emergency services if necessary, else administer first aid.'),
-('ABC', 'Airway - Check that the casualtys airway is unblocked.\nBreathing - Check that the casualty is breathing.\nCirculation - Check that the casulty has circulation with a capillary pinch test, or by measuring their pulse.\nIf any of these fail, administer CPR immediately'),
-('CPR', '1. Lean the casultys head back, with their mouth open.\n2. Perform 30 chest compressions at 100 beats per minute, or to the beat of Stayin Alive by the Bee Gees on the casultys solar plexus / end of their sternum.\n3. After 30 compressions, administer 2 breaths to the casulty.\n4.Repeat until emergency services arrive!');
+INSERT INTO tblProtocol (title, content, category) VALUES
+('HHHH', 'Hazard - Identify any hazards that can cause harm to both you and the casulty.\nHello - Introduce yourself yourself to the casualty, and ask for consent to administer treatment.\nHistory - Ask the casualty what happened.\nHelp - call emergency services if necessary, else administer first aid.', 'medical'),
+('ABC', 'Airway - Check that the casualtys airway is unblocked.\nBreathing - Check that the casualty is breathing.\nCirculation - Check that the casulty has circulation with a capillary pinch test, or by measuring their pulse.\nIf any of these fail, administer CPR immediately', 'medical'),
+('CPR', '1. Lean the casultys head back, with their mouth open.\n2. Perform 30 chest compressions at 100 beats per minute, or to the beat of Stayin Alive by the Bee Gees on the casultys solar plexus / end of their sternum.\n3. After 30 compressions, administer 2 breaths to the casulty.\n4.Repeat until emergency services arrive!', 'medical');
 
 INSERT INTO tblNotes (account_id, title, content, public) VALUES
 (1, 'Lecture Notes', 'Today we covered respiratory physiology. Here are the key points:\n\n* Oxygen and carbon dioxide exchange occurs in the alveoli of the lungs.\n* The respiratory system is controlled by the medulla oblongata in the brainstem.\n* The diaphragm and intercostal muscles are responsible for breathing.\n\n---\n\n', 0),
 (1, 'Study Group', 'Meeting with classmates to review material for upcoming exam. We covered:\n\n* Endocrine system\n* Renal system\n* Hematology\n\nWe created flashcards to help us memorize key concepts.\n\n---\n\n', 1);
 
+UPDATE tblNotes SET content_hash = SHA2(content, 256);
+UPDATE tblAccount SET bytes_used = (SELECT COALESCE(SUM(LENGTH(content)), 0) FROM tblNotes WHERE tblNotes.account_id = tblAccount.account_id AND deleted_at IS NULL);
+
 INSERT INTO tblHospital (hospital_name) VALUES
 ('St. Marys Hospital');
 
@@ -130,10 +229,14 @@ INSERT INTO tblDiscipline (discipline_name) VALUES
 
 INSERT INTO tblEvents (account_id, start_date, end_date, event_name, description) VALUES 
 (1, '2022-01-01', '2022-01-07', 'New Year Event', 'Celebrating the New Year'),
-(1, '2022-02-01', '2022-02-20', 'Rotation 1', 'First rotation of the year');
+(1, '2022-02-01', '2022-02-20', 'Rotation 1', 'First rotation of the year'),
+(1, '2022-04-01', '2022-04-20', 'Rotation 3', 'Third rotation of the year'),
+(1, '2022-03-01', '2022-03-20', 'Rotation 2', 'Second rotation of the year');
 
 INSERT INTO tblRotation (event_id, hospital_id, discipline_id) VALUES 
-(2, 1, 1);
+(2, 1, 1),
+(3, 1, 1),
+(4, 1, 1);
 
 INSERT INTO tblStaff (first_name, last_name, email, cell_number) VALUES
 ('John', 'Doe', 'john.doe@wits.ac.za', '123-456-7890'),
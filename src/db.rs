@@ -1,16 +1,215 @@
+use rocket::fairing;
+use rocket::{Build, Rocket};
 use rocket_db_pools::sqlx;
 use rocket_db_pools::Database;
 use serde::{Serialize, Deserialize};
+use sqlx::migrate::{Migrate, MigrateError, Migration, Migrator};
+
+use crate::endpoints::errors::{ApiErrors, ApiResult};
 
 // Rocket DB integration setup
 #[derive(Database)]
 #[database("sps_mysql")]
 pub struct SPS(sqlx::MySqlPool);
 
+/// Attempts made at a query when `db_retry_attempts` is not set in config.toml
+const DEFAULT_RETRY_ATTEMPTS: i64 = 3;
+
+/// Wait before the first retry when `db_retry_backoff_ms` is not set, doubled
+/// for every retry after it
+const DEFAULT_RETRY_BACKOFF_MS: i64 = 50;
+
+/// Longest a query may run when `db_query_timeout_ms` is not set
+const DEFAULT_QUERY_TIMEOUT_MS: i64 = 5000;
+
+/// MySQL error numbers for a transaction that lost out to another one, and
+/// will likely go through if tried again
+const TRANSIENT_DATABASE_ERRORS: [u16; 2] = [
+    1205, // lock wait timeout
+    1213, // deadlock
+];
+
+/// ## Check for a transient error
+///
+/// Errors a query may not hit if it is simply run again, like a dropped
+/// connection or a deadlock. Anything wrong with the query itself isn't
+pub fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_) => true,
+        sqlx::Error::Database(e) => e
+            .try_downcast_ref::<sqlx::mysql::MySqlDatabaseError>()
+            .map_or(false, |e| TRANSIENT_DATABASE_ERRORS.contains(&e.number())),
+        _ => false,
+    }
+}
+
+/// ## Retry policy
+///
+/// How many times a query is attempted, and how long to wait between
+/// attempts. Used through `retry_transient!`
+pub struct Retry {
+    attempts: u32,
+    made: u32,
+    backoff: std::time::Duration,
+}
+
+impl Retry {
+    pub fn new(attempts: u32, backoff: std::time::Duration) -> Self {
+        Retry {
+            attempts: attempts.max(1),
+            made: 0,
+            backoff,
+        }
+    }
+
+    /// Policy from `db_retry_attempts` and `db_retry_backoff_ms`
+    pub async fn from_settings() -> Self {
+        let settings = crate::SETTINGS.read().await;
+        let attempts = settings
+            .get_int("db_retry_attempts")
+            .unwrap_or(DEFAULT_RETRY_ATTEMPTS);
+        let backoff_ms = settings
+            .get_int("db_retry_backoff_ms")
+            .unwrap_or(DEFAULT_RETRY_BACKOFF_MS);
+
+        Retry::new(
+            attempts.clamp(1, u32::MAX as i64) as u32,
+            std::time::Duration::from_millis(backoff_ms.max(0) as u64),
+        )
+    }
+
+    /// How long to wait before trying again after an attempt failed with
+    /// `error`, or None if the error should be given back as is
+    pub fn after(&mut self, error: &sqlx::Error) -> Option<std::time::Duration> {
+        self.made += 1;
+        match self.made < self.attempts && is_transient(error) {
+            true => Some(self.backoff * 2u32.saturating_pow(self.made - 1)),
+            false => None,
+        }
+    }
+}
+
+/// ## Retry a query on transient errors
+///
+/// Awaits the query, building and running it again with backoff while it
+/// fails with a transient error, up to `db_retry_attempts` times. Used for
+/// read queries, that are always safe to run twice
+///
+/// ```ignore
+/// retry_transient!(sqlx::query!("SELECT ...").fetch_all(&mut *db_conn))
+/// ```
+macro_rules! retry_transient {
+    ($query:expr) => {{
+        let mut retry = $crate::db::Retry::from_settings().await;
+        loop {
+            match $query.await {
+                Err(e) => match retry.after(&e) {
+                    Some(wait) => rocket::tokio::time::sleep(wait).await,
+                    None => break Err(e),
+                },
+                result => break result,
+            }
+        }
+    }};
+}
+pub(crate) use retry_transient;
+
+/// Give up on a query that has run for longer than `timeout`
+pub async fn timed_for<T>(
+    timeout: std::time::Duration,
+    query: impl std::future::Future<Output = T>,
+) -> ApiResult<T> {
+    match rocket::tokio::time::timeout(timeout, query).await {
+        Ok(val) => Ok(val),
+        Err(_) => Err(ApiErrors::InternalError(format!(
+            "Database query timed out after {} ms",
+            timeout.as_millis()
+        ))),
+    }
+}
+
+/// ## Put a time limit on a query
+///
+/// Gives back what the query gave, or an internal error once it has run for
+/// longer than `db_query_timeout_ms`. The query is dropped at that point, so
+/// a stuck query doesn't hold on to the request. Wraps `retry_transient!` on
+/// list endpoints, so the limit covers every attempt
+///
+/// ```ignore
+/// db::timed(async { retry_transient!(sqlx::query!("SELECT ...").fetch_all(&mut *db_conn)) })
+/// ```
+pub async fn timed<T>(query: impl std::future::Future<Output = T>) -> ApiResult<T> {
+    let timeout_ms = crate::SETTINGS
+        .read()
+        .await
+        .get_int("db_query_timeout_ms")
+        .unwrap_or(DEFAULT_QUERY_TIMEOUT_MS);
+
+    timed_for(std::time::Duration::from_millis(timeout_ms.max(1) as u64), query).await
+}
+
+/// Schema migrations in the migrations directory, embedded at compile time
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// ## Bring a database's schema up to date
+///
+/// Runs every migration the database hasn't had yet, returning the ones that
+/// were applied
+pub async fn migrate(pool: &sqlx::MySqlPool) -> Result<Vec<&'static Migration>, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied: Vec<i64> = conn
+        .list_applied_migrations()
+        .await?
+        .iter()
+        .map(|migration| migration.version)
+        .collect();
+    drop(conn);
+
+    MIGRATOR.run(pool).await?;
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .collect())
+}
+
+/// ## Run migrations on ignite
+///
+/// Launch is stopped if the database can't be migrated, rather than serving
+/// requests against a schema the queries don't match
+#[cfg(not(tarpaulin_include))]
+pub async fn run_migrations(rocket: Rocket<Build>) -> fairing::Result {
+    let pool = match SPS::fetch(&rocket) {
+        Some(db) => &db.0,
+        None => return Err(rocket),
+    };
+
+    match migrate(pool).await {
+        Ok(applied) => {
+            for migration in applied {
+                log::info!(
+                    "Applied migration {} {}",
+                    migration.version,
+                    migration.description
+                );
+            }
+            Ok(rocket)
+        }
+        Err(e) => {
+            log::error!("Unable to migrate the database: {e}");
+            Err(rocket)
+        }
+    }
+}
+
 /// SQL Table schema for tblAccount
 ///
 /// Note:
-/// > profile_photo is a blob in the database
+/// > profile_photo is a mediumblob in the database
+/// >
+/// > bytes_used is kept up to date by the notes endpoints, and a missing
+/// > storage_quota_bytes means the quota from config.toml applies
 #[derive(sqlx::FromRow, Debug, Default)]
 pub struct Account {
     pub account_id: i32,
@@ -19,6 +218,14 @@ pub struct Account {
     pub username: String,
     pub cell_number: Option<String>,
     pub profile_photo: Option<Vec<u8>>,
+    pub role: String,
+    pub bytes_used: i64,
+    pub storage_quota_bytes: Option<i64>,
+    pub totp_secret: Option<String>,
+    pub totp_confirmed_at: Option<chrono::NaiveDateTime>,
+    pub totp_last_step: Option<i64>,
+    pub failed_attempts: i32,
+    pub locked_until: Option<chrono::NaiveDateTime>,
 }
 
 /// SQL Table schema for tblEvents
@@ -46,11 +253,13 @@ pub struct Rotation {
     pub discipline_name: String,
 }
 
-#[derive(Debug, Default, Serialize)]
+/// SQL Table schema for tblProtocol
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Protocol {
     pub protocol_id: i32,
     pub title: String,
     pub content: Option<String>,
+    pub category: Option<String>,
 }
 
 /// SQL Table schema for tblNotes
@@ -60,13 +269,77 @@ pub struct Note {
     pub content: String,
     pub title: String,
     pub public: bool,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    pub version: i32,
+}
+
+/// SQL Table schema for tblNoteTags
+pub struct NoteTag {
+    pub note_tag_id: i32,
+    pub note_id: i32,
+    pub tag: String,
+}
+
+/// SQL Table schema for tblNoteAttachments
+///
+/// Note:
+/// > data is a mediumblob in the database, left out here and only read when
+/// > the attachment is downloaded
+pub struct NoteAttachment {
+    pub attachment_id: i32,
+    pub note_id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i32,
+    pub created_at: chrono::NaiveDateTime,
 }
 
 pub struct SessionToken {
     pub session_token_id: i32,
     pub account_id: i32,
     pub token: String,
-    pub expiry_date: chrono::NaiveDate,
+    pub created_at: chrono::NaiveDateTime,
+    pub expires_at: chrono::NaiveDateTime,
+    pub last_active: chrono::NaiveDateTime,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// SQL Table schema for tblEmailChange
+///
+/// Note:
+/// > An email change waiting on the new address to be confirmed
+pub struct EmailChange {
+    pub email_change_id: i32,
+    pub account_id: i32,
+    pub new_email: String,
+    pub token: String,
+    pub expires_at: chrono::NaiveDateTime,
+}
+
+/// SQL Table schema for tblPasswordReset
+///
+/// Note:
+/// > A password reset that was asked for but hasn't been carried out yet
+pub struct PasswordReset {
+    pub password_reset_id: i32,
+    pub account_id: i32,
+    pub token: String,
+    pub expires_at: chrono::NaiveDateTime,
+}
+
+/// SQL Table schema for tblAuditLog
+///
+/// Note:
+/// > The actor is not a foreign key so entries outlive deleted accounts
+pub struct AuditLog {
+    pub audit_log_id: i32,
+    pub actor_account_id: i32,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: i32,
+    pub created_at: chrono::NaiveDateTime,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -82,4 +355,186 @@ pub struct Staff {
     pub last_name: String,
     pub email: String,
     pub cell_number: String
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{Connection, Executor};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Error a dropped connection gives
+    fn connection_reset() -> sqlx::Error {
+        sqlx::Error::Io(std::io::Error::from(std::io::ErrorKind::ConnectionReset))
+    }
+
+    /// Stand in for a query, failing with `error` until it has been run
+    /// `failures` times
+    async fn flaky_query(
+        runs: &AtomicU32,
+        failures: u32,
+        error: fn() -> sqlx::Error,
+    ) -> Result<&'static str, sqlx::Error> {
+        match runs.fetch_add(1, Ordering::SeqCst) < failures {
+            true => Err(error()),
+            false => Ok("row"),
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_retry_transient_fails_once_recovers() {
+        let runs = AtomicU32::new(0);
+
+        let result = super::retry_transient!(flaky_query(&runs, 1, connection_reset));
+
+        assert_eq!(result.unwrap(), "row");
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[rocket::async_test]
+    async fn test_retry_transient_non_transient_not_retried() {
+        let runs = AtomicU32::new(0);
+
+        let result = super::retry_transient!(flaky_query(&runs, 1, || sqlx::Error::RowNotFound));
+
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[rocket::async_test]
+    async fn test_retry_transient_gives_up_after_attempts() {
+        let runs = AtomicU32::new(0);
+
+        let result = super::retry_transient!(flaky_query(&runs, u32::MAX, connection_reset));
+
+        assert!(matches!(result, Err(sqlx::Error::Io(_))));
+        assert_eq!(runs.load(Ordering::SeqCst) as i64, super::DEFAULT_RETRY_ATTEMPTS);
+    }
+
+    #[test]
+    fn test_retry_after_backoff_doubles() {
+        let mut retry = super::Retry::new(3, std::time::Duration::from_millis(50));
+
+        assert_eq!(retry.after(&connection_reset()), Some(std::time::Duration::from_millis(50)));
+        assert_eq!(retry.after(&connection_reset()), Some(std::time::Duration::from_millis(100)));
+        assert_eq!(retry.after(&connection_reset()), None);
+    }
+
+    #[rocket::async_test]
+    async fn test_timed_for_quick_query_result() {
+        let result = super::timed_for(std::time::Duration::from_secs(5), async { 42 }).await;
+        assert_eq!(result.unwrap(), 42);
+    }
+
+    #[rocket::async_test]
+    async fn test_timed_for_slow_query_times_out() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let mut conn = sqlx::MySqlConnection::connect(&url).await.unwrap();
+
+        let result = super::timed_for(
+            std::time::Duration::from_millis(100),
+            sqlx::query("SELECT SLEEP(5)").execute(&mut conn),
+        )
+        .await;
+
+        match result {
+            Err(crate::endpoints::errors::ApiErrors::InternalError(message)) => {
+                assert!(message.contains("timed out after 100 ms"))
+            }
+            _ => panic!("expected the query to time out"),
+        }
+    }
+
+    /// Every table the migrations should leave in a database
+    const TABLES: [&str; 18] = [
+        "tblAccount",
+        "tblHospital",
+        "tblDiscipline",
+        "tblSessionToken",
+        "tblEmailChange",
+        "tblPasswordReset",
+        "tblNotes",
+        "tblNoteTags",
+        "tblNoteAttachments",
+        "tblIdempotencyKeys",
+        "tblProtocol",
+        "tblProtocolRemovals",
+        "tblEvents",
+        "tblRotation",
+        "tblSecurityQuestions",
+        "tblSecurityAnswers",
+        "tblStaff",
+        "tblAuditLog",
+    ];
+
+    #[rocket::async_test]
+    async fn test_migrate_fresh_database_tables_created() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let name = format!("ApolloniusMigrations_{}", uuid::Uuid::new_v4().simple());
+
+        // Throwaway database on the same server as the test database
+        let mut server = sqlx::MySqlConnection::connect(&url).await.unwrap();
+        server
+            .execute(format!("CREATE DATABASE {}", name).as_str())
+            .await
+            .unwrap();
+        let options = url
+            .parse::<sqlx::mysql::MySqlConnectOptions>()
+            .unwrap()
+            .database(&name);
+        let pool = sqlx::MySqlPool::connect_with(options).await.unwrap();
+
+        let first_run = super::migrate(&pool).await;
+        let second_run = super::migrate(&pool).await;
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT CAST(table_name AS CHAR) FROM information_schema.tables WHERE table_schema = ?",
+        )
+        .bind(&name)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        let migrated_columns = table_columns(&mut pool.acquire().await.unwrap(), &name).await;
+        let scripted_columns = {
+            let test_database: String = sqlx::query_scalar("SELECT CAST(DATABASE() AS CHAR)")
+                .fetch_one(&mut server)
+                .await
+                .unwrap();
+            table_columns(&mut server, &test_database).await
+        };
+
+        pool.close().await;
+        server
+            .execute(format!("DROP DATABASE {}", name).as_str())
+            .await
+            .unwrap();
+
+        assert_eq!(first_run.unwrap().len(), super::MIGRATOR.iter().count());
+        assert!(second_run.unwrap().is_empty());
+        for table in TABLES {
+            assert!(tables.contains(&table.to_string()), "{} was not created", table);
+        }
+        // The test database is set up from the SQL scripts, so migrating from
+        // the initial schema has to end up with the same columns
+        assert_eq!(migrated_columns, scripted_columns);
+    }
+
+    /// Name, type and nullability of every column in the migrated tables of a
+    /// database, in a set order
+    async fn table_columns(
+        conn: &mut sqlx::MySqlConnection,
+        database: &str,
+    ) -> Vec<(String, String, String, String)> {
+        let columns: Vec<(String, String, String, String)> = sqlx::query_as(
+            "SELECT CAST(table_name AS CHAR), CAST(column_name AS CHAR), CAST(column_type AS CHAR), CAST(is_nullable AS CHAR) \
+             FROM information_schema.columns WHERE table_schema = ? ORDER BY table_name, column_name",
+        )
+        .bind(database)
+        .fetch_all(conn)
+        .await
+        .unwrap();
+
+        columns
+            .into_iter()
+            .filter(|column| TABLES.contains(&column.0.as_str()))
+            .collect()
+    }
+}
@@ -1,4 +1,5 @@
 pub mod account;
+pub mod audit;
 pub mod auth;
 pub mod events;
 pub mod notes;
@@ -6,12 +7,20 @@ pub mod rotations;
 pub mod security;
 pub mod staff;
 
-mod errors;
+mod conditional;
+pub(crate) mod errors;
+mod guards;
+mod hashing;
+mod params;
 
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::{json, Json, Value};
 use rocket_db_pools::sqlx::{self, Row};
 use rocket_db_pools::Connection;
 
 use crate::db::SPS;
+use crate::endpoints::errors::{ApiErrors, ApiResult};
+use crate::endpoints::guards::AdminAccount;
 
 // Code Demo:
 //  for defining an index
@@ -27,11 +36,86 @@ pub async fn index(mut db: Connection<SPS>) -> &'static str {
     "Wits Student Placement System API"
 }
 
+/// ## Health check
+///
+/// Lets a load balancer know whether the API can reach its database
+///
+/// ### Arguments
+///
+/// * None
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 503 Service Unavailable
+#[get("/health")]
+pub async fn health(mut db_conn: Connection<SPS>) -> (Status, Json<Value>) {
+    match sqlx::query("SELECT 1").execute(&mut *db_conn).await {
+        Ok(_) => (Status::Ok, Json(json!({ "status": "ok" }))),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => (
+            Status::ServiceUnavailable,
+            Json(json!({ "status": "degraded" })),
+        ),
+    }
+}
+
+/// ## Request metrics
+///
+/// Request counts, status codes and latencies for every route, in the
+/// Prometheus text format
+///
+/// ### Arguments
+///
+/// * None
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+#[get("/metrics")]
+pub fn metrics() -> (ContentType, String) {
+    (
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        crate::fairings::metrics::METRICS.render(),
+    )
+}
+
+/// ## Reload the settings
+///
+/// Re-reads config.toml so operators can change settings without a restart.
+/// Replies with the settings now in effect, with secret values hidden
+///
+/// ### Arguments
+///
+/// * None
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 500 Internal Server Error
+#[post("/config/reload")]
+pub async fn reload_config(
+    admin: Result<AdminAccount, ApiErrors>,
+) -> ApiResult<Json<std::collections::BTreeMap<String, Value>>> {
+    admin?;
+
+    match crate::settings::reload(&crate::SETTINGS, crate::settings::CONFIG_FILE).await {
+        Ok(settings) => Ok(Json(crate::settings::redacted(&settings))),
+        #[cfg(not(tarpaulin_include))]
+        Err(e) => Err(ApiErrors::InternalError(format!(
+            "Unable to reload settings: {e}"
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
-    use rocket::http::Status;
+    use rocket::http::{ContentType, Status};
 
+    use crate::endpoints::errors::{ErrorBody, ErrorCode};
     use crate::tests::CLIENT;
 
     #[test]
@@ -42,4 +126,110 @@ mod tests {
         assert_eq!(response.status(), Status::Ok);
         assert!(response.body().is_some());
     }
+
+    #[test]
+    fn test_health_database_reachable_ok() {
+        let client_binding = CLIENT.lock().unwrap();
+
+        let response = client_binding.get(uri!(super::health)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), r#"{"status":"ok"}"#);
+    }
+
+    /// Value of one series in the metrics output, or 0 if it isn't there yet
+    fn metric_value(metrics: &str, series: &str) -> u64 {
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix(series))
+            .map(|val| val.trim().parse().unwrap())
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_metrics_request_counter_increments() {
+        let client_binding = CLIENT.lock().unwrap();
+        let series = r#"sps_http_requests_total{route="health",method="GET",status="200"}"#;
+
+        let response = client_binding.get(uri!(super::metrics)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let before = metric_value(&response.into_string().unwrap(), series);
+
+        let response = client_binding.get(uri!(super::health)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client_binding.get(uri!(super::metrics)).dispatch();
+        let metrics = response.into_string().unwrap();
+        assert_eq!(metric_value(&metrics, series), before + 1);
+        assert!(metrics.contains(r#"sps_http_request_duration_seconds_count{route="health",method="GET"}"#));
+    }
+
+    #[test]
+    fn test_reload_config_admin_current_settings_ok() {
+        let client_binding = CLIENT.lock().unwrap();
+
+        let response = client_binding
+            .post(uri!(super::reload_config))
+            .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let settings = response.into_json::<super::Value>().unwrap();
+        assert_eq!(settings["static_file_directory"], "./static/");
+    }
+
+    #[test]
+    fn test_reload_config_regular_user_forbidden() {
+        let client_binding = CLIENT.lock().unwrap();
+
+        let response = client_binding
+            .post(uri!(super::reload_config))
+            .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(response.body().is_some());
+    }
+
+    /// Status and parsed JSON error body of a response
+    fn error_body(response: rocket::local::blocking::LocalResponse<'_>) -> (Status, ErrorBody) {
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        (response.status(), response.into_json::<ErrorBody>().unwrap())
+    }
+
+    #[test]
+    fn test_unmatched_route_json_not_found() {
+        let client_binding = CLIENT.lock().unwrap();
+
+        let (status, body) = error_body(client_binding.get("/no/such/route").dispatch());
+        assert_eq!(status, Status::NotFound);
+        assert_eq!(body.code, ErrorCode::NotFound);
+        assert!(body.message.contains("/no/such/route"));
+    }
+
+    #[test]
+    fn test_malformed_json_body_json_bad_request() {
+        let client_binding = CLIENT.lock().unwrap();
+
+        let response = client_binding
+            .post("/authentication/credentials")
+            .header(ContentType::JSON)
+            .body(r#"{"email": "0000000@students.wits.ac.za","#)
+            .dispatch();
+        let (status, body) = error_body(response);
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(body.code, ErrorCode::BadRequest);
+    }
+
+    #[test]
+    fn test_wrongly_typed_json_body_json_unprocessable_entity() {
+        let client_binding = CLIENT.lock().unwrap();
+
+        let response = client_binding
+            .post("/authentication/credentials")
+            .header(ContentType::JSON)
+            .body(r#"{"email": 1, "hashed_password": true}"#)
+            .dispatch();
+        let (status, body) = error_body(response);
+        assert_eq!(status, Status::UnprocessableEntity);
+        assert_eq!(body.code, ErrorCode::UnprocessableEntity);
+    }
 }
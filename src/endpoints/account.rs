@@ -1,130 +1,1062 @@
 #[cfg(test)]
 mod tests;
 
+pub(crate) mod delivery;
 mod manage;
 mod password;
 mod security_question;
 
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::ContentType;
 use rocket::serde::json::Json;
 use rocket_db_pools::{sqlx, Connection};
+use sqlx::Acquire;
 
 use crate::db;
 use crate::db::SPS;
-use crate::endpoints::errors::{ApiErrors, ApiResult};
+use crate::endpoints::audit::{self, AuditAction};
+use crate::endpoints::auth::validate_email;
+use crate::endpoints::errors::{ApiErrors, ApiResult, ErrorCode};
+use crate::endpoints::guards::{AdminAccount, AuthAccount};
+use crate::endpoints::hashing;
+use crate::endpoints::params;
 
-use self::security_question::AddSecurityQuestion;
+use self::delivery::TokenPurpose;
+use self::security_question::{AccountSecurityQuestion, AddSecurityQuestion};
+
+/// Minimum password length used when `password_min_length` is not set in config.toml
+const DEFAULT_PASSWORD_MIN_LENGTH: i64 = 8;
+/// How long in seconds an email change can be confirmed for, 1 day
+const DEFAULT_EMAIL_VERIFICATION_TTL_SECONDS: i64 = 24 * 60 * 60;
+/// How long in seconds a password reset token can be used for, 1 hour
+const DEFAULT_PASSWORD_RESET_TTL_SECONDS: i64 = 60 * 60;
+/// Maximum avatar size used when `max_avatar_bytes` is not set in config.toml
+const DEFAULT_MAX_AVATAR_BYTES: i64 = 2 * 1024 * 1024;
+/// Fewest security questions an account can set when `security_questions_min`
+/// is not set in config.toml
+const DEFAULT_SECURITY_QUESTIONS_MIN: i64 = 2;
+
+/// First bytes of every PNG file
+pub(crate) const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+/// First bytes of every JPEG file
+pub(crate) const JPEG_SIGNATURE: &[u8] = &[0xFF, 0xD8, 0xFF];
+
+/// ## Validate a new password
+///
+/// Checks the password is at least `password_min_length` characters long and,
+/// unless turned off with `password_require_letter` / `password_require_digit`,
+/// contains at least one letter and one digit.
+pub(crate) async fn validate_password(password: &str) -> ApiResult<()> {
+    let (min_length, require_letter, require_digit) = {
+        let settings = crate::SETTINGS.read().await;
+        (
+            settings
+                .get_int("password_min_length")
+                .unwrap_or(DEFAULT_PASSWORD_MIN_LENGTH),
+            settings.get_bool("password_require_letter").unwrap_or(true),
+            settings.get_bool("password_require_digit").unwrap_or(true),
+        )
+    };
+
+    if (password.chars().count() as i64) < min_length {
+        return Err(ApiErrors::BadRequest(format!(
+            "Password must be at least {} characters long",
+            min_length
+        ))
+        .with_code(ErrorCode::WeakPassword));
+    }
+
+    if require_letter && !password.chars().any(|c| c.is_alphabetic()) {
+        return Err(ApiErrors::BadRequest("Password must contain at least one letter".to_string())
+            .with_code(ErrorCode::WeakPassword));
+    }
+
+    if require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+        return Err(ApiErrors::BadRequest("Password must contain at least one digit".to_string())
+            .with_code(ErrorCode::WeakPassword));
+    }
+
+    Ok(())
+}
+
+/// ## Request a password reset
+///
+/// Starts a password reset for the account with the given email address,
+/// handing out a single use token that `account_reset_password` needs. Tokens
+/// last `password_reset_ttl_seconds`, and asking again replaces any token the
+/// account already had. The token is delivered as `token_delivery` says
+///
+/// The response is the same whether or not an account has the email address,
+/// so it can't be used to find out which addresses have accounts
+///
+/// ### Arguments
+///
+///  * email
+///
+/// ### Possible Response
+///
+/// * 200 Ok
+#[post("/account/request_password_reset", data = "<reset_request>")]
+pub async fn request_password_reset(
+    mut db_conn: Connection<SPS>,
+    reset_request: Json<password::PasswordResetRequest>,
+) -> ApiResult<Json<password::PasswordResetResponse>> {
+    let account_id = match sqlx::query!(
+        "SELECT account_id FROM tblAccount WHERE email = ?",
+        reset_request.email
+    )
+    .fetch_optional(&mut *db_conn)
+    .await
+    {
+        Ok(Some(val)) => val.account_id,
+        Ok(None) => return Ok(Json(password::PasswordResetResponse { reset_token: None })),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to query database".to_string(),
+            ))
+        }
+    };
+
+    let ttl_seconds = crate::SETTINGS
+        .read()
+        .await
+        .get_int("password_reset_ttl_seconds")
+        .unwrap_or(DEFAULT_PASSWORD_RESET_TTL_SECONDS);
+    let expires_at = chrono::Utc::now().naive_utc() + chrono::Duration::seconds(ttl_seconds.max(0));
+    let token = uuid::Uuid::new_v4().simple().to_string();
+
+    // Only the latest token can be used, which also clears out expired ones
+    match sqlx::query!(
+        "DELETE FROM tblPasswordReset WHERE account_id = ?",
+        account_id
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to remove the previous password reset".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!(
+        "INSERT INTO tblPasswordReset (account_id, token, expires_at) VALUES (?, ?, ?)",
+        account_id,
+        token,
+        expires_at
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to save the password reset".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json(password::PasswordResetResponse {
+        reset_token: delivery::send_token(
+            account_id,
+            &reset_request.email,
+            TokenPurpose::PasswordReset,
+            token,
+        )
+        .await,
+    }))
+}
 
 /// ## Reset password for an account
 ///
+/// Needs a token from `request_password_reset`, and every security question
+/// on the account has to be answered correctly. The token can only be used
+/// once, and is used up by wrong answers too, so a new one has to be asked
+/// for. Every session on the account is ended along with the password change,
+/// in case the old password was compromised
+///
 /// ### Arguments
 ///
-///  * account_id,
+///  * reset_token,
+///  * new_password,
 ///  * questions
 ///
 /// ### Possible Response
 ///
+/// ```json
+///     {
+///         "sessions_terminated": number
+///     }
+/// ```
+///
 /// * 200 Ok
+/// * 400 Bad Request
 /// * 401 Unauthorized
-/// * 404 Not Found
 #[post("/account/reset_password", data = "<reset_details>")]
 pub async fn account_reset_password(
     mut db_conn: Connection<SPS>,
     reset_details: Json<password::NewPasswordRequest>,
-) -> ApiResult<()> {
-    let _db_account = match sqlx::query_as!(
+) -> ApiResult<Json<password::PasswordResetResult>> {
+    validate_password(&reset_details.new_password).await?;
+
+    let password_reset = match sqlx::query_as!(
+        db::PasswordReset,
+        "SELECT * FROM tblPasswordReset WHERE token = ?",
+        reset_details.reset_token
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::Unauthorized("Invalid password reset token".to_string())
+                .with_code(ErrorCode::ResetTokenInvalid))
+        }
+    };
+
+    if chrono::Utc::now().naive_utc() >= password_reset.expires_at {
+        match sqlx::query!(
+            "DELETE FROM tblPasswordReset WHERE password_reset_id = ?",
+            password_reset.password_reset_id
+        )
+        .execute(&mut *db_conn)
+        .await
+        {
+            Ok(_) => (),
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Failed to remove the expired password reset".to_string(),
+                ))
+            }
+        };
+
+        return Err(ApiErrors::Unauthorized("Expired password reset token".to_string())
+            .with_code(ErrorCode::ResetTokenExpired));
+    }
+
+    // The old sessions go with the old password, or neither does. Returning
+    // early drops the transaction, which rolls it back
+    let mut transaction = match (&mut *db_conn).begin().await {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to start a database transaction".to_string(),
+            ))
+        }
+    };
+
+    // Used up before anything else, so of two requests racing with the same
+    // token only one gets past here
+    match sqlx::query!(
+        "DELETE FROM tblPasswordReset WHERE token = ? AND expires_at > ?",
+        reset_details.reset_token,
+        chrono::Utc::now().naive_utc()
+    )
+    .execute(&mut *transaction)
+    .await
+    {
+        Ok(val) if val.rows_affected() == 1 => (),
+        Ok(_) => {
+            return Err(ApiErrors::Unauthorized("Invalid password reset token".to_string())
+                .with_code(ErrorCode::ResetTokenInvalid))
+        }
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to remove the password reset".to_string(),
+            ))
+        }
+    };
+
+    let account_questions = match sqlx::query!(
+        "SELECT secques_id as question_id, answer as correct_answer FROM tblSecurityAnswers WHERE account_id = ?",
+        password_reset.account_id
+    ).fetch_all(&mut *transaction).await {
+        Ok(val) => val,
+        Err(_) => return Err(ApiErrors::InternalError("Failed to fetch answers".to_string()))
+    };
+
+    // Answers stored before argon2 get hashed now that we know they are correct
+    let mut rehashed_answers = Vec::new();
+    let mut answers_correct = true;
+    for account_question in &account_questions {
+        let sent_question = reset_details
+            .questions
+            .iter()
+            .find(|question| question.question_id == account_question.question_id);
+
+        match sent_question {
+            Some(question)
+                if hashing::verify_answer(&question.user_answer, &account_question.correct_answer) =>
+            {
+                if hashing::needs_rehash(&account_question.correct_answer) {
+                    rehashed_answers.push((
+                        account_question.question_id,
+                        hashing::hash_answer(&question.user_answer)?,
+                    ));
+                }
+            }
+            _ => answers_correct = false,
+        }
+    }
+
+    // A wrong answer still uses up the token, so answers can't be guessed
+    // over and over with the same one
+    if !answers_correct {
+        match transaction.commit().await {
+            Ok(_) => (),
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Failed to remove the password reset".to_string(),
+                ))
+            }
+        }
+
+        return Err(ApiErrors::Unauthorized("Invalid answer provided".to_string())
+            .with_code(ErrorCode::IncorrectAnswer));
+    }
+
+    let hashed_password = hashing::hash_password(&reset_details.new_password)?;
+
+    match sqlx::query!(
+        "UPDATE tblAccount SET hashed_password = ? WHERE account_id = ?",
+        hashed_password,
+        password_reset.account_id
+    )
+    .execute(&mut *transaction)
+    .await
+    {
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to update account password".to_string(),
+            ))
+        }
+        _ => (),
+    };
+
+    let sessions_terminated = match sqlx::query!(
+        "DELETE FROM tblSessionToken WHERE account_id = ?",
+        password_reset.account_id
+    )
+    .execute(&mut *transaction)
+    .await
+    {
+        Ok(val) => val.rows_affected(),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove session tokens from database".to_string(),
+            ))
+        }
+    };
+
+    for (question_id, hashed_answer) in &rehashed_answers {
+        match sqlx::query!(
+            "UPDATE tblSecurityAnswers SET answer = ? WHERE account_id = ? AND secques_id = ?",
+            hashed_answer,
+            password_reset.account_id,
+            question_id
+        )
+        .execute(&mut *transaction)
+        .await
+        {
+            Ok(_) => (),
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Failed to update security answers".to_string(),
+                ))
+            }
+        };
+    }
+
+    audit::record(
+        &mut *transaction,
+        password_reset.account_id,
+        AuditAction::ResetPassword,
+        password_reset.account_id,
+    )
+    .await;
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to update account password".to_string(),
+            ))
+        }
+    }
+
+    Ok(Json(password::PasswordResetResult { sessions_terminated }))
+}
+
+/// ## Create a new account
+///
+/// ### Arguments
+///
+/// ```json
+///     {
+///         "email": string,
+///         "hashed_password": string,
+///         "username": string,
+///         "cell_number": string | null
+///     }
+/// ```
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 409 Conflict
+#[post("/account", data = "<new_account>")]
+pub async fn create_account(
+    mut db_conn: Connection<SPS>,
+    new_account: Json<manage::NewAccount>,
+) -> ApiResult<Json<manage::NewAccountResponse>> {
+    validate_email(&new_account.email)?;
+    validate_password(&new_account.hashed_password).await?;
+
+    if new_account.username.trim().is_empty() {
+        return Err(ApiErrors::BadRequest("Username must not be empty".to_string()));
+    }
+
+    match sqlx::query!(
+        "SELECT account_id FROM tblAccount WHERE email = ? OR username = ?",
+        new_account.email,
+        new_account.username
+    )
+    .fetch_optional(&mut *db_conn)
+    .await
+    {
+        Ok(None) => (),
+        Ok(Some(_)) => {
+            return Err(ApiErrors::Conflict(
+                "An account with that email or username already exists".to_string(),
+            )
+            .with_code(ErrorCode::AccountExists))
+        }
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to query database".to_string(),
+            ))
+        }
+    };
+
+    let hashed_password = hashing::hash_password(&new_account.hashed_password)?;
+
+    let account_id = match sqlx::query!(
+        "INSERT INTO tblAccount (email, hashed_password, username, cell_number) VALUES (?, ?, ?, ?)",
+        new_account.email,
+        hashed_password,
+        new_account.username,
+        new_account.cell_number
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val.last_insert_id() as i32,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to create new account".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json(manage::NewAccountResponse { account_id }))
+}
+
+/// ## Update a user's account details
+///
+/// Profile fields are updated straight away. A new email address is only
+/// recorded as pending along with a verification token, and replaces the
+/// current one once the token is confirmed with `confirm_email`. The token is
+/// emailed to the new address, and only sent back in the response when
+/// `token_delivery` is `response`
+///
+/// ### Arguments
+///
+///  * account_id,
+///  * username
+///  * cell_number
+///  * profile_photo
+///  * email (optional)
+///
+/// ### Possible Response
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+/// * 409 Conflict
+#[put("/account", data = "<updated_account>")]
+pub async fn update_account(
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+    updated_account: Json<manage::UpdateAccount>,
+) -> ApiResult<Json<manage::UpdateAccountResponse>> {
+    auth?.check_account(updated_account.account_id)?;
+
+    let db_account = match sqlx::query_as!(
         db::Account,
         "SELECT * FROM tblAccount WHERE account_id = ?",
-        reset_details.account_id
+        updated_account.account_id
     )
     .fetch_one(&mut *db_conn)
     .await
     {
         Ok(val) => val,
-        Err(_) => return Err(ApiErrors::NotFound("Account not found".to_string())),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
+    };
+
+    // Only a different address needs confirming
+    let new_email = match &updated_account.email {
+        Some(email) if email != &db_account.email => {
+            validate_email(email)?;
+            Some(email.to_owned())
+        }
+        _ => None,
+    };
+
+    if let Some(email) = &new_email {
+        match sqlx::query!("SELECT account_id FROM tblAccount WHERE email = ?", email)
+            .fetch_optional(&mut *db_conn)
+            .await
+        {
+            Ok(None) => (),
+            Ok(Some(_)) => {
+                return Err(ApiErrors::Conflict(
+                    "An account with that email already exists".to_string(),
+                )
+                .with_code(ErrorCode::AccountExists))
+            }
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Failed to query database".to_string(),
+                ))
+            }
+        };
+    }
+
+    match sqlx::query!(
+        "UPDATE tblAccount SET username = ?, cell_number = ?, profile_photo = ? WHERE account_id = ?",
+        updated_account.username, updated_account.cell_number, updated_account.profile_photo, updated_account.account_id
+    ).execute(&mut *db_conn).await {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => return Err(ApiErrors::InternalError("Failed to update the account".to_string())),
+    };
+
+    audit::record(
+        &mut *db_conn,
+        updated_account.account_id,
+        AuditAction::UpdateAccount,
+        updated_account.account_id,
+    )
+    .await;
+
+    let email = match new_email {
+        Some(val) => val,
+        None => {
+            return Ok(Json(manage::UpdateAccountResponse {
+                pending_email: None,
+                verification_token: None,
+            }))
+        }
+    };
+
+    let ttl_seconds = crate::SETTINGS
+        .read()
+        .await
+        .get_int("email_verification_ttl_seconds")
+        .unwrap_or(DEFAULT_EMAIL_VERIFICATION_TTL_SECONDS);
+    let expires_at = chrono::Utc::now().naive_utc() + chrono::Duration::seconds(ttl_seconds.max(0));
+    let token = uuid::Uuid::new_v4().simple().to_string();
+
+    // Only the latest requested change can be confirmed
+    match sqlx::query!(
+        "DELETE FROM tblEmailChange WHERE account_id = ?",
+        updated_account.account_id
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to remove the previous email change".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!(
+        "INSERT INTO tblEmailChange (account_id, new_email, token, expires_at) VALUES (?, ?, ?, ?)",
+        updated_account.account_id,
+        email,
+        token,
+        expires_at
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to save the email change".to_string(),
+            ))
+        }
+    };
+
+    let verification_token = delivery::send_token(
+        updated_account.account_id,
+        &email,
+        TokenPurpose::EmailVerification,
+        token,
+    )
+    .await;
+
+    Ok(Json(manage::UpdateAccountResponse {
+        pending_email: Some(email),
+        verification_token,
+    }))
+}
+
+/// ## Confirm a pending email change
+///
+/// ### Arguments
+///
+///  * verification token
+///
+/// ### Possible Response
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 409 Conflict
+#[post("/account/confirm_email/<token>")]
+pub async fn confirm_email(mut db_conn: Connection<SPS>, token: String) -> ApiResult<()> {
+    let email_change = match sqlx::query_as!(
+        db::EmailChange,
+        "SELECT * FROM tblEmailChange WHERE token = ?",
+        token
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::Unauthorized("Invalid email verification token".to_string())
+                .with_code(ErrorCode::VerificationTokenInvalid))
+        }
+    };
+
+    if chrono::Utc::now().naive_utc() >= email_change.expires_at {
+        match sqlx::query!(
+            "DELETE FROM tblEmailChange WHERE email_change_id = ?",
+            email_change.email_change_id
+        )
+        .execute(&mut *db_conn)
+        .await
+        {
+            Ok(_) => (),
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Failed to remove the expired email change".to_string(),
+                ))
+            }
+        };
+
+        return Err(ApiErrors::Unauthorized("Expired email verification token".to_string())
+            .with_code(ErrorCode::VerificationTokenExpired));
+    }
+
+    // The address could have been taken since the change was requested
+    match sqlx::query!(
+        "SELECT account_id FROM tblAccount WHERE email = ?",
+        email_change.new_email
+    )
+    .fetch_optional(&mut *db_conn)
+    .await
+    {
+        Ok(None) => (),
+        Ok(Some(_)) => {
+            return Err(ApiErrors::Conflict("An account with that email already exists".to_string())
+                .with_code(ErrorCode::AccountExists))
+        }
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to query database".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!(
+        "UPDATE tblAccount SET email = ? WHERE account_id = ?",
+        email_change.new_email,
+        email_change.account_id
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to update the account email".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!(
+        "DELETE FROM tblEmailChange WHERE account_id = ?",
+        email_change.account_id
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to remove the email change".to_string(),
+            ))
+        }
+    };
+
+    Ok(())
+}
+
+/// ## Upload a profile picture for an account
+///
+/// The image is sent as the raw request body and stored as the account's
+/// profile photo. Only PNG and JPEG images no larger than `max_avatar_bytes`
+/// are accepted, and the content has to match the declared content type
+///
+/// ### Arguments
+///
+///  * account_id
+///  * image data (Content-Type image/png or image/jpeg)
+///
+/// ### Possible Response
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+/// * 413 Payload Too Large
+#[put("/account/<account_id>/avatar", data = "<image>")]
+pub async fn upload_avatar(
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+    account_id: i32,
+    content_type: &ContentType,
+    image: Data<'_>,
+) -> ApiResult<()> {
+    auth?.check_account(account_id)?;
+
+    let signature = if content_type == &ContentType::PNG {
+        PNG_SIGNATURE
+    } else if content_type == &ContentType::JPEG {
+        JPEG_SIGNATURE
+    } else {
+        return Err(ApiErrors::BadRequest(
+            "Profile pictures must be PNG or JPEG images".to_string(),
+        ));
     };
 
-    let account_questions = match sqlx::query!(
-        "SELECT secques_id as question_id, answer as correct_answer FROM tblSecurityAnswers WHERE account_id = ?",
-        &reset_details.account_id
-    ).fetch_all(&mut *db_conn).await {
+    let max_avatar_bytes = crate::SETTINGS
+        .read()
+        .await
+        .get_int("max_avatar_bytes")
+        .unwrap_or(DEFAULT_MAX_AVATAR_BYTES)
+        .max(0) as u64;
+
+    let image = match image.open(max_avatar_bytes.bytes()).into_bytes().await {
         Ok(val) => val,
-        Err(_) => return Err(ApiErrors::InternalError("Failed to fetch answers".to_string()))
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to read the uploaded image".to_string(),
+            ))
+        }
     };
 
-    for sent_question in &reset_details.questions {
-        for account_question in &account_questions {
-            match &sent_question.question_id == &account_question.question_id {
-                // This could also be done with a hashmap but I think this is easier to both read
-                // and write
-                // Only check if the answers match if we are looking at the same question
-                true => match &sent_question.user_answer == &account_question.correct_answer {
-                    false => return Err(ApiErrors::Unauth("Invalid answer provided".to_string())),
-                    true => (),
-                },
-                false => (),
-            }
-        }
+    if !image.is_complete() {
+        return Err(ApiErrors::PayloadTooLarge(format!(
+            "Profile pictures may not be larger than {} bytes",
+            max_avatar_bytes
+        )));
+    }
+
+    if !image.starts_with(signature) {
+        return Err(ApiErrors::BadRequest(
+            "Uploaded file is not a valid image of the given type".to_string(),
+        ));
     }
 
     match sqlx::query!(
-        "UPDATE tblAccount SET hashed_password = ? WHERE account_id = ?",
-        reset_details.new_password,
-        reset_details.account_id
+        "SELECT account_id FROM tblAccount WHERE account_id = ?",
+        account_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
+    };
+
+    match sqlx::query!(
+        "UPDATE tblAccount SET profile_photo = ? WHERE account_id = ?",
+        image.into_inner(),
+        account_id
     )
     .execute(&mut *db_conn)
     .await
     {
+        Ok(_) => (),
         #[cfg(not(tarpaulin_include))]
         Err(_) => {
             return Err(ApiErrors::InternalError(
-                "Failed to update account password".to_string(),
+                "Failed to save the profile picture".to_string(),
             ))
         }
-        _ => (),
     };
 
     Ok(())
 }
 
-/// ## Update a user's account details
+/// ## Delete an account
+///
+/// Removes the account along with everything that belongs to it: notes and
+/// their attachments, events and rotations, sessions, security answers,
+/// pending email changes and password resets.
+/// Either all of it is removed or none of it is
 ///
 /// ### Arguments
 ///
-///  * account_id,
-///  * username
-///  * cell_number
-///  * profile_photo
+///  * account_id
 ///
 /// ### Possible Response
 ///
 /// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
 /// * 404 Not Found
-#[put("/account", data = "<updated_account>")]
-pub async fn update_account(
+#[delete("/account/<account_id>")]
+pub async fn delete_account(
+    auth: Result<AuthAccount, ApiErrors>,
+    account_id: i32,
     mut db_conn: Connection<SPS>,
-    updated_account: Json<manage::UpdateAccount>,
 ) -> ApiResult<()> {
-    let _db_account = match sqlx::query_as!(
-        db::Account,
-        "SELECT * FROM tblAccount WHERE account_id = ?",
-        updated_account.account_id
+    auth?.check_account(account_id)?;
+
+    match sqlx::query!(
+        "SELECT account_id FROM tblAccount WHERE account_id = ?",
+        account_id
     )
     .fetch_one(&mut *db_conn)
     .await
     {
+        Ok(_) => (),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
+    };
+
+    let mut transaction = match (&mut *db_conn).begin().await {
         Ok(val) => val,
-        Err(_) => return Err(ApiErrors::NotFound("Account not found".to_string())),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to start a database transaction".to_string(),
+            ))
+        }
+    };
+
+    // Children first so no foreign key is left pointing at the account.
+    // Returning early drops the transaction, which rolls it back
+    match sqlx::query!("DELETE tblRotation FROM tblRotation JOIN tblEvents USING (event_id) WHERE tblEvents.account_id = ?", account_id)
+        .execute(&mut transaction)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account's rotations".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!("DELETE FROM tblEvents WHERE account_id = ?", account_id)
+        .execute(&mut transaction)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account's events".to_string(),
+            ))
+        }
     };
 
+    // Keys for notes since transferred to this account belong to someone else
     match sqlx::query!(
-        "UPDATE tblAccount SET username = ?, cell_number = ?, profile_photo = ? WHERE account_id = ?",
-        updated_account.username, updated_account.cell_number, updated_account.profile_photo, updated_account.account_id
-    ).execute(&mut *db_conn).await {
+        "DELETE FROM tblIdempotencyKeys WHERE account_id = ? OR note_id IN (SELECT note_id FROM tblNotes WHERE account_id = ?)",
+        account_id,
+        account_id
+    )
+        .execute(&mut transaction)
+        .await
+    {
         Ok(_) => (),
         #[cfg(not(tarpaulin_include))]
-        Err(_) => return Err(ApiErrors::InternalError("Failed to update the account".to_string())),
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account's idempotency keys".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!("DELETE tblNoteTags FROM tblNoteTags JOIN tblNotes USING (note_id) WHERE tblNotes.account_id = ?", account_id)
+        .execute(&mut transaction)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account's note tags".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!("DELETE tblNoteAttachments FROM tblNoteAttachments JOIN tblNotes USING (note_id) WHERE tblNotes.account_id = ?", account_id)
+        .execute(&mut transaction)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account's note attachments".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!("DELETE FROM tblNotes WHERE account_id = ?", account_id)
+        .execute(&mut transaction)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account's notes".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!("DELETE FROM tblSessionToken WHERE account_id = ?", account_id)
+        .execute(&mut transaction)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account's sessions".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!("DELETE FROM tblSecurityAnswers WHERE account_id = ?", account_id)
+        .execute(&mut transaction)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account's security answers".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!("DELETE FROM tblEmailChange WHERE account_id = ?", account_id)
+        .execute(&mut transaction)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account's email changes".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!("DELETE FROM tblPasswordReset WHERE account_id = ?", account_id)
+        .execute(&mut transaction)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account's password resets".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!("DELETE FROM tblAccount WHERE account_id = ?", account_id)
+        .execute(&mut transaction)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account".to_string(),
+            ))
+        }
     };
 
+    match transaction.commit().await {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove the account".to_string(),
+            ))
+        }
+    }
+
     Ok(())
 }
 
@@ -137,12 +1069,17 @@ pub async fn update_account(
 /// ### Possible Response
 ///
 /// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
 /// * 404 Not Found
 #[get("/account/<account_id>")]
 pub async fn fetch_account(
+    auth: Result<AuthAccount, ApiErrors>,
     mut db_conn: Connection<SPS>,
     account_id: i32,
 ) -> ApiResult<Json<manage::UserAccount>> {
+    auth?.check_account(account_id)?;
+
     let db_account = match sqlx::query_as!(
         db::Account,
         "SELECT * FROM tblAccount WHERE account_id = ?",
@@ -153,9 +1090,8 @@ pub async fn fetch_account(
     {
         Ok(val) => val,
         Err(_) => {
-            return Err(ApiErrors::NotFound(
-                "No account with that ID exists".to_string(),
-            ))
+            return Err(ApiErrors::NotFound("No account with that ID exists".to_string())
+                .with_code(ErrorCode::AccountNotFound))
         }
     };
 
@@ -164,7 +1100,123 @@ pub async fn fetch_account(
     Ok(Json(account))
 }
 
-/// ## Reset a users security questions 
+/// ## Search accounts
+///
+/// Finds accounts whose username or email contains the query, ignoring case.
+/// Results are sorted by account ID and returned a page at a time
+///
+/// ### Arguments
+///
+///  * query,
+///  * limit (optional query, defaults to 100, at most 500)
+///  * offset (optional query, defaults to 0)
+///
+/// ### Possible Response
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+#[get("/account/search/<query>?<limit>&<offset>")]
+pub async fn search_accounts(
+    admin: Result<AdminAccount, ApiErrors>,
+    query: String,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<Vec<manage::AccountSummary>>> {
+    admin?;
+
+    let limit = params::parse_limit(limit)?;
+    let offset = offset.unwrap_or(0);
+    let pattern = format!("%{}%", params::escape_like(&query.to_lowercase()));
+
+    let db_accounts = match sqlx::query!(
+        "SELECT account_id, email, username, role FROM tblAccount WHERE LOWER(username) LIKE ? OR LOWER(email) LIKE ? ORDER BY account_id LIMIT ? OFFSET ?",
+        pattern,
+        pattern,
+        limit,
+        offset
+    )
+    .fetch_all(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to search accounts".to_string(),
+            ))
+        }
+    };
+
+    let accounts = db_accounts
+        .into_iter()
+        .map(|account| manage::AccountSummary {
+            account_id: account.account_id,
+            email: account.email,
+            username: account.username,
+            role: account.role,
+        })
+        .collect();
+
+    Ok(Json(accounts))
+}
+
+/// ## Check a new set of security questions
+///
+/// Every question has to be a different one out of tblSecurityQuestions, and
+/// there have to be at least `security_questions_min` of them
+async fn validate_security_questions<'c>(
+    executor: impl sqlx::MySqlExecutor<'c>,
+    add_questions: &AddSecurityQuestion,
+) -> ApiResult<()> {
+    let min_questions = crate::SETTINGS
+        .read()
+        .await
+        .get_int("security_questions_min")
+        .unwrap_or(DEFAULT_SECURITY_QUESTIONS_MIN);
+
+    if (add_questions.questions.len() as i64) < min_questions {
+        return Err(ApiErrors::BadRequest(format!(
+            "At least {} security questions must be answered",
+            min_questions
+        )));
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    if !add_questions.questions.iter().all(|question| seen.insert(question.question_id)) {
+        return Err(ApiErrors::BadRequest(
+            "Each security question can only be answered once".to_string(),
+        ));
+    }
+
+    let known_ids = match sqlx::query_scalar!("SELECT secques_id FROM tblSecurityQuestions")
+        .fetch_all(executor)
+        .await
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to fetch the security questions".to_string(),
+            ))
+        }
+    };
+
+    match add_questions.questions.iter().find(|question| !known_ids.contains(&question.question_id)) {
+        Some(question) => Err(ApiErrors::BadRequest(format!(
+            "No security question with ID {} exists",
+            question.question_id
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// ## Reset a users security questions
+///
+/// Replaces every answer the account had with the new ones, which are only
+/// stored hashed. Either all of the new answers are saved or none are
 ///
 /// ### Arguments
 ///
@@ -174,9 +1226,18 @@ pub async fn fetch_account(
 /// ### Possible Response
 ///
 /// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
 /// * 404 Not Found
 #[post("/account/security", data = "<add_questions>")]
-pub async fn add_questions(mut db_conn: Connection<SPS>, add_questions: Json<AddSecurityQuestion>) -> ApiResult<()> {
+pub async fn add_questions(
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+    add_questions: Json<AddSecurityQuestion>,
+) -> ApiResult<()> {
+    auth?.check_account(add_questions.account_id)?;
+
     let db_account = match sqlx::query_as!(
         db::Account,
         "SELECT * FROM tblAccount WHERE account_id = ?",
@@ -187,8 +1248,21 @@ pub async fn add_questions(mut db_conn: Connection<SPS>, add_questions: Json<Add
     {
         Ok(val) => val,
         Err(_) => {
-            return Err(ApiErrors::NotFound(
-                "No account with that ID exists".to_string(),
+            return Err(ApiErrors::NotFound("No account with that ID exists".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
+    };
+
+    validate_security_questions(&mut *db_conn, &add_questions).await?;
+
+    // Returning early drops the transaction, which rolls it back and keeps
+    // the old answers
+    let mut transaction = match (&mut *db_conn).begin().await {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to start a database transaction".to_string(),
             ))
         }
     };
@@ -196,22 +1270,71 @@ pub async fn add_questions(mut db_conn: Connection<SPS>, add_questions: Json<Add
     match sqlx::query!(
         "DELETE FROM tblSecurityAnswers WHERE account_id = ?",
         add_questions.account_id
-    ).execute(&mut *db_conn).await {
+    ).execute(&mut *transaction).await {
         Ok(_) => (),
         #[cfg(not(tarpaulin_include))]
         Err(_) => return Err(ApiErrors::InternalError("Failed to remove old security questions".to_string())),
     };
 
     for question in &add_questions.questions {
+        let hashed_answer = hashing::hash_answer(&question.user_answer)?;
+
         match sqlx::query!(
             "INSERT INTO tblSecurityAnswers (secques_id, account_id, answer) VALUES (?, ?, ?)",
-            question.question_id, add_questions.account_id, question.user_answer
-        ).execute(&mut *db_conn).await {
+            question.question_id, add_questions.account_id, hashed_answer
+        ).execute(&mut *transaction).await {
             Ok(_) => (),
             #[cfg(not(tarpaulin_include))]
             Err(_) => return Err(ApiErrors::InternalError("Failed to insert the new questions".to_string()))
         }
     }
 
+    match transaction.commit().await {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to insert the new questions".to_string(),
+            ))
+        }
+    }
+
     Ok(())
+}
+
+/// ## Fetch a users security questions
+///
+/// Lists the questions the account has answered, never the answers
+///
+/// ### Arguments
+///
+///  * account_id
+///
+/// ### Possible Response
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+#[get("/account/<account_id>/security", rank = 2)]
+pub async fn fetch_account_security_questions(
+    auth: Result<AuthAccount, ApiErrors>,
+    account_id: i32,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<Vec<AccountSecurityQuestion>>> {
+    auth?.check_account(account_id)?;
+
+    match sqlx::query_as!(
+        AccountSecurityQuestion,
+        "SELECT tblSecurityQuestions.secques_id AS question_id, question FROM tblSecurityAnswers JOIN tblSecurityQuestions USING (secques_id) WHERE account_id = ? ORDER BY secques_id",
+        account_id
+    )
+    .fetch_all(&mut *db_conn)
+    .await
+    {
+        Ok(val) => Ok(Json(val)),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => Err(ApiErrors::InternalError(
+            "Failed to fetch the security questions".to_string(),
+        )),
+    }
 }
\ No newline at end of file
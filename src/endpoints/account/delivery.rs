@@ -0,0 +1,196 @@
+//! # Token delivery
+//! Getting password reset and email verification tokens to the account holder
+//! without sending them back to whoever asked for them
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Ways tokens can be delivered, as `token_delivery` is set
+pub const DELIVERY_MODES: [&str; 2] = ["mail", "response"];
+
+/// Program mail is handed to when `sendmail_command` is not set
+const DEFAULT_SENDMAIL_COMMAND: &str = "/usr/sbin/sendmail";
+
+/// Sender of token emails when `mail_from` is not set
+const DEFAULT_MAIL_FROM: &str = "no-reply@sps.wits.ac.za";
+
+/// What a token lets the account holder do
+#[derive(Clone, Copy)]
+pub enum TokenPurpose {
+    PasswordReset,
+    EmailVerification,
+}
+
+impl TokenPurpose {
+    fn name(&self) -> &'static str {
+        match self {
+            TokenPurpose::PasswordReset => "Password reset",
+            TokenPurpose::EmailVerification => "Email verification",
+        }
+    }
+
+    fn body(&self, token: &str) -> String {
+        match self {
+            TokenPurpose::PasswordReset => format!(
+                "A password reset was requested for your account. Use this code to choose a new password:\n\n{}\n\nIf you didn't ask for this you can ignore this email.\n",
+                token
+            ),
+            TokenPurpose::EmailVerification => format!(
+                "Use this code to confirm this email address for your account:\n\n{}\n\nIf you didn't ask for this you can ignore this email.\n",
+                token
+            ),
+        }
+    }
+}
+
+/// ## Send an email
+///
+/// Anything that can send mail on the API's behalf
+pub trait Mailer {
+    fn send(&self, to: &str, subject: &str, body: &str) -> std::io::Result<()>;
+}
+
+/// Hands messages to a local sendmail compatible program, which delivers them
+pub struct SendmailMailer {
+    pub command: String,
+    pub from: String,
+}
+
+impl Mailer for SendmailMailer {
+    fn send(&self, to: &str, subject: &str, body: &str) -> std::io::Result<()> {
+        // Addresses go on the command line rather than being read from the
+        // headers, so nothing in the message can add recipients
+        let mut child = Command::new(&self.command)
+            .args(["-i", "-f", &self.from, "--", to])
+            .stdin(Stdio::piped())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            write!(
+                stdin,
+                "From: {}\r\nTo: {}\r\nSubject: {}\r\n\r\n{}",
+                self.from, to, subject, body
+            )?;
+        }
+
+        match child.wait()?.success() {
+            true => Ok(()),
+            false => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("{} exited unsuccessfully", self.command),
+            )),
+        }
+    }
+}
+
+/// ## Send a token
+///
+/// Emails the token to the address with the configured `Mailer`, unless
+/// `token_delivery` is `response`. That is meant for local development, and
+/// gives the token back to send in the response instead. Defaults to `mail`
+///
+/// The token itself is never logged. Failing to send is logged without
+/// failing the request, so the response doesn't show whether mail went out
+pub async fn send_token(
+    account_id: i32,
+    email: &str,
+    purpose: TokenPurpose,
+    token: String,
+) -> Option<String> {
+    let settings = crate::SETTINGS.read().await;
+    let mode = settings
+        .get_string("token_delivery")
+        .unwrap_or("mail".to_string());
+
+    if mode == "response" {
+        log::info!("{} token issued for account {}", purpose.name(), account_id);
+        return Some(token);
+    }
+
+    let mailer = SendmailMailer {
+        command: settings
+            .get_string("sendmail_command")
+            .unwrap_or(DEFAULT_SENDMAIL_COMMAND.to_string()),
+        from: settings
+            .get_string("mail_from")
+            .unwrap_or(DEFAULT_MAIL_FROM.to_string()),
+    };
+    drop(settings);
+
+    let to = email.to_string();
+    let sent = match rocket::tokio::task::spawn_blocking(move || {
+        mailer.send(&to, purpose.name(), &purpose.body(&token))
+    })
+    .await
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
+    };
+
+    match sent {
+        Ok(()) => log::info!("{} token emailed for account {}", purpose.name(), account_id),
+        Err(e) => log::error!(
+            "Unable to email the {} token for account {}: {}",
+            purpose.name().to_lowercase(),
+            account_id,
+            e
+        ),
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mailer;
+
+    /// A sendmail stand in that writes the message and its arguments to `output`
+    fn capturing_mailer(output: &std::path::Path) -> super::SendmailMailer {
+        let script = output.with_extension("sh");
+        std::fs::write(
+            &script,
+            format!("#!/bin/sh\necho \"$@\" > {0}\ncat >> {0}\n", output.display()),
+        )
+        .unwrap();
+        std::process::Command::new("chmod").arg("+x").arg(&script).status().unwrap();
+
+        super::SendmailMailer {
+            command: script.to_str().unwrap().to_string(),
+            from: "no-reply@sps.wits.ac.za".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_sendmail_mailer_message_handed_over() {
+        let output = std::env::temp_dir().join(format!("sps-mail-{}", uuid::Uuid::new_v4()));
+        let mailer = capturing_mailer(&output);
+
+        mailer
+            .send(
+                "0000000@students.wits.ac.za",
+                "Password reset",
+                &super::TokenPurpose::PasswordReset.body("abc123"),
+            )
+            .unwrap();
+
+        let sent = std::fs::read_to_string(&output).unwrap();
+        assert!(sent.starts_with("-i -f no-reply@sps.wits.ac.za -- 0000000@students.wits.ac.za\n"));
+        assert!(sent.contains("To: 0000000@students.wits.ac.za\r\n"));
+        assert!(sent.contains("Subject: Password reset\r\n"));
+        assert!(sent.contains("abc123"));
+
+        std::fs::remove_file(&output).unwrap();
+        std::fs::remove_file(output.with_extension("sh")).unwrap();
+    }
+
+    #[test]
+    fn test_sendmail_mailer_missing_command_err() {
+        let mailer = super::SendmailMailer {
+            command: "/nonexistent/sendmail".to_string(),
+            from: "no-reply@sps.wits.ac.za".to_string(),
+        };
+
+        assert!(mailer.send("0000000@students.wits.ac.za", "Password reset", "abc123").is_err());
+    }
+}
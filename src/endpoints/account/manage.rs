@@ -2,6 +2,9 @@ use serde::{Deserialize, Serialize};
 
 use crate::db;
 
+/// An account as sent back to its owner. Built field by field from the
+/// database row so the password hash, role and security answers never leave
+/// the API, even if columns are added to tblAccount later
 #[derive(Serialize, Deserialize)]
 pub struct UserAccount {
     pub username: String,
@@ -29,10 +32,41 @@ impl From<db::Account> for UserAccount {
     }
 }
 
+/// What an admin sees of an account when searching, with no password or photo
+#[derive(Serialize, Deserialize)]
+pub struct AccountSummary {
+    pub account_id: i32,
+    pub email: String,
+    pub username: String,
+    pub role: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct UpdateAccount {
     pub account_id: i32,
     pub username: String,
     pub cell_number: String,
     pub profile_photo: Vec<u8>,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+/// The verification token is only sent back when `token_delivery` is `response`
+#[derive(Serialize, Deserialize)]
+pub struct UpdateAccountResponse {
+    pub pending_email: Option<String>,
+    pub verification_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NewAccount {
+    pub email: String,
+    pub hashed_password: String,
+    pub username: String,
+    pub cell_number: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NewAccountResponse {
+    pub account_id: i32,
 }
@@ -2,9 +2,26 @@ use serde::{Deserialize, Serialize};
 
 use crate::endpoints::account::security_question::SecurityQuestion;
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+/// The token is only sent back when `token_delivery` is `response`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasswordResetResponse {
+    pub reset_token: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NewPasswordRequest {
-    pub account_id: i32,
+    pub reset_token: String,
     pub new_password: String,
     pub questions: Vec<SecurityQuestion>,
 }
+
+/// How many sessions were ended by a successful reset
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasswordResetResult {
+    pub sessions_terminated: u64,
+}
@@ -10,4 +10,11 @@ pub struct SecurityQuestion {
 pub struct AddSecurityQuestion {
     pub account_id: i32,
     pub questions: Vec<SecurityQuestion>,
-}
\ No newline at end of file
+}
+
+/// A security question an account has answered, without the answer
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccountSecurityQuestion {
+    pub question_id: i32,
+    pub question: String,
+}
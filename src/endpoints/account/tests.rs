@@ -7,16 +7,119 @@ use crate::tests::CLIENT;
 #[test]
 fn test_fetch_account_existing_account_ok() {
     let client_binding = CLIENT.lock().unwrap();
-    let response = client_binding.get(uri!(super::fetch_account(1))).dispatch();
+    let response = client_binding
+        .get(uri!(super::fetch_account(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.body().is_some());
+}
+
+/// Search accounts as the given session, giving back the status and JSON body
+fn search(token: &str, query: &str) -> (Status, serde_json::Value) {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::search_accounts(query, _, _)))
+        .header(crate::tests::bearer(token))
+        .dispatch();
+
+    (response.status(), response.into_json().unwrap())
+}
+
+#[test]
+fn test_search_accounts_partial_username_ok() {
+    let (status, accounts) = search(crate::tests::ADMIN_TOKEN, "REGULAR_us");
+    assert_eq!(status, Status::Ok);
+
+    let accounts = accounts.as_array().unwrap();
+    assert!(accounts
+        .iter()
+        .any(|account| account["username"] == "test_account_regular_user"));
+}
+
+#[test]
+fn test_search_accounts_partial_email_ok() {
+    let (status, accounts) = search(crate::tests::ADMIN_TOKEN, "2222222@students");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(accounts[0]["email"], "2222222@students.wits.ac.za");
+}
+
+#[test]
+fn test_search_accounts_no_match_empty_ok() {
+    let (status, accounts) = search(crate::tests::ADMIN_TOKEN, "nobody_has_this_name");
+    assert_eq!(status, Status::Ok);
+    assert!(accounts.as_array().unwrap().is_empty());
+}
+
+#[test]
+fn test_search_accounts_wildcard_literal_empty_ok() {
+    let (status, accounts) = search(crate::tests::ADMIN_TOKEN, "%");
+    assert_eq!(status, Status::Ok);
+    assert!(accounts.as_array().unwrap().is_empty());
+}
+
+#[test]
+fn test_search_accounts_sensitive_fields_omitted() {
+    let (_, accounts) = search(crate::tests::ADMIN_TOKEN, "test_account");
+    let account = accounts[0].as_object().unwrap();
+
+    assert!(account.contains_key("username"));
+    assert!(!account.contains_key("hashed_password"));
+    assert!(!account.contains_key("profile_photo"));
+}
+
+#[test]
+fn test_search_accounts_regular_user_forbidden() {
+    let (status, body) = search(crate::tests::USER_TOKEN, "test_account");
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(body["code"], "ADMIN_REQUIRED");
+}
+
+#[test]
+fn test_fetch_account_secret_fields_omitted() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_account(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
     assert_eq!(response.status(), Status::Ok);
+
+    // Account 1 has a password hash and security answers stored
+    let body = response.into_string().unwrap();
+    let account = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+    assert!(account.get("username").is_some());
+    assert!(!body.contains("password"));
+    assert!(!body.contains("answer"));
+    assert!(!body.contains("0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e"));
+}
+
+#[test]
+fn test_fetch_account_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_account(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
     assert!(response.body().is_some());
 }
 
 #[test]
-fn test_fetch_account_nonexisting_account_not_found() {
+fn test_fetch_account_no_session_unauth() {
     let client_binding = CLIENT.lock().unwrap();
-    let response = client_binding.get(uri!(super::fetch_account(0))).dispatch();
-    assert_eq!(response.status(), Status::NotFound);
+    let response = client_binding.get(uri!(super::fetch_account(1))).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_account_invalid_session_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_account(1)))
+        .header(crate::tests::bearer("not_a_real_token"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
     assert!(response.body().is_some());
 }
 
@@ -29,19 +132,23 @@ fn test_update_account_existing_account_ok() {
         username: "Test Username".to_string(),
         cell_number: "0715791902".to_string(),
         profile_photo: vec![0, 0, 0, 0, 0, 0, 0, 0, 0],
+        email: None,
     };
 
     let response = client_binding
         .put(uri!(super::update_account))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
         .body(serde_json::to_string(&req_body).unwrap())
         .dispatch();
 
     assert_eq!(response.status(), Status::Ok);
-    assert!(response.body().is_none());
+    let update = response.into_json::<super::manage::UpdateAccountResponse>().unwrap();
+    assert!(update.pending_email.is_none());
+    assert!(update.verification_token.is_none());
 }
 
 #[test]
-fn test_update_account_nonexisting_account_not_found() {
+fn test_update_account_other_account_forbidden() {
     let client_binding = CLIENT.lock().unwrap();
 
     let req_body = super::manage::UpdateAccount {
@@ -49,26 +156,106 @@ fn test_update_account_nonexisting_account_not_found() {
         username: "Test Username".to_string(),
         cell_number: "0715791902".to_string(),
         profile_photo: vec![0, 0, 0, 0, 0, 0, 0, 0, 0],
+        email: None,
     };
 
     let response = client_binding
         .put(uri!(super::update_account))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
         .body(serde_json::to_string(&req_body).unwrap())
         .dispatch();
 
-    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.status(), Status::Forbidden);
     assert!(response.body().is_some());
 }
 
+/// Run `test` with `token_delivery` set, putting the settings back after
+fn with_token_delivery<T>(mode: &str, test: impl FnOnce() -> T) -> T {
+    let previous = crate::SETTINGS.blocking_read().clone();
+    *crate::SETTINGS.blocking_write() = config::Config::builder()
+        .add_source(previous.clone())
+        .set_override("token_delivery", mode)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let result = test();
+    *crate::SETTINGS.blocking_write() = previous;
+    result
+}
+
+/// Ask for a password reset, with tokens sent back in the response
+fn request_reset(client: &rocket::local::blocking::Client, email: &str) -> super::password::PasswordResetResponse {
+    let req_body = super::password::PasswordResetRequest {
+        email: email.to_string(),
+    };
+
+    let response = with_token_delivery("response", || {
+        client
+            .post(uri!(super::request_password_reset))
+            .body(serde_json::to_string(&req_body).unwrap())
+            .dispatch()
+    });
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json::<super::password::PasswordResetResponse>().unwrap()
+}
+
+/// Start a password reset for an account, returning the token
+fn reset_token(client: &rocket::local::blocking::Client, email: &str) -> String {
+    request_reset(client, email).reset_token.unwrap()
+}
+
+/// Start a password reset for the seeded admin account. Resets that succeed
+/// end every session on the account, so only use this where the reset fails
+fn admin_reset_token(client: &rocket::local::blocking::Client) -> String {
+    reset_token(client, "0000000@students.wits.ac.za")
+}
+
+/// Create an account with the same security answers as the seeded admin
+/// account, so it can be reset without ending the admin's sessions
+fn create_reset_test_account(client: &rocket::local::blocking::Client, email: &str, username: &str) -> i32 {
+    let account_id = create_email_test_account(client, email, username);
+    let answers = vec![
+        (1, super::hashing::hash_answer("jeffrey").unwrap()),
+        (2, super::hashing::hash_answer("manchester united").unwrap()),
+    ];
+
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            for (question_id, answer) in answers {
+                sqlx::query("INSERT INTO tblSecurityAnswers (secques_id, account_id, answer) VALUES (?, ?, ?)")
+                    .bind(question_id)
+                    .bind(account_id)
+                    .bind(answer)
+                    .execute(&mut *conn)
+                    .await
+                    .unwrap();
+            }
+        })
+    });
+
+    account_id
+}
+
+/// Correct answers to the seeded admin account's security questions
+fn admin_answers() -> Vec<super::security_question::SecurityQuestion> {
+    vec![
+        super::security_question::SecurityQuestion { question_id: 1, user_answer: "jeffrey".to_string() },
+        super::security_question::SecurityQuestion { question_id: 2, user_answer: "manchester united".to_string() },
+    ]
+}
+
 #[test]
 fn test_account_reset_password_correct_answers_ok() {
     let client_binding = CLIENT.lock().unwrap();
+    create_reset_test_account(&client_binding, "5151515@students.wits.ac.za", "reset_correct_answers");
     let req_body = super::password::NewPasswordRequest {
-        account_id: 1,
+        reset_token: reset_token(&client_binding, "5151515@students.wits.ac.za"),
         new_password:  "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e".to_string(),
         questions: vec![
-            super::security_question::SecurityQuestion { question_id: 1, user_answer: "a459891617d735655dcfed3e37db66fa07f0175866ebf35f9de8ccc59c0840bb".to_string() },
-            super::security_question::SecurityQuestion { question_id: 2, user_answer: "b93b9776163702f1fad6cbaf815326a41b3285d0961f4e838ebdb8ad52e5f16e".to_string() },
+            super::security_question::SecurityQuestion { question_id: 1, user_answer: "jeffrey".to_string() },
+            super::security_question::SecurityQuestion { question_id: 2, user_answer: "manchester united".to_string() },
         ],
     };
 
@@ -77,14 +264,15 @@ fn test_account_reset_password_correct_answers_ok() {
         .dispatch();
 
     assert_eq!(response.status(), Status::Ok);
-    assert!(response.body().is_none());
+    let result = response.into_json::<super::password::PasswordResetResult>().unwrap();
+    assert_eq!(result.sessions_terminated, 0);
 }
 
 #[test]
 fn test_account_reset_password_incorrect_answers_unauth() {
     let client_binding = CLIENT.lock().unwrap();
     let req_body = super::password::NewPasswordRequest {
-        account_id: 1,
+        reset_token: admin_reset_token(&client_binding),
         new_password:  "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e".to_string(),
         questions: vec![
             super::security_question::SecurityQuestion { question_id: 1, user_answer: "lbue".to_string() },
@@ -101,14 +289,77 @@ fn test_account_reset_password_incorrect_answers_unauth() {
 }
 
 #[test]
-fn test_account_reset_password_no_account_not_found() {
+fn test_account_reset_password_different_case_answers_ok() {
     let client_binding = CLIENT.lock().unwrap();
+    create_reset_test_account(&client_binding, "5252525@students.wits.ac.za", "reset_different_case");
     let req_body = super::password::NewPasswordRequest {
-        account_id: 0,
-        new_password:  "".to_string(),
+        reset_token: reset_token(&client_binding, "5252525@students.wits.ac.za"),
+        new_password:  "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e".to_string(),
+        questions: vec![
+            super::security_question::SecurityQuestion { question_id: 1, user_answer: " Jeffrey".to_string() },
+            super::security_question::SecurityQuestion { question_id: 2, user_answer: "MANCHESTER United ".to_string() },
+        ],
+    };
+
+    let response = client_binding.post(uri!(super::account_reset_password))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let result = response.into_json::<super::password::PasswordResetResult>().unwrap();
+    assert_eq!(result.sessions_terminated, 0);
+}
+
+#[test]
+fn test_account_reset_password_legacy_answers_rehashed_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "5858580@students.wits.ac.za";
+    let account_id = create_email_test_account(&client_binding, email, "reset_legacy_answers");
+
+    // SHA-256 of the answers, the way they were stored before being salted
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            for (question_id, answer) in [
+                (1, "a459891617d735655dcfed3e37db66fa07f0175866ebf35f9de8ccc59c0840bb"),
+                (2, "b93b9776163702f1fad6cbaf815326a41b3285d0961f4e838ebdb8ad52e5f16e"),
+            ] {
+                sqlx::query("INSERT INTO tblSecurityAnswers (secques_id, account_id, answer) VALUES (?, ?, ?)")
+                    .bind(question_id)
+                    .bind(account_id)
+                    .bind(answer)
+                    .execute(&mut *conn)
+                    .await
+                    .unwrap();
+            }
+        })
+    });
+
+    assert_eq!(reset_with_answers(&client_binding, email, ["jeffrey", "manchester united"]), Status::Ok);
+
+    let stored: Vec<String> = crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar("SELECT answer FROM tblSecurityAnswers WHERE account_id = ? ORDER BY secques_id")
+                .bind(account_id)
+                .fetch_all(conn)
+                .await
+                .unwrap()
+        })
+    });
+    assert_eq!(stored.len(), 2);
+    assert!(stored.iter().all(|answer| !super::hashing::needs_rehash(answer)));
+
+    // The new hashes still take the same answers
+    assert_eq!(reset_with_answers(&client_binding, email, ["Jeffrey", "manchester united"]), Status::Ok);
+}
+
+#[test]
+fn test_account_reset_password_missing_answer_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let req_body = super::password::NewPasswordRequest {
+        reset_token: admin_reset_token(&client_binding),
+        new_password:  "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e".to_string(),
         questions: vec![
-            super::security_question::SecurityQuestion { question_id: 1, user_answer: "".to_string() },
-            super::security_question::SecurityQuestion { question_id: 2, user_answer: "".to_string() },
+            super::security_question::SecurityQuestion { question_id: 1, user_answer: "jeffrey".to_string() },
         ],
     };
 
@@ -116,23 +367,259 @@ fn test_account_reset_password_no_account_not_found() {
         .body(serde_json::to_string(&req_body).unwrap())
         .dispatch();
 
-    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.status(), Status::Unauthorized);
     assert!(response.body().is_some());
 }
 
+#[rocket::async_test]
+async fn test_validate_password_too_short_bad_request() {
+    let error = weak_password_error("abc123").await;
+    assert!(error.message.contains("at least 8"));
+}
+
+#[rocket::async_test]
+async fn test_validate_password_missing_digit_bad_request() {
+    let error = weak_password_error("onlyletters").await;
+    assert!(error.message.contains("digit"));
+}
+
+#[rocket::async_test]
+async fn test_validate_password_missing_letter_bad_request() {
+    let error = weak_password_error("1234567890").await;
+    assert!(error.message.contains("letter"));
+}
+
+/// Check a password is refused as a bad request for being weak, giving back
+/// the error body
+async fn weak_password_error(password: &str) -> crate::endpoints::errors::ErrorBody {
+    let error = super::validate_password(password).await.unwrap_err();
+    assert_eq!(error.status(), Status::BadRequest);
+
+    let body = crate::endpoints::errors::ErrorBody::from(error);
+    assert_eq!(body.code, crate::endpoints::errors::ErrorCode::WeakPassword);
+    body
+}
+
+#[rocket::async_test]
+async fn test_validate_password_acceptable_ok() {
+    assert!(super::validate_password("password_1").await.is_ok());
+}
+
+#[test]
+fn test_account_reset_password_weak_password_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let req_body = super::password::NewPasswordRequest {
+        reset_token: admin_reset_token(&client_binding),
+        new_password: "short1".to_string(),
+        questions: vec![
+            super::security_question::SecurityQuestion { question_id: 1, user_answer: "jeffrey".to_string() },
+            super::security_question::SecurityQuestion { question_id: 2, user_answer: "manchester united".to_string() },
+        ],
+    };
+
+    let response = client_binding.post(uri!(super::account_reset_password))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_request_password_reset_no_account_same_response_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let req_body = super::password::PasswordResetRequest {
+        email: "9999999@students.wits.ac.za".to_string(),
+    };
+
+    let response = client_binding.post(uri!(super::request_password_reset))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let unknown = response.into_string().unwrap();
+
+    let req_body = super::password::PasswordResetRequest {
+        email: "5656560@students.wits.ac.za".to_string(),
+    };
+    create_email_test_account(&client_binding, &req_body.email, "reset_same_response");
+
+    let response = client_binding.post(uri!(super::request_password_reset))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), unknown);
+}
+
+#[test]
+fn test_request_password_reset_mail_delivery_token_not_sent_back() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "5757570@students.wits.ac.za";
+    create_email_test_account(&client_binding, email, "reset_mail_delivery");
+    let req_body = super::password::PasswordResetRequest {
+        email: email.to_string(),
+    };
+
+    let response = with_token_delivery("mail", || {
+        client_binding
+            .post(uri!(super::request_password_reset))
+            .body(serde_json::to_string(&req_body).unwrap())
+            .dispatch()
+    });
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response
+        .into_json::<super::password::PasswordResetResponse>()
+        .unwrap()
+        .reset_token
+        .is_none());
+
+    // The token was still made, ready for whoever it was delivered to
+    let account_resets: i64 = crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar("SELECT COUNT(*) FROM tblPasswordReset JOIN tblAccount USING (account_id) WHERE email = ?")
+                .bind(email)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    });
+    assert_eq!(account_resets, 1);
+}
+
+#[test]
+fn test_account_reset_password_invalid_token_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let req_body = super::password::NewPasswordRequest {
+        reset_token: "not_a_reset_token".to_string(),
+        new_password: "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e".to_string(),
+        questions: admin_answers(),
+    };
+
+    let response = client_binding.post(uri!(super::account_reset_password))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_account_reset_password_reused_token_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    create_reset_test_account(&client_binding, "5353535@students.wits.ac.za", "reset_reused_token");
+    let token = reset_token(&client_binding, "5353535@students.wits.ac.za");
+    let reset = |token: &str| {
+        let req_body = super::password::NewPasswordRequest {
+            reset_token: token.to_string(),
+            new_password: "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e".to_string(),
+            questions: admin_answers(),
+        };
+        client_binding.post(uri!(super::account_reset_password))
+            .body(serde_json::to_string(&req_body).unwrap())
+            .dispatch()
+            .status()
+    };
+
+    assert_eq!(reset(&token), Status::Ok);
+    assert_eq!(reset(&token), Status::Unauthorized);
+}
+
+#[test]
+fn test_account_reset_password_wrong_answers_token_used_up() {
+    let client_binding = CLIENT.lock().unwrap();
+    create_reset_test_account(&client_binding, "5959590@students.wits.ac.za", "reset_wrong_answers");
+    let token = reset_token(&client_binding, "5959590@students.wits.ac.za");
+    let reset = |questions: Vec<super::security_question::SecurityQuestion>| {
+        let req_body = super::password::NewPasswordRequest {
+            reset_token: token.clone(),
+            new_password: "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e".to_string(),
+            questions,
+        };
+        client_binding.post(uri!(super::account_reset_password))
+            .body(serde_json::to_string(&req_body).unwrap())
+            .dispatch()
+            .status()
+    };
+
+    let wrong_answers = vec![
+        super::security_question::SecurityQuestion { question_id: 1, user_answer: "rex".to_string() },
+        super::security_question::SecurityQuestion { question_id: 2, user_answer: "manchester united".to_string() },
+    ];
+    assert_eq!(reset(wrong_answers), Status::Unauthorized);
+    // The right answers are too late, the token is gone
+    assert_eq!(reset(admin_answers()), Status::Unauthorized);
+}
+
+#[test]
+fn test_account_reset_password_active_sessions_terminated() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_reset_test_account(&client_binding, "5454545@students.wits.ac.za", "reset_with_sessions");
+    let sessions = [crate::tests::create_session(account_id), crate::tests::create_session(account_id)];
+
+    let req_body = super::password::NewPasswordRequest {
+        reset_token: reset_token(&client_binding, "5454545@students.wits.ac.za"),
+        new_password: "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e".to_string(),
+        questions: admin_answers(),
+    };
+    let response = client_binding.post(uri!(super::account_reset_password))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let result = response.into_json::<super::password::PasswordResetResult>().unwrap();
+    assert_eq!(result.sessions_terminated, 2);
+    assert_eq!(account_row_count("tblSessionToken", account_id), 0);
+
+    for session in sessions {
+        let response = client_binding
+            .get(uri!(super::fetch_account(account_id)))
+            .header(crate::tests::bearer(&session))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+}
+
+#[test]
+fn test_account_reset_password_expired_token_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let token = admin_reset_token(&client_binding);
+    let expired_token = token.clone();
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query("UPDATE tblPasswordReset SET expires_at = '2023-01-01 00:00:00' WHERE token = ?")
+                .bind(expired_token)
+                .execute(conn)
+                .await
+                .unwrap();
+        })
+    });
+
+    let req_body = super::password::NewPasswordRequest {
+        reset_token: token,
+        new_password: "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e".to_string(),
+        questions: admin_answers(),
+    };
+    let response = client_binding.post(uri!(super::account_reset_password))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.into_string().unwrap().contains("Expired"));
+}
+
 #[test]
 fn test_add_questions_existing_account_ok() {
     let client_binding = CLIENT.lock().unwrap();
     let req_body = super::security_question::AddSecurityQuestion{
         account_id: 1,
         questions: vec![
-            super::security_question::SecurityQuestion { question_id: 1, user_answer: "a459891617d735655dcfed3e37db66fa07f0175866ebf35f9de8ccc59c0840bb".to_string() },
-            super::security_question::SecurityQuestion { question_id: 2, user_answer: "b93b9776163702f1fad6cbaf815326a41b3285d0961f4e838ebdb8ad52e5f16e".to_string() },
+            super::security_question::SecurityQuestion { question_id: 1, user_answer: "jeffrey".to_string() },
+            super::security_question::SecurityQuestion { question_id: 2, user_answer: "manchester united".to_string() },
         ],
     };
 
     let response = client_binding
         .post(uri!(super::add_questions))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
         .body(serde_json::to_string(&req_body).unwrap())
         .dispatch();
 
@@ -141,21 +628,552 @@ fn test_add_questions_existing_account_ok() {
 }
 
 #[test]
-fn test_add_questions_nonexisting_account_ok() {
+fn test_add_questions_other_account_forbidden() {
     let client_binding = CLIENT.lock().unwrap();
     let req_body = super::security_question::AddSecurityQuestion{
         account_id: 0,
         questions: vec![
-            super::security_question::SecurityQuestion { question_id: 1, user_answer: "a459891617d735655dcfed3e37db66fa07f0175866ebf35f9de8ccc59c0840bb".to_string() },
-            super::security_question::SecurityQuestion { question_id: 2, user_answer: "b93b9776163702f1fad6cbaf815326a41b3285d0961f4e838ebdb8ad52e5f16e".to_string() },
+            super::security_question::SecurityQuestion { question_id: 1, user_answer: "jeffrey".to_string() },
+            super::security_question::SecurityQuestion { question_id: 2, user_answer: "manchester united".to_string() },
         ],
     };
 
     let response = client_binding
         .post(uri!(super::add_questions))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
         .body(serde_json::to_string(&req_body).unwrap())
         .dispatch();
 
-    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.status(), Status::Forbidden);
     assert!(response.body().is_some());
-}
\ No newline at end of file
+}
+
+/// Set an account's security questions with a session for it
+fn set_questions<'c>(
+    client: &'c rocket::local::blocking::Client,
+    account_id: i32,
+    token: &str,
+    questions: Vec<(i32, &str)>,
+) -> rocket::local::blocking::LocalResponse<'c> {
+    let req_body = super::security_question::AddSecurityQuestion {
+        account_id,
+        questions: questions
+            .into_iter()
+            .map(|(question_id, answer)| super::security_question::SecurityQuestion {
+                question_id,
+                user_answer: answer.to_string(),
+            })
+            .collect(),
+    };
+
+    client
+        .post(uri!(super::add_questions))
+        .header(crate::tests::bearer(token))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch()
+}
+
+/// Try a password reset answering the questions with the given answers
+fn reset_with_answers(client: &rocket::local::blocking::Client, email: &str, answers: [&str; 2]) -> Status {
+    let req_body = super::password::NewPasswordRequest {
+        reset_token: reset_token(client, email),
+        new_password: "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e".to_string(),
+        questions: vec![
+            super::security_question::SecurityQuestion { question_id: 1, user_answer: answers[0].to_string() },
+            super::security_question::SecurityQuestion { question_id: 2, user_answer: answers[1].to_string() },
+        ],
+    };
+
+    client
+        .post(uri!(super::account_reset_password))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch()
+        .status()
+}
+
+#[test]
+fn test_add_questions_new_answers_used_for_reset_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "6262626@students.wits.ac.za";
+    let account_id = create_reset_test_account(&client_binding, email, "security_questions_changed");
+    let token = crate::tests::create_session(account_id);
+
+    let response = set_questions(&client_binding, account_id, &token, vec![(1, "rex"), (2, "kaizer chiefs")]);
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client_binding
+        .get(uri!(super::fetch_account_security_questions(account_id)))
+        .header(crate::tests::bearer(&token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body = response.into_string().unwrap();
+    assert!(!body.contains("rex") && !body.contains("argon2"));
+    let questions: Vec<super::security_question::AccountSecurityQuestion> = serde_json::from_str(&body).unwrap();
+    let question_ids: Vec<i32> = questions.iter().map(|question| question.question_id).collect();
+    assert_eq!(question_ids, vec![1, 2]);
+    assert_eq!(questions[0].question, "What was the name of your first pet?");
+
+    assert_eq!(reset_with_answers(&client_binding, email, ["jeffrey", "manchester united"]), Status::Unauthorized);
+    assert_eq!(reset_with_answers(&client_binding, email, ["Rex", "kaizer chiefs"]), Status::Ok);
+}
+
+#[test]
+fn test_add_questions_too_few_answers_unchanged_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "6363636@students.wits.ac.za";
+    let account_id = create_reset_test_account(&client_binding, email, "security_questions_too_few");
+    let token = crate::tests::create_session(account_id);
+
+    let response = set_questions(&client_binding, account_id, &token, vec![(1, "rex")]);
+    assert_eq!(response.status(), Status::BadRequest);
+
+    assert_eq!(reset_with_answers(&client_binding, email, ["jeffrey", "manchester united"]), Status::Ok);
+}
+
+#[test]
+fn test_add_questions_repeated_or_unknown_question_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    for questions in [vec![(1, "rex"), (1, "fido")], vec![(1, "rex"), (999, "nowhere")]] {
+        let response = set_questions(&client_binding, 1, crate::tests::ADMIN_TOKEN, questions);
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+}
+
+#[test]
+fn test_fetch_account_security_questions_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let response = client_binding
+        .get(uri!(super::fetch_account_security_questions(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+#[test]
+fn test_create_account_new_account_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let req_body = super::manage::NewAccount {
+        email: "7777777@students.wits.ac.za".to_string(),
+        hashed_password: "password_1".to_string(),
+        username: "test_account_created".to_string(),
+        cell_number: Some("1234567890".to_string()),
+    };
+
+    let response = client_binding
+        .post(uri!(super::create_account))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let account_id = response
+        .into_json::<super::manage::NewAccountResponse>()
+        .unwrap()
+        .account_id;
+
+    let response = client_binding
+        .get(uri!(super::fetch_account(account_id)))
+        .header(crate::tests::bearer(&crate::tests::create_session(account_id)))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let account = response.into_json::<super::manage::UserAccount>().unwrap();
+    assert_eq!(account.username, "test_account_created");
+}
+
+#[test]
+fn test_create_account_duplicate_email_conflict() {
+    let client_binding = CLIENT.lock().unwrap();
+    let req_body = super::manage::NewAccount {
+        email: "0000000@students.wits.ac.za".to_string(),
+        hashed_password: "password_1".to_string(),
+        username: "test_account_duplicate_email".to_string(),
+        cell_number: None,
+    };
+
+    let response = client_binding
+        .post(uri!(super::create_account))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_create_account_duplicate_username_conflict() {
+    let client_binding = CLIENT.lock().unwrap();
+    let req_body = super::manage::NewAccount {
+        email: "8888888@students.wits.ac.za".to_string(),
+        hashed_password: "password_1".to_string(),
+        username: "test_account_missing_everything".to_string(),
+        cell_number: None,
+    };
+
+    let response = client_binding
+        .post(uri!(super::create_account))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_create_account_empty_username_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let req_body = super::manage::NewAccount {
+        email: "8888888@students.wits.ac.za".to_string(),
+        hashed_password: "password_1".to_string(),
+        username: " ".to_string(),
+        cell_number: None,
+    };
+
+    let response = client_binding
+        .post(uri!(super::create_account))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+/// Create an account to change the email of, returning its id
+fn create_email_test_account(client: &rocket::local::blocking::Client, email: &str, username: &str) -> i32 {
+    let req_body = super::manage::NewAccount {
+        email: email.to_string(),
+        hashed_password: "password_1".to_string(),
+        username: username.to_string(),
+        cell_number: None,
+    };
+
+    let response = client
+        .post(uri!(super::create_account))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json::<super::manage::NewAccountResponse>().unwrap().account_id
+}
+
+/// Request an email change for an account, with tokens sent back in the response
+fn request_email_change(
+    client: &rocket::local::blocking::Client,
+    account_id: i32,
+    username: &str,
+    email: &str,
+) -> super::manage::UpdateAccountResponse {
+    let req_body = super::manage::UpdateAccount {
+        account_id,
+        username: username.to_string(),
+        cell_number: "0715791902".to_string(),
+        profile_photo: vec![],
+        email: Some(email.to_string()),
+    };
+
+    let token = crate::tests::create_session(account_id);
+    let response = with_token_delivery("response", || {
+        client
+            .put(uri!(super::update_account))
+            .header(crate::tests::bearer(&token))
+            .body(serde_json::to_string(&req_body).unwrap())
+            .dispatch()
+    });
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json::<super::manage::UpdateAccountResponse>().unwrap()
+}
+
+/// Email address currently stored against an account
+fn account_email(account_id: i32) -> String {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar::<_, String>("SELECT email FROM tblAccount WHERE account_id = ?")
+                .bind(account_id)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    })
+}
+
+#[test]
+fn test_update_account_new_email_pending_until_confirmed_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_email_test_account(&client_binding, "2020202@students.wits.ac.za", "email_change_confirmed");
+
+    let update = request_email_change(&client_binding, account_id, "email_change_confirmed", "2121212@students.wits.ac.za");
+    assert_eq!(update.pending_email.as_deref(), Some("2121212@students.wits.ac.za"));
+    assert_eq!(account_email(account_id), "2020202@students.wits.ac.za");
+
+    let response = client_binding
+        .post(uri!(super::confirm_email(update.verification_token.unwrap())))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(account_email(account_id), "2121212@students.wits.ac.za");
+}
+
+#[test]
+fn test_update_account_mail_delivery_token_not_sent_back() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_email_test_account(&client_binding, "2424240@students.wits.ac.za", "email_change_mailed");
+    let req_body = super::manage::UpdateAccount {
+        account_id,
+        username: "email_change_mailed".to_string(),
+        cell_number: "0715791902".to_string(),
+        profile_photo: vec![],
+        email: Some("2525250@students.wits.ac.za".to_string()),
+    };
+
+    let token = crate::tests::create_session(account_id);
+    let response = with_token_delivery("mail", || {
+        client_binding
+            .put(uri!(super::update_account))
+            .header(crate::tests::bearer(&token))
+            .body(serde_json::to_string(&req_body).unwrap())
+            .dispatch()
+    });
+    assert_eq!(response.status(), Status::Ok);
+
+    let update = response.into_json::<super::manage::UpdateAccountResponse>().unwrap();
+    assert_eq!(update.pending_email.as_deref(), Some("2525250@students.wits.ac.za"));
+    assert!(update.verification_token.is_none());
+    assert_eq!(account_email(account_id), "2424240@students.wits.ac.za");
+}
+
+#[test]
+fn test_update_account_taken_email_conflict() {
+    let client_binding = CLIENT.lock().unwrap();
+    let req_body = super::manage::UpdateAccount {
+        account_id: 2,
+        username: "test_account_missing_everything".to_string(),
+        cell_number: "1234567890".to_string(),
+        profile_photo: vec![],
+        email: Some("0000000@students.wits.ac.za".to_string()),
+    };
+
+    let response = client_binding
+        .put(uri!(super::update_account))
+        .header(crate::tests::bearer(&crate::tests::create_session(2)))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_confirm_email_invalid_token_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .post(uri!(super::confirm_email("not_a_real_token")))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_confirm_email_expired_token_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_email_test_account(&client_binding, "2222220@students.wits.ac.za", "email_change_expired");
+    let update = request_email_change(&client_binding, account_id, "email_change_expired", "2323232@students.wits.ac.za");
+    let token = update.verification_token.unwrap();
+
+    let expired_token = token.clone();
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query("UPDATE tblEmailChange SET expires_at = '2023-01-01' WHERE token = ?")
+                .bind(expired_token)
+                .execute(conn)
+                .await
+                .unwrap();
+        })
+    });
+
+    let response = client_binding
+        .post(uri!(super::confirm_email(token)))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(account_email(account_id), "2222220@students.wits.ac.za");
+}
+
+/// Smallest PNG worth uploading, a single transparent pixel
+const TEST_PNG: &[u8] = &[
+    0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F, 0x15, 0xC4,
+    0x89, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9C, 0x63, 0x00, 0x01, 0x00, 0x00,
+    0x05, 0x00, 0x01, 0x0D, 0x0A, 0x2D, 0xB4, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4E, 0x44, 0xAE,
+    0x42, 0x60, 0x82,
+];
+
+#[test]
+fn test_upload_avatar_valid_png_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .put(uri!(super::upload_avatar(3)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .header(rocket::http::ContentType::PNG)
+        .body(TEST_PNG)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client_binding
+        .get(uri!(super::fetch_account(3)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    let account = response.into_json::<super::manage::UserAccount>().unwrap();
+    assert_eq!(account.profile_photo, TEST_PNG);
+}
+
+#[test]
+fn test_upload_avatar_text_file_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .put(uri!(super::upload_avatar(3)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .header(rocket::http::ContentType::Plain)
+        .body("definitely not an image")
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_upload_avatar_mislabelled_text_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .put(uri!(super::upload_avatar(3)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .header(rocket::http::ContentType::JPEG)
+        .body("definitely not an image")
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_upload_avatar_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .put(uri!(super::upload_avatar(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .header(rocket::http::ContentType::PNG)
+        .body(TEST_PNG)
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+/// Number of rows in a table belonging to an account
+fn account_row_count(table: &'static str, account_id: i32) -> i64 {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT COUNT(*) FROM {} WHERE account_id = ?",
+                table
+            ))
+            .bind(account_id)
+            .fetch_one(conn)
+            .await
+            .unwrap()
+        })
+    })
+}
+
+#[test]
+fn test_delete_account_account_and_notes_removed_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_email_test_account(&client_binding, "3030303@students.wits.ac.za", "account_to_delete");
+    let session_token = crate::tests::create_session(account_id);
+
+    for title in ["First note", "Second note"] {
+        let note = serde_json::json!({
+            "account_id": account_id,
+            "note_title": title,
+            "note_content": "# Soon to be gone",
+            "note_public": false,
+        });
+        let response = client_binding
+            .post(uri!(crate::endpoints::notes::add_note(_, _)))
+            .header(crate::tests::bearer(&session_token))
+            .body(note.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+    assert_eq!(account_row_count("tblNotes", account_id), 2);
+
+    let response = client_binding
+        .delete(uri!(super::delete_account(account_id)))
+        .header(crate::tests::bearer(&session_token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_eq!(account_row_count("tblNotes", account_id), 0);
+    assert_eq!(account_row_count("tblSessionToken", account_id), 0);
+    assert_eq!(account_row_count("tblAccount", account_id), 0);
+
+    // The session went along with the account
+    let response = client_binding
+        .get(uri!(super::fetch_account(account_id)))
+        .header(crate::tests::bearer(&session_token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn test_delete_account_note_attachments_removed_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_email_test_account(&client_binding, "3131313@students.wits.ac.za", "attachments_to_delete");
+    let session_token = crate::tests::create_session(account_id);
+
+    let note = serde_json::json!({
+        "account_id": account_id,
+        "note_title": "Note with a scan",
+        "note_content": "Scan attached",
+        "note_public": false,
+    });
+    let response = client_binding
+        .post(uri!(crate::endpoints::notes::add_note(_, _)))
+        .header(crate::tests::bearer(&session_token))
+        .body(note.to_string())
+        .dispatch();
+    let note_id = response.into_json::<serde_json::Value>().unwrap()["note_id"].as_i64().unwrap() as i32;
+
+    let response = client_binding
+        .post(uri!(crate::endpoints::notes::upload_note_attachment(note_id, "scan.pdf")))
+        .header(crate::tests::bearer(&session_token))
+        .header(rocket::http::ContentType::PDF)
+        .body("%PDF-1.7")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let attachment_count = || {
+        crate::tests::with_db(move |conn| {
+            Box::pin(async move {
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM tblNoteAttachments WHERE note_id = ?")
+                    .bind(note_id)
+                    .fetch_one(conn)
+                    .await
+                    .unwrap()
+            })
+        })
+    };
+    assert_eq!(attachment_count(), 1);
+
+    let response = client_binding
+        .delete(uri!(super::delete_account(account_id)))
+        .header(crate::tests::bearer(&session_token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_eq!(attachment_count(), 0);
+    assert_eq!(account_row_count("tblNotes", account_id), 0);
+}
+
+#[test]
+fn test_delete_account_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .delete(uri!(super::delete_account(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+
+    assert_eq!(account_row_count("tblAccount", 1), 1);
+}
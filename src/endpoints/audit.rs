@@ -0,0 +1,131 @@
+mod audit_api;
+#[cfg(test)]
+mod tests;
+
+use rocket::serde::json::Json;
+use rocket_db_pools::{sqlx, Connection};
+
+use crate::db::{self, retry_transient, SPS};
+use crate::endpoints::errors::{ApiErrors, ApiResult};
+use crate::endpoints::guards::AdminAccount;
+use crate::endpoints::params;
+
+/// Changes recorded in the audit log
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AuditAction {
+    AddNote,
+    RemoveNote,
+    UpdateAccount,
+    ResetPassword,
+}
+
+impl AuditAction {
+    /// Name stored in the action column
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuditAction::AddNote => "add_note",
+            AuditAction::RemoveNote => "remove_note",
+            AuditAction::UpdateAccount => "update_account",
+            AuditAction::ResetPassword => "reset_password",
+        }
+    }
+
+    /// Kind of record the target ID refers to
+    pub fn target_type(&self) -> &'static str {
+        match self {
+            AuditAction::AddNote | AuditAction::RemoveNote => "note",
+            AuditAction::UpdateAccount | AuditAction::ResetPassword => "account",
+        }
+    }
+}
+
+/// Add an entry to the audit log. The change itself has already been made by
+/// the time this runs, so a failure is logged rather than failing the request
+pub async fn record<'c>(
+    executor: impl sqlx::MySqlExecutor<'c>,
+    actor_account_id: i32,
+    action: AuditAction,
+    target_id: i32,
+) {
+    if let Err(e) = sqlx::query!(
+        "INSERT INTO tblAuditLog (actor_account_id, action, target_type, target_id, created_at) VALUES (?, ?, ?, ?, ?)",
+        actor_account_id,
+        action.name(),
+        action.target_type(),
+        target_id,
+        chrono::Utc::now().naive_utc()
+    )
+    .execute(executor)
+    .await
+    {
+        log::error!(
+            "Unable to record {} of {} {} in the audit log: {}",
+            action.name(),
+            action.target_type(),
+            target_id,
+            e
+        );
+    }
+}
+
+/// ## Fetch the audit log
+///
+/// Entries for account and note changes, newest first, optionally only those
+/// made between the dates from and to (both inclusive). Admins only
+///
+/// ### Arguments
+///
+/// * from (optional query, YYYY-MM-DD)
+/// * to (optional query, YYYY-MM-DD)
+/// * limit (optional query, defaults to 100, at most 500)
+/// * offset (optional query, defaults to 0)
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+#[get("/audit?<from>&<to>&<limit>&<offset>")]
+pub async fn fetch_audit_log(
+    admin: Result<AdminAccount, ApiErrors>,
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<Vec<audit_api::AuditLogResponse>>> {
+    admin?;
+
+    let (window_start, window_end) = params::parse_date_window(from, to)?;
+    let limit = params::parse_limit(limit)?;
+    let offset = offset.unwrap_or(0);
+
+    let db_entries = match db::timed(async {
+        retry_transient!(
+            sqlx::query_as!(
+                db::AuditLog,
+                "SELECT * FROM tblAuditLog WHERE (? IS NULL OR created_at >= ?) AND (? IS NULL OR created_at < ?) ORDER BY created_at DESC, audit_log_id DESC LIMIT ? OFFSET ?",
+                window_start,
+                window_start,
+                window_end,
+                window_end,
+                limit,
+                offset
+            )
+            .fetch_all(&mut *db_conn)
+        )
+    })
+    .await?
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to fetch the audit log".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json(db_entries.iter().map(|entry| entry.into()).collect()))
+}
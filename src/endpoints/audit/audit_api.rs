@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use chrono::{TimeZone, Utc};
+
+#[derive(Serialize, Deserialize)]
+pub struct AuditLogResponse {
+    pub audit_log_id: i32,
+    pub actor_account_id: i32,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: i32,
+    pub created_at: String,
+}
+
+impl From<&db::AuditLog> for AuditLogResponse {
+    fn from(value: &db::AuditLog) -> Self {
+        AuditLogResponse {
+            audit_log_id: value.audit_log_id,
+            actor_account_id: value.actor_account_id,
+            action: value.action.clone(),
+            target_type: value.target_type.clone(),
+            target_id: value.target_id,
+            created_at: Utc.from_utc_datetime(&value.created_at).to_string(),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use rocket::http::Status;
+use rocket::serde::json::json;
+
+use crate::rocket;
+use crate::tests::CLIENT;
+
+/// Today's audit log entries as seen by the seeded admin account
+fn todays_entries(client: &rocket::local::blocking::Client) -> Vec<super::audit_api::AuditLogResponse> {
+    let today = chrono::Utc::now().date_naive().format("%Y-%m-%d").to_string();
+    let response = client
+        .get(format!("/audit?from={}&to={}", today, today))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json::<Vec<super::audit_api::AuditLogResponse>>().unwrap()
+}
+
+/// Whether today's entries include the action on a target
+fn has_entry(client: &rocket::local::blocking::Client, action: &str, target_type: &str, target_id: i32) -> bool {
+    todays_entries(client)
+        .iter()
+        .any(|entry| entry.action == action && entry.target_type == target_type && entry.target_id == target_id)
+}
+
+#[test]
+fn test_fetch_audit_log_note_added_and_removed_entries_recorded() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let response = client_binding
+        .post("/notes")
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(
+            json!({
+                "account_id": 1,
+                "note_title": format!("Rocket Audit Test Note {}", uuid::Uuid::new_v4().simple()),
+                "note_content": "Audited",
+                "note_public": false,
+            })
+            .to_string(),
+        )
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let note_id = response.into_json::<rocket::serde::json::Value>().unwrap()["note_id"]
+        .as_i64()
+        .unwrap() as i32;
+
+    assert!(has_entry(&client_binding, "add_note", "note", note_id));
+    assert!(!has_entry(&client_binding, "remove_note", "note", note_id));
+
+    let response = client_binding
+        .delete(format!("/notes/1/{}", note_id))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let entries = todays_entries(&client_binding);
+    let removed = entries
+        .iter()
+        .find(|entry| entry.action == "remove_note" && entry.target_id == note_id)
+        .expect("note removal was not audited");
+    assert_eq!(removed.actor_account_id, 1);
+    assert_eq!(removed.target_type, "note");
+}
+
+#[test]
+fn test_fetch_audit_log_invalid_window_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let response = client_binding
+        .get("/audit?from=2023-02-01&to=2023-01-01")
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_fetch_audit_log_regular_user_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let response = client_binding
+        .get(uri!(super::fetch_audit_log(_, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+#[test]
+fn test_audit_action_target_types() {
+    assert_eq!(super::AuditAction::AddNote.target_type(), "note");
+    assert_eq!(super::AuditAction::RemoveNote.target_type(), "note");
+    assert_eq!(super::AuditAction::UpdateAccount.target_type(), "account");
+    assert_eq!(super::AuditAction::ResetPassword.target_type(), "account");
+}
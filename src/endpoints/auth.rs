@@ -5,21 +5,46 @@
 mod tests;
 
 mod credentials;
+mod rate_limit;
 mod security_questions;
 mod session_token;
+pub(crate) mod totp;
 
+use std::time::Duration;
+
+use chrono::TimeZone;
+use lazy_static::lazy_static;
 use regex::Regex;
 use rocket::serde::json::Json;
 use rocket_db_pools::{sqlx, Connection};
+use sqlx::Acquire;
 
 use crate::db::{self, SPS};
-use crate::endpoints::errors::{ApiErrors, ApiResult};
+use crate::endpoints::errors::{ApiErrors, ApiResult, ErrorCode};
+use crate::endpoints::guards::{AuthAccount, ClientInfo};
+use crate::endpoints::hashing;
+
+/// Failed logins allowed per email address before it is locked out
+const DEFAULT_LOGIN_MAX_ATTEMPTS: i64 = 5;
+/// How long in seconds failed logins are counted for
+const DEFAULT_LOGIN_WINDOW_SECONDS: i64 = 300;
+/// Bad passwords in a row that lock an account
+const DEFAULT_ACCOUNT_LOCK_THRESHOLD: i64 = 10;
+/// How long in seconds a locked account stays locked
+const DEFAULT_ACCOUNT_LOCK_SECONDS: i64 = 15 * 60;
+/// How long in seconds a session lasts without being used, 180 days
+const DEFAULT_SESSION_TTL_SECONDS: i64 = 180 * 24 * 60 * 60;
+
+lazy_static! {
+    /// Failed login attempts, keyed by email address
+    static ref LOGIN_LIMITER: rate_limit::RateLimiter = rate_limit::RateLimiter::default();
+}
 
 /// ## Validate email address
 ///
 /// Checks whether or not a given email address is a valid student
 /// email address.
-fn validate_email(email: &String) -> ApiResult<()> {
+pub(crate) fn validate_email(email: &String) -> ApiResult<()> {
     // Regex setup and error handling
     let email_rule_regex = match Regex::new(r"^([0-9]{7}@students\.wits\.ac\.za|\S+\.\S+@wits\.ac\.za)$") {
         Ok(val) => val,
@@ -34,36 +59,191 @@ fn validate_email(email: &String) -> ApiResult<()> {
 
     // Ensure that the received email address is a student account
     if !email_rule_regex.is_match(&email) {
-        return Err(ApiErrors::Unauth(
-            "Invalid email address provided".to_string(),
-        ));
+        return Err(ApiErrors::Unauthorized("Invalid email address provided".to_string())
+            .with_code(ErrorCode::InvalidEmail));
     }
 
     Ok(())
 }
 
+/// ## Session lifetime
+///
+/// Read from `session_ttl_seconds` on every call so it can be changed without a restart
+pub(crate) async fn session_ttl() -> chrono::Duration {
+    let ttl_seconds = crate::SETTINGS
+        .read()
+        .await
+        .get_int("session_ttl_seconds")
+        .unwrap_or(DEFAULT_SESSION_TTL_SECONDS);
+
+    chrono::Duration::seconds(ttl_seconds.max(0))
+}
+
+/// ## Check a two-factor authentication code
+///
+/// Does nothing for accounts without two-factor authentication. Otherwise a
+/// code is required, and each one is only accepted once
+async fn check_totp(
+    db_conn: &mut Connection<SPS>,
+    account: &db::Account,
+    code: Option<&str>,
+) -> ApiResult<()> {
+    let stored_secret = match (&account.totp_confirmed_at, &account.totp_secret) {
+        (Some(_), Some(val)) => val,
+        _ => return Ok(()),
+    };
+    let code = match code {
+        Some(val) => val,
+        None => {
+            return Err(ApiErrors::Unauthorized(
+                "Two-factor authentication code required".to_string(),
+            )
+            .with_code(ErrorCode::TotpRequired))
+        }
+    };
+
+    let secret = totp::decrypt_secret(account.account_id, stored_secret).await?;
+    let now_step = totp::time_step(chrono::Utc::now().timestamp());
+    let step = match totp::verify(&secret, code, now_step, totp::skew_steps().await, account.totp_last_step) {
+        Some(val) => val,
+        None => {
+            return Err(ApiErrors::Unauthorized(
+                "Invalid or already used two-factor authentication code".to_string(),
+            )
+            .with_code(ErrorCode::TotpInvalid))
+        }
+    };
+
+    // Only ever moves forward, so two logins racing with the same code can't both get in
+    match sqlx::query!(
+        "UPDATE tblAccount SET totp_last_step = ? WHERE account_id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)",
+        step,
+        account.account_id,
+        step
+    )
+    .execute(&mut **db_conn)
+    .await
+    {
+        Ok(val) if val.rows_affected() == 1 => Ok(()),
+        Ok(_) => Err(ApiErrors::Unauthorized(
+            "Invalid or already used two-factor authentication code".to_string(),
+        )
+        .with_code(ErrorCode::TotpInvalid)),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => Err(ApiErrors::InternalError(
+            "Failed to record two-factor authentication code".to_string(),
+        )),
+    }
+}
+
+/// ## Count a bad password against an account
+///
+/// Once `account_lock_threshold` bad passwords have been given in a row the
+/// account is locked for `account_lock_seconds`, and the count starts over
+async fn record_failed_password(db_conn: &mut Connection<SPS>, account_id: i32) -> ApiResult<()> {
+    let (threshold, lock_seconds) = {
+        let settings = crate::SETTINGS.read().await;
+        (
+            settings
+                .get_int("account_lock_threshold")
+                .unwrap_or(DEFAULT_ACCOUNT_LOCK_THRESHOLD),
+            settings
+                .get_int("account_lock_seconds")
+                .unwrap_or(DEFAULT_ACCOUNT_LOCK_SECONDS),
+        )
+    };
+    let locked_until = chrono::Utc::now().naive_utc() + chrono::Duration::seconds(lock_seconds.max(0));
+
+    // Counted in the database, so concurrent logins can't lose a failure
+    match sqlx::query!(
+        "UPDATE tblAccount SET locked_until = IF(failed_attempts + 1 >= ?, ?, locked_until), failed_attempts = IF(failed_attempts + 1 >= ?, 0, failed_attempts + 1) WHERE account_id = ?",
+        threshold,
+        locked_until,
+        threshold,
+        account_id
+    )
+    .execute(&mut **db_conn)
+    .await
+    {
+        Ok(_) => Ok(()),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => Err(ApiErrors::InternalError(
+            "Failed to record failed login".to_string(),
+        )),
+    }
+}
+
 /// ## Authenticate User Credentials
 ///
+/// Logging in with an email that has no account creates one, so the password
+/// has to pass the same strength checks as when it is changed
+///
+/// Accounts with two-factor authentication enabled also have to send a
+/// current `totp_code`. Wrong codes count as failed logins
+///
+/// After `login_max_attempts` failed logins for an email address within
+/// `login_window_seconds`, further attempts are refused until the window runs out
+///
+/// `account_lock_threshold` bad passwords in a row, however spread out, lock
+/// the account for `account_lock_seconds`. Logins to a locked account are
+/// refused with the time it unlocks, even with the right password
+///
+/// The new session records the client's IP address and `User-Agent`. Behind
+/// a proxy listed in `trusted_proxies`, the address comes from `X-Forwarded-For`
+///
 /// ### Arguments
 ///
 /// ```json
 ///     {
 ///         "email": string,
-///         "hashed_password": string
+///         "hashed_password": string,
+///         "totp_code": string (optional)
 ///     }
 /// ```
 ///
 /// ### Possible Responses
 ///
 /// * 200 Ok
+/// * 400 Bad Request
 /// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 429 Too Many Requests
+/// * 503 Service Unavailable
 #[post("/authentication/credentials", data = "<credentials>")]
 pub async fn auth_credentials(
     mut db_conn: Connection<SPS>,
+    client: ClientInfo,
     credentials: Json<credentials::CredentialRequest>,
 ) -> ApiResult<Json<credentials::CredentialReponse>> {
     validate_email(&credentials.email)?;
 
+    let (max_attempts, window) = {
+        let settings = crate::SETTINGS.read().await;
+        (
+            settings
+                .get_int("login_max_attempts")
+                .unwrap_or(DEFAULT_LOGIN_MAX_ATTEMPTS)
+                .max(1) as u32,
+            Duration::from_secs(
+                settings
+                    .get_int("login_window_seconds")
+                    .unwrap_or(DEFAULT_LOGIN_WINDOW_SECONDS)
+                    .max(0) as u64,
+            ),
+        )
+    };
+    let limiter_key = credentials.email.to_lowercase();
+
+    if LOGIN_LIMITER.is_limited(&limiter_key, max_attempts, window) {
+        // Rounded up, so a client waiting exactly this long isn't refused again
+        let retry_after = LOGIN_LIMITER.retry_after(&limiter_key, window);
+        return Err(ApiErrors::TooManyRequests(
+            "Too many failed login attempts, try again later".to_string(),
+            Some(retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)),
+        )
+        .with_code(ErrorCode::TooManyLoginAttempts));
+    }
+
     let mut is_new_account = false;
     let db_account = match sqlx::query_as!(
         db::Account,
@@ -83,9 +263,11 @@ pub async fn auth_credentials(
             // means we can maintain the whole appearance of never signing up while still
             // actually having a sign up process. Its just obfuscated
             is_new_account = true;
+            crate::endpoints::account::validate_password(&credentials.hashed_password).await?;
+
             let mut new_account = db::Account::default();
             new_account.email = credentials.email.to_owned();
-            new_account.hashed_password = credentials.hashed_password.to_owned();
+            new_account.hashed_password = hashing::hash_password(&credentials.hashed_password)?;
 
             match sqlx::query!(
                 "INSERT INTO tblAccount(email, hashed_password, username, cell_number) VALUES (?, ?, ?, ?)",
@@ -100,17 +282,86 @@ pub async fn auth_credentials(
         }
     };
 
-    if &db_account.hashed_password != &credentials.hashed_password {
-        return Err(ApiErrors::Unauth("Incorrect provided password".to_string()));
+    if let Some(locked_until) = db_account.locked_until {
+        if locked_until > chrono::Utc::now().naive_utc() {
+            return Err(ApiErrors::Forbidden(format!(
+                "Account is locked after too many failed logins until {}",
+                chrono::Utc.from_utc_datetime(&locked_until)
+            ))
+            .with_code(ErrorCode::AccountLocked));
+        }
     }
 
-    let token = session_token::generate_session_token(&db_account);
+    if !hashing::verify_password(&credentials.hashed_password, &db_account.hashed_password) {
+        LOGIN_LIMITER.record_failure(&limiter_key, window);
+        record_failed_password(&mut db_conn, db_account.account_id).await?;
+        return Err(ApiErrors::Unauthorized("Incorrect provided password".to_string())
+            .with_code(ErrorCode::IncorrectPassword));
+    }
+
+    match check_totp(&mut db_conn, &db_account, credentials.totp_code.as_deref()).await {
+        Ok(_) => (),
+        Err(e) if e.status() == rocket::http::Status::Unauthorized => {
+            LOGIN_LIMITER.record_failure(&limiter_key, window);
+            return Err(e);
+        }
+        Err(e) => return Err(e),
+    }
 
+    LOGIN_LIMITER.reset(&limiter_key);
+
+    if db_account.failed_attempts > 0 || db_account.locked_until.is_some() {
+        match sqlx::query!(
+            "UPDATE tblAccount SET failed_attempts = 0, locked_until = NULL WHERE account_id = ?",
+            db_account.account_id
+        )
+        .execute(&mut *db_conn)
+        .await
+        {
+            Ok(_) => (),
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Failed to reset failed logins".to_string(),
+                ))
+            }
+        };
+    }
+
+    // Passwords stored before hashing was introduced get hashed now that we know they are correct
+    if hashing::needs_rehash(&db_account.hashed_password) {
+        let hashed_password = hashing::hash_password(&credentials.hashed_password)?;
+
+        match sqlx::query!(
+            "UPDATE tblAccount SET hashed_password = ? WHERE account_id = ?",
+            hashed_password,
+            db_account.account_id
+        )
+        .execute(&mut *db_conn)
+        .await
+        {
+            Ok(_) => (),
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Failed to update account password".to_string(),
+                ))
+            }
+        };
+    }
+
+    let token = session_token::generate_session_token(&db_account, session_ttl().await);
+
+    let now = chrono::Utc::now().naive_utc();
     match sqlx::query!(
-        "INSERT INTO tblSessionToken (account_id, token, expiry_date) VALUES (?, ?, ?)",
+        "INSERT INTO tblSessionToken (account_id, token, created_at, expires_at, last_active, ip_address, user_agent) VALUES (?, ?, ?, ?, ?, ?, ?)",
         token.account_id,
         token.token,
-        token.expiry_date
+        now,
+        token.expires_at,
+        now,
+        client.ip_address.map(|ip| ip.to_string()),
+        client.user_agent
     )
     .execute(&mut *db_conn)
     .await
@@ -128,6 +379,9 @@ pub async fn auth_credentials(
 
 /// ## Send email for forgot password
 ///
+/// Lists the security questions set on the account. Answers are only stored
+/// hashed and are checked when resetting the password
+///
 /// ### Arguments
 ///
 /// ```json
@@ -139,7 +393,7 @@ pub async fn auth_credentials(
 /// ### Possible Responses
 ///
 /// * 200 Ok
-/// * 404 Not Found
+/// * 401 Unauthorized
 #[post("/authentication/security_questions", data = "<reset_details>")]
 pub async fn auth_security_questions(
     mut db_conn: Connection<SPS>,
@@ -148,7 +402,7 @@ pub async fn auth_security_questions(
     validate_email(&reset_details.email)?;
 
     let account_questions = match sqlx::query!(
-        "SELECT tblAccount.email as email, tblAccount.account_id as account_id, tblSecurityAnswers.secques_id as question_id, tblSecurityQuestions.question as question, tblSecurityAnswers.answer as answer FROM tblSecurityAnswers JOIN tblAccount ON tblAccount.account_id = tblSecurityAnswers.account_id JOIN tblSecurityQuestions ON tblSecurityAnswers.secques_id = tblSecurityQuestions.secques_id WHERE tblAccount.email = ?",
+        "SELECT tblAccount.email as email, tblAccount.account_id as account_id, tblSecurityAnswers.secques_id as question_id, tblSecurityQuestions.question as question FROM tblSecurityAnswers JOIN tblAccount ON tblAccount.account_id = tblSecurityAnswers.account_id JOIN tblSecurityQuestions ON tblSecurityAnswers.secques_id = tblSecurityQuestions.secques_id WHERE tblAccount.email = ?",
         &reset_details.email
     ).fetch_all(&mut *db_conn).await {
         Ok(val) => val,
@@ -158,8 +412,8 @@ pub async fn auth_security_questions(
 
     match account_questions.len() == 0 {
         true => {
-            return Err(ApiErrors::NotFound(
-                "Provided email address not found in database".to_string(),
+            return Err(ApiErrors::Unauthorized(
+                "No security questions found for the provided email address".to_string(),
             ))
         }
         _ => (),
@@ -174,58 +428,43 @@ pub async fn auth_security_questions(
             .push(security_questions::SecurityQuestion {
                 question_id: question.question_id,
                 question: question.question.to_owned(),
-                answer: question.answer.to_owned(),
             })
     }
 
     Ok(Json(sq_response))
 }
 
-/// ## Authenticate a session token
+/// ## Validate a session token
 ///
-/// ### Arguments
-/// ```json
-///     {
-///         "account_id": i32,
-///         "token": string
-///     }
-/// ```
-///
-/// ### Possible Responses
-///
-/// * 200 Ok
-/// * 401 Unauthorized
-/// * 404 Not Found
-#[post("/authentication/session", data = "<token>")]
-pub async fn auth_session(
-    mut db_conn: Connection<SPS>,
-    token: Json<session_token::TokenRequest>,
-) -> ApiResult<()> {
+/// Looks up the token for the account, removing it if it has expired
+async fn validate_session(
+    db_conn: &mut Connection<SPS>,
+    account_id: i32,
+    session_token: &str,
+) -> ApiResult<db::SessionToken> {
     let token = match sqlx::query_as!(
         db::SessionToken,
         "SELECT * FROM tblSessionToken WHERE account_id = ? AND token = ?",
-        token.account_id,
-        token.session_token
+        account_id,
+        session_token
     )
-    .fetch_one(&mut *db_conn)
+    .fetch_one(&mut **db_conn)
     .await
     {
         Ok(val) => val,
-        Err(_) => return Err(ApiErrors::Unauth("Session Token not found".to_string())),
+        Err(_) => {
+            return Err(ApiErrors::Unauthorized("Session Token not found".to_string())
+                .with_code(ErrorCode::SessionNotFound))
+        }
     };
 
-    // Converting for comparison against the current value
-    let expiry_datetime = match token.expiry_date.and_hms_opt(0, 0, 0) {
-        Some(val) => val,
-        None => chrono::NaiveDateTime::MIN,
-    };
-    match chrono::Utc::now().naive_utc() > expiry_datetime {
+    match chrono::Utc::now().naive_utc() >= token.expires_at {
         true => {
             match sqlx::query!(
                 "DELETE FROM tblSessionToken WHERE session_token_id = ?",
                 token.session_token_id
             )
-            .execute(&mut *db_conn)
+            .execute(&mut **db_conn)
             .await
             {
                 Ok(_) => (),
@@ -237,14 +476,145 @@ pub async fn auth_session(
                 }
             };
 
-            return Err(ApiErrors::Unauth("Expired Session Token".to_string()));
+            return Err(ApiErrors::Unauthorized("Expired Session Token".to_string())
+                .with_code(ErrorCode::SessionExpired));
         }
         false => (),
     };
 
+    Ok(token)
+}
+
+/// ## Authenticate a session token
+///
+/// Expired tokens are removed. Using a valid token pushes its expiry out by
+/// another session lifetime, so sessions only end after going unused
+///
+/// ### Arguments
+/// ```json
+///     {
+///         "account_id": i32,
+///         "token": string
+///     }
+/// ```
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+#[post("/authentication/session", data = "<token>")]
+pub async fn auth_session(
+    mut db_conn: Connection<SPS>,
+    token: Json<session_token::TokenRequest>,
+) -> ApiResult<()> {
+    let token = validate_session(&mut db_conn, token.account_id as i32, &token.session_token).await?;
+
+    let now = chrono::Utc::now().naive_utc();
+    match sqlx::query!(
+        "UPDATE tblSessionToken SET expires_at = ?, last_active = ? WHERE session_token_id = ?",
+        now + session_ttl().await,
+        now,
+        token.session_token_id
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to renew session token".to_string(),
+            ))
+        }
+    };
+
     Ok(())
 }
 
+/// ## Remove every session for an account
+///
+/// Logs the account out everywhere, including the session making the
+/// request. Only the account's own sessions can be removed
+///
+/// ### Arguments
+///
+/// * account id
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+#[delete("/authentication/sessions/<account_id>")]
+pub async fn remove_all_sessions(
+    auth: Result<AuthAccount, ApiErrors>,
+    account_id: i32,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<()> {
+    auth?.check_account(account_id)?;
+
+    match sqlx::query!(
+        "DELETE FROM tblSessionToken WHERE account_id = ?",
+        account_id
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove session tokens from database".to_string(),
+            ))
+        }
+    };
+
+    Ok(())
+}
+
+/// ## List the active sessions for an account
+///
+/// Expired sessions are left out. Only the account's own sessions can be
+/// listed
+///
+/// ### Arguments
+///
+/// * account id
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+#[get("/authentication/sessions/<account_id>")]
+pub async fn list_sessions(
+    auth: Result<AuthAccount, ApiErrors>,
+    account_id: i32,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<Vec<session_token::Session>>> {
+    auth?.check_account(account_id)?;
+
+    let db_sessions = match sqlx::query_as!(
+        db::SessionToken,
+        "SELECT * FROM tblSessionToken WHERE account_id = ? AND expires_at > ? ORDER BY created_at, session_token_id",
+        account_id,
+        chrono::Utc::now().naive_utc()
+    )
+    .fetch_all(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to fetch sessions".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json(db_sessions.iter().map(|session| session.into()).collect()))
+}
+
 /// ## Remove the specified session token from the database
 /// 
 /// ### Arguments
@@ -265,7 +635,10 @@ pub async fn remove_session(token: String, mut db_conn: Connection<SPS>) -> ApiR
     .await
     {
         Ok(val) => val.session_token_id,
-        Err(_) => return Err(ApiErrors::NotFound("Session token not found".to_string())),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Session token not found".to_string())
+                .with_code(ErrorCode::SessionNotFound))
+        }
     };
 
     match sqlx::query!(
@@ -286,3 +659,201 @@ pub async fn remove_session(token: String, mut db_conn: Connection<SPS>) -> ApiR
 
     Ok(())
 }
+
+/// ## Start enrolling in two-factor authentication
+///
+/// Generates a new secret for the account, stored encrypted with
+/// `totp_encryption_key`. Nothing changes at login until the enrollment is
+/// confirmed with a code. Enrolling again before then replaces the secret
+///
+/// ### Arguments
+///
+/// * account id
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 409 Conflict
+/// * 503 Service Unavailable
+#[post("/authentication/totp/<account_id>")]
+pub async fn enroll_totp(
+    auth: Result<AuthAccount, ApiErrors>,
+    account_id: i32,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<totp::TotpEnrollment>> {
+    auth?.check_account(account_id)?;
+
+    let account = match sqlx::query!(
+        "SELECT email, totp_confirmed_at FROM tblAccount WHERE account_id = ?",
+        account_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => return Err(ApiErrors::InternalError("Failed to fetch account".to_string())),
+    };
+
+    if account.totp_confirmed_at.is_some() {
+        return Err(ApiErrors::Conflict("Two-factor authentication is already enabled".to_string())
+            .with_code(ErrorCode::TotpAlreadyEnabled));
+    }
+
+    let secret = totp::generate_secret();
+    let stored_secret = totp::encrypt_secret(account_id, &secret).await?;
+
+    match sqlx::query!(
+        "UPDATE tblAccount SET totp_secret = ?, totp_last_step = NULL WHERE account_id = ?",
+        stored_secret,
+        account_id
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to store two-factor authentication secret".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json(totp::TotpEnrollment {
+        secret: totp::base32_encode(&secret),
+        otpauth_uri: totp::otpauth_uri(&account.email, &secret),
+    }))
+}
+
+/// ## Confirm two-factor authentication
+///
+/// Turns two-factor authentication on once a code from the enrolled secret
+/// checks out. Every other session for the account is ended, so the only
+/// sessions left are ones that have passed a code
+///
+/// ### Arguments
+///
+/// * account id
+///
+/// ```json
+///     {
+///         "code": string
+///     }
+/// ```
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+/// * 409 Conflict
+/// * 503 Service Unavailable
+#[post("/authentication/totp/<account_id>/confirm", data = "<confirmation>")]
+pub async fn confirm_totp(
+    auth: Result<AuthAccount, ApiErrors>,
+    account_id: i32,
+    mut db_conn: Connection<SPS>,
+    confirmation: Json<totp::TotpConfirmation>,
+) -> ApiResult<()> {
+    let auth = auth?;
+    auth.check_account(account_id)?;
+
+    let account = match sqlx::query!(
+        "SELECT totp_secret, totp_confirmed_at FROM tblAccount WHERE account_id = ?",
+        account_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => return Err(ApiErrors::InternalError("Failed to fetch account".to_string())),
+    };
+
+    if account.totp_confirmed_at.is_some() {
+        return Err(ApiErrors::Conflict("Two-factor authentication is already enabled".to_string())
+            .with_code(ErrorCode::TotpAlreadyEnabled));
+    }
+    let stored_secret: String = match account.totp_secret {
+        Some(val) => val,
+        None => {
+            return Err(ApiErrors::NotFound(
+                "No two-factor authentication enrollment to confirm".to_string(),
+            )
+            .with_code(ErrorCode::TotpNotEnrolled))
+        }
+    };
+
+    let secret = totp::decrypt_secret(account_id, &stored_secret).await?;
+    let now = chrono::Utc::now();
+    let step = match totp::verify(
+        &secret,
+        &confirmation.code,
+        totp::time_step(now.timestamp()),
+        totp::skew_steps().await,
+        None,
+    ) {
+        Some(val) => val,
+        None => {
+            return Err(ApiErrors::Unauthorized("Invalid two-factor authentication code".to_string())
+                .with_code(ErrorCode::TotpInvalid))
+        }
+    };
+
+    let mut transaction = match (&mut *db_conn).begin().await {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to start transaction".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!(
+        "UPDATE tblAccount SET totp_confirmed_at = ?, totp_last_step = ? WHERE account_id = ?",
+        now.naive_utc(),
+        step,
+        account_id
+    )
+    .execute(&mut transaction)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to enable two-factor authentication".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!(
+        "DELETE FROM tblSessionToken WHERE account_id = ? AND session_token_id <> ?",
+        account_id,
+        auth.session_id
+    )
+    .execute(&mut transaction)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove session tokens from database".to_string(),
+            ))
+        }
+    };
+
+    match transaction.commit().await {
+        Ok(_) => Ok(()),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => Err(ApiErrors::InternalError(
+            "Failed to enable two-factor authentication".to_string(),
+        )),
+    }
+}
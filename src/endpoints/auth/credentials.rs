@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 pub struct CredentialRequest {
     pub email: String,
     pub hashed_password: String,
+    /// Only needed once two-factor authentication is enabled
+    pub totp_code: Option<String>,
 }
 
 #[derive(Serialize)]
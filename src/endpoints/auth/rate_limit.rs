@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Failed attempts made for a single key within the current window
+struct Attempts {
+    count: u32,
+    window_start: Instant,
+}
+
+/// ## In memory rate limiter
+///
+/// Counts failed attempts per key within a fixed window that starts at the
+/// first failure. Once the maximum is reached the key stays limited until
+/// the window runs out
+#[derive(Default)]
+pub struct RateLimiter {
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+impl RateLimiter {
+    /// Whether the key has used up its attempts for the current window
+    pub fn is_limited(&self, key: &str, max_attempts: u32, window: Duration) -> bool {
+        let mut attempts = self.attempts.lock().unwrap();
+
+        match attempts.get(key) {
+            Some(entry) if entry.window_start.elapsed() >= window => {
+                attempts.remove(key);
+                false
+            }
+            Some(entry) => entry.count >= max_attempts,
+            None => false,
+        }
+    }
+
+    /// How long until the key's current window runs out
+    pub fn retry_after(&self, key: &str, window: Duration) -> Duration {
+        match self.attempts.lock().unwrap().get(key) {
+            Some(entry) => window.saturating_sub(entry.window_start.elapsed()),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Count a failed attempt against the key
+    pub fn record_failure(&self, key: &str, window: Duration) {
+        let mut attempts = self.attempts.lock().unwrap();
+
+        let entry = attempts.entry(key.to_string()).or_insert(Attempts {
+            count: 0,
+            window_start: Instant::now(),
+        });
+
+        if entry.window_start.elapsed() >= window {
+            entry.count = 0;
+            entry.window_start = Instant::now();
+        }
+        entry.count += 1;
+    }
+
+    /// Forget every failed attempt made for the key
+    pub fn reset(&self, key: &str) {
+        self.attempts.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RateLimiter;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_rate_limiter_limited_after_max_failures() {
+        let limiter = RateLimiter::default();
+
+        for _ in 0..3 {
+            assert!(!limiter.is_limited("key", 3, WINDOW));
+            limiter.record_failure("key", WINDOW);
+        }
+
+        assert!(limiter.is_limited("key", 3, WINDOW));
+        assert!(!limiter.is_limited("other key", 3, WINDOW));
+    }
+
+    #[test]
+    fn test_rate_limiter_reset_clears_failures() {
+        let limiter = RateLimiter::default();
+
+        for _ in 0..3 {
+            limiter.record_failure("key", WINDOW);
+        }
+        limiter.reset("key");
+
+        assert!(!limiter.is_limited("key", 3, WINDOW));
+    }
+
+    #[test]
+    fn test_rate_limiter_retry_after_within_window() {
+        let limiter = RateLimiter::default();
+        assert_eq!(limiter.retry_after("key", WINDOW), Duration::ZERO);
+
+        limiter.record_failure("key", WINDOW);
+        let retry_after = limiter.retry_after("key", WINDOW);
+        assert!(retry_after > Duration::ZERO && retry_after <= WINDOW);
+    }
+
+    #[test]
+    fn test_rate_limiter_window_expired_not_limited() {
+        let limiter = RateLimiter::default();
+
+        for _ in 0..3 {
+            limiter.record_failure("key", Duration::ZERO);
+        }
+
+        assert!(!limiter.is_limited("key", 3, Duration::ZERO));
+    }
+}
@@ -15,5 +15,4 @@ pub struct SecurityQuestionsResponse {
 pub struct SecurityQuestion {
     pub question_id: i32,
     pub question: String,
-    pub answer: String,
 }
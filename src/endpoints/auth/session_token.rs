@@ -1,4 +1,5 @@
-use crate::db::Account;
+use crate::db::{self, Account};
+use chrono::{TimeZone, Utc};
 use crypto::{digest::Digest, sha2::Sha256};
 use serde::{Deserialize, Serialize};
 
@@ -8,17 +9,43 @@ pub struct TokenRequest {
     pub account_id: u32,
 }
 
+/// An active session as shown to the account it belongs to. The token itself
+/// is never sent back
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Session {
+    pub session_id: i32,
+    pub created_at: String,
+    pub expires_at: String,
+    pub last_active: String,
+    /// Where the session was started from, if known
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl From<&db::SessionToken> for Session {
+    fn from(value: &db::SessionToken) -> Self {
+        Session {
+            session_id: value.session_token_id,
+            created_at: Utc.from_utc_datetime(&value.created_at).to_string(),
+            expires_at: Utc.from_utc_datetime(&value.expires_at).to_string(),
+            last_active: Utc.from_utc_datetime(&value.last_active).to_string(),
+            ip_address: value.ip_address.clone(),
+            user_agent: value.user_agent.clone(),
+        }
+    }
+}
+
 pub struct SessionToken {
     pub account_id: i32,
     pub session_token_id: i32,
     pub token: String,
-    pub expiry_date: chrono::NaiveDate,
+    pub expires_at: chrono::NaiveDateTime,
 }
 
 // Generate a unique Hash session token based off the account requesting
 // the token, and the time it was requested. Helps keep the tokens unique
 // even if multiple requests from the same account or same time happen
-pub fn generate_session_token(account: &Account) -> SessionToken {
+pub fn generate_session_token(account: &Account, ttl: chrono::Duration) -> SessionToken {
     let timestamp_millis = chrono::Utc::now().timestamp_millis().to_string();
     let email = &account.email;
     let id = account.account_id.to_string();
@@ -26,13 +53,11 @@ pub fn generate_session_token(account: &Account) -> SessionToken {
     let mut hasher = Sha256::new();
     hasher.input_str(format!("{}{}{}", timestamp_millis, email, id).as_str());
 
-    let expiry_date = chrono::Utc::now().naive_utc() + chrono::Duration::days(180);
-
     SessionToken {
         session_token_id: 0,
         account_id: account.account_id,
         token: hasher.result_str(),
-        expiry_date: expiry_date.date(),
+        expires_at: chrono::Utc::now().naive_utc() + ttl,
     }
 }
 
@@ -47,7 +72,8 @@ mod tests {
         test_account.email = "testEmailAddress@gmail.com".to_string();
         test_account.hashed_password = "test_password+1".to_string();
 
-        let token_result = super::generate_session_token(&test_account);
+        let token_result =
+            super::generate_session_token(&test_account, chrono::Duration::days(1));
         let hex_result = hex::decode(&token_result.token); // if this fails then its an invalid hex string
                                                            // and concequently an invalid sha256
 
@@ -55,4 +81,13 @@ mod tests {
         let hex_str = hex_result.unwrap();
         assert_eq!(hex_str.len(), 32);
     }
+
+    #[test]
+    fn test_generate_session_token_expires_after_ttl() {
+        let before = chrono::Utc::now().naive_utc();
+        let token_result = super::generate_session_token(&Account::default(), chrono::Duration::hours(2));
+
+        assert!(token_result.expires_at >= before + chrono::Duration::hours(2));
+        assert!(token_result.expires_at <= chrono::Utc::now().naive_utc() + chrono::Duration::hours(2));
+    }
 }
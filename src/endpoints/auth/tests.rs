@@ -44,6 +44,7 @@ fn test_auth_credentials_correct_credentials_ok() {
         email: "0000000@students.wits.ac.za".to_string(),
         hashed_password: "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e"
             .to_string(),
+        totp_code: None,
     };
 
     let response = client_binding
@@ -62,6 +63,7 @@ fn test_auth_credentials_incorrect_credentials_unauth() {
     let valid_body = super::credentials::CredentialRequest {
         email: "0000000@gmail.wits.ac.za".to_string(),
         hashed_password: "password2".to_string(),
+        totp_code: None,
     };
 
     let response = client_binding
@@ -73,6 +75,75 @@ fn test_auth_credentials_incorrect_credentials_unauth() {
     assert!(response.body().is_some());
 }
 
+/// Attempt a login, returning the status of the response
+fn login(client: &rocket::local::blocking::Client, email: &str, password: &str) -> Status {
+    let body = super::credentials::CredentialRequest {
+        email: email.to_string(),
+        hashed_password: password.to_string(),
+        totp_code: None,
+    };
+
+    client
+        .post(uri!(super::auth_credentials))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch()
+        .status()
+}
+
+#[test]
+fn test_auth_credentials_new_account_weak_password_bad_request() {
+    let client_binding = crate::tests::CLIENT.lock().unwrap();
+    let email = "6161610@students.wits.ac.za";
+
+    assert_eq!(login(&client_binding, email, "short1"), Status::BadRequest);
+    assert_eq!(login(&client_binding, email, "no_digits_here"), Status::BadRequest);
+
+    let accounts = crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM tblAccount WHERE email = ?")
+                .bind(email)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    });
+    assert_eq!(accounts, 0);
+}
+
+#[test]
+fn test_auth_credentials_repeated_failures_too_many_requests() {
+    let client_binding = crate::tests::CLIENT.lock().unwrap();
+    let email = "3333333@students.wits.ac.za";
+
+    // Creates the account the first time round
+    assert_eq!(login(&client_binding, email, "password_1"), Status::Ok);
+
+    for _ in 0..super::DEFAULT_LOGIN_MAX_ATTEMPTS {
+        assert_eq!(login(&client_binding, email, "password_2"), Status::Unauthorized);
+    }
+
+    // Locked out even with the correct password
+    assert_eq!(login(&client_binding, email, "password_1"), Status::TooManyRequests);
+}
+
+#[test]
+fn test_auth_credentials_success_resets_failures_ok() {
+    let client_binding = crate::tests::CLIENT.lock().unwrap();
+    let email = "4444444@students.wits.ac.za";
+
+    assert_eq!(login(&client_binding, email, "password_1"), Status::Ok);
+
+    for _ in 1..super::DEFAULT_LOGIN_MAX_ATTEMPTS {
+        assert_eq!(login(&client_binding, email, "password_2"), Status::Unauthorized);
+    }
+    assert_eq!(login(&client_binding, email, "password_1"), Status::Ok);
+
+    for _ in 1..super::DEFAULT_LOGIN_MAX_ATTEMPTS {
+        assert_eq!(login(&client_binding, email, "password_2"), Status::Unauthorized);
+    }
+    assert_eq!(login(&client_binding, email, "password_1"), Status::Ok);
+}
+
 #[test]
 fn test_auth_security_questions_valid_email_ok() {
     let client_binding = crate::tests::CLIENT.lock().unwrap();
@@ -102,6 +173,55 @@ fn test_auth_security_questions_invalid_email_unauth() {
     assert!(response.body().is_some());
 }
 
+#[test]
+fn test_auth_security_questions_unknown_email_unauth() {
+    let client_binding = crate::tests::CLIENT.lock().unwrap();
+    let body = super::security_questions::SecurityQuestionsRequest {
+        email: "9999999@students.wits.ac.za".to_string()
+    };
+
+    let response = client_binding.post(uri!(super::auth_security_questions))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_auth_credentials_wrong_password_unauth_not_not_found() {
+    let client_binding = crate::tests::CLIENT.lock().unwrap();
+    let body = super::credentials::CredentialRequest {
+        email: "0000000@students.wits.ac.za".to_string(),
+        hashed_password: "not_the_password_1".to_string(),
+        totp_code: None,
+    };
+
+    let response = client_binding
+        .post(uri!(super::auth_credentials))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let error = response.into_json::<serde_json::Value>().unwrap();
+    assert_eq!(error["code"], "INCORRECT_PASSWORD");
+}
+
+#[test]
+fn test_auth_session_wrong_account_unauth_not_not_found() {
+    let client_binding = crate::tests::CLIENT.lock().unwrap();
+    let body = super::session_token::TokenRequest {
+        session_token: "expiring_session_token".to_string(),
+        account_id: 2
+    };
+
+    let response = client_binding.post(uri!(super::auth_session))
+        .body(serde_json::to_string(&body).unwrap()).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let error = response.into_json::<serde_json::Value>().unwrap();
+    assert_eq!(error["code"], "SESSION_NOT_FOUND");
+}
+
 #[test]
 fn test_auth_session_no_token_unauth() {
     let client_binding = crate::tests::CLIENT.lock().unwrap();
@@ -137,4 +257,718 @@ fn test_remove_session_nonexisiting_token_not_found() {
 
     assert_eq!(response.status(), Status::Ok);
     assert!(response.body().is_none());
-}
\ No newline at end of file
+}
+
+/// Expiry stored against a session token
+fn session_expiry(token: &'static str) -> chrono::NaiveDateTime {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar::<_, chrono::NaiveDateTime>(
+                "SELECT expires_at FROM tblSessionToken WHERE token = ?",
+            )
+            .bind(token)
+            .fetch_one(conn)
+            .await
+            .unwrap()
+        })
+    })
+}
+
+#[test]
+fn test_auth_session_fresh_token_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let login_body = super::credentials::CredentialRequest {
+        email: "0000000@students.wits.ac.za".to_string(),
+        hashed_password: "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e"
+            .to_string(),
+        totp_code: None,
+    };
+
+    let response = client_binding
+        .post(uri!(super::auth_credentials))
+        .body(serde_json::to_string(&login_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let login = response.into_json::<serde_json::Value>().unwrap();
+
+    let body = super::session_token::TokenRequest {
+        session_token: login["session_token"].as_str().unwrap().to_string(),
+        account_id: 1,
+    };
+    let response = client_binding
+        .post(uri!(super::auth_session))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn test_auth_session_expired_token_unauth_and_removed() {
+    let client_binding = CLIENT.lock().unwrap();
+    let body = super::session_token::TokenRequest {
+        session_token: "expired_session_token".to_string(),
+        account_id: 1,
+    };
+
+    let response = client_binding
+        .post(uri!(super::auth_session))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // The expired token is gone, so it is no longer even found
+    let response = client_binding
+        .post(uri!(super::auth_session))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(
+        response.into_json::<serde_json::Value>().unwrap()["message"],
+        "Session Token not found"
+    );
+}
+
+#[test]
+fn test_auth_session_valid_token_expiry_renewed() {
+    let client_binding = CLIENT.lock().unwrap();
+    let body = super::session_token::TokenRequest {
+        session_token: "expiring_session_token".to_string(),
+        account_id: 1,
+    };
+
+    let response = client_binding
+        .post(uri!(super::auth_session))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // Seeded to expire within a day, renewed by a whole session lifetime
+    let expires_at = session_expiry("expiring_session_token");
+    assert!(expires_at > chrono::Utc::now().naive_utc() + chrono::Duration::days(2));
+}
+
+/// Add a session for account 1 last used at `last_active` and expiring at
+/// `expires_at`
+fn insert_session(
+    token: &'static str,
+    last_active: chrono::NaiveDateTime,
+    expires_at: chrono::NaiveDateTime,
+) {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query("INSERT INTO tblSessionToken (account_id, token, created_at, expires_at, last_active) VALUES (1, ?, ?, ?, ?)")
+                .bind(token)
+                .bind(last_active)
+                .bind(expires_at)
+                .bind(last_active)
+                .execute(conn)
+                .await
+                .unwrap();
+        })
+    });
+}
+
+#[test]
+fn test_session_guard_idle_session_expiry_renewed() {
+    let client_binding = CLIENT.lock().unwrap();
+    let now = chrono::Utc::now().naive_utc();
+    insert_session(
+        "guard_idle_session_token",
+        now - chrono::Duration::hours(2),
+        now + chrono::Duration::hours(1),
+    );
+
+    let response = client_binding
+        .get("/notes/1")
+        .header(crate::tests::bearer("guard_idle_session_token"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let expires_at = session_expiry("guard_idle_session_token");
+    assert!(expires_at > chrono::Utc::now().naive_utc() + chrono::Duration::days(2));
+}
+
+#[test]
+fn test_session_guard_recently_used_session_not_rewritten() {
+    let client_binding = CLIENT.lock().unwrap();
+    let now = chrono::Utc::now().naive_utc();
+    insert_session(
+        "guard_recent_session_token",
+        now,
+        now + chrono::Duration::hours(1),
+    );
+    let before = session_expiry("guard_recent_session_token");
+
+    let response = client_binding
+        .get("/notes/1")
+        .header(crate::tests::bearer("guard_recent_session_token"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // Used within the renewal interval, so left alone
+    assert_eq!(session_expiry("guard_recent_session_token"), before);
+}
+
+/// Log in as the given account, returning the new session token
+fn login_token(client: &rocket::local::blocking::Client, email: &str) -> String {
+    let body = super::credentials::CredentialRequest {
+        email: email.to_string(),
+        hashed_password: "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e"
+            .to_string(),
+        totp_code: None,
+    };
+
+    let response = client
+        .post(uri!(super::auth_credentials))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json::<serde_json::Value>().unwrap()["session_token"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn test_remove_all_sessions_every_session_removed_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let first_token = login_token(&client_binding, "1111111@students.wits.ac.za");
+    let second_token = login_token(&client_binding, "1111111@students.wits.ac.za");
+
+    let response = client_binding
+        .delete(uri!(super::remove_all_sessions(2)))
+        .header(crate::tests::bearer(&first_token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    for token in [first_token, second_token] {
+        let body = super::session_token::TokenRequest {
+            session_token: token,
+            account_id: 2,
+        };
+        let response = client_binding
+            .post(uri!(super::auth_session))
+            .body(serde_json::to_string(&body).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+}
+
+#[test]
+fn test_remove_all_sessions_other_accounts_token_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let token = login_token(&client_binding, "0000000@students.wits.ac.za");
+
+    let response = client_binding
+        .delete(uri!(super::remove_all_sessions(2)))
+        .header(crate::tests::bearer(&token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_remove_all_sessions_no_token_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .delete(uri!(super::remove_all_sessions(2)))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn test_list_sessions_active_sessions_listed_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "5555555@students.wits.ac.za";
+
+    // Creates the account the first time round
+    login_token(&client_binding, email);
+
+    // Start again from just an expired session, which shouldn't be listed
+    let account_id: i32 = crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            let account_id = sqlx::query_scalar::<_, i32>(
+                "SELECT account_id FROM tblAccount WHERE email = ?",
+            )
+            .bind(email)
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+
+            sqlx::query("DELETE FROM tblSessionToken WHERE account_id = ?")
+                .bind(account_id)
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO tblSessionToken (account_id, token, created_at, expires_at, last_active) VALUES (?, 'listed_expired_token', '2023-01-01', '2023-06-30', '2023-01-01')")
+                .bind(account_id)
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+
+            account_id
+        })
+    });
+
+    let first_token = login_token(&client_binding, email);
+    login_token(&client_binding, email);
+
+    let response = client_binding
+        .get(uri!(super::list_sessions(account_id)))
+        .header(crate::tests::bearer(&first_token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let sessions = response
+        .into_json::<Vec<super::session_token::Session>>()
+        .unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_ne!(sessions[0].session_id, sessions[1].session_id);
+    for session in &sessions {
+        assert!(session.created_at.ends_with("UTC"));
+        assert!(session.created_at < session.expires_at);
+        assert!(!session.last_active.is_empty());
+    }
+}
+
+#[test]
+fn test_list_sessions_invalid_token_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::list_sessions(1)))
+        .header(crate::tests::bearer("asdfasdf"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_list_sessions_other_accounts_session_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::list_sessions(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+#[test]
+fn test_auth_credentials_client_info_stored_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let body = super::credentials::CredentialRequest {
+        email: "6666666@students.wits.ac.za".to_string(),
+        hashed_password: "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e"
+            .to_string(),
+        totp_code: None,
+    };
+
+    // Not sent by a trusted proxy, so the forwarded address is ignored
+    let response = client_binding
+        .post(uri!(super::auth_credentials))
+        .remote("203.0.113.5:51000".parse().unwrap())
+        .header(rocket::http::Header::new("User-Agent", "SPS-Frontend/1.0 (Android)"))
+        .header(rocket::http::Header::new("X-Forwarded-For", "198.51.100.1"))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let credentials = response.into_json::<serde_json::Value>().unwrap();
+
+    let response = client_binding
+        .get(uri!(super::list_sessions(credentials["account_id"].as_i64().unwrap() as i32)))
+        .header(crate::tests::bearer(credentials["session_token"].as_str().unwrap()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let sessions = response
+        .into_json::<Vec<super::session_token::Session>>()
+        .unwrap();
+    let session = sessions.last().unwrap();
+    assert_eq!(session.ip_address.as_deref(), Some("203.0.113.5"));
+    assert_eq!(session.user_agent.as_deref(), Some("SPS-Frontend/1.0 (Android)"));
+}
+
+/// Password stored against an account
+fn stored_password(email: &'static str) -> String {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar::<_, String>("SELECT hashed_password FROM tblAccount WHERE email = ?")
+                .bind(email)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    })
+}
+
+#[test]
+fn test_auth_credentials_legacy_password_rehashed_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "1111111@students.wits.ac.za";
+    let password = "0b14d501a594442a01c6859541bcb3e8164d183d32937b851835442f69d5c94e";
+
+    assert_eq!(login(&client_binding, email, password), Status::Ok);
+
+    let stored = stored_password(email);
+    assert_ne!(stored, password);
+    assert!(!crate::endpoints::hashing::needs_rehash(&stored));
+
+    // Still able to log in against the hash, and not with anything else
+    assert_eq!(login(&client_binding, email, password), Status::Ok);
+    assert_eq!(login(&client_binding, email, "password_2"), Status::Unauthorized);
+    assert_eq!(login(&client_binding, email, password), Status::Ok);
+}
+
+#[test]
+fn test_auth_credentials_new_account_password_hashed() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "6666666@students.wits.ac.za";
+
+    assert_eq!(login(&client_binding, email, "password_1"), Status::Ok);
+    assert!(!crate::endpoints::hashing::needs_rehash(&stored_password(email)));
+}
+
+/// Key TOTP secrets are encrypted with in tests
+const TEST_TOTP_KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+/// Run a test with `totp_encryption_key` set, putting the settings back after
+fn with_totp_key<T>(test: impl FnOnce() -> T) -> T {
+    let previous = crate::SETTINGS.blocking_read().clone();
+    *crate::SETTINGS.blocking_write() = config::Config::builder()
+        .add_source(previous.clone())
+        .set_override("totp_encryption_key", TEST_TOTP_KEY)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let result = test();
+    *crate::SETTINGS.blocking_write() = previous;
+    result
+}
+
+/// Secret bytes from the base32 an enrollment hands back
+fn base32_decode(encoded: &str) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in encoded.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'2'..=b'7' => c - b'2' + 26,
+            _ => panic!("Not base32: {}", encoded),
+        };
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+
+    decoded
+}
+
+/// Code for the step `offset` steps from now
+fn totp_code(secret: &[u8], offset: i64) -> String {
+    let now_step = super::totp::time_step(chrono::Utc::now().timestamp());
+    super::totp::code_at(secret, now_step + offset)
+}
+
+/// Log in with a two-factor code, returning the status of the response
+fn login_with_code(client: &rocket::local::blocking::Client, email: &str, code: &str) -> Status {
+    let body = super::credentials::CredentialRequest {
+        email: email.to_string(),
+        hashed_password: "password_1".to_string(),
+        totp_code: Some(code.to_string()),
+    };
+
+    client
+        .post(uri!(super::auth_credentials))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch()
+        .status()
+}
+
+/// Create an account by logging in, then enroll it in two-factor
+/// authentication, returning the account id, session token and secret
+fn enroll_new_account(client: &rocket::local::blocking::Client, email: &str) -> (i32, String, Vec<u8>) {
+    let body = super::credentials::CredentialRequest {
+        email: email.to_string(),
+        hashed_password: "password_1".to_string(),
+        totp_code: None,
+    };
+    let login = client
+        .post(uri!(super::auth_credentials))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch()
+        .into_json::<serde_json::Value>()
+        .unwrap();
+    let account_id = login["account_id"].as_i64().unwrap() as i32;
+    let session_token = login["session_token"].as_str().unwrap().to_string();
+
+    let response = client
+        .post(uri!(super::enroll_totp(account_id)))
+        .header(crate::tests::bearer(&session_token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let enrollment = response.into_json::<serde_json::Value>().unwrap();
+    let secret = enrollment["secret"].as_str().unwrap();
+    assert!(enrollment["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with(&format!("otpauth://totp/SPS:{}?secret={}", email, secret)));
+
+    (account_id, session_token, base32_decode(secret))
+}
+
+/// Confirm an enrollment, returning the status of the response
+fn confirm(client: &rocket::local::blocking::Client, account_id: i32, session_token: &str, code: &str) -> Status {
+    client
+        .post(uri!(super::confirm_totp(account_id)))
+        .header(crate::tests::bearer(session_token))
+        .body(serde_json::json!({ "code": code }).to_string())
+        .dispatch()
+        .status()
+}
+
+#[test]
+fn test_confirm_totp_code_required_at_login() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "6060601@students.wits.ac.za";
+
+    with_totp_key(|| {
+        let (account_id, session_token, secret) = enroll_new_account(&client_binding, email);
+
+        // Not enabled until confirmed
+        assert_eq!(login(&client_binding, email, "password_1"), Status::Ok);
+
+        assert_eq!(
+            confirm(&client_binding, account_id, &session_token, &totp_code(&secret, 0)),
+            Status::Ok
+        );
+        assert_eq!(login(&client_binding, email, "password_1"), Status::Unauthorized);
+        assert_eq!(login_with_code(&client_binding, email, &totp_code(&secret, 1)), Status::Ok);
+
+        // Enrolling again can't quietly swap the secret out
+        let response = client_binding
+            .post(uri!(super::enroll_totp(account_id)))
+            .header(crate::tests::bearer(&session_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+    });
+}
+
+#[test]
+fn test_confirm_totp_other_sessions_removed() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "6060602@students.wits.ac.za";
+
+    with_totp_key(|| {
+        let (account_id, session_token, secret) = enroll_new_account(&client_binding, email);
+        let other_token = crate::tests::create_session(account_id);
+
+        assert_eq!(
+            confirm(&client_binding, account_id, &session_token, &totp_code(&secret, 0)),
+            Status::Ok
+        );
+
+        let session = |token: &str| {
+            client_binding
+                .post(uri!(super::auth_session))
+                .body(serde_json::json!({ "account_id": account_id, "session_token": token }).to_string())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(session(&session_token), Status::Ok);
+        assert_eq!(session(&other_token), Status::Unauthorized);
+    });
+}
+
+#[test]
+fn test_auth_credentials_replayed_totp_code_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "6060603@students.wits.ac.za";
+
+    with_totp_key(|| {
+        let (account_id, session_token, secret) = enroll_new_account(&client_binding, email);
+        let confirm_code = totp_code(&secret, 0);
+        assert_eq!(confirm(&client_binding, account_id, &session_token, &confirm_code), Status::Ok);
+
+        // Already used to confirm
+        assert_eq!(login_with_code(&client_binding, email, &confirm_code), Status::Unauthorized);
+
+        let code = totp_code(&secret, 1);
+        assert_eq!(login_with_code(&client_binding, email, &code), Status::Ok);
+        assert_eq!(login_with_code(&client_binding, email, &code), Status::Unauthorized);
+    });
+}
+
+#[test]
+fn test_auth_credentials_expired_totp_code_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "6060604@students.wits.ac.za";
+
+    with_totp_key(|| {
+        let (account_id, session_token, secret) = enroll_new_account(&client_binding, email);
+
+        // Outside the default skew of one step either side
+        assert_eq!(
+            confirm(&client_binding, account_id, &session_token, &totp_code(&secret, -3)),
+            Status::Unauthorized
+        );
+        assert_eq!(
+            confirm(&client_binding, account_id, &session_token, &totp_code(&secret, 0)),
+            Status::Ok
+        );
+
+        assert_eq!(login_with_code(&client_binding, email, &totp_code(&secret, -3)), Status::Unauthorized);
+        assert_eq!(login_with_code(&client_binding, email, "000000x"), Status::Unauthorized);
+    });
+}
+
+#[test]
+fn test_enroll_totp_no_key_service_unavailable() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "6060605@students.wits.ac.za";
+    let session_token = login_token(&client_binding, email);
+    let account_id = crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar::<_, i32>("SELECT account_id FROM tblAccount WHERE email = ?")
+                .bind(email)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    });
+
+    let response = client_binding
+        .post(uri!(super::enroll_totp(account_id)))
+        .header(crate::tests::bearer(&session_token))
+        .dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+}
+
+#[test]
+fn test_enroll_totp_other_accounts_session_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .post(uri!(super::enroll_totp(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+/// Run a test with accounts locking after three bad passwords, and the rate
+/// limiter set high enough to stay out of the way
+fn with_account_lock<T>(test: impl FnOnce() -> T) -> T {
+    let previous = crate::SETTINGS.blocking_read().clone();
+    *crate::SETTINGS.blocking_write() = config::Config::builder()
+        .add_source(previous.clone())
+        .set_override("login_max_attempts", 100)
+        .unwrap()
+        .set_override("account_lock_threshold", 3)
+        .unwrap()
+        .set_override("account_lock_seconds", 600)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let result = test();
+    *crate::SETTINGS.blocking_write() = previous;
+    result
+}
+
+/// Failed attempts and lock recorded for an account
+fn account_lock(email: &'static str) -> (i32, Option<chrono::NaiveDateTime>) {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_as("SELECT failed_attempts, locked_until FROM tblAccount WHERE email = ?")
+                .bind(email)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    })
+}
+
+#[test]
+fn test_auth_credentials_threshold_reached_account_locked_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "7070701@students.wits.ac.za";
+
+    with_account_lock(|| {
+        assert_eq!(login(&client_binding, email, "password_1"), Status::Ok);
+        for _ in 0..3 {
+            assert_eq!(login(&client_binding, email, "password_2"), Status::Unauthorized);
+        }
+
+        let (failed_attempts, locked_until) = account_lock(email);
+        assert_eq!(failed_attempts, 0);
+        assert!(locked_until.unwrap() > chrono::Utc::now().naive_utc() + chrono::Duration::seconds(500));
+
+        // Refused even with the right password, and told when to come back
+        let body = super::credentials::CredentialRequest {
+            email: email.to_string(),
+            hashed_password: "password_1".to_string(),
+            totp_code: None,
+        };
+        let response = client_binding
+            .post(uri!(super::auth_credentials))
+            .body(serde_json::to_string(&body).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let error = response
+            .into_json::<crate::endpoints::errors::ErrorBody>()
+            .unwrap();
+        assert!(error.message.contains("locked"));
+        assert!(error.message.contains(&locked_until.unwrap().date().to_string()));
+    });
+}
+
+#[test]
+fn test_auth_credentials_lock_expired_ok_and_reset() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "7070702@students.wits.ac.za";
+
+    with_account_lock(|| {
+        assert_eq!(login(&client_binding, email, "password_1"), Status::Ok);
+        for _ in 0..3 {
+            assert_eq!(login(&client_binding, email, "password_2"), Status::Unauthorized);
+        }
+        assert_eq!(login(&client_binding, email, "password_1"), Status::Forbidden);
+
+        crate::tests::with_db(|conn| {
+            Box::pin(async move {
+                sqlx::query("UPDATE tblAccount SET locked_until = DATE_SUB(UTC_TIMESTAMP(), INTERVAL 1 SECOND) WHERE email = ?")
+                    .bind(email)
+                    .execute(conn)
+                    .await
+                    .unwrap()
+            })
+        });
+
+        assert_eq!(login(&client_binding, email, "password_1"), Status::Ok);
+        assert_eq!(account_lock(email), (0, None));
+    });
+}
+
+#[test]
+fn test_auth_credentials_success_resets_failed_attempts_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let email = "7070703@students.wits.ac.za";
+
+    with_account_lock(|| {
+        assert_eq!(login(&client_binding, email, "password_1"), Status::Ok);
+        for _ in 0..2 {
+            for _ in 0..2 {
+                assert_eq!(login(&client_binding, email, "password_2"), Status::Unauthorized);
+            }
+            assert_eq!(account_lock(email).0, 2);
+            assert_eq!(login(&client_binding, email, "password_1"), Status::Ok);
+        }
+
+        assert_eq!(account_lock(email), (0, None));
+    });
+}
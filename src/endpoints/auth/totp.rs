@@ -0,0 +1,302 @@
+//! # TOTP
+//! Time based one time passwords (RFC 6238) for two-factor authentication,
+//! and encrypting the shared secret while it is stored on `tblAccount`
+
+use crypto::aead::{AeadDecryptor, AeadEncryptor};
+use crypto::aes::KeySize;
+use crypto::aes_gcm::AesGcm;
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha1::Sha1;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+use serde::{Deserialize, Serialize};
+
+use crate::endpoints::errors::{ApiErrors, ApiResult};
+
+/// Name shown next to the account in authenticator apps
+const ISSUER: &str = "SPS";
+
+/// Length of a shared secret, as recommended for HMAC-SHA1
+const SECRET_BYTES: usize = 20;
+
+/// Digits in a code
+const DIGITS: u32 = 6;
+
+/// Seconds each code is valid for
+pub const STEP_SECONDS: i64 = 30;
+
+/// Steps either side of the current one a code is still accepted for, to
+/// allow for clocks that have drifted. Used when `totp_skew_steps` is not set
+const DEFAULT_SKEW_STEPS: i64 = 1;
+
+/// AES-256 key length, `totp_encryption_key` is this many bytes as hex
+const KEY_BYTES: usize = 32;
+
+/// AES-GCM nonce and tag lengths
+const NONCE_BYTES: usize = 12;
+const TAG_BYTES: usize = 16;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[derive(Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TotpConfirmation {
+    pub code: String,
+}
+
+/// RFC 4648 base32 without padding, the form authenticator apps take secrets in
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() * 8 + 4) / 5);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1F) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1F) as usize] as char);
+    }
+
+    encoded
+}
+
+/// Random bytes, made the same way as session tokens
+fn random_bytes(length: usize) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.input(uuid::Uuid::new_v4().as_bytes());
+    hasher.input(uuid::Uuid::new_v4().as_bytes());
+
+    let mut bytes = vec![0u8; hasher.output_bytes()];
+    hasher.result(&mut bytes);
+    bytes.truncate(length);
+
+    bytes
+}
+
+/// A new shared secret for an account
+pub fn generate_secret() -> Vec<u8> {
+    random_bytes(SECRET_BYTES)
+}
+
+/// The time step a moment falls in
+pub fn time_step(unix_seconds: i64) -> i64 {
+    unix_seconds.div_euclid(STEP_SECONDS)
+}
+
+/// The code for one time step (RFC 4226 HOTP with the step as the counter)
+pub fn code_at(secret: &[u8], step: i64) -> String {
+    let mut mac = Hmac::new(Sha1::new(), secret);
+    mac.input(&(step as u64).to_be_bytes());
+    let result = mac.result();
+    let hash = result.code();
+
+    let offset = (hash[hash.len() - 1] & 0x0F) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7FFF_FFFF;
+
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// ## Verify a code
+///
+/// Accepts codes for up to `skew` steps either side of `now_step`. Steps at or
+/// before `last_step` were already used and are refused, so a code can only be
+/// used once. Gives back the step the code matched
+pub fn verify(
+    secret: &[u8],
+    code: &str,
+    now_step: i64,
+    skew: i64,
+    last_step: Option<i64>,
+) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+
+    ((now_step - skew)..=(now_step + skew))
+        .filter(|step| last_step.map_or(true, |last| *step > last))
+        .find(|step| fixed_time_eq(code_at(secret, *step).as_bytes(), code.as_bytes()))
+}
+
+/// Steps either side of the current one to accept, from `totp_skew_steps`
+pub async fn skew_steps() -> i64 {
+    crate::SETTINGS
+        .read()
+        .await
+        .get_int("totp_skew_steps")
+        .unwrap_or(DEFAULT_SKEW_STEPS)
+        .max(0)
+}
+
+/// Link authenticator apps read, usually shown as a QR code
+pub fn otpauth_uri(email: &str, secret: &[u8]) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{label}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}",
+        issuer = ISSUER,
+        label = rocket::http::RawStr::new(email).percent_encode(),
+        secret = base32_encode(secret),
+    )
+}
+
+/// Whether `totp_encryption_key` can be used as a key
+pub fn is_valid_key(key: &str) -> bool {
+    matches!(hex::decode(key), Ok(val) if val.len() == KEY_BYTES)
+}
+
+/// The key secrets are encrypted with, from `totp_encryption_key`
+async fn encryption_key() -> ApiResult<Vec<u8>> {
+    let key = match crate::SETTINGS
+        .read()
+        .await
+        .get_string("totp_encryption_key")
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::ServiceUnavailable(
+                "Two-factor authentication is not set up on this server".to_string(),
+                None,
+            ))
+        }
+    };
+
+    match hex::decode(key) {
+        Ok(val) if val.len() == KEY_BYTES => Ok(val),
+        #[cfg(not(tarpaulin_include))]
+        _ => Err(ApiErrors::InternalError(
+            "Two-factor authentication key is invalid".to_string(),
+        )),
+    }
+}
+
+/// ## Encrypt a secret
+///
+/// AES-256-GCM with a fresh nonce, bound to the account so a stored secret
+/// can't be copied onto another account. Stored as hex of nonce, ciphertext
+/// and tag
+pub async fn encrypt_secret(account_id: i32, secret: &[u8]) -> ApiResult<String> {
+    let key = encryption_key().await?;
+    let nonce = random_bytes(NONCE_BYTES);
+
+    let mut ciphertext = vec![0u8; secret.len()];
+    let mut tag = [0u8; TAG_BYTES];
+    AesGcm::new(KeySize::KeySize256, &key, &nonce, &account_id.to_be_bytes()).encrypt(
+        secret,
+        &mut ciphertext,
+        &mut tag,
+    );
+
+    Ok(hex::encode([nonce, ciphertext, tag.to_vec()].concat()))
+}
+
+/// Decrypt a secret stored by `encrypt_secret`
+pub async fn decrypt_secret(account_id: i32, stored: &str) -> ApiResult<Vec<u8>> {
+    let key = encryption_key().await?;
+    let bytes = match hex::decode(stored) {
+        Ok(val) if val.len() > NONCE_BYTES + TAG_BYTES => val,
+        #[cfg(not(tarpaulin_include))]
+        _ => {
+            return Err(ApiErrors::InternalError(
+                "Stored TOTP secret is invalid".to_string(),
+            ))
+        }
+    };
+
+    let (nonce, rest) = bytes.split_at(NONCE_BYTES);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_BYTES);
+    let mut secret = vec![0u8; ciphertext.len()];
+
+    match AesGcm::new(KeySize::KeySize256, &key, nonce, &account_id.to_be_bytes()).decrypt(
+        ciphertext,
+        &mut secret,
+        tag,
+    ) {
+        true => Ok(secret),
+        #[cfg(not(tarpaulin_include))]
+        false => Err(ApiErrors::InternalError(
+            "Unable to decrypt TOTP secret".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /// Secret used by the RFC 6238 test vectors
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_base32_encode_rfc_vectors() {
+        assert_eq!(super::base32_encode(b""), "");
+        assert_eq!(super::base32_encode(b"f"), "MY");
+        assert_eq!(super::base32_encode(b"foob"), "MZXW6YQ");
+        assert_eq!(super::base32_encode(b"foobar"), "MZXW6YTBOI");
+    }
+
+    #[test]
+    fn test_code_at_rfc_vectors() {
+        // RFC 6238 gives 8 digit codes, these are their last 6 digits
+        assert_eq!(super::code_at(RFC_SECRET, super::time_step(59)), "287082");
+        assert_eq!(
+            super::code_at(RFC_SECRET, super::time_step(1111111109)),
+            "081804"
+        );
+        assert_eq!(
+            super::code_at(RFC_SECRET, super::time_step(2000000000)),
+            "279037"
+        );
+    }
+
+    #[test]
+    fn test_verify_within_skew_ok() {
+        let code = super::code_at(RFC_SECRET, 100);
+        assert_eq!(super::verify(RFC_SECRET, &code, 100, 1, None), Some(100));
+        assert_eq!(super::verify(RFC_SECRET, &code, 101, 1, None), Some(100));
+        assert_eq!(super::verify(RFC_SECRET, &code, 99, 1, None), Some(100));
+    }
+
+    #[test]
+    fn test_verify_outside_skew_none() {
+        let code = super::code_at(RFC_SECRET, 100);
+        assert_eq!(super::verify(RFC_SECRET, &code, 102, 1, None), None);
+        assert_eq!(super::verify(RFC_SECRET, &code, 101, 0, None), None);
+        assert_eq!(super::verify(RFC_SECRET, "12345", 100, 1, None), None);
+    }
+
+    #[test]
+    fn test_verify_used_step_none() {
+        let code = super::code_at(RFC_SECRET, 100);
+        assert_eq!(super::verify(RFC_SECRET, &code, 100, 1, Some(100)), None);
+        assert_eq!(
+            super::verify(RFC_SECRET, &code, 100, 1, Some(99)),
+            Some(100)
+        );
+    }
+
+    #[test]
+    fn test_otpauth_uri_label_encoded() {
+        let uri = super::otpauth_uri("first last@wits.ac.za", b"foobar");
+        assert!(uri.starts_with("otpauth://totp/SPS:first%20last@wits.ac.za?"));
+        assert!(uri.contains("secret=MZXW6YTBOI"));
+        assert!(uri.contains("issuer=SPS"));
+    }
+}
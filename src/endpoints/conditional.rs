@@ -0,0 +1,217 @@
+//! # Conditional Requests
+//! ETags and `If-None-Match`, or modification times and `If-Modified-Since`,
+//! so clients polling for content that hasn't changed get a 304 instead of the
+//! same bytes again
+
+use chrono::{DateTime, NaiveDateTime, Timelike};
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+
+/// ## If-None-Match guard
+///
+/// The ETags a client already has, if it sent any. Never fails, a request
+/// without the header just isn't conditional
+pub struct IfNoneMatch(Option<String>);
+
+impl IfNoneMatch {
+    /// Whether the client's copy is the one with the given ETag. Weak
+    /// validators compare equal to strong ones, as GET only needs a weak match
+    pub fn matches(&self, etag: &str) -> bool {
+        let header = match &self.0 {
+            Some(val) => val,
+            None => return false,
+        };
+
+        header
+            .split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfNoneMatch {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(IfNoneMatch(
+            request
+                .headers()
+                .get_one("If-None-Match")
+                .map(|val| val.to_string()),
+        ))
+    }
+}
+
+/// ## If-Modified-Since guard
+///
+/// When the client's copy was last modified, if it sent a date it could be
+/// read. Never fails, a missing or unreadable date just isn't conditional
+pub struct IfModifiedSince(Option<NaiveDateTime>);
+
+impl IfModifiedSince {
+    /// Whether the client's copy is at least as new as `last_modified`. HTTP
+    /// dates only have whole seconds, so neither does the comparison
+    pub fn unchanged_since(&self, last_modified: &NaiveDateTime) -> bool {
+        match &self.0 {
+            Some(since) => last_modified.with_nanosecond(0).unwrap() <= *since,
+            None => false,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfModifiedSince {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(IfModifiedSince(
+            request
+                .headers()
+                .get_one("If-Modified-Since")
+                .and_then(|val| DateTime::parse_from_rfc2822(val).ok())
+                .map(|val| val.naive_utc()),
+        ))
+    }
+}
+
+/// A UTC time in the HTTP date format, as in `Last-Modified`
+pub fn http_date(time: &NaiveDateTime) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Strong ETag for a content hash
+pub fn etag(hash: &str) -> String {
+    format!("\"{}\"", hash)
+}
+
+/// ## Conditional response
+///
+/// Either the full response, or a 304 Not Modified when the client already
+/// has it. Both carry the ETag
+pub enum Conditional<R> {
+    Fresh(R, String),
+    NotModified(String),
+}
+
+impl<R> Conditional<R> {
+    /// A 304 if the client's copy matches the ETag, otherwise the response
+    /// built by `fresh`, only called when it is needed
+    pub fn new<F>(if_none_match: &IfNoneMatch, etag: String, fresh: F) -> Conditional<R>
+    where
+        F: FnOnce() -> R,
+    {
+        match if_none_match.matches(&etag) {
+            true => Conditional::NotModified(etag),
+            false => Conditional::Fresh(fresh(), etag),
+        }
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Conditional<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        match self {
+            Conditional::Fresh(responder, etag) => {
+                Response::build_from(responder.respond_to(request)?)
+                    .header(Header::new("ETag", etag))
+                    .ok()
+            }
+            Conditional::NotModified(etag) => Response::build()
+                .status(Status::NotModified)
+                .header(Header::new("ETag", etag))
+                .ok(),
+        }
+    }
+}
+
+/// ## Response cached by modification time
+///
+/// Either the full response, or a 304 Not Modified when the client's copy is
+/// no older than the content. Both carry `Last-Modified`, when there is a time
+/// to send, and a `Cache-Control` telling clients to check back before reusing
+/// their copy
+pub enum ModifiedSince<R> {
+    Fresh(R, Option<NaiveDateTime>),
+    NotModified(NaiveDateTime),
+}
+
+/// Clients may keep a copy, but have to revalidate it every time
+const REVALIDATE: &str = "public, no-cache";
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for ModifiedSince<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        match self {
+            ModifiedSince::Fresh(responder, last_modified) => {
+                let mut response = Response::build_from(responder.respond_to(request)?);
+                response.header(Header::new("Cache-Control", REVALIDATE));
+                if let Some(val) = last_modified {
+                    response.header(Header::new("Last-Modified", http_date(&val)));
+                }
+                response.ok()
+            }
+            ModifiedSince::NotModified(last_modified) => Response::build()
+                .status(Status::NotModified)
+                .header(Header::new("Cache-Control", REVALIDATE))
+                .header(Header::new("Last-Modified", http_date(&last_modified)))
+                .ok(),
+        }
+    }
+}
+
+/// A response with a `Last-Modified` header added
+pub struct LastModified<R>(pub R, pub NaiveDateTime);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for LastModified<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        Response::build_from(self.0.respond_to(request)?)
+            .header(Header::new("Last-Modified", http_date(&self.1)))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::{IfModifiedSince, IfNoneMatch};
+
+    #[test]
+    fn test_if_none_match_matching_etag() {
+        let etag = super::etag("abc123");
+        assert!(IfNoneMatch(Some("\"abc123\"".to_string())).matches(&etag));
+        assert!(IfNoneMatch(Some("\"old\", W/\"abc123\"".to_string())).matches(&etag));
+        assert!(IfNoneMatch(Some("*".to_string())).matches(&etag));
+    }
+
+    #[test]
+    fn test_if_none_match_other_or_missing_etag() {
+        let etag = super::etag("abc123");
+        assert!(!IfNoneMatch(Some("\"old\"".to_string())).matches(&etag));
+        assert!(!IfNoneMatch(None).matches(&etag));
+    }
+
+    #[test]
+    fn test_if_modified_since_unchanged() {
+        let last_modified = NaiveDate::from_ymd_opt(2023, 5, 1)
+            .unwrap()
+            .and_hms_milli_opt(8, 30, 0, 750)
+            .unwrap();
+        let since = |val: &str| {
+            IfModifiedSince(Some(
+                chrono::DateTime::parse_from_rfc2822(val).unwrap().naive_utc(),
+            ))
+        };
+
+        assert!(since("Mon, 01 May 2023 08:30:00 GMT").unchanged_since(&last_modified));
+        assert!(since("Tue, 02 May 2023 00:00:00 GMT").unchanged_since(&last_modified));
+        assert!(!since("Mon, 01 May 2023 08:29:59 GMT").unchanged_since(&last_modified));
+        assert!(!IfModifiedSince(None).unchanged_since(&last_modified));
+    }
+
+    #[test]
+    fn test_http_date_format() {
+        let time = NaiveDate::from_ymd_opt(2023, 5, 1).unwrap().and_hms_opt(8, 5, 9).unwrap();
+        assert_eq!(super::http_date(&time), "Mon, 01 May 2023 08:05:09 GMT");
+    }
+}
@@ -1,17 +1,532 @@
-use rocket::response::Responder;
+use rocket::http::{Header, Status, StatusClass};
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use rocket::Request;
+use serde::{Deserialize, Serialize};
 
 // Custom Rocket Error handler
 // Mostly for future proofing convenience atm
-#[derive(Responder, Debug)]
+#[derive(Debug)]
 pub enum ApiErrors {
-    #[response(status = 401)]
-    Unauth(String),
-    #[response(status = 404)]
+    Unauthorized(String),
+    Forbidden(String),
     NotFound(String),
-    #[response(status = 400)]
+    Conflict(String),
     BadRequest(String),
-    #[response(status = 500)]
+    PayloadTooLarge(String),
+    UnprocessableEntity(String),
+    /// Message, and the seconds until the client may try again if known,
+    /// sent as a Retry-After header
+    TooManyRequests(String, Option<u64>),
     InternalError(String),
+    /// Message, and the seconds until the service is expected back if known,
+    /// sent as a Retry-After header
+    ServiceUnavailable(String, Option<u64>),
+    /// Another error with a code for its specific cause, made with `with_code`.
+    /// Keeps the status and message of the error it wraps
+    Coded(ErrorCode, Box<ApiErrors>),
 }
 
 pub type ApiResult<T> = Result<T, ApiErrors>;
+
+/// Machine readable error codes sent alongside the error message, so the
+/// frontend doesn't have to string match on messages
+///
+/// Errors the frontend needs to tell apart get a code for their cause.
+/// Anything else falls back to the code for its status
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    BadRequest,
+    PayloadTooLarge,
+    UnprocessableEntity,
+    TooManyRequests,
+    InternalError,
+    ServiceUnavailable,
+
+    // Accounts
+    AccountNotFound,
+    AccountExists,
+    AccountLocked,
+    TooManyLoginAttempts,
+    InvalidEmail,
+    WeakPassword,
+    IncorrectPassword,
+    IncorrectAnswer,
+    ResetTokenInvalid,
+    ResetTokenExpired,
+    VerificationTokenInvalid,
+    VerificationTokenExpired,
+
+    // Sessions
+    SessionRequired,
+    SessionNotFound,
+    SessionExpired,
+    SessionNotOwned,
+    AdminRequired,
+    TotpRequired,
+    TotpInvalid,
+    TotpAlreadyEnabled,
+    TotpNotEnrolled,
+
+    // Notes
+    NoteNotFound,
+    NoteNotOwned,
+    NoteTitleExists,
+    NoteVersionMismatch,
+    NoteLimitReached,
+    IdempotencyKeyInUse,
+    IdempotentNoteRemoved,
+    AttachmentNotFound,
+    ProtocolNotFound,
+
+    // Events
+    EventNotFound,
+}
+
+/// JSON body sent back for every error
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ApiErrors {
+    /// Give the error a code for its specific cause
+    pub fn with_code(self, code: ErrorCode) -> ApiErrors {
+        ApiErrors::Coded(code, Box::new(self))
+    }
+
+    /// HTTP status the error is sent with
+    pub fn status(&self) -> Status {
+        match self {
+            ApiErrors::Unauthorized(_) => Status::Unauthorized,
+            ApiErrors::Forbidden(_) => Status::Forbidden,
+            ApiErrors::NotFound(_) => Status::NotFound,
+            ApiErrors::Conflict(_) => Status::Conflict,
+            ApiErrors::BadRequest(_) => Status::BadRequest,
+            ApiErrors::PayloadTooLarge(_) => Status::PayloadTooLarge,
+            ApiErrors::UnprocessableEntity(_) => Status::UnprocessableEntity,
+            ApiErrors::TooManyRequests(_, _) => Status::TooManyRequests,
+            ApiErrors::InternalError(_) => Status::InternalServerError,
+            ApiErrors::ServiceUnavailable(_, _) => Status::ServiceUnavailable,
+            ApiErrors::Coded(_, error) => error.status(),
+        }
+    }
+
+    /// Seconds the client should wait before trying again, if known
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            ApiErrors::TooManyRequests(_, retry_after)
+            | ApiErrors::ServiceUnavailable(_, retry_after) => *retry_after,
+            ApiErrors::Coded(_, error) => error.retry_after(),
+            _ => None,
+        }
+    }
+
+    /// Split the error into its HTTP status, error code and message
+    fn into_parts(self) -> (Status, ErrorCode, String) {
+        match self {
+            ApiErrors::Unauthorized(message) => (Status::Unauthorized, ErrorCode::Unauthorized, message),
+            ApiErrors::Forbidden(message) => (Status::Forbidden, ErrorCode::Forbidden, message),
+            ApiErrors::NotFound(message) => (Status::NotFound, ErrorCode::NotFound, message),
+            ApiErrors::Conflict(message) => (Status::Conflict, ErrorCode::Conflict, message),
+            ApiErrors::BadRequest(message) => (Status::BadRequest, ErrorCode::BadRequest, message),
+            ApiErrors::PayloadTooLarge(message) => {
+                (Status::PayloadTooLarge, ErrorCode::PayloadTooLarge, message)
+            }
+            ApiErrors::UnprocessableEntity(message) => {
+                (Status::UnprocessableEntity, ErrorCode::UnprocessableEntity, message)
+            }
+            ApiErrors::TooManyRequests(message, _) => {
+                (Status::TooManyRequests, ErrorCode::TooManyRequests, message)
+            }
+            ApiErrors::InternalError(message) => {
+                (Status::InternalServerError, ErrorCode::InternalError, message)
+            }
+            ApiErrors::ServiceUnavailable(message, _) => {
+                (Status::ServiceUnavailable, ErrorCode::ServiceUnavailable, message)
+            }
+            ApiErrors::Coded(code, error) => {
+                let (status, _, message) = error.into_parts();
+                (status, code, message)
+            }
+        }
+    }
+}
+
+impl From<ApiErrors> for ErrorBody {
+    fn from(value: ApiErrors) -> Self {
+        let (_, code, message) = value.into_parts();
+        ErrorBody { code, message }
+    }
+}
+
+/// Log an error response along with the request that caused it and its
+/// request ID. Server errors are logged as errors, anything the client got
+/// wrong only as a warning
+fn log_error(logger: &dyn log::Log, request: &Request<'_>, status: Status, message: &str) {
+    let level = match status.class() {
+        StatusClass::ServerError => log::Level::Error,
+        _ => log::Level::Warn,
+    };
+
+    logger.log(
+        &log::Record::builder()
+            .args(format_args!(
+                "{} {}: {} {} (request {})",
+                request.method(),
+                request.uri(),
+                status,
+                message,
+                crate::fairings::request_id::request_id(request)
+            ))
+            .level(level)
+            .target(module_path!())
+            .build(),
+    );
+}
+
+impl<'r> Responder<'r, 'static> for ApiErrors {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let retry_after = self.retry_after();
+        let (status, code, message) = self.into_parts();
+        log_error(log::logger(), request, status, &message);
+        let mut response = (status, Json(ErrorBody { code, message })).respond_to(request)?;
+
+        if let Some(seconds) = retry_after {
+            response.set_header(Header::new("Retry-After", seconds.to_string()));
+        }
+
+        Ok(response)
+    }
+}
+
+// Catchers for errors Rocket raises itself, such as unmatched routes or
+// bodies that don't parse, which would otherwise get its plain HTML pages.
+// Each one sends the same JSON body as `ApiErrors`
+
+#[catch(400)]
+pub fn bad_request(_request: &Request<'_>) -> ApiErrors {
+    ApiErrors::BadRequest("The request is malformed".to_string())
+}
+
+#[catch(404)]
+pub fn not_found(request: &Request<'_>) -> ApiErrors {
+    ApiErrors::NotFound(format!(
+        "Nothing found for {} {}",
+        request.method(),
+        request.uri().path()
+    ))
+}
+
+#[catch(422)]
+pub fn unprocessable_entity(_request: &Request<'_>) -> ApiErrors {
+    ApiErrors::UnprocessableEntity(
+        "The request body does not have the expected fields and types".to_string(),
+    )
+}
+
+#[catch(500)]
+pub fn internal_error(_request: &Request<'_>) -> ApiErrors {
+    ApiErrors::InternalError("Internal Server Error".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{ContentType, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::response::Responder;
+    use std::sync::Mutex;
+
+    use super::{ApiErrors, ErrorBody, ErrorCode};
+
+    /// Logger that keeps every record it receives so tests can inspect them
+    #[derive(Default)]
+    struct CapturingLogger {
+        records: Mutex<Vec<(log::Level, String)>>,
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.records
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Run an error through its responder and parse the JSON body it produces
+    async fn respond(error: ApiErrors) -> (Status, Option<ContentType>, ErrorBody) {
+        let (status, content_type, body, _) = respond_with_retry_after(error).await;
+        (status, content_type, body)
+    }
+
+    /// Like `respond`, also giving back the Retry-After header if one was set
+    async fn respond_with_retry_after(
+        error: ApiErrors,
+    ) -> (Status, Option<ContentType>, ErrorBody, Option<String>) {
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let request = client.get("/");
+
+        let mut response = error.respond_to(request.inner()).unwrap();
+        let body = response.body_mut().to_string().await.unwrap();
+        let retry_after = response
+            .headers()
+            .get_one("Retry-After")
+            .map(|val| val.to_string());
+
+        (
+            response.status(),
+            response.content_type(),
+            serde_json::from_str(&body).unwrap(),
+            retry_after,
+        )
+    }
+
+    #[rocket::async_test]
+    async fn test_forbidden_responder_forbidden() {
+        let (status, content_type, body) =
+            respond(ApiErrors::Forbidden("Note does not belong to this account".to_string())).await;
+
+        assert_eq!(status, Status::Forbidden);
+        assert_eq!(content_type, Some(ContentType::JSON));
+        assert_eq!(body.code, ErrorCode::Forbidden);
+        assert_eq!(body.message, "Note does not belong to this account");
+    }
+
+    #[rocket::async_test]
+    async fn test_conflict_responder_conflict() {
+        let (status, content_type, body) =
+            respond(ApiErrors::Conflict("An account with that email already exists".to_string())).await;
+
+        assert_eq!(status, Status::Conflict);
+        assert_eq!(status.code, 409);
+        assert_eq!(content_type, Some(ContentType::JSON));
+        assert_eq!(body.code, ErrorCode::Conflict);
+        assert_eq!(body.message, "An account with that email already exists");
+    }
+
+    #[rocket::async_test]
+    async fn test_bad_request_responder_bad_request() {
+        let (status, content_type, body) =
+            respond(ApiErrors::BadRequest("Invalid date provided: 2023-13-01".to_string())).await;
+
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(status.code, 400);
+        assert_eq!(content_type, Some(ContentType::JSON));
+        assert_eq!(body.code, ErrorCode::BadRequest);
+        assert_eq!(body.message, "Invalid date provided: 2023-13-01");
+    }
+
+    #[rocket::async_test]
+    async fn test_payload_too_large_responder_payload_too_large() {
+        let (status, content_type, body) = respond(ApiErrors::PayloadTooLarge(
+            "Note content exceeds the maximum size of 524288 bytes".to_string(),
+        ))
+        .await;
+
+        assert_eq!(status, Status::PayloadTooLarge);
+        assert_eq!(status.code, 413);
+        assert_eq!(content_type, Some(ContentType::JSON));
+        assert_eq!(body.code, ErrorCode::PayloadTooLarge);
+        assert_eq!(body.message, "Note content exceeds the maximum size of 524288 bytes");
+    }
+
+    #[rocket::async_test]
+    async fn test_responder_every_variant_code_and_message() {
+        let message = || "Test error message".to_string();
+        let cases = vec![
+            (ApiErrors::Unauthorized(message()), Status::Unauthorized, ErrorCode::Unauthorized),
+            (ApiErrors::Forbidden(message()), Status::Forbidden, ErrorCode::Forbidden),
+            (ApiErrors::NotFound(message()), Status::NotFound, ErrorCode::NotFound),
+            (ApiErrors::Conflict(message()), Status::Conflict, ErrorCode::Conflict),
+            (ApiErrors::BadRequest(message()), Status::BadRequest, ErrorCode::BadRequest),
+            (ApiErrors::PayloadTooLarge(message()), Status::PayloadTooLarge, ErrorCode::PayloadTooLarge),
+            (ApiErrors::UnprocessableEntity(message()), Status::UnprocessableEntity, ErrorCode::UnprocessableEntity),
+            (ApiErrors::TooManyRequests(message(), None), Status::TooManyRequests, ErrorCode::TooManyRequests),
+            (ApiErrors::InternalError(message()), Status::InternalServerError, ErrorCode::InternalError),
+            (ApiErrors::ServiceUnavailable(message(), None), Status::ServiceUnavailable, ErrorCode::ServiceUnavailable),
+        ];
+
+        for (error, expected_status, expected_code) in cases {
+            let (status, _, body) = respond(error).await;
+
+            assert_eq!(status, expected_status);
+            assert_eq!(body.code, expected_code);
+            assert_eq!(body.message, message());
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_too_many_requests_responder_retry_after() {
+        let (status, content_type, body, retry_after) = respond_with_retry_after(
+            ApiErrors::TooManyRequests("Too many failed login attempts".to_string(), Some(90)),
+        )
+        .await;
+
+        assert_eq!(status, Status::TooManyRequests);
+        assert_eq!(status.code, 429);
+        assert_eq!(content_type, Some(ContentType::JSON));
+        assert_eq!(body.code, ErrorCode::TooManyRequests);
+        assert_eq!(body.message, "Too many failed login attempts");
+        assert_eq!(retry_after.as_deref(), Some("90"));
+    }
+
+    #[rocket::async_test]
+    async fn test_too_many_requests_responder_no_retry_after() {
+        let (status, _, _, retry_after) =
+            respond_with_retry_after(ApiErrors::TooManyRequests("Slow down".to_string(), None)).await;
+
+        assert_eq!(status, Status::TooManyRequests);
+        assert!(retry_after.is_none());
+    }
+
+    #[rocket::async_test]
+    async fn test_service_unavailable_responder_retry_after() {
+        let (status, content_type, body, retry_after) = respond_with_retry_after(
+            ApiErrors::ServiceUnavailable("Down for maintenance".to_string(), Some(300)),
+        )
+        .await;
+
+        assert_eq!(status, Status::ServiceUnavailable);
+        assert_eq!(status.code, 503);
+        assert_eq!(content_type, Some(ContentType::JSON));
+        assert_eq!(body.code, ErrorCode::ServiceUnavailable);
+        assert_eq!(body.message, "Down for maintenance");
+        assert_eq!(retry_after.as_deref(), Some("300"));
+    }
+
+    #[rocket::async_test]
+    async fn test_service_unavailable_responder_no_retry_after() {
+        let (status, _, body, retry_after) = respond_with_retry_after(ApiErrors::ServiceUnavailable(
+            "Database unreachable".to_string(),
+            None,
+        ))
+        .await;
+
+        assert_eq!(status, Status::ServiceUnavailable);
+        assert_eq!(body.code, ErrorCode::ServiceUnavailable);
+        assert!(retry_after.is_none());
+    }
+
+    #[rocket::async_test]
+    async fn test_coded_responder_cause_code_status_kept() {
+        let (status, content_type, body) = respond(
+            ApiErrors::NotFound("Note not found".to_string()).with_code(ErrorCode::NoteNotFound),
+        )
+        .await;
+
+        assert_eq!(status, Status::NotFound);
+        assert_eq!(content_type, Some(ContentType::JSON));
+        assert_eq!(body.code, ErrorCode::NoteNotFound);
+        assert_eq!(body.message, "Note not found");
+    }
+
+    #[rocket::async_test]
+    async fn test_coded_responder_retry_after_kept() {
+        let (status, _, body, retry_after) = respond_with_retry_after(
+            ApiErrors::TooManyRequests("Too many failed login attempts".to_string(), Some(90))
+                .with_code(ErrorCode::TooManyLoginAttempts),
+        )
+        .await;
+
+        assert_eq!(status, Status::TooManyRequests);
+        assert_eq!(body.code, ErrorCode::TooManyLoginAttempts);
+        assert_eq!(retry_after.as_deref(), Some("90"));
+    }
+
+    #[test]
+    fn test_error_code_serialize_screaming_snake_case() {
+        assert_eq!(
+            serde_json::to_string(&ErrorCode::PayloadTooLarge).unwrap(),
+            "\"PAYLOAD_TOO_LARGE\""
+        );
+        assert_eq!(serde_json::to_string(&ErrorCode::NotFound).unwrap(), "\"NOT_FOUND\"");
+        assert_eq!(serde_json::to_string(&ErrorCode::Conflict).unwrap(), "\"CONFLICT\"");
+        assert_eq!(
+            serde_json::to_string(&ErrorCode::ServiceUnavailable).unwrap(),
+            "\"SERVICE_UNAVAILABLE\""
+        );
+        assert_eq!(serde_json::to_string(&ErrorCode::NoteNotFound).unwrap(), "\"NOTE_NOT_FOUND\"");
+        assert_eq!(serde_json::to_string(&ErrorCode::AccountExists).unwrap(), "\"ACCOUNT_EXISTS\"");
+    }
+
+    #[rocket::async_test]
+    async fn test_log_error_internal_error_logged_as_error() {
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let request = client.post("/notes");
+        let logger = CapturingLogger::default();
+
+        super::log_error(
+            &logger,
+            request.inner(),
+            Status::InternalServerError,
+            "Unable to save file in database",
+        );
+
+        let records = logger.records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].0, log::Level::Error);
+        assert!(records[0].1.contains("POST /notes"));
+        assert!(records[0].1.contains("Unable to save file in database"));
+    }
+
+    #[rocket::async_test]
+    async fn test_log_error_client_error_logged_as_warning() {
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let request = client.get("/notes/0");
+        let logger = CapturingLogger::default();
+
+        super::log_error(&logger, request.inner(), Status::NotFound, "User account not found");
+
+        let records = logger.records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].0, log::Level::Warn);
+        assert!(records[0].1.contains("GET /notes/0"));
+    }
+
+    #[rocket::async_test]
+    async fn test_log_error_includes_request_id() {
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let request = client
+            .get("/notes/0")
+            .header(rocket::http::Header::new("X-Request-Id", "frontend-5678"));
+        let logger = CapturingLogger::default();
+
+        super::log_error(&logger, request.inner(), Status::InternalServerError, "Unable to fetch notes");
+
+        let records = logger.records.lock().unwrap();
+        assert!(records[0].1.contains("frontend-5678"));
+    }
+
+    #[get("/fail")]
+    fn fail() -> Status {
+        Status::InternalServerError
+    }
+
+    #[rocket::async_test]
+    async fn test_internal_error_catcher_json_body() {
+        let rocket = rocket::build()
+            .mount("/", routes![fail])
+            .register("/", catchers![super::internal_error]);
+        let client = Client::untracked(rocket).await.unwrap();
+
+        let response = client.get("/fail").dispatch().await;
+        assert_eq!(response.status(), Status::InternalServerError);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+
+        let body = response.into_json::<ErrorBody>().await.unwrap();
+        assert_eq!(body.code, ErrorCode::InternalError);
+    }
+}
@@ -2,27 +2,39 @@ mod event_api;
 #[cfg(test)]
 mod tests;
 
+use rocket::http::ContentType;
 use rocket::serde::json::Json;
 use rocket_db_pools::{sqlx, Connection};
 
-use crate::db::{self, SPS};
-use crate::endpoints::errors::{ApiErrors, ApiResult};
+use crate::db::{self, retry_transient, SPS};
+use crate::endpoints::errors::{ApiErrors, ApiResult, ErrorCode};
+use crate::endpoints::guards::AdminAccount;
+use crate::endpoints::params;
 
 /// ## Fetch events for an account
 ///
+/// Optionally only the events overlapping the dates from and to (both inclusive)
+///
 /// ### Arguments
 ///
 /// * account id
+/// * from (optional query, YYYY-MM-DD)
+/// * to (optional query, YYYY-MM-DD)
 ///
 /// ### Possible Response
 ///
 /// * 200 Ok
+/// * 400 Bad Request
 /// * 404 Not Found
-#[get("/events/<account_id>")]
+#[get("/events/<account_id>?<from>&<to>")]
 pub async fn fetch_events(
     account_id: i32,
+    from: Option<String>,
+    to: Option<String>,
     mut db_conn: Connection<SPS>,
 ) -> ApiResult<Json<Vec<event_api::EventFile>>> {
+    let (window_start, window_end) = params::parse_date_window(from, to)?;
+
     // Checking the user account actually exists
     match sqlx::query!(
         "SELECT account_id FROM tblAccount WHERE account_id = ?",
@@ -32,16 +44,27 @@ pub async fn fetch_events(
     .await
     {
         Ok(_) => (),
-        Err(_) => return Err(ApiErrors::NotFound("User account not found".to_string())),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("User account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
     }
 
-    let db_events = match sqlx::query_as!(
-        db::Event,
-        "SELECT * FROM tblEvents WHERE account_id = ?",
-        account_id
-    )
-    .fetch_all(&mut *db_conn)
-    .await
+    let db_events = match db::timed(async {
+        retry_transient!(
+            sqlx::query_as!(
+                db::Event,
+                "SELECT * FROM tblEvents WHERE account_id = ? AND (? IS NULL OR end_date >= ?) AND (? IS NULL OR start_date < ?)",
+                account_id,
+                window_start,
+                window_start,
+                window_end,
+                window_end
+            )
+            .fetch_all(&mut *db_conn)
+        )
+    })
+    .await?
     {
         Ok(val) => val,
         #[cfg(not(tarpaulin_include))]
@@ -71,10 +94,43 @@ pub async fn fetch_events(
 }
 
 
+/// ## Export all events as an iCalendar feed
+///
+/// Lets calendar apps subscribe to the events
+///
+/// ### Arguments
+///
+/// * None
+///
+/// ### Possible Response
+///
+/// * 200 Ok
+#[get("/events/export.ics")]
+pub async fn events_ics(mut db_conn: Connection<SPS>) -> ApiResult<(ContentType, String)> {
+    let db_events: Vec<db::Event> = match db::timed(async {
+        retry_transient!(
+            sqlx::query_as!(db::Event, "SELECT * FROM tblEvents")
+                .fetch_all(&mut *db_conn)
+        )
+    })
+    .await?
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to fetch events".to_string(),
+            ))
+        }
+    };
+
+    Ok((ContentType::Calendar, event_api::events_to_ics(&db_events)))
+}
+
 //insert a new record
 /// ## Add a event file to an account
 ///
-/// Add a event to an account
+/// Add a event to an account, returning the newly created event
 ///
 /// ### Arguments
 ///
@@ -84,12 +140,17 @@ pub async fn fetch_events(
 /// ### Responses
 ///
 /// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
 /// * 404 Not Found
 #[post("/events", data = "<new_event>")]
 pub async fn add_event(
+    admin: Result<AdminAccount, ApiErrors>,
     new_event: Json<event_api::NewEvent>,
     mut db_conn: Connection<SPS>,
-) -> ApiResult<()> {
+) -> ApiResult<Json<event_api::EventFile>> {
+    admin?;
+
     // Checking the user account actually exists
     match sqlx::query!(
         "SELECT account_id FROM tblAccount WHERE account_id = ?",
@@ -99,10 +160,13 @@ pub async fn add_event(
     .await
     {
         Ok(_) => (),
-        Err(_) => return Err(ApiErrors::NotFound("User account not found".to_string())),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("User account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
     }
 
-    match sqlx::query!(
+    let event_id = match sqlx::query!(
         "INSERT INTO tblEvents (account_id, start_date, end_date, event_name, description) VALUES (?, ?, ?, ?, ?)",
         new_event.account_id,
         new_event.start_date,
@@ -113,16 +177,34 @@ pub async fn add_event(
     .execute(&mut *db_conn)
     .await
     {
-        Ok(_) => (),
+        Ok(val) => val.last_insert_id() as i32,
         #[cfg(not(tarpaulin_include))]
         Err(_) => {
             return Err(ApiErrors::InternalError(
                 "Unable to save file in database".to_string(),
             ))
         }
-    }
+    };
 
-    Ok(())
+    // Reading the event back so the dates come back in the same format as fetch_events
+    let db_event = match sqlx::query_as!(
+        db::Event,
+        "SELECT * FROM tblEvents WHERE event_id = ?",
+        event_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to fetch the new event".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json((&db_event).into()))
 }
 
 //Update a specific event
@@ -138,12 +220,17 @@ pub async fn add_event(
 /// ### Responses
 ///
 /// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
 /// * 404 Not Found
 #[put("/events", data = "<update_event>")]
 pub async fn update_event(
+    admin: Result<AdminAccount, ApiErrors>,
     update_event: Json<event_api::UpdateEvent>,
     mut db_conn: Connection<SPS>,
 ) -> ApiResult<()> {
+    admin?;
+
     // Fetching the event record
     let _db_event = match sqlx::query_as!(
         db::Event,
@@ -154,7 +241,10 @@ pub async fn update_event(
     .await
     {
         Ok(val) => val,
-        Err(_) => return Err(ApiErrors::NotFound("Event not found".to_string())),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Event not found".to_string())
+                .with_code(ErrorCode::EventNotFound))
+        }
     };
 
     // Updating the record
@@ -196,9 +286,17 @@ pub async fn update_event(
 /// ### Responses
 ///
 /// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
 /// * 404 Not Found
 #[delete("/events/<event_id>")]
-pub async fn remove_event(event_id: i32, mut db_conn: Connection<SPS>) -> ApiResult<()> {
+pub async fn remove_event(
+    admin: Result<AdminAccount, ApiErrors>,
+    event_id: i32,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<()> {
+    admin?;
+
     // Fetching the event record
     let _db_event = match sqlx::query_as!(
         db::Event,
@@ -209,7 +307,10 @@ pub async fn remove_event(event_id: i32, mut db_conn: Connection<SPS>) -> ApiRes
     .await
     {
         Ok(val) => val,
-        Err(_) => return Err(ApiErrors::NotFound("Event not found".to_string())),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Event not found".to_string())
+                .with_code(ErrorCode::EventNotFound))
+        }
     };
 
     match sqlx::query!("DELETE FROM tblEvents WHERE event_id = ?", event_id)
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::db;
-use chrono::{TimeZone, Utc};
+use chrono::{NaiveTime, TimeZone, Utc};
 
 #[derive(Serialize, Deserialize)]
 pub struct EventFile {
@@ -48,3 +48,69 @@ impl From<&db::Event> for EventFile {
         }
     }
 }
+
+/// Escape text for use in an iCalendar property value (RFC 5545 section 3.3.11)
+fn escape_ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Fold a content line so that no line is longer than 75 octets (RFC 5545 section 3.1)
+fn fold_ics_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut line_length = 0;
+
+    for c in line.chars() {
+        if line_length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            line_length = 1;
+        }
+        folded.push(c);
+        line_length += c.len_utf8();
+    }
+
+    folded.push_str("\r\n");
+    folded
+}
+
+/// Serialize events into an iCalendar document with one VEVENT per event.
+///
+/// Events starting and ending at midnight are treated as all day events
+pub fn events_to_ics(events: &[db::Event]) -> String {
+    let timestamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Wits//Student Placement System//EN".to_string(),
+    ];
+
+    for event in events {
+        let all_day = event.start_date.time() == NaiveTime::MIN
+            && event.end_date.time() == NaiveTime::MIN;
+
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:event-{}@sps.wits.ac.za", event.event_id));
+        lines.push(format!("DTSTAMP:{}", timestamp));
+        if all_day {
+            // DTEND is exclusive for all day events, so it is the day after the last day
+            let end_date = event.end_date.date().succ_opt().unwrap_or(event.end_date.date());
+            lines.push(format!("DTSTART;VALUE=DATE:{}", event.start_date.format("%Y%m%d")));
+            lines.push(format!("DTEND;VALUE=DATE:{}", end_date.format("%Y%m%d")));
+        } else {
+            lines.push(format!("DTSTART:{}", event.start_date.format("%Y%m%dT%H%M%SZ")));
+            lines.push(format!("DTEND:{}", event.end_date.format("%Y%m%dT%H%M%SZ")));
+        }
+        lines.push(format!("SUMMARY:{}", escape_ics_text(&event.event_name)));
+        if let Some(description) = &event.description {
+            lines.push(format!("DESCRIPTION:{}", escape_ics_text(description)));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold_ics_line(line)).collect()
+}
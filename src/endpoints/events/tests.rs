@@ -6,7 +6,7 @@ use crate::tests::CLIENT;
 #[test]
 fn test_fetch_events_existing_account_with_notes_ok() {
     let client_binding = CLIENT.lock().unwrap();
-    let response = client_binding.get(uri!(super::fetch_events(1))).dispatch();
+    let response = client_binding.get(uri!(super::fetch_events(1, _, _))).dispatch();
     assert_eq!(response.status(), Status::Ok);
     assert!(response.body().is_some());
 }
@@ -14,7 +14,7 @@ fn test_fetch_events_existing_account_with_notes_ok() {
 #[test]
 fn test_fetch_events_nonexisting_account_not_found() {
     let client_binding = CLIENT.lock().unwrap();
-    let response = client_binding.get(uri!(super::fetch_events(0))).dispatch();
+    let response = client_binding.get(uri!(super::fetch_events(0, _, _))).dispatch();
     assert_eq!(response.status(), Status::NotFound);
     assert!(response.body().is_some());
 }
@@ -22,7 +22,7 @@ fn test_fetch_events_nonexisting_account_not_found() {
 #[test]
 fn test_fetch_events_existing_account_without_events_not_found() {
     let client_binding = CLIENT.lock().unwrap();
-    let response = client_binding.get(uri!(super::fetch_events(10))).dispatch();
+    let response = client_binding.get(uri!(super::fetch_events(10, _, _))).dispatch();
     assert_eq!(response.status(), Status::NotFound);
     assert!(response.body().is_some());
 }
@@ -38,9 +38,12 @@ fn test_insert_event_existing_account_ok() {
         description: Some("TEST ROCKET EVENT DESCRIPTION".to_string()),
     };
     let response = client_binding.post(uri!(super::add_event))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
         .body(serde_json::to_string(&body).unwrap()).dispatch();
     assert_eq!(response.status(), Status::Ok);
-    assert!(response.body().is_none());
+    let event = response.into_json::<super::event_api::EventFile>().unwrap();
+    assert_eq!(event.account_id, 1);
+    assert_eq!(event.event_name, "TEST ROCKET EVENT INSERT");
 }
 
 #[test]
@@ -54,7 +57,213 @@ fn test_insert_event_nonexisting_account_not_found() {
         description: Some("TEST ROCKET EVENT DESCRIPTION".to_string()),
     };
     let response = client_binding.post(uri!(super::add_event))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
         .body(serde_json::to_string(&body).unwrap()).dispatch();
     assert_eq!(response.status(), Status::NotFound);
     assert!(response.body().is_some());
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_fetch_events_inclusive_range_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get("/events/1?from=2022-01-07&to=2022-01-07")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let events = response.into_json::<Vec<super::event_api::EventFile>>().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event_name, "New Year Event");
+}
+
+#[test]
+fn test_fetch_events_empty_range_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get("/events/1?from=2021-01-01&to=2021-12-31")
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_events_malformed_date_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get("/events/1?from=2022-13-45").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_events_from_after_to_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get("/events/1?from=2022-02-01&to=2022-01-01")
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_event_lifecycle_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let body = super::event_api::NewEvent {
+        account_id: 1,
+        start_date: "2023-03-01 09:00:00.000".to_string(),
+        end_date: "2023-03-01 17:00:00.000".to_string(),
+        event_name: "TEST ROCKET EVENT LIFECYCLE".to_string(),
+        description: None,
+    };
+    let response = client_binding.post(uri!(super::add_event))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&body).unwrap()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let event = response.into_json::<super::event_api::EventFile>().unwrap();
+
+    let response = client_binding.get(uri!(super::fetch_events(1, _, _))).dispatch();
+    let events = response.into_json::<Vec<super::event_api::EventFile>>().unwrap();
+    assert!(events.iter().any(|e| e.event_id == event.event_id));
+
+    let body = super::event_api::UpdateEvent {
+        event_id: event.event_id,
+        start_date: "2023-03-01 09:00:00.000".to_string(),
+        end_date: "2023-03-01 17:00:00.000".to_string(),
+        event_name: "TEST ROCKET EVENT LIFECYCLE UPDATED".to_string(),
+        description: Some("Updated".to_string()),
+    };
+    let response = client_binding.put(uri!(super::update_event))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&body).unwrap()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client_binding.get(uri!(super::fetch_events(1, _, _))).dispatch();
+    let events = response.into_json::<Vec<super::event_api::EventFile>>().unwrap();
+    let updated = events.iter().find(|e| e.event_id == event.event_id).unwrap();
+    assert_eq!(updated.event_name, "TEST ROCKET EVENT LIFECYCLE UPDATED");
+    assert_eq!(updated.description, Some("Updated".to_string()));
+
+    let response = client_binding.delete(uri!(super::remove_event(event.event_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client_binding.get(uri!(super::fetch_events(1, _, _))).dispatch();
+    let events = response.into_json::<Vec<super::event_api::EventFile>>().unwrap();
+    assert!(events.iter().all(|e| e.event_id != event.event_id));
+}
+
+#[test]
+fn test_update_event_nonexisting_event_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+    let body = super::event_api::UpdateEvent {
+        event_id: 0,
+        start_date: "2023-01-01 00:00:00.000".to_string(),
+        end_date: "2023-01-01 00:00:00.000".to_string(),
+        event_name: "TEST ROCKET EVENT UPDATE".to_string(),
+        description: None,
+    };
+    let response = client_binding.put(uri!(super::update_event))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&body).unwrap()).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_remove_event_nonexisting_event_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.delete(uri!(super::remove_event(0)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_remove_event_rotation_event_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.delete(uri!(super::remove_event(2)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_events_to_ics_all_day_and_timed_events() {
+    let events = vec![
+        crate::db::Event {
+            account_id: 1,
+            event_id: 1,
+            start_date: chrono::NaiveDate::from_ymd_opt(2022, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            end_date: chrono::NaiveDate::from_ymd_opt(2022, 1, 7).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            event_name: "New Year, Event".to_string(),
+            description: Some("Line one\nLine two".to_string()),
+        },
+        crate::db::Event {
+            account_id: 1,
+            event_id: 2,
+            start_date: chrono::NaiveDate::from_ymd_opt(2022, 2, 1).unwrap().and_hms_opt(9, 30, 0).unwrap(),
+            end_date: chrono::NaiveDate::from_ymd_opt(2022, 2, 1).unwrap().and_hms_opt(17, 0, 0).unwrap(),
+            event_name: "Ward Round".to_string(),
+            description: None,
+        },
+    ];
+
+    let ics = super::event_api::events_to_ics(&events);
+
+    assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert!(ics.ends_with("END:VCALENDAR\r\n"));
+    assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+    assert!(ics.contains("DTSTART;VALUE=DATE:20220101\r\n"));
+    assert!(ics.contains("DTEND;VALUE=DATE:20220108\r\n"));
+    assert!(ics.contains("DTSTART:20220201T093000Z\r\n"));
+    assert!(ics.contains("DTEND:20220201T170000Z\r\n"));
+    assert!(ics.contains("SUMMARY:New Year\\, Event\r\n"));
+    assert!(ics.contains("DESCRIPTION:Line one\\nLine two\r\n"));
+    assert!(ics.lines().all(|line| line.len() <= 75));
+}
+
+#[test]
+fn test_events_ics_seeded_event_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get(uri!(super::events_ics)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(rocket::http::ContentType::Calendar));
+
+    let body = response.into_string().unwrap();
+    assert!(body.contains("BEGIN:VEVENT"));
+    assert!(body.contains("SUMMARY:New Year Event"));
+}
+
+#[test]
+fn test_remove_event_regular_user_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .delete(uri!(super::remove_event(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_update_event_invalid_session_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let body = super::event_api::UpdateEvent {
+        event_id: 1,
+        start_date: "2022-01-01 00:00:00".to_string(),
+        end_date: "2022-01-07 00:00:00".to_string(),
+        event_name: "Hijacked".to_string(),
+        description: None,
+    };
+
+    let response = client_binding
+        .put(uri!(super::update_event))
+        .header(crate::tests::bearer("not_a_session_token"))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
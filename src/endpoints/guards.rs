@@ -0,0 +1,438 @@
+//! # Request Guards
+//! Guards that resolve the account making a request from its session token.
+//! The token is sent as `Authorization: Bearer <token>`, or in a
+//! `session_token` cookie.
+//!
+//! Also home to the guards for the note version a client expects to be
+//! updating, used to stop one edit silently overwriting another, the
+//! idempotency key that stops a retried request adding a note twice, and the
+//! client details recorded against new sessions.
+//!
+//! Guards fail with an `ApiErrors`, so endpoints take them as
+//! `Result<Guard, ApiErrors>` and return the error with `?` to keep the
+//! usual JSON error body.
+
+use std::net::IpAddr;
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_db_pools::{sqlx, Connection};
+
+use crate::db::SPS;
+use crate::endpoints::errors::{ApiErrors, ApiResult, ErrorCode};
+
+/// Role given to accounts allowed to manage shared content
+pub const ADMIN_ROLE: &str = "admin";
+
+/// Seconds a session has to go unused before a request renews it, so a busy
+/// client doesn't write to tblSessionToken on every request
+const SESSION_RENEW_INTERVAL_SECONDS: i64 = 60;
+
+/// Account a valid session token belongs to
+struct SessionAccount {
+    session_id: i32,
+    account_id: i32,
+    role: String,
+}
+
+/// ## Resolve the account for a request
+///
+/// Reads the session token and checks it against the unexpired sessions.
+/// Using a session pushes its expiry out by another session lifetime, the
+/// same as authenticating it, at most once every
+/// `SESSION_RENEW_INTERVAL_SECONDS`
+async fn session_account(request: &Request<'_>) -> Result<SessionAccount, ApiErrors> {
+    let header_token = request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(|token| token.to_string());
+    let cookie_token = || {
+        request
+            .cookies()
+            .get("session_token")
+            .map(|cookie| cookie.value().to_string())
+    };
+
+    let token = match header_token.or_else(cookie_token) {
+        Some(val) => val,
+        None => {
+            return Err(ApiErrors::Unauthorized("No session token provided".to_string())
+                .with_code(ErrorCode::SessionRequired))
+        }
+    };
+
+    let mut db_conn = match request.guard::<Connection<SPS>>().await {
+        Outcome::Success(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        _ => {
+            return Err(ApiErrors::InternalError(
+                "Unable to connect to the database".to_string(),
+            ))
+        }
+    };
+
+    let now = chrono::Utc::now().naive_utc();
+    let session = match sqlx::query!(
+        "SELECT tblSessionToken.session_token_id, tblSessionToken.last_active, tblAccount.account_id, tblAccount.role FROM tblSessionToken JOIN tblAccount USING (account_id) WHERE tblSessionToken.token = ? AND tblSessionToken.expires_at > ?",
+        token,
+        now
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::Unauthorized("Session Token not found".to_string())
+                .with_code(ErrorCode::SessionNotFound))
+        }
+    };
+
+    // The request goes ahead even if the renewal can't be saved, the session
+    // is still valid until its current expiry
+    if now.signed_duration_since(session.last_active)
+        >= chrono::Duration::seconds(SESSION_RENEW_INTERVAL_SECONDS)
+    {
+        if let Err(e) = sqlx::query!(
+            "UPDATE tblSessionToken SET expires_at = ?, last_active = ? WHERE session_token_id = ?",
+            now + crate::endpoints::auth::session_ttl().await,
+            now,
+            session.session_token_id
+        )
+        .execute(&mut *db_conn)
+        .await
+        {
+            log::error!(
+                "Unable to renew session {}: {}",
+                session.session_token_id,
+                e
+            );
+        }
+    }
+
+    Ok(SessionAccount {
+        session_id: session.session_token_id,
+        account_id: session.account_id,
+        role: session.role,
+    })
+}
+
+/// ## Administrator account guard
+///
+/// Only lets through requests made with a session of an admin account
+pub struct AdminAccount {
+    pub account_id: i32,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAccount {
+    type Error = ApiErrors;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match session_account(request).await {
+            Ok(account) if account.role == ADMIN_ROLE => Outcome::Success(AdminAccount {
+                account_id: account.account_id,
+            }),
+            Ok(_) => Outcome::Failure((
+                Status::Forbidden,
+                ApiErrors::Forbidden("Only administrators can do this".to_string())
+                    .with_code(ErrorCode::AdminRequired),
+            )),
+            Err(e) => Outcome::Failure((Status::Unauthorized, e)),
+        }
+    }
+}
+
+/// ## Authenticated account guard
+///
+/// Lets through requests made with any valid session, and resolves the
+/// account the session belongs to
+pub struct AuthAccount {
+    pub account_id: i32,
+    /// ID of the session the request was made with
+    pub session_id: i32,
+}
+
+impl AuthAccount {
+    /// Only allow acting on the account the session belongs to
+    pub fn check_account(&self, account_id: i32) -> ApiResult<()> {
+        match self.account_id == account_id {
+            true => Ok(()),
+            false => {
+                Err(ApiErrors::Forbidden("Session does not belong to this account".to_string())
+                    .with_code(ErrorCode::SessionNotOwned))
+            }
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthAccount {
+    type Error = ApiErrors;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match session_account(request).await {
+            Ok(account) => Outcome::Success(AuthAccount {
+                account_id: account.account_id,
+                session_id: account.session_id,
+            }),
+            Err(e) => Outcome::Failure((Status::Unauthorized, e)),
+        }
+    }
+}
+
+/// ## Expected note version guard
+///
+/// Version of a note the client last read, sent as `If-Match: <version>`.
+/// Updates made against an older version are refused
+pub struct NoteVersion(pub i32);
+
+impl NoteVersion {
+    /// Refuse the update if the note has changed since the client read it
+    pub fn check(&self, version: i32) -> ApiResult<()> {
+        match self.0 == version {
+            true => Ok(()),
+            false => Err(ApiErrors::Conflict(format!(
+                "Note has changed since version {} was read, fetch it again before updating",
+                self.0
+            ))
+            .with_code(ErrorCode::NoteVersionMismatch)),
+        }
+    }
+}
+
+/// Read a note version from an `If-Match` header, quoted or not
+fn parse_note_version(header: &str) -> ApiResult<i32> {
+    match header.trim().trim_matches('"').parse::<i32>() {
+        Ok(val) => Ok(val),
+        Err(_) => Err(ApiErrors::BadRequest(format!(
+            "Invalid note version: {}",
+            header
+        ))),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for NoteVersion {
+    type Error = ApiErrors;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let header = match request.headers().get_one("If-Match") {
+            Some(val) => val,
+            None => {
+                return Outcome::Failure((
+                    Status::BadRequest,
+                    ApiErrors::BadRequest(
+                        "The note version must be sent in an If-Match header".to_string(),
+                    ),
+                ))
+            }
+        };
+
+        match parse_note_version(header) {
+            Ok(val) => Outcome::Success(NoteVersion(val)),
+            Err(e) => Outcome::Failure((Status::BadRequest, e)),
+        }
+    }
+}
+
+/// Longest idempotency key accepted, matching the column in tblIdempotencyKeys
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// ## Idempotency key guard
+///
+/// Key a client sends as `Idempotency-Key` so that retrying a request it
+/// isn't sure went through doesn't repeat it. Requests without one are simply
+/// not idempotent
+pub struct IdempotencyKey(pub Option<String>);
+
+/// Check an `Idempotency-Key` header is usable as a key
+fn parse_idempotency_key(header: &str) -> ApiResult<String> {
+    let key = header.trim();
+    match key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+        true => Err(ApiErrors::BadRequest(format!(
+            "Idempotency-Key must be between 1 and {} characters",
+            MAX_IDEMPOTENCY_KEY_LENGTH
+        ))),
+        false => Ok(key.to_string()),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IdempotencyKey {
+    type Error = ApiErrors;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("Idempotency-Key") {
+            Some(header) => match parse_idempotency_key(header) {
+                Ok(key) => Outcome::Success(IdempotencyKey(Some(key))),
+                Err(e) => Outcome::Failure((Status::BadRequest, e)),
+            },
+            None => Outcome::Success(IdempotencyKey(None)),
+        }
+    }
+}
+
+/// Longest user agent kept, matching the column in tblSessionToken
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// ## Client info guard
+///
+/// The IP address and `User-Agent` of the client making a request, either of
+/// which may be unknown. Never fails, so endpoints take it directly
+pub struct ClientInfo {
+    pub ip_address: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+/// ## Find the client's IP address
+///
+/// Starts from the address the request came from. While that is one of the
+/// `trusted` proxies, the last address it added to `X-Forwarded-For` is taken
+/// as the next hop back. Addresses added by anyone else could be made up, so
+/// the first untrusted address reached is the client
+fn forwarded_client_ip(
+    remote: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted: &[IpAddr],
+) -> Option<IpAddr> {
+    let mut hops = forwarded_for
+        .unwrap_or_default()
+        .split(',')
+        .map(|hop| hop.trim())
+        .filter(|hop| !hop.is_empty())
+        .rev();
+
+    let mut client = remote?;
+    while trusted.contains(&client) {
+        match hops.next().map(|hop| hop.parse::<IpAddr>()) {
+            Some(Ok(val)) => client = val,
+            // A proxy passing on garbage leaves the proxy as the best guess
+            Some(Err(_)) | None => break,
+        }
+    }
+
+    Some(client)
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = ApiErrors;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // Checked when the settings are loaded, so anything unparsable is skipped
+        let trusted: Vec<IpAddr> = crate::SETTINGS
+            .read()
+            .await
+            .get::<Vec<String>>("trusted_proxies")
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|proxy| proxy.parse().ok())
+            .collect();
+
+        let ip_address = forwarded_client_ip(
+            request.remote().map(|remote| remote.ip()),
+            request.headers().get_one("X-Forwarded-For"),
+            &trusted,
+        );
+        let user_agent = request
+            .headers()
+            .get_one("User-Agent")
+            .map(|val| val.trim())
+            .filter(|val| !val.is_empty())
+            .map(|val| val.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+        Outcome::Success(ClientInfo {
+            ip_address,
+            user_agent,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+
+    #[test]
+    fn test_check_account_same_account_ok() {
+        let auth = super::AuthAccount {
+            account_id: 1,
+            session_id: 1,
+        };
+        assert!(auth.check_account(1).is_ok());
+    }
+
+    #[test]
+    fn test_check_account_other_account_forbidden() {
+        let auth = super::AuthAccount {
+            account_id: 1,
+            session_id: 1,
+        };
+        let error = auth.check_account(2).unwrap_err();
+        assert_eq!(error.status(), Status::Forbidden);
+        assert_eq!(
+            crate::endpoints::errors::ErrorBody::from(error).code,
+            super::ErrorCode::SessionNotOwned
+        );
+    }
+
+    #[test]
+    fn test_parse_note_version_quoted_and_bare_ok() {
+        assert_eq!(super::parse_note_version("3").unwrap(), 3);
+        assert_eq!(super::parse_note_version("\"3\"").unwrap(), 3);
+        assert!(super::parse_note_version("latest").is_err());
+    }
+
+    #[test]
+    fn test_parse_idempotency_key_bounds() {
+        assert_eq!(super::parse_idempotency_key(" retry-1 ").unwrap(), "retry-1");
+        assert!(super::parse_idempotency_key("   ").is_err());
+        assert!(super::parse_idempotency_key(&"k".repeat(256)).is_err());
+    }
+
+    #[test]
+    fn test_note_version_stale_conflict() {
+        assert!(super::NoteVersion(2).check(2).is_ok());
+        let error = super::NoteVersion(1).check(2).unwrap_err();
+        assert_eq!(error.status(), Status::Conflict);
+        assert_eq!(
+            crate::endpoints::errors::ErrorBody::from(error).code,
+            super::ErrorCode::NoteVersionMismatch
+        );
+    }
+
+    #[test]
+    fn test_forwarded_client_ip_untrusted_remote_header_ignored() {
+        let remote = "203.0.113.7".parse().ok();
+        assert_eq!(
+            super::forwarded_client_ip(remote, Some("198.51.100.1"), &[]),
+            remote
+        );
+    }
+
+    #[test]
+    fn test_forwarded_client_ip_trusted_proxies_skipped() {
+        let proxies = ["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+        // The client made up the first entry, the proxies added the rest
+        let forwarded_for = "192.0.2.99, 198.51.100.1, 10.0.0.2";
+
+        assert_eq!(
+            super::forwarded_client_ip("10.0.0.1".parse().ok(), Some(forwarded_for), &proxies),
+            "198.51.100.1".parse().ok()
+        );
+    }
+
+    #[test]
+    fn test_forwarded_client_ip_missing_or_bad_header_proxy_kept() {
+        let proxy = "10.0.0.1".parse().unwrap();
+        assert_eq!(
+            super::forwarded_client_ip(Some(proxy), None, &[proxy]),
+            Some(proxy)
+        );
+        assert_eq!(
+            super::forwarded_client_ip(Some(proxy), Some("not an ip"), &[proxy]),
+            Some(proxy)
+        );
+        assert_eq!(super::forwarded_client_ip(None, Some("198.51.100.1"), &[proxy]), None);
+    }
+}
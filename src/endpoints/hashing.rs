@@ -0,0 +1,178 @@
+//! # Hashing
+//! Salted hashing of secrets before they are stored in the database
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use crypto::digest::Digest;
+use crypto::scrypt::scrypt_check;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+
+use crate::endpoints::errors::{ApiErrors, ApiResult};
+
+/// Prefix of every hash made by `hash_secret`
+const HASH_PREFIX: &str = "$argon2id$";
+
+/// Prefix of the scrypt hashes made before secrets were hashed with argon2
+const SCRYPT_PREFIX: &str = "$rscrypt$";
+
+/// ## Hash a secret
+///
+/// Produces a salted argon2id hash as a PHC string, which embeds its own
+/// parameters so it can be checked later with just the secret
+pub fn hash_secret(secret: &str) -> ApiResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    match Argon2::default().hash_password(secret.as_bytes(), &salt) {
+        Ok(val) => Ok(val.to_string()),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => Err(ApiErrors::InternalError(
+            "Failed to hash secret".to_string(),
+        )),
+    }
+}
+
+/// ## Verify a secret
+///
+/// Checks the secret against a hash made by `hash_secret`, or an older scrypt
+/// hash. A malformed hash never matches
+pub fn verify_secret(secret: &str, hashed: &str) -> bool {
+    if hashed.starts_with(SCRYPT_PREFIX) {
+        return scrypt_check(secret, hashed).unwrap_or(false);
+    }
+
+    match PasswordHash::new(hashed) {
+        Ok(val) => Argon2::default()
+            .verify_password(secret.as_bytes(), &val)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// ## Hash a password
+pub fn hash_password(password: &str) -> ApiResult<String> {
+    hash_secret(password)
+}
+
+/// ## Verify a password
+///
+/// Accounts from before passwords were hashed still hold the password exactly
+/// as the client sent it. Those are compared directly until `needs_rehash`
+/// has them replaced with a hash on the next successful login
+pub fn verify_password(password: &str, stored: &str) -> bool {
+    match stored.starts_with(HASH_PREFIX) || stored.starts_with(SCRYPT_PREFIX) {
+        true => verify_secret(password, stored),
+        false => {
+            !stored.is_empty() && fixed_time_eq(password.as_bytes(), stored.as_bytes())
+        }
+    }
+}
+
+/// ## Whether a stored secret predates argon2 hashing
+///
+/// True for plain passwords and for scrypt hashes, both of which get replaced
+/// with an argon2 hash once the secret is known to be correct
+pub fn needs_rehash(stored: &str) -> bool {
+    !stored.starts_with(HASH_PREFIX)
+}
+
+/// ## Normalize a security question answer
+///
+/// Answers are compared ignoring surrounding whitespace and capitalization
+fn normalize_answer(answer: &str) -> String {
+    answer.trim().to_lowercase()
+}
+
+/// ## Hash a security question answer
+pub fn hash_answer(answer: &str) -> ApiResult<String> {
+    hash_secret(&normalize_answer(answer))
+}
+
+/// ## Verify a security question answer
+///
+/// Answers set before they were salted are stored as the SHA-256 of the
+/// answer in hex. Those are checked the same way until `needs_rehash` has them
+/// replaced with a hash on the next successful password reset
+pub fn verify_answer(answer: &str, stored: &str) -> bool {
+    let answer = normalize_answer(answer);
+
+    match stored.starts_with(HASH_PREFIX) || stored.starts_with(SCRYPT_PREFIX) {
+        true => verify_secret(&answer, stored),
+        false => {
+            let mut hasher = Sha256::new();
+            hasher.input_str(&answer);
+            fixed_time_eq(hasher.result_str().as_bytes(), stored.to_lowercase().as_bytes())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_verify_answer_correct_answer_passes() {
+        let hashed = super::hash_answer("jeffrey").unwrap();
+        assert_ne!(hashed, "jeffrey");
+        assert!(super::verify_answer("jeffrey", &hashed));
+    }
+
+    #[test]
+    fn test_verify_answer_different_case_and_whitespace_passes() {
+        let hashed = super::hash_answer("Manchester United").unwrap();
+        assert!(super::verify_answer("  MANCHESTER united ", &hashed));
+    }
+
+    #[test]
+    fn test_verify_answer_wrong_answer_fails() {
+        let hashed = super::hash_answer("jeffrey").unwrap();
+        assert!(!super::verify_answer("geoffrey", &hashed));
+    }
+
+    #[test]
+    fn test_verify_answer_legacy_sha256() {
+        let stored = "a459891617d735655dcfed3e37db66fa07f0175866ebf35f9de8ccc59c0840bb";
+
+        assert!(super::needs_rehash(stored));
+        assert!(super::verify_answer(" Jeffrey", stored));
+        assert!(!super::verify_answer("geoffrey", stored));
+        assert!(!super::verify_answer(stored, stored));
+    }
+
+    #[test]
+    fn test_verify_secret_malformed_hash_fails() {
+        assert!(!super::verify_secret("jeffrey", "jeffrey"));
+    }
+
+    #[test]
+    fn test_verify_password_hash_round_trip() {
+        let hashed = super::hash_password("password_1").unwrap();
+
+        assert!(hashed.starts_with("$argon2id$v=19$"));
+        assert!(!super::needs_rehash(&hashed));
+        assert!(super::verify_password("password_1", &hashed));
+    }
+
+    #[test]
+    fn test_verify_password_wrong_password_rejected() {
+        let hashed = super::hash_password("password_1").unwrap();
+        assert!(!super::verify_password("password_2", &hashed));
+        assert!(!super::verify_password("Password_1", &hashed));
+    }
+
+    #[test]
+    fn test_verify_password_legacy_plaintext() {
+        assert!(super::needs_rehash("password_1"));
+        assert!(super::verify_password("password_1", "password_1"));
+        assert!(!super::verify_password("password_2", "password_1"));
+        assert!(!super::verify_password("", ""));
+    }
+
+    #[test]
+    fn test_verify_password_legacy_scrypt() {
+        let hashed = "$rscrypt$0$DggB$b/uzVqyTgNC6VkDzBH8NxA==$Eo2gMli3B8bu6CHvKgRwwfiP0bAOGCJRFm/Kz5ep+KI=$";
+
+        assert!(super::needs_rehash(hashed));
+        assert!(super::verify_password("jeffrey", hashed));
+        assert!(!super::verify_password("geoffrey", hashed));
+    }
+}
@@ -4,280 +4,3358 @@
 #[cfg(test)]
 mod tests;
 
+mod export;
 mod note_api;
+mod protocol_api;
 
+use std::collections::HashSet;
+
+use chrono::Timelike;
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use rocket::data::{Data, ToByteUnit};
+use rocket::futures::StreamExt;
+use rocket::http::ContentType;
+use rocket::response::stream::ByteStream;
 use rocket::serde::json::Json;
 use rocket_db_pools::{sqlx, Connection};
+use sqlx::Acquire;
+
+use crate::db::{self, retry_transient, SPS};
+use crate::endpoints::account::{JPEG_SIGNATURE, PNG_SIGNATURE};
+use crate::endpoints::audit::{self, AuditAction};
+use crate::endpoints::conditional::{
+    self, Conditional, IfModifiedSince, IfNoneMatch, LastModified, ModifiedSince,
+};
+use crate::endpoints::errors::{ApiErrors, ApiResult, ErrorBody, ErrorCode};
+use crate::endpoints::guards::{AdminAccount, AuthAccount, IdempotencyKey, NoteVersion};
+use crate::endpoints::params;
+
+/// Maximum note size used when `max_note_bytes` is not set in config.toml.
+/// Kept under the default `max_json_body_bytes` so the check below is what rejects large notes
+const DEFAULT_MAX_NOTE_BYTES: i64 = 512 * 1024;
+
+/// Bytes read from a streamed note upload at a time
+const STREAM_CHUNK_BYTES: usize = 8 * 1024;
+
+/// Storage quota used when `storage_quota_bytes` is not set in config.toml, 50 MiB
+const DEFAULT_STORAGE_QUOTA_BYTES: i64 = 50 * 1024 * 1024;
+
+/// How long in seconds an idempotency key is remembered for when
+/// `idempotency_key_ttl_seconds` is not set in config.toml, 1 day
+const DEFAULT_IDEMPOTENCY_KEY_TTL_SECONDS: i64 = 24 * 60 * 60;
+
+/// Longest tag that can be put on a note, matching the tag column in tblNoteTags
+const MAX_TAG_LENGTH: usize = 64;
+
+/// Most note IDs that can be fetched in one batch request
+const MAX_BATCH_NOTE_IDS: usize = 100;
+
+/// ## Normalize a note tag
+///
+/// Tags are trimmed and lowercased so "Cardiology " and "cardiology" are the
+/// same tag. Empty and overly long tags are rejected
+fn normalize_tag(tag: &str) -> ApiResult<String> {
+    let tag = tag.trim().to_lowercase();
+
+    if tag.is_empty() {
+        return Err(ApiErrors::BadRequest("Tag can not be empty".to_string()));
+    }
+
+    if tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(ApiErrors::BadRequest(format!(
+            "Tag can be at most {} characters",
+            MAX_TAG_LENGTH
+        )));
+    }
+
+    Ok(tag)
+}
+
+/// Maximum attachment size used when `max_attachment_bytes` is not set in config.toml
+const DEFAULT_MAX_ATTACHMENT_BYTES: i64 = 5 * 1024 * 1024;
+
+/// First bytes of every PDF document
+const PDF_SIGNATURE: &[u8] = b"%PDF-";
+
+/// Longest attachment file name, matching the file_name column in tblNoteAttachments
+const MAX_FILE_NAME_LENGTH: usize = 255;
+
+/// First bytes an attachment of the given type must start with, or None if
+/// files of that type can't be attached to notes
+fn attachment_signature(content_type: &ContentType) -> Option<&'static [u8]> {
+    if content_type == &ContentType::PNG {
+        Some(PNG_SIGNATURE)
+    } else if content_type == &ContentType::JPEG {
+        Some(JPEG_SIGNATURE)
+    } else if content_type == &ContentType::PDF {
+        Some(PDF_SIGNATURE)
+    } else {
+        None
+    }
+}
+
+/// ## Normalize an attachment file name
+///
+/// Only the last path segment is kept, so a name sent by a browser as a full
+/// path doesn't leak the uploader's folders. Empty and overly long names are
+/// rejected
+fn normalize_file_name(file_name: &str) -> ApiResult<String> {
+    let file_name = file_name
+        .rsplit(|c| c == '/' || c == '\\')
+        .next()
+        .unwrap_or_default()
+        .trim();
+
+    if file_name.is_empty() {
+        return Err(ApiErrors::BadRequest("File name can not be empty".to_string()));
+    }
+
+    if file_name.chars().count() > MAX_FILE_NAME_LENGTH {
+        return Err(ApiErrors::BadRequest(format!(
+            "File name can be at most {} characters",
+            MAX_FILE_NAME_LENGTH
+        )));
+    }
+
+    Ok(file_name.to_string())
+}
+
+/// Longest protocol category, matching the category column in tblProtocol
+const MAX_CATEGORY_LENGTH: usize = 64;
+
+/// ## Normalize a protocol category
+///
+/// Categories are trimmed and lowercased like tags, so "Fire" and "fire" are
+/// listed together. A blank category leaves the protocol uncategorised
+fn normalize_category(category: Option<&str>) -> ApiResult<Option<String>> {
+    let category = match category.map(|val| val.trim().to_lowercase()) {
+        Some(val) if !val.is_empty() => val,
+        _ => return Ok(None),
+    };
+
+    if category.chars().count() > MAX_CATEGORY_LENGTH {
+        return Err(ApiErrors::BadRequest(format!(
+            "Category can be at most {} characters",
+            MAX_CATEGORY_LENGTH
+        )));
+    }
+
+    Ok(Some(category))
+}
+
+/// Longest note title. The title column holds 255 characters, which leaves
+/// room for the suffix added to duplicate titles
+const MAX_TITLE_LENGTH: usize = 200;
+
+/// ## Validate a note title
+///
+/// Titles end up as file names when notes are exported, so they can't be
+/// overly long or contain control characters or path separators
+fn validate_note_title(title: &str) -> ApiResult<()> {
+    if title.chars().count() > MAX_TITLE_LENGTH {
+        return Err(ApiErrors::BadRequest(format!(
+            "Note title can be at most {} characters",
+            MAX_TITLE_LENGTH
+        )));
+    }
+
+    if title.chars().any(|c| c.is_control()) {
+        return Err(ApiErrors::BadRequest(
+            "Note title can not contain control characters".to_string(),
+        ));
+    }
+
+    if title.contains('/') || title.contains('\\') {
+        return Err(ApiErrors::BadRequest(
+            "Note title can not contain slashes".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// ## Validate note content
+///
+/// Checks that the content of a note is no larger than the
+/// `max_note_bytes` setting, and that it is plain text rather than
+/// binary data smuggled through as a string.
+async fn validate_note_content(content: &String) -> ApiResult<()> {
+    let max_note_bytes = crate::SETTINGS
+        .read()
+        .await
+        .get_int("max_note_bytes")
+        .unwrap_or(DEFAULT_MAX_NOTE_BYTES);
+
+    if content.len() as i64 > max_note_bytes {
+        return Err(ApiErrors::PayloadTooLarge(format!(
+            "Note content exceeds the maximum size of {} bytes",
+            max_note_bytes
+        )));
+    }
+
+    // JSON strings are always valid UTF-8, but they can still carry null bytes and other
+    // control characters that have no place in a markdown file
+    if content
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+    {
+        return Err(ApiErrors::BadRequest(
+            "Note content must be plain text markdown".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// ## Check an account's storage quota
+///
+/// Fails if `extra_bytes` more note content would take the account past its
+/// quota, which is its own storage_quota_bytes if it has one and otherwise
+/// `storage_quota_bytes` from config.toml. Shrinking notes is always allowed
+async fn check_storage_quota(
+    bytes_used: i64,
+    account_quota: Option<i64>,
+    extra_bytes: i64,
+) -> ApiResult<()> {
+    let quota = match account_quota {
+        Some(val) => val,
+        None => crate::SETTINGS
+            .read()
+            .await
+            .get_int("storage_quota_bytes")
+            .unwrap_or(DEFAULT_STORAGE_QUOTA_BYTES),
+    };
+
+    if extra_bytes > 0 && bytes_used + extra_bytes > quota {
+        return Err(ApiErrors::PayloadTooLarge(format!(
+            "Note would take the account past its storage quota of {} bytes",
+            quota
+        )));
+    }
+
+    Ok(())
+}
+
+/// ## Check an account's note limit
+///
+/// Fails if the account already has `max_notes_per_account` notes outside
+/// the trash. Without the setting accounts can have any number of notes
+async fn check_note_limit<'c>(
+    executor: impl sqlx::MySqlExecutor<'c>,
+    account_id: i32,
+) -> ApiResult<()> {
+    let max_notes = match crate::SETTINGS.read().await.get_int("max_notes_per_account") {
+        Ok(val) => val,
+        Err(_) => return Ok(()),
+    };
+
+    let note_count = match sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM tblNotes WHERE account_id = ? AND deleted_at IS NULL",
+        account_id
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to count notes".to_string(),
+            ))
+        }
+    };
+
+    if note_count >= max_notes {
+        return Err(ApiErrors::Conflict(format!(
+            "Account has reached its limit of {} notes",
+            max_notes
+        ))
+        .with_code(ErrorCode::NoteLimitReached));
+    }
+
+    Ok(())
+}
+
+/// ## Recompute an account's storage usage
+///
+/// Sets bytes_used to the size of every note the account has outside the
+/// trash. Run after anything that adds, resizes, removes or restores notes.
+/// The note change has already been made by then, so a failure is only
+/// logged, and the total is put right by the next change
+async fn recompute_bytes_used<'c>(executor: impl sqlx::MySqlExecutor<'c>, account_id: i32) {
+    if let Err(e) = sqlx::query!(
+        "UPDATE tblAccount SET bytes_used = (SELECT COALESCE(SUM(LENGTH(content)), 0) FROM tblNotes WHERE tblNotes.account_id = ? AND deleted_at IS NULL) WHERE account_id = ?",
+        account_id,
+        account_id
+    )
+    .execute(executor)
+    .await
+    {
+        log::error!("Unable to update storage usage for account {}: {}", account_id, e);
+    }
+}
+
+/// ## Note checks
+///
+/// Runs the checks on a new note. Normally the first failing check is the
+/// response, but when only validating every failure is kept for the report
+struct Checks {
+    validate_only: bool,
+    errors: Vec<ErrorBody>,
+}
+
+impl Checks {
+    fn new(validate_only: bool) -> Self {
+        Checks {
+            validate_only,
+            errors: Vec::new(),
+        }
+    }
+
+    /// The value of a passing check. A failing check is returned as the
+    /// error, or when only validating kept and given back as None
+    fn check<T>(&mut self, result: ApiResult<T>) -> ApiResult<Option<T>> {
+        match result {
+            Ok(val) => Ok(Some(val)),
+            Err(e) if self.validate_only => {
+                self.errors.push(e.into());
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Report of every check that failed, for a note that would be saved as `note_title`
+    fn into_report(self, note_title: String) -> note_api::AddNoteResponse {
+        note_api::AddNoteResponse::Validated(Json(note_api::NoteValidation {
+            valid: self.errors.is_empty(),
+            note_title,
+            errors: self.errors,
+        }))
+    }
+}
+
+/// What to do when a new note has the same title as one the account already has
+#[derive(Debug, PartialEq)]
+enum DuplicateTitles {
+    /// Keep both notes with the same title
+    Allow,
+    /// Refuse to add the note
+    Reject,
+    /// Add the note with a numbered title, like `Title (2)`
+    Suffix,
+}
+
+/// ## Pick how duplicate note titles are handled
+///
+/// Uses the `on_duplicate` query flag when given, otherwise the
+/// `duplicate_note_titles` setting, defaulting to allowing duplicates
+async fn duplicate_titles(on_duplicate: Option<String>) -> ApiResult<DuplicateTitles> {
+    let choice = match on_duplicate {
+        Some(val) => val,
+        None => crate::SETTINGS
+            .read()
+            .await
+            .get_string("duplicate_note_titles")
+            .unwrap_or("allow".to_string()),
+    };
+
+    match choice.to_lowercase().as_str() {
+        "allow" => Ok(DuplicateTitles::Allow),
+        "reject" => Ok(DuplicateTitles::Reject),
+        "suffix" => Ok(DuplicateTitles::Suffix),
+        _ => Err(ApiErrors::BadRequest(
+            "Duplicate titles can only be allowed, rejected, or suffixed".to_string(),
+        )),
+    }
+}
+
+/// First `Title (n)` not already taken, ignoring case like MySQL does
+fn suffixed_title(title: &str, taken: &[String]) -> String {
+    let taken: Vec<String> = taken.iter().map(|val| val.to_lowercase()).collect();
+
+    (2..)
+        .map(|count| format!("{} ({})", title, count))
+        .find(|candidate| !taken.contains(&candidate.to_lowercase()))
+        .unwrap()
+}
+
+/// Titles of an account's notes that a new note called `title` would clash
+/// with, being the title itself and any numbered copies of it
+async fn taken_titles<'c>(
+    executor: impl sqlx::MySqlExecutor<'c>,
+    account_id: i32,
+    title: &str,
+) -> ApiResult<Vec<String>> {
+    match sqlx::query_scalar!(
+        "SELECT title FROM tblNotes WHERE account_id = ? AND deleted_at IS NULL AND (title = ? OR title LIKE ?)",
+        account_id,
+        title,
+        format!("{} (%)", params::escape_like(title))
+    )
+    .fetch_all(executor)
+    .await
+    {
+        Ok(val) => Ok(val),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => Err(ApiErrors::InternalError(
+            "Unable to check for duplicate titles".to_string(),
+        )),
+    }
+}
+
+/// ## Fetch Emergency Protocols
+///
+/// Return the emergency protocols stored in the database, sorted by ID. A
+/// category only keeps protocols in that category. Without a limit every
+/// protocol is returned, up to the maximum page size
+///
+/// A query only keeps protocols whose title or content matches it, ignoring
+/// case. Search results are sorted by relevance, most relevant first, and
+/// each one comes with its score
+///
+/// Last-Modified is the last time any protocol was added, changed or
+/// removed, and a client sending it back in If-Modified-Since gets a 304 until one does
+///
+/// ### Arguments
+///
+/// * query (optional query)
+/// * category (optional query)
+/// * limit (optional query, defaults to and at most 500)
+/// * offset (optional query, defaults to 0)
+/// * Last modified (optional If-Modified-Since header)
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 304 Not Modified
+/// * 400 Bad Request
+/// * 404 Not Found
+#[get("/notes/protocols?<query>&<category>&<limit>&<offset>")]
+pub async fn fetch_protocols(
+    query: Option<String>,
+    category: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    if_modified_since: IfModifiedSince,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<ModifiedSince<Json<Vec<protocol_api::ProtocolResult>>>> {
+    let limit = params::parse_limit(Some(limit.unwrap_or(params::MAX_LIMIT)))?;
+    let offset = offset.unwrap_or(0);
+    let category = normalize_category(category.as_deref())?;
+
+    let last_modified = match retry_transient!(
+        sqlx::query_scalar!(
+            "SELECT MAX(changed_at) FROM (SELECT updated_at AS changed_at FROM tblProtocol UNION ALL SELECT removed_at FROM tblProtocolRemovals) AS changes"
+        )
+        .fetch_one(&mut *db_conn)
+    ) {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to fetch protocols".to_string(),
+            ))
+        }
+    };
+
+    if let Some(val) = last_modified.filter(|val| if_modified_since.unchanged_since(val)) {
+        return Ok(ModifiedSince::NotModified(val));
+    }
+
+    if let Some(query) = query {
+        let protocols = search_protocols(&mut db_conn, &query, category, limit, offset).await?;
+        return Ok(ModifiedSince::Fresh(Json(protocols), last_modified));
+    }
+
+    let db_protocols = match db::timed(async {
+        retry_transient!(
+            sqlx::query_as!(
+                db::Protocol,
+                "SELECT protocol_id, title, content, category FROM tblProtocol WHERE ? IS NULL OR category = ? ORDER BY protocol_id LIMIT ? OFFSET ?",
+                category,
+                category,
+                limit,
+                offset
+            )
+            .fetch_all(&mut *db_conn)
+        )
+    })
+    .await?
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to fetch protocols".to_string(),
+            ))
+        }
+    };
+
+    // A filtered page with nothing in it is still a valid answer
+    if db_protocols.is_empty() && category.is_none() && offset == 0 {
+        return Err(ApiErrors::NotFound("No protocols were found".to_string()));
+    }
+
+    let protocols = db_protocols
+        .into_iter()
+        .map(|protocol| protocol_api::ProtocolResult { protocol, score: None })
+        .collect();
+
+    Ok(ModifiedSince::Fresh(Json(protocols), last_modified))
+}
+
+/// ## Search emergency protocols
+///
+/// Ranks protocols by the full-text relevance of their title and content.
+/// Protocols that only contain the query part way through a word still
+/// match, with a score of 0. Without a FULLTEXT index, a title match scores 2
+/// and a content match 1
+async fn search_protocols(
+    db_conn: &mut Connection<SPS>,
+    query: &str,
+    category: Option<String>,
+    limit: u32,
+    offset: u32,
+) -> ApiResult<Vec<protocol_api::ProtocolResult>> {
+    let pattern = format!("%{}%", params::escape_like(&query.to_lowercase()));
+
+    let ranked = retry_transient!(
+        sqlx::query!(
+            "SELECT protocol_id, title, content, category, MATCH (title, content) AGAINST (? IN NATURAL LANGUAGE MODE) as `score!: f64` FROM tblProtocol WHERE (MATCH (title, content) AGAINST (? IN NATURAL LANGUAGE MODE) OR LOWER(title) LIKE ? OR LOWER(content) LIKE ?) AND (? IS NULL OR category = ?) ORDER BY MATCH (title, content) AGAINST (? IN NATURAL LANGUAGE MODE) DESC, protocol_id LIMIT ? OFFSET ?",
+            query,
+            query,
+            pattern,
+            pattern,
+            category,
+            category,
+            query,
+            limit,
+            offset
+        )
+        .fetch_all(&mut **db_conn)
+    );
+
+    let protocols = match ranked {
+        Ok(val) => val
+            .into_iter()
+            .map(|row| protocol_api::ProtocolResult {
+                protocol: db::Protocol {
+                    protocol_id: row.protocol_id,
+                    title: row.title,
+                    content: row.content,
+                    category: row.category,
+                },
+                score: Some(row.score),
+            })
+            .collect(),
+        Err(e) if params::fulltext_unavailable(&e) => {
+            match retry_transient!(
+                sqlx::query!(
+                    "SELECT protocol_id, title, content, category, CAST((LOWER(title) LIKE ?) * 2 + COALESCE(LOWER(content) LIKE ?, 0) AS DOUBLE) as `score!: f64` FROM tblProtocol WHERE (LOWER(title) LIKE ? OR LOWER(content) LIKE ?) AND (? IS NULL OR category = ?) ORDER BY 5 DESC, protocol_id LIMIT ? OFFSET ?",
+                    pattern,
+                    pattern,
+                    pattern,
+                    pattern,
+                    category,
+                    category,
+                    limit,
+                    offset
+                )
+                .fetch_all(&mut **db_conn)
+            ) {
+                Ok(val) => val
+                    .into_iter()
+                    .map(|row| protocol_api::ProtocolResult {
+                        protocol: db::Protocol {
+                            protocol_id: row.protocol_id,
+                            title: row.title,
+                            content: row.content,
+                            category: row.category,
+                        },
+                        score: Some(row.score),
+                    })
+                    .collect(),
+                #[cfg(not(tarpaulin_include))]
+                Err(_) => {
+                    return Err(ApiErrors::InternalError(
+                        "Failed to search protocols".to_string(),
+                    ))
+                }
+            }
+        }
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to search protocols".to_string(),
+            ))
+        }
+    };
+
+    Ok(protocols)
+}
+
+/// ## Fetch Protocol Categories
+///
+/// Return every category in use by an emergency protocol, sorted by name
+///
+/// ### Arguments
+///
+/// * None
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+#[get("/notes/protocols/categories")]
+pub async fn fetch_protocol_categories(
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<Vec<String>>> {
+    let categories = match db::timed(async {
+        retry_transient!(
+            sqlx::query_scalar!(
+                "SELECT DISTINCT category as `category!` FROM tblProtocol WHERE category IS NOT NULL ORDER BY category",
+            )
+            .fetch_all(&mut *db_conn)
+        )
+    })
+    .await?
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to fetch protocol categories".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json(categories))
+}
+
+/// ## Fetch an Emergency Protocol
+///
+/// Return a single emergency protocol by its ID
+///
+/// ### Arguments
+///
+/// * Protocol ID
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 404 Not Found
+#[get("/notes/protocols/<protocol_id>")]
+pub async fn fetch_protocol(
+    protocol_id: i32,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<db::Protocol>> {
+    let db_protocol = match retry_transient!(
+        sqlx::query_as!(
+            db::Protocol,
+            "SELECT protocol_id, title, content, category FROM tblProtocol WHERE protocol_id = ?",
+            protocol_id
+        )
+        .fetch_one(&mut *db_conn)
+    ) {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Protocol not found".to_string())
+                .with_code(ErrorCode::ProtocolNotFound))
+        }
+    };
+
+    Ok(Json(db_protocol))
+}
+
+/// ## Add an Emergency Protocol
+///
+/// Add a new emergency protocol, returning the newly created protocol
+///
+/// ### Arguments
+///
+/// * New protocol title, content and category
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+#[post("/notes/protocols", data = "<new_protocol>")]
+pub async fn add_protocol(
+    admin: Result<AdminAccount, ApiErrors>,
+    new_protocol: Json<protocol_api::ProtocolRequest>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<db::Protocol>> {
+    admin?;
+    let category = normalize_category(new_protocol.category.as_deref())?;
+
+    let protocol_id = match sqlx::query!(
+        "INSERT INTO tblProtocol (title, content, category, updated_at) VALUES (?, ?, ?, ?)",
+        new_protocol.title,
+        new_protocol.content,
+        category,
+        chrono::Utc::now().naive_utc(),
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val.last_insert_id() as i32,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to save protocol in database".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json(db::Protocol {
+        protocol_id,
+        title: new_protocol.title.clone(),
+        content: new_protocol.content.clone(),
+        category,
+    }))
+}
 
-use crate::db::{self, SPS};
-use crate::endpoints::errors::{ApiErrors, ApiResult};
+/// ## Update an Emergency Protocol
+///
+/// Replace the title, content and category of an existing emergency protocol
+///
+/// ### Arguments
+///
+/// * Protocol ID
+/// * Updated protocol title, content and category
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[put("/notes/protocols/<protocol_id>", data = "<updated_protocol>")]
+pub async fn update_protocol(
+    admin: Result<AdminAccount, ApiErrors>,
+    protocol_id: i32,
+    updated_protocol: Json<protocol_api::ProtocolRequest>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<()> {
+    admin?;
+    let category = normalize_category(updated_protocol.category.as_deref())?;
+
+    match sqlx::query!(
+        "SELECT protocol_id FROM tblProtocol WHERE protocol_id = ?",
+        protocol_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Protocol not found".to_string())
+                .with_code(ErrorCode::ProtocolNotFound))
+        }
+    }
+
+    match sqlx::query!(
+        "UPDATE tblProtocol SET title = ?, content = ?, category = ?, updated_at = ? WHERE protocol_id = ?",
+        updated_protocol.title,
+        updated_protocol.content,
+        category,
+        chrono::Utc::now().naive_utc(),
+        protocol_id,
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to update the protocol".to_string(),
+            ))
+        }
+    };
+
+    Ok(())
+}
+
+/// ## Delete an Emergency Protocol
+///
+/// ### Arguments
+///
+/// * Protocol ID
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[delete("/notes/protocols/<protocol_id>")]
+pub async fn remove_protocol(
+    admin: Result<AdminAccount, ApiErrors>,
+    protocol_id: i32,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<()> {
+    admin?;
+
+    match sqlx::query!(
+        "SELECT protocol_id FROM tblProtocol WHERE protocol_id = ?",
+        protocol_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Protocol not found".to_string())
+                .with_code(ErrorCode::ProtocolNotFound))
+        }
+    }
+
+    let mut transaction = match (&mut *db_conn).begin().await {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to start a database transaction".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!("DELETE FROM tblProtocol WHERE protocol_id = ?", protocol_id)
+        .execute(&mut transaction)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove protocol from database".to_string(),
+            ))
+        }
+    }
+
+    // A removed protocol has no updated_at left to move Last-Modified on, so
+    // when it went is kept instead, leaving the other protocols untouched
+    match sqlx::query!(
+        "INSERT INTO tblProtocolRemovals (protocol_id, removed_at) VALUES (?, ?) ON DUPLICATE KEY UPDATE removed_at = VALUES(removed_at)",
+        protocol_id,
+        chrono::Utc::now().naive_utc()
+    )
+    .execute(&mut transaction)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove protocol from database".to_string(),
+            ))
+        }
+    }
+
+    match transaction.commit().await {
+        Ok(_) => Ok(()),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => Err(ApiErrors::InternalError(
+            "Unable to remove protocol from database".to_string(),
+        )),
+    }
+}
+
+/// ## Fetch List of Notes
+///
+/// Returns the notes of an account, with their content and size in bytes.
+/// Passing a tag only returns the notes with that tag. An account with no
+/// notes gets an empty list. Notes are in the order set by `reorder_notes`,
+/// with any not yet placed after the rest sorted by ID, and are returned a
+/// page at a time. Without a limit or an envelope every note is returned,
+/// the way the list was before it had pages
+///
+/// ### Arguments
+///
+/// * Account ID
+/// * Tag (optional)
+/// * limit (optional query, defaults to 100 with an envelope, at most 500)
+/// * offset (optional query, defaults to 0)
+/// * envelope (optional query, wraps the page with the total count when true)
+/// * preview (optional query, adds the start of each note when true)
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+/// * 500 Internal Server Error
+#[get("/notes/<account_id>?<tag>&<limit>&<offset>&<envelope>&<preview>")]
+pub async fn fetch_notes(
+    auth: Result<AuthAccount, ApiErrors>,
+    account_id: i32,
+    tag: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    envelope: Option<bool>,
+    preview: Option<bool>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<params::Listing<note_api::NoteResponse>>> {
+    auth?.check_account(account_id)?;
+
+    let tag = match tag {
+        Some(val) => Some(normalize_tag(&val)?),
+        None => None,
+    };
+    let unlimited = limit.is_none() && !envelope.unwrap_or(false);
+    let limit = params::parse_limit(limit)?;
+    let page_size = match unlimited {
+        true => u64::MAX,
+        false => limit as u64,
+    };
+    let offset = offset.unwrap_or(0);
+
+    // Checking the user account actually exists
+    match sqlx::query!(
+        "SELECT account_id FROM tblAccount WHERE account_id = ?",
+        account_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(ApiErrors::NotFound("User account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to fetch the account".to_string(),
+            ))
+        }
+    }
+
+    let db_notes = match db::timed(async {
+        retry_transient!(
+            sqlx::query_as!(
+                db::Note,
+                "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE account_id = ? AND deleted_at IS NULL AND (? IS NULL OR EXISTS (SELECT 1 FROM tblNoteTags WHERE tblNoteTags.note_id = tblNotes.note_id AND tblNoteTags.tag = ?)) ORDER BY sort_position IS NULL, sort_position, note_id LIMIT ? OFFSET ?",
+                account_id,
+                tag,
+                tag,
+                page_size,
+                offset
+            )
+            .fetch_all(&mut *db_conn)
+        )
+    })
+    .await?
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to fetch notes".to_string(),
+            ))
+        }
+    };
+
+    let preview = preview.unwrap_or(false);
+    let notes: Vec<note_api::NoteResponse> = db_notes
+        .iter()
+        .map(|note| note_api::NoteResponse {
+            preview: preview.then(|| note_api::preview(&note.content)),
+            ..note.into()
+        })
+        .collect();
+
+    let total = async {
+        match sqlx::query!(
+            "SELECT COUNT(*) AS total FROM tblNotes WHERE account_id = ? AND deleted_at IS NULL AND (? IS NULL OR EXISTS (SELECT 1 FROM tblNoteTags WHERE tblNoteTags.note_id = tblNotes.note_id AND tblNoteTags.tag = ?))",
+            account_id,
+            tag,
+            tag
+        )
+        .fetch_one(&mut *db_conn)
+        .await
+        {
+            Ok(val) => Ok(val.total),
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => Err(ApiErrors::InternalError(
+                "Unable to count notes".to_string(),
+            )),
+        }
+    };
+
+    Ok(Json(
+        params::Listing::new(notes, envelope, limit, offset, total).await?,
+    ))
+}
+
+/// ## Reorder an account's notes
+///
+/// Gives the listed notes a position matching their place in the list, which
+/// `fetch_notes` sorts by. Notes left out of the list lose their position and
+/// go after the listed ones. Every ID has to be a note of the account that
+/// isn't in the trash, otherwise nothing is changed
+///
+/// ### Arguments
+///
+/// * Account ID
+/// * List of note IDs, in the new order
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+#[put("/notes/<account_id>/order", data = "<note_ids>", rank = 2)]
+pub async fn reorder_notes(
+    auth: Result<AuthAccount, ApiErrors>,
+    account_id: i32,
+    note_ids: Json<Vec<i32>>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<()> {
+    auth?.check_account(account_id)?;
+
+    let account_notes: HashSet<i32> = match retry_transient!(
+        sqlx::query!(
+            "SELECT note_id FROM tblNotes WHERE account_id = ? AND deleted_at IS NULL",
+            account_id
+        )
+        .fetch_all(&mut *db_conn)
+    ) {
+        Ok(val) => val.into_iter().map(|row| row.note_id).collect(),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to fetch notes".to_string(),
+            ))
+        }
+    };
+
+    let mut seen = HashSet::new();
+    for note_id in note_ids.iter() {
+        if !account_notes.contains(note_id) {
+            return Err(ApiErrors::Forbidden(format!(
+                "Note {} does not belong to this account",
+                note_id
+            ))
+            .with_code(ErrorCode::NoteNotOwned));
+        }
+        if !seen.insert(*note_id) {
+            return Err(ApiErrors::BadRequest(format!(
+                "Note {} is listed more than once",
+                note_id
+            )));
+        }
+    }
+
+    let mut transaction = match (&mut *db_conn).begin().await {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to start a database transaction".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query!(
+        "UPDATE tblNotes SET sort_position = NULL WHERE account_id = ?",
+        account_id
+    )
+    .execute(&mut transaction)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to reorder notes".to_string(),
+            ))
+        }
+    }
+
+    for (position, note_id) in note_ids.iter().enumerate() {
+        match sqlx::query!(
+            "UPDATE tblNotes SET sort_position = ? WHERE note_id = ? AND account_id = ?",
+            position as i32,
+            note_id,
+            account_id
+        )
+        .execute(&mut transaction)
+        .await
+        {
+            Ok(_) => (),
+            // Dropping the transaction without committing rolls it back
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Unable to reorder notes".to_string(),
+                ))
+            }
+        }
+    }
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to reorder notes".to_string(),
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+/// ## Download a note
+///
+/// Sends back the content of a note as a markdown file, only to the account
+/// that owns it. The ETag is the hash of the content, so a client sending it
+/// back in If-None-Match gets a 304 until the note changes. Last-Modified is
+/// when the note was last edited
+///
+/// ### Arguments
+///
+/// * Note ID
+/// * ETag (optional If-None-Match header)
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 304 Not Modified
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[get("/notes/download/<note_id>")]
+pub async fn download_note(
+    note_id: i32,
+    auth: Result<AuthAccount, ApiErrors>,
+    if_none_match: IfNoneMatch,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<LastModified<Conditional<(ContentType, String)>>> {
+    let auth = auth?;
+
+    let db_note = match sqlx::query_as!(
+        db::Note,
+        "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if auth.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    // Hashed here rather than read from content_hash, which isn't set on
+    // notes saved before hashes were kept
+    let etag = conditional::etag(&note_api::content_hash(&db_note.content));
+
+    Ok(LastModified(
+        Conditional::new(&if_none_match, etag, || {
+            (ContentType::Markdown, db_note.content)
+        }),
+        db_note.updated_at,
+    ))
+}
+
+/// ## Check a note without downloading it
+///
+/// The same headers as downloading the note, with Content-Length the size of
+/// the note in bytes, but no body. Only the size and hash of the content are
+/// read from the database
+///
+/// ### Arguments
+///
+/// * Note ID
+/// * ETag (optional If-None-Match header)
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 304 Not Modified
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[head("/notes/download/<note_id>")]
+pub async fn head_note(
+    note_id: i32,
+    auth: Result<AuthAccount, ApiErrors>,
+    if_none_match: IfNoneMatch,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<LastModified<Conditional<note_api::NoteHead>>> {
+    let auth = auth?;
+
+    // Notes saved before hashes were kept are hashed by the database, so
+    // the content still doesn't have to be sent over
+    let db_note = match sqlx::query!(
+        "SELECT account_id, OCTET_LENGTH(content) AS `size!: i64`, COALESCE(content_hash, SHA2(content, 256)) AS `hash!: String`, updated_at FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if auth.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    let etag = conditional::etag(&db_note.hash);
+
+    Ok(LastModified(
+        Conditional::new(&if_none_match, etag, || note_api::NoteHead {
+            size: db_note.size as usize,
+        }),
+        db_note.updated_at,
+    ))
+}
+
+/// ## Tag a note
+///
+/// Puts a tag on a note, replying with every tag the note now has. Tagging a
+/// note with a tag it already has changes nothing
+///
+/// ### Arguments
+///
+/// * Note ID
+/// * Tag
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[post("/notes/<note_id>/tags", data = "<new_tag>")]
+pub async fn add_note_tag(
+    note_id: i32,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+    new_tag: Json<note_api::NewTag>,
+) -> ApiResult<Json<Vec<String>>> {
+    let auth = auth?;
+    let tag = normalize_tag(&new_tag.tag)?;
+
+    let db_note = match sqlx::query_as!(
+        db::Note,
+        "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if auth.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    // The unique key on (note_id, tag) keeps a note from having a tag twice
+    match sqlx::query!(
+        "INSERT IGNORE INTO tblNoteTags (note_id, tag) VALUES (?, ?)",
+        note_id,
+        tag
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to tag the note".to_string(),
+            ))
+        }
+    };
+
+    let db_tags = match sqlx::query_as!(
+        db::NoteTag,
+        "SELECT * FROM tblNoteTags WHERE note_id = ? ORDER BY tag",
+        note_id
+    )
+    .fetch_all(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to fetch the note's tags".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json(db_tags.into_iter().map(|db_tag| db_tag.tag).collect()))
+}
+
+/// ## Remove a tag from a note
+///
+/// ### Arguments
+///
+/// * Note ID
+/// * Tag
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[delete("/notes/<note_id>/tags/<tag>")]
+pub async fn remove_note_tag(
+    note_id: i32,
+    tag: String,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<()> {
+    let auth = auth?;
+    let tag = normalize_tag(&tag)?;
+
+    let db_note = match sqlx::query_as!(
+        db::Note,
+        "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if auth.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    match sqlx::query!(
+        "DELETE FROM tblNoteTags WHERE note_id = ? AND tag = ?",
+        note_id,
+        tag
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(val) if val.rows_affected() == 0 => {
+            Err(ApiErrors::NotFound("Note does not have that tag".to_string()))
+        }
+        Ok(_) => Ok(()),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => Err(ApiErrors::InternalError(
+            "Unable to remove the tag from the note".to_string(),
+        )),
+    }
+}
+
+/// ## Upload a note attachment
+///
+/// Attaches a file to a note, sent as the raw request body with its
+/// Content-Type. Only PNG and JPEG images and PDF documents no larger than
+/// `max_attachment_bytes` (5 MiB by default) are accepted, and only the
+/// account that owns the note can attach files to it
+///
+/// ### Arguments
+///
+/// * Note ID
+/// * File name
+/// * File contents
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+/// * 413 Payload Too Large
+#[post("/notes/<note_id>/attachments?<file_name>", data = "<file>")]
+pub async fn upload_note_attachment(
+    note_id: i32,
+    file_name: String,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+    content_type: &ContentType,
+    file: Data<'_>,
+) -> ApiResult<Json<note_api::AttachmentResponse>> {
+    let auth = auth?;
+    let file_name = normalize_file_name(&file_name)?;
+
+    let signature = match attachment_signature(content_type) {
+        Some(val) => val,
+        None => {
+            return Err(ApiErrors::BadRequest(
+                "Attachments must be PNG or JPEG images or PDF documents".to_string(),
+            ))
+        }
+    };
+
+    let db_note = match sqlx::query_as!(
+        db::Note,
+        "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if auth.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    let max_attachment_bytes = crate::SETTINGS
+        .read()
+        .await
+        .get_int("max_attachment_bytes")
+        .unwrap_or(DEFAULT_MAX_ATTACHMENT_BYTES)
+        .max(0) as u64;
+
+    let file = match file.open(max_attachment_bytes.bytes()).into_bytes().await {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to read the uploaded file".to_string(),
+            ))
+        }
+    };
+
+    if !file.is_complete() {
+        return Err(ApiErrors::PayloadTooLarge(format!(
+            "Attachments may not be larger than {} bytes",
+            max_attachment_bytes
+        )));
+    }
+
+    if !file.starts_with(signature) {
+        return Err(ApiErrors::BadRequest(
+            "Uploaded file is not a valid file of the given type".to_string(),
+        ));
+    }
+
+    let file = file.into_inner();
+    let content_type = content_type.media_type().to_string();
+
+    let attachment_id = match sqlx::query!(
+        "INSERT INTO tblNoteAttachments (note_id, file_name, content_type, size, data) VALUES (?, ?, ?, ?, ?)",
+        note_id,
+        file_name,
+        content_type,
+        file.len() as i32,
+        file
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val.last_insert_id() as i32,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to save the attachment".to_string(),
+            ))
+        }
+    };
+
+    match sqlx::query_as!(
+        db::NoteAttachment,
+        "SELECT attachment_id, note_id, file_name, content_type, size, created_at FROM tblNoteAttachments WHERE attachment_id = ?",
+        attachment_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => Ok(Json((&val).into())),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => Err(ApiErrors::InternalError(
+            "Unable to fetch the saved attachment".to_string(),
+        )),
+    }
+}
+
+/// ## List a note's attachments
+///
+/// Returns the names, types and sizes of the files attached to a note, oldest
+/// first, without their contents. Public notes can be listed by anyone,
+/// private notes only by the account that owns them
+///
+/// ### Arguments
+///
+/// * Note ID
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[get("/notes/<note_id>/attachments", rank = 2)]
+pub async fn fetch_note_attachments(
+    note_id: i32,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<Vec<note_api::AttachmentResponse>>> {
+    let db_note = match retry_transient!(
+        sqlx::query_as!(
+            db::Note,
+            "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+            note_id
+        )
+        .fetch_one(&mut *db_conn)
+    ) {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if !db_note.public && auth?.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    let db_attachments = match db::timed(async {
+        retry_transient!(
+            sqlx::query_as!(
+                db::NoteAttachment,
+                "SELECT attachment_id, note_id, file_name, content_type, size, created_at FROM tblNoteAttachments WHERE note_id = ? ORDER BY attachment_id",
+                note_id
+            )
+            .fetch_all(&mut *db_conn)
+        )
+    })
+    .await?
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to fetch the note's attachments".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json(db_attachments.iter().map(|val| val.into()).collect()))
+}
+
+/// ## Download a note attachment
+///
+/// Sends back an attached file with the Content-Type it was uploaded with.
+/// Public notes' attachments can be downloaded by anyone, private notes'
+/// only by the account that owns them
+///
+/// ### Arguments
+///
+/// * Note ID
+/// * Attachment ID
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[get("/notes/<note_id>/attachments/<attachment_id>")]
+pub async fn download_note_attachment(
+    note_id: i32,
+    attachment_id: i32,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<(ContentType, Vec<u8>)> {
+    let db_note = match retry_transient!(
+        sqlx::query_as!(
+            db::Note,
+            "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+            note_id
+        )
+        .fetch_one(&mut *db_conn)
+    ) {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if !db_note.public && auth?.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    let db_file = match retry_transient!(
+        sqlx::query!(
+            "SELECT content_type, data FROM tblNoteAttachments WHERE attachment_id = ? AND note_id = ?",
+            attachment_id,
+            note_id
+        )
+        .fetch_one(&mut *db_conn)
+    ) {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Attachment not found".to_string())
+                .with_code(ErrorCode::AttachmentNotFound))
+        }
+    };
+
+    // Only types that passed attachment_signature are ever stored
+    let content_type =
+        ContentType::parse_flexible(&db_file.content_type).unwrap_or(ContentType::Binary);
+
+    Ok((content_type, db_file.data))
+}
+
+/// ## Remove a note attachment
+///
+/// ### Arguments
+///
+/// * Note ID
+/// * Attachment ID
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[delete("/notes/<note_id>/attachments/<attachment_id>")]
+pub async fn remove_note_attachment(
+    note_id: i32,
+    attachment_id: i32,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<()> {
+    let auth = auth?;
+
+    let db_note = match sqlx::query_as!(
+        db::Note,
+        "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if auth.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    match sqlx::query!(
+        "DELETE FROM tblNoteAttachments WHERE attachment_id = ? AND note_id = ?",
+        attachment_id,
+        note_id
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(val) if val.rows_affected() == 0 => {
+            Err(ApiErrors::NotFound("Attachment not found".to_string())
+                .with_code(ErrorCode::AttachmentNotFound))
+        }
+        Ok(_) => Ok(()),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => Err(ApiErrors::InternalError(
+            "Unable to remove the attachment".to_string(),
+        )),
+    }
+}
+
+/// ## Export every note as a ZIP
+///
+/// Streams a ZIP archive of the account's notes, one markdown file per note
+/// named after its title, followed by a manifest.json listing which file
+/// holds which note. Notes are written out as they are read, so the archive
+/// is never held in memory as a whole
+///
+/// ### Arguments
+///
+/// * Account ID
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+#[get("/notes/<account_id>/export", rank = 2)]
+pub async fn export_notes(
+    account_id: i32,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<(ContentType, ByteStream![Vec<u8>])> {
+    auth?.check_account(account_id)?;
+
+    let archive = ByteStream! {
+        let mut zip = export::ZipWriter::default();
+        let mut file_names = export::FileNames::default();
+        let mut manifest = Vec::new();
+
+        let mut db_notes = sqlx::query_as!(
+            db::Note,
+            "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE account_id = ? AND deleted_at IS NULL ORDER BY note_id",
+            account_id
+        )
+        .fetch(&mut *db_conn);
+
+        while let Some(db_note) = db_notes.next().await {
+            let db_note = match db_note {
+                Ok(val) => val,
+                // Headers are long gone by now, so all that can be done is cut the archive short
+                #[cfg(not(tarpaulin_include))]
+                Err(e) => {
+                    log::error!("Unable to export notes for account {}: {}", account_id, e);
+                    return;
+                }
+            };
+
+            let file_name = file_names.next(&db_note.title);
+            yield zip.add(&file_name, db_note.content.as_bytes(), &db_note.updated_at);
+
+            manifest.push(export::ManifestEntry {
+                file_name,
+                note_id: db_note.note_id,
+                note_title: db_note.title,
+            });
+        }
+
+        let manifest = serde_json::to_vec_pretty(&manifest).unwrap_or_default();
+        yield zip.add(export::MANIFEST_NAME, &manifest, &chrono::Utc::now().naive_utc());
+        yield zip.finish();
+    };
+
+    Ok((ContentType::ZIP, archive))
+}
+
+/// ## Fetch a single note
+///
+/// Returns one note with its content, so a client can load a note in one
+/// call. Public notes can be fetched by anyone, private notes only by the
+/// account that owns them
+///
+/// ### Arguments
+///
+/// * Note ID
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[get("/notes/single/<note_id>")]
+pub async fn fetch_note(
+    note_id: i32,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<note_api::NoteResponse>> {
+    let db_note = match retry_transient!(
+        sqlx::query_as!(
+            db::Note,
+            "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+            note_id
+        )
+        .fetch_one(&mut *db_conn)
+    ) {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if !db_note.public && auth?.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    Ok(Json((&db_note).into()))
+}
+
+/// ## Fetch several notes at once
+///
+/// Returns the listed notes in the order asked for, so a client can load a
+/// set of notes in one call. IDs that don't exist or belong to another
+/// account are left out rather than failing the request
+///
+/// ### Arguments
+///
+/// * List of note IDs, at most 100
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+#[post("/notes/batch", data = "<note_ids>")]
+pub async fn fetch_notes_by_ids(
+    note_ids: Json<Vec<i32>>,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<Vec<note_api::NoteResponse>>> {
+    let auth = auth?;
+
+    if note_ids.len() > MAX_BATCH_NOTE_IDS {
+        return Err(ApiErrors::BadRequest(format!(
+            "At most {} notes can be fetched at once",
+            MAX_BATCH_NOTE_IDS
+        )));
+    }
+
+    let mut seen = HashSet::new();
+    let mut notes = Vec::new();
+    for note_id in note_ids.iter().filter(|note_id| seen.insert(**note_id)) {
+        match retry_transient!(
+            sqlx::query_as!(
+                db::Note,
+                "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND account_id = ? AND deleted_at IS NULL",
+                note_id,
+                auth.account_id
+            )
+            .fetch_optional(&mut *db_conn)
+        ) {
+            Ok(Some(val)) => notes.push((&val).into()),
+            Ok(None) => (),
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Unable to fetch notes from database".to_string(),
+                ))
+            }
+        }
+    }
+
+    Ok(Json(notes))
+}
+
+/// ## Render a note as HTML
+///
+/// Renders the markdown of a note to sanitized HTML, so clients don't need a
+/// markdown renderer of their own. Public notes can be rendered by anyone,
+/// private notes only by the account that owns them
+///
+/// ### Arguments
+///
+/// * Note ID
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[get("/notes/<note_id>/html", rank = 2)]
+pub async fn render_note(
+    note_id: i32,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<(ContentType, String)> {
+    let db_note = match sqlx::query_as!(
+        db::Note,
+        "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if !db_note.public && auth?.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    Ok((ContentType::HTML, note_api::render_markdown(&db_note.content)))
+}
+
+/// ## Transfer a note to another account
+///
+/// Hands a note over to another account, for example when merging accounts.
+/// Only the owner changes; the tags and content stay with the note
+///
+/// ### Arguments
+///
+/// * Note ID
+/// * Target account ID
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[put("/notes/<note_id>/owner/<target_account_id>")]
+pub async fn transfer_note(
+    note_id: i32,
+    target_account_id: i32,
+    admin: Result<AdminAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<()> {
+    admin?;
+
+    let owner_account_id = match sqlx::query!(
+        "SELECT account_id FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val.account_id,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    match sqlx::query!(
+        "SELECT account_id FROM tblAccount WHERE account_id = ?",
+        target_account_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Target account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
+    };
+
+    match sqlx::query!(
+        "UPDATE tblNotes SET account_id = ? WHERE note_id = ?",
+        target_account_id,
+        note_id
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to transfer the note".to_string(),
+            ))
+        }
+    };
+
+    // The note's size moves from the old owner's usage to the new owner's
+    recompute_bytes_used(&mut *db_conn, owner_account_id).await;
+    recompute_bytes_used(&mut *db_conn, target_account_id).await;
+
+    Ok(())
+}
+
+/// ## Verify a note's integrity
+///
+/// Recomputes the hash of a note's stored content and compares it with the
+/// hash saved when the note was last written through the API, so operators
+/// can spot corrupted or tampered notes. Notes saved before hashes were kept
+/// have no stored hash and never match
+///
+/// ### Arguments
+///
+/// * Note ID
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[get("/notes/<note_id>/verify", rank = 2)]
+pub async fn verify_note(
+    note_id: i32,
+    admin: Result<AdminAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<note_api::NoteIntegrity>> {
+    admin?;
+
+    let db_note = match sqlx::query!(
+        "SELECT content, content_hash FROM tblNotes WHERE note_id = ?",
+        note_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    let computed_hash = note_api::content_hash(&db_note.content);
+
+    Ok(Json(note_api::NoteIntegrity {
+        note_id,
+        matches: db_note.content_hash.as_deref() == Some(computed_hash.as_str()),
+        stored_hash: db_note.content_hash,
+        computed_hash,
+    }))
+}
+
+/// ## Search an account's notes by title
+///
+/// Returns the notes of an account whose title matches the query, ignoring
+/// case. Notes are sorted by full-text relevance, most relevant first, and
+/// each one comes with its score. Titles that only contain the query part
+/// way through a word still match, with a score of 0
+///
+/// ### Arguments
+///
+/// * Account ID
+/// * Search query
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[get("/notes/<account_id>/search/<query>")]
+pub async fn fetch_notes_search(
+    auth: Result<AuthAccount, ApiErrors>,
+    account_id: i32,
+    query: String,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<Vec<note_api::NoteSearchResult>>> {
+    auth?.check_account(account_id)?;
+
+    // Checking the user account actually exists
+    match sqlx::query!(
+        "SELECT account_id FROM tblAccount WHERE account_id = ?",
+        account_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(_) => (),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("User account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
+    }
+
+    let title_pattern = format!("%{}%", params::escape_like(&query.to_lowercase()));
+
+    let ranked = retry_transient!(
+        sqlx::query!(
+            "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version, MATCH (title) AGAINST (? IN NATURAL LANGUAGE MODE) as `score!: f64` FROM tblNotes WHERE account_id = ? AND deleted_at IS NULL AND (MATCH (title) AGAINST (? IN NATURAL LANGUAGE MODE) OR LOWER(title) LIKE ?) ORDER BY MATCH (title) AGAINST (? IN NATURAL LANGUAGE MODE) DESC, note_id",
+            query,
+            account_id,
+            query,
+            title_pattern,
+            query
+        )
+        .fetch_all(&mut *db_conn)
+    );
+
+    let notes: Vec<note_api::NoteSearchResult> = match ranked {
+        Ok(val) => val
+            .into_iter()
+            .map(|row| {
+                let note = db::Note {
+                    note_id: row.note_id,
+                    account_id: row.account_id,
+                    title: row.title,
+                    content: row.content,
+                    public: row.public,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                    version: row.version,
+                };
+
+                note_api::NoteSearchResult {
+                    note: (&note).into(),
+                    score: row.score,
+                }
+            })
+            .collect(),
+        // Without a FULLTEXT index every matching title scores the same
+        Err(e) if params::fulltext_unavailable(&e) => {
+            match retry_transient!(
+                sqlx::query_as!(
+                    db::Note,
+                    "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE account_id = ? AND deleted_at IS NULL AND LOWER(title) LIKE ? ORDER BY note_id",
+                    account_id,
+                    title_pattern
+                )
+                .fetch_all(&mut *db_conn)
+            ) {
+                Ok(val) => val
+                    .iter()
+                    .map(|note| note_api::NoteSearchResult {
+                        note: note.into(),
+                        score: 1.0,
+                    })
+                    .collect(),
+                #[cfg(not(tarpaulin_include))]
+                Err(_) => {
+                    return Err(ApiErrors::InternalError(
+                        "Unable to search notes".to_string(),
+                    ))
+                }
+            }
+        }
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to search notes".to_string(),
+            ))
+        }
+    };
+
+    if notes.is_empty() {
+        return Err(ApiErrors::NotFound("No matching notes were found".to_string()));
+    }
+
+    Ok(Json(notes))
+}
+
+/// ## Fetch List of Public Notes
+///
+/// Returns a list of notes if they have been flagged as public
+///
+/// ### Arguments
+///
+/// * none 
+///
+/// ### Possible Responses
+///
+/// * 200 Ok
+/// * 404 Not Found
+#[get("/notes/public")]
+pub async fn fetch_public_notes(mut db_conn: Connection<SPS>) -> ApiResult<Json<Vec<note_api::NoteResponse>>> {
+    let db_notes = match db::timed(async {
+        retry_transient!(
+            sqlx::query_as!(
+                db::Note,
+                "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE public = 1 AND deleted_at IS NULL",
+            )
+            .fetch_all(&mut *db_conn)
+        )
+    })
+    .await?
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to fetch notes".to_string(),
+            ))
+        }
+    };
+
+    if db_notes.len() == 0 {
+        return Err(ApiErrors::NotFound("No notes were found".to_string()));
+    }
+
+    let notes: Vec<note_api::NoteResponse> = db_notes.iter().map(|note| note.into()).collect();
+
+    Ok(Json(notes))
+}
+
+/// ## Add a note file to an account
+///
+/// Add a note to an account, returning the newly created note
+///
+/// If the account already has a note with the same title, `on_duplicate`
+/// decides whether it is still added (`allow`), refused (`reject`), or added
+/// with a numbered title (`suffix`)
+///
+/// A request sent again with the same Idempotency-Key header for the same
+/// account gets back the note the first request added, instead of adding it
+/// twice, or a conflict if that note has been removed since. Keys are
+/// remembered for `idempotency_key_ttl_seconds`
+///
+/// Notes outside the trash count towards the account's storage quota and
+/// `max_notes_per_account`, and a note that would take the account past
+/// either is refused
+///
+/// With `validate_only=true` the note is put through every check without
+/// being saved, and a report of everything wrong with it is sent back instead
+///
+/// ### Arguments
+///
+/// * Account ID
+/// * New note file
+/// * How to handle a duplicate title (optional)
+/// * Only validate the note (optional)
+/// * Idempotency key (optional Idempotency-Key header)
+///
+/// ### Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+/// * 409 Conflict
+/// * 413 Payload Too Large
+#[post("/notes?<on_duplicate>&<validate_only>", data = "<new_note>")]
+pub async fn add_note(
+    auth: Result<AuthAccount, ApiErrors>,
+    on_duplicate: Option<String>,
+    validate_only: Option<bool>,
+    new_note: Json<note_api::NewNote>,
+    idempotency_key: Result<IdempotencyKey, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<note_api::AddNoteResponse> {
+    let auth = auth?;
+    auth.check_account(new_note.account_id)?;
+    let idempotency_key = idempotency_key?.0;
+    let mut checks = Checks::new(validate_only.unwrap_or(false));
+    checks.check(validate_note_title(&new_note.note_title))?;
+    checks.check(validate_note_content(&new_note.note_content).await)?;
+    // A bad on_duplicate leaves nothing to check titles against
+    let duplicate_titles = checks
+        .check(duplicate_titles(on_duplicate).await)?
+        .unwrap_or(DuplicateTitles::Allow);
+
+    // Checking the user account actually exists
+    let db_account = match sqlx::query!(
+        "SELECT bytes_used, storage_quota_bytes FROM tblAccount WHERE account_id = ?",
+        new_note.account_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            checks.check(Err::<(), _>(ApiErrors::NotFound("User account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound)))?;
+            return Ok(checks.into_report(new_note.note_title.clone()));
+        }
+    };
+
+    // DATETIME columns only keep whole seconds
+    let now = chrono::Utc::now().naive_utc().with_nanosecond(0).unwrap();
+
+    // A retry of a request that already added a note gets that note back.
+    // If the note has since been removed the request was still handled, so it
+    // isn't added again. Validating doesn't use up the key, so there is
+    // nothing to look up
+    if let Some(key) = idempotency_key.as_ref().filter(|_| !checks.validate_only) {
+        match sqlx::query!(
+            "SELECT tblNotes.note_id, tblNotes.account_id, title, content, public as `public: bool`, created_at, updated_at, version, deleted_at FROM tblNotes JOIN tblIdempotencyKeys USING (note_id) WHERE tblIdempotencyKeys.account_id = ? AND idempotency_key = ? AND expires_at > ?",
+            new_note.account_id,
+            key,
+            now
+        )
+        .fetch_optional(&mut *db_conn)
+        .await
+        {
+            Ok(Some(val)) if val.deleted_at.is_some() => {
+                return Err(ApiErrors::Conflict(
+                    "The note added with this Idempotency-Key has since been removed".to_string(),
+                )
+                .with_code(ErrorCode::IdempotentNoteRemoved))
+            }
+            Ok(Some(val)) => {
+                return Ok(note_api::AddNoteResponse::Added(Json(
+                    (&db::Note {
+                        note_id: val.note_id,
+                        account_id: val.account_id,
+                        content: val.content,
+                        title: val.title,
+                        public: val.public,
+                        created_at: val.created_at,
+                        updated_at: val.updated_at,
+                        version: val.version,
+                    })
+                        .into(),
+                )))
+            }
+            Ok(None) => (),
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Unable to check the idempotency key".to_string(),
+                ))
+            }
+        }
+    }
+
+    checks.check(
+        check_storage_quota(
+            db_account.bytes_used,
+            db_account.storage_quota_bytes,
+            new_note.note_content.len() as i64,
+        )
+        .await,
+    )?;
+    checks.check(check_note_limit(&mut *db_conn, new_note.account_id).await)?;
+
+    let mut title = new_note.note_title.clone();
+    if duplicate_titles != DuplicateTitles::Allow {
+        let taken = taken_titles(&mut *db_conn, new_note.account_id, &new_note.note_title).await?;
+
+        let is_duplicate = taken
+            .iter()
+            .any(|val| val.to_lowercase() == title.to_lowercase());
+
+        match (is_duplicate, duplicate_titles) {
+            (true, DuplicateTitles::Reject) => {
+                checks.check(Err::<(), _>(ApiErrors::Conflict(
+                    "A note with that title already exists".to_string(),
+                )
+                .with_code(ErrorCode::NoteTitleExists)))?;
+            }
+            (true, _) => title = suffixed_title(&title, &taken),
+            (false, _) => (),
+        }
+    }
+
+    // Every check has run, and nothing has been written
+    if checks.validate_only {
+        return Ok(checks.into_report(title));
+    }
+
+    let mut transaction = match (&mut *db_conn).begin().await {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to start a database transaction".to_string(),
+            ))
+        }
+    };
+
+    let note_id = match sqlx::query!(
+        "INSERT INTO tblNotes (account_id, content, content_hash, title, public, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        new_note.account_id,
+        new_note.note_content,
+        note_api::content_hash(&new_note.note_content),
+        title,
+        new_note.note_public,
+        now,
+        now,
+    )
+    .execute(&mut transaction)
+    .await
+    {
+        Ok(val) => val.last_insert_id() as i32,
+        #[cfg(not(tarpauling_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to save file in database".to_string(),
+            ))
+        }
+    };
+
+    // Saved in the same transaction, so a key is never left without its note
+    if let Some(key) = &idempotency_key {
+        let ttl_seconds = crate::SETTINGS
+            .read()
+            .await
+            .get_int("idempotency_key_ttl_seconds")
+            .unwrap_or(DEFAULT_IDEMPOTENCY_KEY_TTL_SECONDS);
+
+        // An expired use of the same key no longer counts
+        match sqlx::query!(
+            "DELETE FROM tblIdempotencyKeys WHERE account_id = ? AND idempotency_key = ? AND expires_at <= ?",
+            new_note.account_id,
+            key,
+            now
+        )
+        .execute(&mut transaction)
+        .await
+        {
+            Ok(_) => (),
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Unable to save the idempotency key".to_string(),
+                ))
+            }
+        };
+
+        // Only fails on the unique key, when a request with the same key
+        // added its note while this one was running
+        match sqlx::query!(
+            "INSERT INTO tblIdempotencyKeys (account_id, idempotency_key, note_id, expires_at) VALUES (?, ?, ?, ?)",
+            new_note.account_id,
+            key,
+            note_id,
+            now + chrono::Duration::seconds(ttl_seconds)
+        )
+        .execute(&mut transaction)
+        .await
+        {
+            Ok(_) => (),
+            Err(_) => {
+                return Err(ApiErrors::Conflict(
+                    "A request with this Idempotency-Key is already being handled".to_string(),
+                )
+                .with_code(ErrorCode::IdempotencyKeyInUse))
+            }
+        };
+    }
+
+    recompute_bytes_used(&mut transaction, new_note.account_id).await;
+    audit::record(&mut transaction, auth.account_id, AuditAction::AddNote, note_id).await;
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to save file in database".to_string(),
+            ))
+        }
+    }
+
+    Ok(note_api::AddNoteResponse::Added(Json(
+        (&db::Note {
+            note_id,
+            account_id: new_note.account_id,
+            content: new_note.note_content.clone(),
+            title,
+            public: new_note.note_public,
+            created_at: now,
+            updated_at: now,
+            version: 1,
+        })
+            .into(),
+    )))
+}
+
+/// ## Read a streamed note upload
+///
+/// Reads the body a chunk at a time, hashing it as it goes. Stops as soon as
+/// the body runs past `max_note_bytes`, so the rest of an oversized upload is
+/// never read and what was read so far is dropped. Gives back the content and
+/// its SHA-256 as hex
+async fn read_note_stream(content: Data<'_>) -> ApiResult<(String, String)> {
+    use rocket::tokio::io::AsyncReadExt;
+
+    let max_note_bytes = crate::SETTINGS
+        .read()
+        .await
+        .get_int("max_note_bytes")
+        .unwrap_or(DEFAULT_MAX_NOTE_BYTES)
+        .max(0) as usize;
+
+    // One byte over the limit is enough to know the body is too large
+    let mut stream = content.open((max_note_bytes as u64 + 1).bytes());
+    let mut hasher = Sha256::new();
+    let mut received = Vec::new();
+    let mut chunk = vec![0u8; STREAM_CHUNK_BYTES];
+
+    loop {
+        let read = match stream.read(&mut chunk).await {
+            Ok(0) => break,
+            Ok(val) => val,
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Failed to read the uploaded note".to_string(),
+                ))
+            }
+        };
+
+        if received.len() + read > max_note_bytes {
+            return Err(ApiErrors::PayloadTooLarge(format!(
+                "Note content exceeds the maximum size of {} bytes",
+                max_note_bytes
+            )));
+        }
+        hasher.input(&chunk[..read]);
+        received.extend_from_slice(&chunk[..read]);
+    }
+
+    match String::from_utf8(received) {
+        Ok(val) => Ok((val, hasher.result_str())),
+        Err(_) => Err(ApiErrors::BadRequest(
+            "Note content must be plain text markdown".to_string(),
+        )),
+    }
+}
+
+/// ## Add a note from a streamed upload
+///
+/// Adds a note to the session's account with the raw markdown as the request
+/// body, for notes too large to comfortably send as JSON. The body is read in
+/// chunks and refused as soon as it passes `max_note_bytes`, without waiting
+/// for the rest of it. Titles, the storage quota and `max_notes_per_account`
+/// are handled the same way as adding a note
+///
+/// ### Arguments
+///
+/// * Note title
+/// * Whether the note is public (optional, defaults to private)
+/// * How to handle a duplicate title (optional)
+/// * Note content as the request body
+///
+/// ### Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 409 Conflict
+/// * 413 Payload Too Large
+#[post("/notes/stream?<title>&<public>&<on_duplicate>", data = "<content>")]
+pub async fn stream_note(
+    title: String,
+    public: Option<bool>,
+    on_duplicate: Option<String>,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+    content: Data<'_>,
+) -> ApiResult<Json<note_api::NoteResponse>> {
+    let auth = auth?;
+    validate_note_title(&title)?;
+    let duplicate_titles = duplicate_titles(on_duplicate).await?;
+
+    let (content, content_hash) = read_note_stream(content).await?;
+    validate_note_content(&content).await?;
+
+    let db_account = match sqlx::query!(
+        "SELECT bytes_used, storage_quota_bytes FROM tblAccount WHERE account_id = ?",
+        auth.account_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to fetch the account".to_string(),
+            ))
+        }
+    };
+
+    check_storage_quota(
+        db_account.bytes_used,
+        db_account.storage_quota_bytes,
+        content.len() as i64,
+    )
+    .await?;
+    check_note_limit(&mut *db_conn, auth.account_id).await?;
+
+    let mut title = title;
+    if duplicate_titles != DuplicateTitles::Allow {
+        let taken = taken_titles(&mut *db_conn, auth.account_id, &title).await?;
+
+        let is_duplicate = taken
+            .iter()
+            .any(|val| val.to_lowercase() == title.to_lowercase());
+
+        match (is_duplicate, duplicate_titles) {
+            (true, DuplicateTitles::Reject) => {
+                return Err(ApiErrors::Conflict("A note with that title already exists".to_string())
+                    .with_code(ErrorCode::NoteTitleExists))
+            }
+            (true, _) => title = suffixed_title(&title, &taken),
+            (false, _) => (),
+        }
+    }
+
+    // DATETIME columns only keep whole seconds
+    let now = chrono::Utc::now().naive_utc().with_nanosecond(0).unwrap();
+    let public = public.unwrap_or(false);
+
+    let mut transaction = match (&mut *db_conn).begin().await {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to start a database transaction".to_string(),
+            ))
+        }
+    };
+
+    let note_id = match sqlx::query!(
+        "INSERT INTO tblNotes (account_id, content, content_hash, title, public, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        auth.account_id,
+        content,
+        content_hash,
+        title,
+        public,
+        now,
+        now,
+    )
+    .execute(&mut transaction)
+    .await
+    {
+        Ok(val) => val.last_insert_id() as i32,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to save file in database".to_string(),
+            ))
+        }
+    };
+
+    recompute_bytes_used(&mut transaction, auth.account_id).await;
+    audit::record(&mut transaction, auth.account_id, AuditAction::AddNote, note_id).await;
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to save file in database".to_string(),
+            ))
+        }
+    }
+
+    Ok(Json(
+        (&db::Note {
+            note_id,
+            account_id: auth.account_id,
+            content,
+            title,
+            public,
+            created_at: now,
+            updated_at: now,
+            version: 1,
+        })
+            .into(),
+    ))
+}
+
+/// ## Duplicate a note
+///
+/// Copies a note into a new note of the same account, titled
+/// `<original> (copy)`, as a starting point for another note. The copy starts
+/// out private and without tags. A copy whose title is already taken is
+/// handled the same way as adding a note, by `on_duplicate` or the
+/// `duplicate_note_titles` setting. The copy counts towards the storage quota
+/// and `max_notes_per_account` like any other note
+///
+/// ### Arguments
+///
+/// * Note ID
+/// * How to handle a duplicate title (optional)
+///
+/// ### Responses
+///
+/// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+/// * 409 Conflict
+/// * 413 Payload Too Large
+#[post("/notes/<note_id>/duplicate?<on_duplicate>")]
+pub async fn duplicate_note(
+    note_id: i32,
+    on_duplicate: Option<String>,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<note_api::NoteResponse>> {
+    let auth = auth?;
+    let duplicate_titles = duplicate_titles(on_duplicate).await?;
+
+    let db_note = match sqlx::query_as!(
+        db::Note,
+        "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if auth.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    let mut title = format!("{} (copy)", db_note.title);
+    validate_note_title(&title)?;
+
+    let db_account = match sqlx::query!(
+        "SELECT bytes_used, storage_quota_bytes FROM tblAccount WHERE account_id = ?",
+        db_note.account_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to fetch the account".to_string(),
+            ))
+        }
+    };
+
+    check_storage_quota(
+        db_account.bytes_used,
+        db_account.storage_quota_bytes,
+        db_note.content.len() as i64,
+    )
+    .await?;
+    check_note_limit(&mut *db_conn, db_note.account_id).await?;
+
+    if duplicate_titles != DuplicateTitles::Allow {
+        let taken = taken_titles(&mut *db_conn, db_note.account_id, &title).await?;
+
+        let is_duplicate = taken
+            .iter()
+            .any(|val| val.to_lowercase() == title.to_lowercase());
+
+        match (is_duplicate, duplicate_titles) {
+            (true, DuplicateTitles::Reject) => {
+                return Err(ApiErrors::Conflict("A note with that title already exists".to_string())
+                    .with_code(ErrorCode::NoteTitleExists))
+            }
+            (true, _) => title = suffixed_title(&title, &taken),
+            (false, _) => (),
+        }
+    }
+
+    // DATETIME columns only keep whole seconds
+    let now = chrono::Utc::now().naive_utc().with_nanosecond(0).unwrap();
+
+    let mut transaction = match (&mut *db_conn).begin().await {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to start a database transaction".to_string(),
+            ))
+        }
+    };
 
-/// ## Fetch Emergency Protocols
-///
-/// Return all the emergency protocols stored in the database
-///
-/// ### Arguments
-///
-/// * None
-///
-/// ### Possible Responses
-///
-/// * 200 Ok
-/// * 404 Not Found
-#[get("/notes/protocols")]
-pub async fn fetch_protocols(mut db_conn: Connection<SPS>) -> ApiResult<Json<Vec<db::Protocol>>> {
-    let db_protocols = match sqlx::query_as!(
-        db::Protocol,
-        "SELECT protocol_id, title, content FROM tblProtocol",
+    let copy_id = match sqlx::query!(
+        "INSERT INTO tblNotes (account_id, content, content_hash, title, public, created_at, updated_at) VALUES (?, ?, ?, ?, FALSE, ?, ?)",
+        db_note.account_id,
+        db_note.content,
+        note_api::content_hash(&db_note.content),
+        title,
+        now,
+        now,
     )
-    .fetch_all(&mut *db_conn)
+    .execute(&mut transaction)
     .await
     {
-        Ok(val) => val,
+        Ok(val) => val.last_insert_id() as i32,
         #[cfg(not(tarpaulin_include))]
         Err(_) => {
             return Err(ApiErrors::InternalError(
-                "Failed to fetch protocols".to_string(),
+                "Unable to save file in database".to_string(),
             ))
         }
     };
 
-    if db_protocols.is_empty() {
-        return Err(ApiErrors::NotFound("No protocols were found".to_string()));
+    recompute_bytes_used(&mut transaction, db_note.account_id).await;
+    audit::record(&mut transaction, auth.account_id, AuditAction::AddNote, copy_id).await;
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to save file in database".to_string(),
+            ))
+        }
     }
 
-    Ok(Json(db_protocols))
+    Ok(Json(
+        (&db::Note {
+            note_id: copy_id,
+            account_id: db_note.account_id,
+            content: db_note.content,
+            title,
+            public: false,
+            created_at: now,
+            updated_at: now,
+            version: 1,
+        })
+            .into(),
+    ))
 }
 
-/// ## Fetch List of Notes
+/// ## Update a specific notes file content
 ///
-/// Returns a list of note ID's and the URL to the static file
+/// Update a the content of the note file, not the title. The version of the
+/// note being edited is sent in an If-Match header, and the update refused if
+/// the note has changed since. Growing a note past the account's storage
+/// quota is refused as well
 ///
 /// ### Arguments
 ///
 /// * Account ID
+/// * Updated note file
+/// * Note version (If-Match header)
 ///
-/// ### Possible Responses
+/// ### Responses
 ///
 /// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
 /// * 404 Not Found
-#[get("/notes/<account_id>")]
-pub async fn fetch_notes(
+/// * 409 Conflict
+/// * 413 Payload Too Large
+#[put("/notes/<account_id>", data = "<update_note>")]
+pub async fn update_note(
     account_id: i32,
+    update_note: Json<note_api::UpdateNote>,
+    auth: Result<AuthAccount, ApiErrors>,
+    version: Result<NoteVersion, ApiErrors>,
     mut db_conn: Connection<SPS>,
-) -> ApiResult<Json<Vec<note_api::NoteResponse>>> {
-    // Checking the user account actually exists
-    match sqlx::query!(
-        "SELECT account_id FROM tblAccount WHERE account_id = ?",
-        account_id
+) -> ApiResult<()> {
+    let auth = auth?;
+    auth.check_account(account_id)?;
+    validate_note_title(&update_note.note_title)?;
+    validate_note_content(&update_note.note_content).await?;
+
+    // Fetching the notes record
+    let db_note = match sqlx::query_as!(
+        db::Note,
+        "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        update_note.note_id
     )
     .fetch_one(&mut *db_conn)
     .await
     {
-        Ok(_) => (),
-        Err(_) => return Err(ApiErrors::NotFound("User account not found".to_string())),
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if auth.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
     }
 
-    let db_notes = match sqlx::query_as!(
-        db::Note,
-        "SELECT note_id, account_id, title, content, public as `public: bool` FROM tblNotes WHERE account_id = ?",
+    let version = version?;
+    version.check(db_note.version)?;
+
+    let db_account = match sqlx::query!(
+        "SELECT bytes_used, storage_quota_bytes FROM tblAccount WHERE account_id = ?",
         account_id
     )
-    .fetch_all(&mut *db_conn)
+    .fetch_one(&mut *db_conn)
     .await
     {
         Ok(val) => val,
-        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::NotFound("User account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
+    };
+
+    check_storage_quota(
+        db_account.bytes_used,
+        db_account.storage_quota_bytes,
+        update_note.note_content.len() as i64 - db_note.content.len() as i64,
+    )
+    .await?;
+
+    // Updating the recrod. Only a new title or content counts as an update,
+    // so updated_at is set before either column changes. Matching on the
+    // version as well catches an edit that landed since the note was fetched
+    match sqlx::query!(
+        "UPDATE tblNotes SET updated_at = IF(title <> ? OR content <> ?, ?, updated_at), title = ?, content = ?, content_hash = ?, public = ?, version = version + 1 WHERE note_id = ? AND version = ?",
+        update_note.note_title,
+        update_note.note_content,
+        chrono::Utc::now().naive_utc(),
+        update_note.note_title,
+        update_note.note_content,
+        note_api::content_hash(&update_note.note_content),
+        update_note.note_public,
+        update_note.note_id,
+        version.0,
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(val) if val.rows_affected() == 0 => {
+            return Err(ApiErrors::Conflict(
+                "Note was changed while updating, fetch it again before updating".to_string(),
+            )
+            .with_code(ErrorCode::NoteVersionMismatch))
+        }
+        Ok(_) => (),
+        #[cfg(not(tarpauling_include))]
         Err(_) => {
             return Err(ApiErrors::InternalError(
-                "Unable to fetch notes".to_string(),
+                "Failed to update the note".to_string(),
             ))
         }
     };
 
-    if db_notes.len() == 0 {
-        return Err(ApiErrors::NotFound("No notes were found".to_string()));
-    }
-
-    let notes: Vec<note_api::NoteResponse> = db_notes.iter().map(|note| note.into()).collect();
+    recompute_bytes_used(&mut *db_conn, account_id).await;
 
-    Ok(Json(notes))
+    Ok(())
 }
 
-
-/// ## Fetch List of Public Notes
+/// ## Rename a note
 ///
-/// Returns a list of notes if they have been flagged as public
+/// Changes only the title of a note, leaving its content alone. Like
+/// update_note, the version being renamed is sent in an If-Match header
 ///
 /// ### Arguments
 ///
-/// * none 
+/// * Note ID
+/// * New title
+/// * Note version (If-Match header)
 ///
 /// ### Possible Responses
 ///
 /// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
 /// * 404 Not Found
-#[get("/notes/public")]
-pub async fn fetch_public_notes(mut db_conn: Connection<SPS>) -> ApiResult<Json<Vec<note_api::NoteResponse>>> {
-    let db_notes = match sqlx::query_as!(
+/// * 409 Conflict
+#[patch("/notes/<note_id>/title", data = "<note_title>")]
+pub async fn rename_note(
+    note_id: i32,
+    note_title: Json<note_api::NoteTitle>,
+    auth: Result<AuthAccount, ApiErrors>,
+    version: Result<NoteVersion, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<()> {
+    let auth = auth?;
+    validate_note_title(&note_title.note_title)?;
+
+    let db_note = match sqlx::query_as!(
         db::Note,
-        "SELECT note_id, account_id, title, content, public as `public: bool` FROM tblNotes WHERE public = 1",
+        "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
     )
-    .fetch_all(&mut *db_conn)
+    .fetch_one(&mut *db_conn)
     .await
     {
         Ok(val) => val,
-        #[cfg(not(tarpaulin_include))]
         Err(_) => {
-            return Err(ApiErrors::InternalError(
-                "Unable to fetch notes".to_string(),
-            ))
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
         }
     };
 
-    if db_notes.len() == 0 {
-        return Err(ApiErrors::NotFound("No notes were found".to_string()));
+    if auth.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
     }
 
-    let notes: Vec<note_api::NoteResponse> = db_notes.iter().map(|note| note.into()).collect();
+    let version = version?;
+    version.check(db_note.version)?;
 
-    Ok(Json(notes))
+    // Renaming a note to the title it already has isn't an update
+    match sqlx::query!(
+        "UPDATE tblNotes SET updated_at = IF(title <> ?, ?, updated_at), title = ?, version = version + 1 WHERE note_id = ? AND version = ?",
+        note_title.note_title,
+        chrono::Utc::now().naive_utc(),
+        note_title.note_title,
+        note_id,
+        version.0,
+    )
+    .execute(&mut *db_conn)
+    .await
+    {
+        Ok(val) if val.rows_affected() == 0 => {
+            return Err(ApiErrors::Conflict(
+                "Note was changed while renaming, fetch it again before renaming".to_string(),
+            )
+            .with_code(ErrorCode::NoteVersionMismatch))
+        }
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Failed to rename the note".to_string(),
+            ))
+        }
+    };
+
+    Ok(())
 }
 
-/// ## Add a note file to an account
+/// ## Patch a note's content
 ///
-/// Add a note to an account
+/// Replaces a range of lines in a note, so a small edit to a large note
+/// doesn't need the whole note sent again. Like update_note, the version
+/// being patched is sent in an If-Match header, and the patched note has to
+/// be valid content that fits in the account's storage quota
 ///
 /// ### Arguments
 ///
-/// * Account ID
-/// * New note file
+/// * Note ID
+/// * Line range and replacement
+/// * Note version (If-Match header)
 ///
-/// ### Responses
+/// ```json
+///     {
+///         "start": number,
+///         "end": number,
+///         "replacement": string
+///     }
+/// ```
+///
+/// ### Possible Responses
 ///
 /// * 200 Ok
+/// * 400 Bad Request
+/// * 401 Unauthorized
+/// * 403 Forbidden
 /// * 404 Not Found
-#[post("/notes", data = "<new_note>")]
-pub async fn add_note(
-    new_note: Json<note_api::NewNote>,
+/// * 409 Conflict
+/// * 413 Payload Too Large
+#[patch("/notes/<note_id>/content", data = "<text_patch>")]
+pub async fn patch_note(
+    note_id: i32,
+    text_patch: Json<note_api::TextPatch>,
+    auth: Result<AuthAccount, ApiErrors>,
+    version: Result<NoteVersion, ApiErrors>,
     mut db_conn: Connection<SPS>,
-) -> ApiResult<()> {
-    // Checking the user account actually exists
-    match sqlx::query!(
-        "SELECT account_id FROM tblAccount WHERE account_id = ?",
-        new_note.account_id
+) -> ApiResult<Json<note_api::NoteResponse>> {
+    let auth = auth?;
+
+    let db_note = match sqlx::query_as!(
+        db::Note,
+        "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
     )
     .fetch_one(&mut *db_conn)
     .await
     {
-        Ok(_) => (),
-        Err(_) => return Err(ApiErrors::NotFound("User account not found".to_string())),
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
+    };
+
+    if auth.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
     }
 
+    let version = version?;
+    version.check(db_note.version)?;
+
+    let content = text_patch.apply(&db_note.content)?;
+    validate_note_content(&content).await?;
+
+    let db_account = match sqlx::query!(
+        "SELECT bytes_used, storage_quota_bytes FROM tblAccount WHERE account_id = ?",
+        db_note.account_id
+    )
+    .fetch_one(&mut *db_conn)
+    .await
+    {
+        Ok(val) => val,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("User account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
+    };
+
+    check_storage_quota(
+        db_account.bytes_used,
+        db_account.storage_quota_bytes,
+        content.len() as i64 - db_note.content.len() as i64,
+    )
+    .await?;
+
+    // The patch was worked out from this version, so it only applies if the
+    // note is still on it
+    let updated_at = match content == db_note.content {
+        true => db_note.updated_at,
+        false => chrono::Utc::now().naive_utc().with_nanosecond(0).unwrap(),
+    };
     match sqlx::query!(
-        "INSERT INTO tblNotes (account_id, content, title, public) VALUES (?, ?, ?, ?)",
-        new_note.account_id,
-        new_note.note_content,
-        new_note.note_title,
-        new_note.note_public,
+        "UPDATE tblNotes SET content = ?, content_hash = ?, updated_at = ?, version = version + 1 WHERE note_id = ? AND version = ?",
+        content,
+        note_api::content_hash(&content),
+        updated_at,
+        note_id,
+        version.0,
     )
     .execute(&mut *db_conn)
     .await
     {
+        Ok(val) if val.rows_affected() == 0 => {
+            return Err(ApiErrors::Conflict(
+                "Note was changed while patching, fetch it again before patching".to_string(),
+            )
+            .with_code(ErrorCode::NoteVersionMismatch))
+        }
         Ok(_) => (),
-        #[cfg(not(tarpauling_include))]
+        #[cfg(not(tarpaulin_include))]
         Err(_) => {
             return Err(ApiErrors::InternalError(
-                "Unable to save file in database".to_string(),
+                "Failed to patch the note".to_string(),
             ))
         }
-    }
+    };
 
-    Ok(())
+    recompute_bytes_used(&mut *db_conn, db_note.account_id).await;
+
+    let patched = db::Note {
+        content,
+        updated_at,
+        version: db_note.version + 1,
+        ..db_note
+    };
+    Ok(Json((&patched).into()))
 }
 
-/// ## Update a specific notes file content
+/// ## Delete a notes file
 ///
-/// Update a the content of the note file, not the title
+/// Moves the note to the trash by flagging it as deleted. Deleted notes and
+/// their attachments are hidden from every fetch, but can be brought back with
+/// the restore endpoint
 ///
 /// ### Arguments
 ///
+/// * Account ID
 /// * Note ID
-/// * Updated note file
 ///
 /// ### Responses
 ///
 /// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
 /// * 404 Not Found
-#[put("/notes", data = "<update_note>")]
-pub async fn update_note(
-    update_note: Json<note_api::UpdateNote>,
+#[delete("/notes/<account_id>/<note_id>", rank = 3)]
+pub async fn remove_note(
+    account_id: i32,
+    note_id: i32,
+    auth: Result<AuthAccount, ApiErrors>,
     mut db_conn: Connection<SPS>,
 ) -> ApiResult<()> {
+    let auth = auth?;
+    auth.check_account(account_id)?;
+
     // Fetching the notes record
-    let _db_note = match sqlx::query_as!(
+    let db_note = match sqlx::query_as!(
         db::Note,
-        "SELECT note_id, account_id, title, content, public as `public: bool` FROM tblNotes WHERE note_id = ?",
-        update_note.note_id
+        "SELECT note_id, account_id, title, content, public as `public: bool`, created_at, updated_at, version FROM tblNotes WHERE note_id = ? AND deleted_at IS NULL",
+        note_id
     )
     .fetch_one(&mut *db_conn)
     .await
     {
         Ok(val) => val,
-        Err(_) => return Err(ApiErrors::NotFound("Note not found".to_string())),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
     };
 
-    // Updating the recrod
+    if auth.check_account(db_note.account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    match sqlx::query!("UPDATE tblNotes SET deleted_at = NOW() WHERE note_id = ?", note_id)
+        .execute(&mut *db_conn)
+        .await
+    {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove file from database".to_string(),
+            ))
+        }
+    }
+
+    recompute_bytes_used(&mut *db_conn, account_id).await;
+    audit::record(&mut *db_conn, auth.account_id, AuditAction::RemoveNote, note_id).await;
+
+    Ok(())
+}
+
+/// ## Delete many notes at once
+///
+/// Moves every listed note belonging to the account to the trash in a single
+/// transaction. IDs that don't exist or belong to another account are reported
+/// back rather than failing the whole request, but any database error rolls
+/// back every delete
+///
+/// ### Arguments
+///
+/// * Account ID
+/// * List of note IDs
+///
+/// ### Responses
+///
+/// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
+/// * 404 Not Found
+#[delete("/notes/<account_id>/bulk", data = "<note_ids>", rank = 2)]
+pub async fn remove_notes_bulk(
+    account_id: i32,
+    note_ids: Json<Vec<i32>>,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<Json<note_api::BulkRemoveResponse>> {
+    auth?.check_account(account_id)?;
+
+    // Checking the user account actually exists
     match sqlx::query!(
-        "UPDATE tblNotes SET title = ?, content = ?, public = ? WHERE note_id = ?",
-        update_note.note_title,
-        update_note.note_content,
-        update_note.note_public,
-        update_note.note_id,
+        "SELECT account_id FROM tblAccount WHERE account_id = ?",
+        account_id
     )
-    .execute(&mut *db_conn)
+    .fetch_one(&mut *db_conn)
     .await
     {
         Ok(_) => (),
-        #[cfg(not(tarpauling_include))]
+        Err(_) => {
+            return Err(ApiErrors::NotFound("User account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
+    }
+
+    let mut transaction = match (&mut *db_conn).begin().await {
+        Ok(val) => val,
+        #[cfg(not(tarpaulin_include))]
         Err(_) => {
             return Err(ApiErrors::InternalError(
-                "Failed to update the note".to_string(),
+                "Unable to start a database transaction".to_string(),
             ))
         }
     };
 
-    Ok(())
+    let mut summary = note_api::BulkRemoveResponse::default();
+    for note_id in note_ids.iter() {
+        match sqlx::query!(
+            "UPDATE tblNotes SET deleted_at = NOW() WHERE note_id = ? AND account_id = ? AND deleted_at IS NULL",
+            note_id,
+            account_id
+        )
+        .execute(&mut transaction)
+        .await
+        {
+            Ok(val) if val.rows_affected() > 0 => summary.removed.push(*note_id),
+            Ok(_) => summary.not_found.push(*note_id),
+            // Dropping the transaction without committing rolls it back
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => {
+                return Err(ApiErrors::InternalError(
+                    "Unable to remove notes from database".to_string(),
+                ))
+            }
+        }
+    }
+
+    recompute_bytes_used(&mut transaction, account_id).await;
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => {
+            return Err(ApiErrors::InternalError(
+                "Unable to remove notes from database".to_string(),
+            ))
+        }
+    }
+
+    Ok(Json(summary))
 }
 
-/// ## Delete a notes file
+/// ## Restore a deleted note
 ///
-/// Removes both the database record, and the static file for the note
+/// Takes a note back out of the trash
 ///
 /// ### Arguments
 ///
+/// * Account ID
 /// * Note ID
 ///
 /// ### Responses
 ///
 /// * 200 Ok
+/// * 401 Unauthorized
+/// * 403 Forbidden
 /// * 404 Not Found
-#[delete("/notes/<note_id>")]
-pub async fn remove_note(note_id: i32, mut db_conn: Connection<SPS>) -> ApiResult<()> {
-    // Fetching the notes record
-    let _db_note = match sqlx::query_as!(
-        db::Note,
-        "SELECT note_id, account_id, title, content, public as `public: bool` FROM tblNotes WHERE note_id = ?",
+#[post("/notes/<account_id>/<note_id>/restore")]
+pub async fn restore_note(
+    account_id: i32,
+    note_id: i32,
+    auth: Result<AuthAccount, ApiErrors>,
+    mut db_conn: Connection<SPS>,
+) -> ApiResult<()> {
+    let auth = auth?;
+    auth.check_account(account_id)?;
+
+    let note_account_id = match sqlx::query!(
+        "SELECT account_id FROM tblNotes WHERE note_id = ? AND deleted_at IS NOT NULL",
         note_id
     )
     .fetch_one(&mut *db_conn)
     .await
     {
-        Ok(val) => val,
-        Err(_) => return Err(ApiErrors::NotFound("Note not found".to_string())),
+        Ok(val) => val.account_id,
+        Err(_) => {
+            return Err(ApiErrors::NotFound("Deleted note not found".to_string())
+                .with_code(ErrorCode::NoteNotFound))
+        }
     };
 
-    match sqlx::query!("DELETE FROM tblNotes WHERE note_id = ?", note_id)
+    if auth.check_account(note_account_id).is_err() {
+        return Err(ApiErrors::Forbidden("Note does not belong to this account".to_string())
+            .with_code(ErrorCode::NoteNotOwned));
+    }
+
+    match sqlx::query!("UPDATE tblNotes SET deleted_at = NULL WHERE note_id = ?", note_id)
         .execute(&mut *db_conn)
         .await
     {
@@ -285,10 +3363,12 @@ pub async fn remove_note(note_id: i32, mut db_conn: Connection<SPS>) -> ApiResul
         #[cfg(not(tarpaulin_include))]
         Err(_) => {
             return Err(ApiErrors::InternalError(
-                "Unable to remove file from database".to_string(),
+                "Unable to restore the note".to_string(),
             ))
         }
     }
 
+    recompute_bytes_used(&mut *db_conn, account_id).await;
+
     Ok(())
 }
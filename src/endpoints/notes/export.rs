@@ -0,0 +1,210 @@
+//! # Note Export
+//! Writing an account's notes out as a ZIP archive. Entries are stored
+//! uncompressed, which keeps the writer small and lets each note be sent as
+//! soon as it is read, without holding the whole archive in memory
+
+use std::collections::HashSet;
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+/// Longest file name given to an exported note, not counting the extension
+const MAX_FILE_NAME_LENGTH: usize = 100;
+
+/// Name of the manifest written at the end of every export
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// One manifest line, tying an exported file back to its note
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub file_name: String,
+    pub note_id: i32,
+    pub note_title: String,
+}
+
+/// CRC-32 (IEEE) of some data, as ZIP archives expect
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+/// Turn a note title into a file name that is safe on every platform
+fn sanitize_file_name(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .map(|c| match c.is_alphanumeric() || " -_.()".contains(c) {
+            true => c,
+            false => '_',
+        })
+        .take(MAX_FILE_NAME_LENGTH)
+        .collect();
+
+    // Leading dots would hide the file, trailing dots and spaces upset Windows
+    let cleaned = cleaned.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    match cleaned.is_empty() {
+        true => "Untitled".to_string(),
+        false => cleaned.to_string(),
+    }
+}
+
+/// Hands out a unique file name for every note in an export
+#[derive(Default)]
+pub struct FileNames {
+    used: HashSet<String>,
+}
+
+impl FileNames {
+    /// File name for a note with the given title. Titles that clash get a
+    /// numbered suffix, ignoring case
+    pub fn next(&mut self, title: &str) -> String {
+        let base = sanitize_file_name(title);
+        let mut name = format!("{}.md", base);
+        let mut count = 1;
+
+        while !self.used.insert(name.to_lowercase()) {
+            count += 1;
+            name = format!("{} ({}).md", base, count);
+        }
+
+        name
+    }
+}
+
+/// Date and time in the MS-DOS format used by ZIP headers
+fn dos_date_time(modified: &NaiveDateTime) -> (u16, u16) {
+    // DOS dates start in 1980
+    let year = modified.year().clamp(1980, 2107) as u16;
+    let date = ((year - 1980) << 9) | ((modified.month() as u16) << 5) | modified.day() as u16;
+    let time = ((modified.hour() as u16) << 11)
+        | ((modified.minute() as u16) << 5)
+        | (modified.second() as u16 / 2);
+
+    (date, time)
+}
+
+/// What the central directory needs to know about an entry already written
+struct CentralEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+    date: u16,
+    time: u16,
+}
+
+/// ## ZIP writer
+///
+/// Produces an archive one piece at a time. `add` gives back the bytes for an
+/// entry, and `finish` the central directory that ends the archive
+#[derive(Default)]
+pub struct ZipWriter {
+    offset: u32,
+    entries: Vec<CentralEntry>,
+}
+
+impl ZipWriter {
+    /// Bytes for one file in the archive
+    pub fn add(&mut self, name: &str, data: &[u8], modified: &NaiveDateTime) -> Vec<u8> {
+        let (date, time) = dos_date_time(modified);
+        let entry = CentralEntry {
+            name: name.to_string(),
+            crc: crc32(data),
+            size: data.len() as u32,
+            offset: self.offset,
+            date,
+            time,
+        };
+
+        let mut bytes = Vec::with_capacity(30 + name.len() + data.len());
+        bytes.extend_from_slice(&0x0403_4B50u32.to_le_bytes());
+        bytes.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        bytes.extend_from_slice(&0x0800u16.to_le_bytes()); // names are UTF-8
+        bytes.extend_from_slice(&0u16.to_le_bytes()); // stored, no compression
+        bytes.extend_from_slice(&entry.time.to_le_bytes());
+        bytes.extend_from_slice(&entry.date.to_le_bytes());
+        bytes.extend_from_slice(&entry.crc.to_le_bytes());
+        bytes.extend_from_slice(&entry.size.to_le_bytes()); // compressed size
+        bytes.extend_from_slice(&entry.size.to_le_bytes()); // uncompressed size
+        bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(data);
+
+        self.offset += bytes.len() as u32;
+        self.entries.push(entry);
+
+        bytes
+    }
+
+    /// Bytes for the central directory, closing off the archive
+    pub fn finish(self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        for entry in &self.entries {
+            bytes.extend_from_slice(&0x0201_4B50u32.to_le_bytes());
+            bytes.extend_from_slice(&20u16.to_le_bytes()); // version made by
+            bytes.extend_from_slice(&20u16.to_le_bytes()); // version needed
+            bytes.extend_from_slice(&0x0800u16.to_le_bytes());
+            bytes.extend_from_slice(&0u16.to_le_bytes());
+            bytes.extend_from_slice(&entry.time.to_le_bytes());
+            bytes.extend_from_slice(&entry.date.to_le_bytes());
+            bytes.extend_from_slice(&entry.crc.to_le_bytes());
+            bytes.extend_from_slice(&entry.size.to_le_bytes());
+            bytes.extend_from_slice(&entry.size.to_le_bytes());
+            bytes.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+            bytes.extend_from_slice(&0u16.to_le_bytes()); // comment length
+            bytes.extend_from_slice(&0u16.to_le_bytes()); // disk number
+            bytes.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+            bytes.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+            bytes.extend_from_slice(&entry.offset.to_le_bytes());
+            bytes.extend_from_slice(entry.name.as_bytes());
+        }
+
+        let count = self.entries.len() as u16;
+        let directory_size = bytes.len() as u32;
+        bytes.extend_from_slice(&0x0605_4B50u32.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes()); // this disk
+        bytes.extend_from_slice(&0u16.to_le_bytes()); // disk with the directory
+        bytes.extend_from_slice(&count.to_le_bytes());
+        bytes.extend_from_slice(&count.to_le_bytes());
+        bytes.extend_from_slice(&directory_size.to_le_bytes());
+        bytes.extend_from_slice(&self.offset.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes()); // comment length
+
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(super::crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_file_names_unsafe_characters_replaced() {
+        let mut names = super::FileNames::default();
+        assert_eq!(names.next("Week 1/2: ward \"notes\""), "Week 1_2_ ward _notes_.md");
+        assert_eq!(names.next("../../etc/passwd"), "_.._etc_passwd.md");
+        assert_eq!(names.next("   "), "Untitled.md");
+    }
+
+    #[test]
+    fn test_file_names_duplicates_numbered() {
+        let mut names = super::FileNames::default();
+        assert_eq!(names.next("Rounds"), "Rounds.md");
+        assert_eq!(names.next("rounds"), "rounds (2).md");
+        assert_eq!(names.next("Rounds"), "Rounds (3).md");
+    }
+}
@@ -1,13 +1,40 @@
+use chrono::{TimeZone, Utc};
+use crypto::{digest::Digest, sha2::Sha256};
+use std::io::Cursor;
+
+use pulldown_cmark::{html, Event, Options, Parser};
+use rocket::http::ContentType;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use rocket::Request;
 use serde::{Deserialize, Serialize};
 
 use crate::db;
+use crate::endpoints::errors::{ApiErrors, ApiResult, ErrorBody};
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct NoteResponse {
     pub note_id: i32,
     pub note_title: String,
     pub note_content: String,
     pub note_public: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    pub note_version: i32,
+    /// Size of the content in bytes
+    pub byte_size: usize,
+    /// Start of the content for list previews, only sent when asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+}
+
+/// A note found by a search, with how well it matched the query. Higher
+/// scores are more relevant
+#[derive(Serialize, Deserialize)]
+pub struct NoteSearchResult {
+    #[serde(flatten)]
+    pub note: NoteResponse,
+    pub score: f64,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -18,6 +45,38 @@ pub struct NewNote {
     pub note_public: bool,
 }
 
+/// Result of checking a new note without saving it
+#[derive(Serialize, Deserialize)]
+pub struct NoteValidation {
+    pub valid: bool,
+    /// Title the note would be saved with, numbered if `on_duplicate=suffix` needed to
+    pub note_title: String,
+    pub errors: Vec<ErrorBody>,
+}
+
+/// What adding a note sends back, the note that was added or, when only
+/// validating, the report on it
+#[derive(Responder)]
+pub enum AddNoteResponse {
+    Added(Json<NoteResponse>),
+    Validated(Json<NoteValidation>),
+}
+
+/// What a HEAD request for a note sends back. The body is left empty, but
+/// sized to the note so Content-Length matches the download
+pub struct NoteHead {
+    pub size: usize,
+}
+
+impl<'r> Responder<'r, 'static> for NoteHead {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(ContentType::Markdown)
+            .sized_body(Some(self.size), Cursor::new(&[][..]))
+            .ok()
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct UpdateNote {
     pub note_id: i32,
@@ -26,6 +85,90 @@ pub struct UpdateNote {
     pub note_public: bool,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct NoteTitle {
+    pub note_title: String,
+}
+
+/// ## Line range replacement
+///
+/// Replaces lines `start` up to but not including `end`, counted from 0,
+/// with `replacement`. `start == end` inserts before line `start`. The
+/// replacement goes in as is, so it should end with a newline unless it ends
+/// the note
+#[derive(Serialize, Deserialize)]
+pub struct TextPatch {
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+}
+
+impl TextPatch {
+    /// Content with the patch applied, or an error if the range doesn't fit it
+    pub fn apply(&self, content: &str) -> ApiResult<String> {
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+
+        if self.start > self.end || self.end > lines.len() {
+            return Err(ApiErrors::BadRequest(format!(
+                "Lines {} to {} are outside the note, which has {} lines",
+                self.start,
+                self.end,
+                lines.len()
+            )));
+        }
+
+        Ok(lines[..self.start]
+            .iter()
+            .copied()
+            .chain(std::iter::once(self.replacement.as_str()))
+            .chain(lines[self.end..].iter().copied())
+            .collect())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NewTag {
+    pub tag: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NoteIntegrity {
+    pub note_id: i32,
+    pub stored_hash: Option<String>,
+    pub computed_hash: String,
+    pub matches: bool,
+}
+
+/// A file attached to a note, without its contents
+#[derive(Serialize, Deserialize)]
+pub struct AttachmentResponse {
+    pub attachment_id: i32,
+    pub note_id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i32,
+    pub created_at: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct BulkRemoveResponse {
+    pub removed: Vec<i32>,
+    pub not_found: Vec<i32>,
+}
+
+impl From<&db::NoteAttachment> for AttachmentResponse {
+    fn from(value: &db::NoteAttachment) -> Self {
+        AttachmentResponse {
+            attachment_id: value.attachment_id,
+            note_id: value.note_id,
+            file_name: value.file_name.clone(),
+            content_type: value.content_type.clone(),
+            size: value.size,
+            created_at: Utc.from_utc_datetime(&value.created_at).to_string(),
+        }
+    }
+}
+
 impl From<&db::Note> for NoteResponse {
     fn from(value: &db::Note) -> Self {
         NoteResponse {
@@ -33,6 +176,46 @@ impl From<&db::Note> for NoteResponse {
             note_title: value.title.clone(),
             note_content: value.content.clone(),
             note_public: value.public.clone(),
+            created_at: Utc.from_utc_datetime(&value.created_at).to_string(),
+            updated_at: Utc.from_utc_datetime(&value.updated_at).to_string(),
+            note_version: value.version,
+            byte_size: value.content.len(),
+            preview: None,
         }
     }
 }
+
+/// Characters of content kept in a note preview
+pub const PREVIEW_LENGTH: usize = 200;
+
+/// The first `PREVIEW_LENGTH` characters of a note, with an ellipsis when
+/// the rest has been cut off
+pub fn preview(content: &str) -> String {
+    match content.char_indices().nth(PREVIEW_LENGTH) {
+        Some((cut, _)) => format!("{}…", content[..cut].trim_end()),
+        None => content.to_string(),
+    }
+}
+
+/// Render a note's markdown to HTML that is safe to show in a browser.
+/// Raw HTML in the markdown is shown as text, and the output is sanitized
+/// to catch anything else, such as `javascript:` links
+pub fn render_markdown(markdown: &str) -> String {
+    let events = Parser::new_ext(markdown, Options::all()).map(|event| match event {
+        Event::Html(raw) => Event::Text(raw),
+        _ => event,
+    });
+
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, events);
+
+    ammonia::clean(&rendered)
+}
+
+/// SHA-256 of a note's content as hex, stored alongside the note so changes
+/// made behind the API's back can be spotted
+pub fn content_hash(content: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input_str(content);
+    hasher.result_str()
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+
+#[derive(Serialize, Deserialize)]
+pub struct ProtocolRequest {
+    pub title: String,
+    pub content: Option<String>,
+    /// Group the protocol is listed under, such as fire or medical
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+/// A protocol as listed by `fetch_protocols`. Searches also give how well the
+/// protocol matched, higher being more relevant
+#[derive(Serialize, Deserialize)]
+pub struct ProtocolResult {
+    #[serde(flatten)]
+    pub protocol: db::Protocol,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+}
@@ -6,119 +6,2924 @@ use crate::tests::CLIENT;
 #[test]
 fn test_fetch_protocols_none_ok() {
     let client_binding = crate::tests::CLIENT.lock().unwrap();
-    let response = client_binding.get(uri!(super::fetch_protocols)).dispatch();
+    let response = client_binding.get(uri!(super::fetch_protocols(_, _, _, _))).dispatch();
     assert_eq!(response.status(), Status::Ok);
     assert!(response.body().is_some());
 }
 
 #[test]
-fn test_fetch_public_notes_none_ok() {
+fn test_fetch_protocols_keyword_search_ok() {
     let client_binding = CLIENT.lock().unwrap();
-    let response = client_binding.get(uri!(super::fetch_public_notes)).dispatch();
+    let response = client_binding
+        .get(uri!(super::fetch_protocols(Some("stayin alive"), _, _, _)))
+        .dispatch();
     assert_eq!(response.status(), Status::Ok);
-    assert!(response.body().is_some());
+
+    let protocols = response.into_json::<Vec<crate::db::Protocol>>().unwrap();
+    assert_eq!(protocols.len(), 1);
+    assert_eq!(protocols[0].title, "CPR");
 }
 
 #[test]
-fn test_fetch_notes_existing_account_with_notes_ok() {
+fn test_fetch_protocols_no_match_empty_ok() {
     let client_binding = CLIENT.lock().unwrap();
-    let response = client_binding.get(uri!(super::fetch_notes(1))).dispatch();
+    let response = client_binding
+        .get(uri!(super::fetch_protocols(Some("zebra%_unmatched"), _, _, _)))
+        .dispatch();
     assert_eq!(response.status(), Status::Ok);
-    assert!(response.body().is_some());
+    assert!(response.into_json::<Vec<crate::db::Protocol>>().unwrap().is_empty());
 }
 
 #[test]
-fn test_fetch_notes_non_existing_account_not_found() {
+fn test_fetch_protocols_page_window_ok() {
     let client_binding = CLIENT.lock().unwrap();
-    let response = client_binding.get(uri!(super::fetch_notes(0))).dispatch();
-    assert_eq!(response.status(), Status::NotFound);
-    assert!(response.body().is_some());
+    let response = client_binding
+        .get(uri!(super::fetch_protocols(_, _, Some(1), Some(1))))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let protocols = response.into_json::<Vec<crate::db::Protocol>>().unwrap();
+    assert_eq!(protocols.len(), 1);
+    assert_eq!(protocols[0].title, "ABC");
 }
 
 #[test]
-fn test_fetch_notes_existing_account_no_notes_not_found() {
+fn test_fetch_protocols_limit_too_large_bad_request() {
     let client_binding = CLIENT.lock().unwrap();
-    let response = client_binding.get(uri!(super::fetch_notes(2))).dispatch();
-    assert_eq!(response.status(), Status::NotFound);
-    assert!(response.body().is_some());
+    let response = client_binding
+        .get(uri!(super::fetch_protocols(_, _, Some(crate::endpoints::params::MAX_LIMIT + 1), _)))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+/// Add a protocol in the given category as the seeded admin account
+fn add_categorised_protocol(
+    client: &rocket::local::blocking::Client,
+    title: &str,
+    category: &str,
+) -> crate::db::Protocol {
+    let req_body = super::protocol_api::ProtocolRequest {
+        title: title.to_string(),
+        content: Some("Rocket test protocol content".to_string()),
+        category: Some(category.to_string()),
+    };
+    let response = client
+        .post(uri!(super::add_protocol))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json::<crate::db::Protocol>().unwrap()
+}
+
+/// Remove a protocol added by a test
+fn remove_test_protocol(client: &rocket::local::blocking::Client, protocol_id: i32) {
+    let response = client
+        .delete(uri!(super::remove_protocol(protocol_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
 }
 
 #[test]
-fn test_add_note_existing_account_ok() {
+fn test_fetch_protocols_category_filter_ok() {
     let client_binding = CLIENT.lock().unwrap();
+    let fire = add_categorised_protocol(&client_binding, "Rocket Fire Evacuation", " Fire ");
+    let medical = add_categorised_protocol(&client_binding, "Rocket Recovery Position", "medical");
+    assert_eq!(fire.category.as_deref(), Some("fire"));
 
-    let req_body = super::note_api::NewNote {
-        account_id: 1,
-        note_title: "Rocket Test Note".to_string(),
-        note_content: "".to_string(),
-        note_public: false,
+    let response = client_binding
+        .get(uri!(super::fetch_protocols(_, Some("FIRE"), _, _)))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let protocols = response.into_json::<Vec<crate::db::Protocol>>().unwrap();
+    assert!(protocols.iter().all(|p| p.category.as_deref() == Some("fire")));
+    assert!(protocols.iter().any(|p| p.protocol_id == fire.protocol_id));
+    assert!(protocols.iter().all(|p| p.protocol_id != medical.protocol_id));
+
+    let response = client_binding
+        .get(uri!(super::fetch_protocol_categories))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let categories = response.into_json::<Vec<String>>().unwrap();
+    assert!(categories.contains(&"fire".to_string()));
+    assert!(categories.contains(&"medical".to_string()));
+    assert_eq!(categories.iter().filter(|val| *val == "medical").count(), 1);
+
+    remove_test_protocol(&client_binding, fire.protocol_id);
+    remove_test_protocol(&client_binding, medical.protocol_id);
+}
+
+#[test]
+fn test_fetch_protocols_search_more_relevant_ranked_first() {
+    let client_binding = CLIENT.lock().unwrap();
+    let weaker = add_categorised_protocol(&client_binding, "Rocket Burn Dressing", "fire");
+    let stronger = add_categorised_protocol(&client_binding, "Rocket Chemical Burn", "fire");
+
+    let response = client_binding
+        .get(uri!(super::fetch_protocols(Some("chemical burn"), _, _, _)))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let results = response
+        .into_json::<Vec<super::protocol_api::ProtocolResult>>()
+        .unwrap();
+    let position = |protocol_id| {
+        results
+            .iter()
+            .position(|val| val.protocol.protocol_id == protocol_id)
+            .unwrap()
     };
+    assert!(position(stronger.protocol_id) < position(weaker.protocol_id));
+    assert!(results.iter().all(|val| val.score.is_some()));
+
+    remove_test_protocol(&client_binding, weaker.protocol_id);
+    remove_test_protocol(&client_binding, stronger.protocol_id);
+}
 
+#[test]
+fn test_fetch_protocols_unused_category_empty_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_protocols(_, Some("flood"), _, _)))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.into_json::<Vec<crate::db::Protocol>>().unwrap().is_empty());
+}
+
+#[test]
+fn test_add_protocol_category_too_long_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let req_body = super::protocol_api::ProtocolRequest {
+        title: "Rocket Long Category Protocol".to_string(),
+        content: None,
+        category: Some("x".repeat(65)),
+    };
     let response = client_binding
-        .post(uri!(super::add_note))
+        .post(uri!(super::add_protocol))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
         .body(serde_json::to_string(&req_body).unwrap())
         .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
 
+#[test]
+fn test_fetch_protocol_existing_protocol_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get(uri!(super::fetch_protocol(1))).dispatch();
     assert_eq!(response.status(), Status::Ok);
-    assert!(response.body().is_none());
+    let protocol = response.into_json::<crate::db::Protocol>().unwrap();
+    assert_eq!(protocol.protocol_id, 1);
+    assert_eq!(protocol.title, "HHHH");
 }
 
 #[test]
-fn test_add_note_non_existing_account_not_found() {
+fn test_fetch_protocol_nonexisting_protocol_not_found() {
     let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get(uri!(super::fetch_protocol(0))).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
 
-    let req_body = super::note_api::NewNote {
-        account_id: 0,
-        note_title: "Rocket Test Note".to_string(),
-        note_content: "".to_string(),
-        note_public: false,
+    let body = response.into_json::<crate::endpoints::errors::ErrorBody>().unwrap();
+    assert_eq!(body.code, crate::endpoints::errors::ErrorCode::ProtocolNotFound);
+}
+
+#[test]
+fn test_protocol_lifecycle_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::protocol_api::ProtocolRequest {
+        title: "Rocket Test Protocol".to_string(),
+        content: Some("Rocket test protocol content".to_string()),
+        category: None,
     };
+    let response = client_binding
+        .post(uri!(super::add_protocol))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let protocol = response.into_json::<crate::db::Protocol>().unwrap();
 
+    let response = client_binding.get(uri!(super::fetch_protocols(_, _, _, _))).dispatch();
+    let protocols = response.into_json::<Vec<crate::db::Protocol>>().unwrap();
+    let fetched = protocols.iter().find(|p| p.protocol_id == protocol.protocol_id).unwrap();
+    assert_eq!(fetched.title, "Rocket Test Protocol");
+
+    let req_body = super::protocol_api::ProtocolRequest {
+        title: "Rocket Test Protocol".to_string(),
+        content: Some("Updated rocket test protocol content".to_string()),
+        category: None,
+    };
     let response = client_binding
-        .post(uri!(super::add_note))
+        .put(uri!(super::update_protocol(protocol.protocol_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
         .body(serde_json::to_string(&req_body).unwrap())
         .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client_binding.get(uri!(super::fetch_protocols(_, _, _, _))).dispatch();
+    let protocols = response.into_json::<Vec<crate::db::Protocol>>().unwrap();
+    let fetched = protocols.iter().find(|p| p.protocol_id == protocol.protocol_id).unwrap();
+    assert_eq!(fetched.content, Some("Updated rocket test protocol content".to_string()));
+
+    let response = client_binding
+        .delete(uri!(super::remove_protocol(protocol.protocol_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client_binding.get(uri!(super::fetch_protocols(_, _, _, _))).dispatch();
+    let protocols = response.into_json::<Vec<crate::db::Protocol>>().unwrap();
+    assert!(protocols.iter().all(|p| p.protocol_id != protocol.protocol_id));
+}
+
+#[test]
+fn test_update_protocol_nonexisting_protocol_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
 
+    let req_body = super::protocol_api::ProtocolRequest {
+        title: "Rocket Test Protocol".to_string(),
+        content: None,
+        category: None,
+    };
+    let response = client_binding
+        .put(uri!(super::update_protocol(0)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
     assert_eq!(response.status(), Status::NotFound);
     assert!(response.body().is_some());
 }
 
 #[test]
-fn test_update_note_existing_note_ok() {
+fn test_remove_protocol_nonexisting_protocol_not_found() {
     let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.delete(uri!(super::remove_protocol(0)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.body().is_some());
+}
 
-    let req_body = super::note_api::UpdateNote {
-        note_content: "Today we covered respiratory physiology. Here are the key points:\n\n* Oxygen and carbon dioxide exchange occurs in the alveoli of the lungs.\n* The respiratory system is controlled by the medulla oblongata in the brainstem.\n* The diaphragm and intercostal muscles are responsible for breathing.".to_string(),
-        note_id: 1,
-        note_title: "Lecture Notes".to_string(),
-        note_public: false,
-    };
+#[test]
+fn test_fetch_public_notes_none_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get(uri!(super::fetch_public_notes)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.body().is_some());
+}
 
+#[test]
+fn test_fetch_notes_existing_account_with_notes_ok() {
+    let client_binding = CLIENT.lock().unwrap();
     let response = client_binding
-        .put(uri!(super::update_note))
-        .body(serde_json::to_string(&req_body).unwrap())
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_notes_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_notes(3, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
         .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_notes_no_session_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get(uri!(super::fetch_notes(1, _, _, _, _, _))).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
 
+/// An account's notes as listed by `fetch_notes`, with or without previews
+fn fetch_previews(
+    client: &rocket::local::blocking::Client,
+    account_id: i32,
+    token: &str,
+    preview: Option<bool>,
+) -> Vec<super::note_api::NoteResponse> {
+    let response = client
+        .get(uri!(super::fetch_notes(account_id, _, _, _, _, preview)))
+        .header(crate::tests::bearer(token))
+        .dispatch();
     assert_eq!(response.status(), Status::Ok);
-    assert!(response.body().is_none());
+
+    response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap()
 }
 
 #[test]
-fn test_update_note_nonexisting_note_not_found() {
+fn test_fetch_notes_preview_shortened_content_ok() {
     let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_quota_account(1_000_000);
+    let token = crate::tests::create_session(account_id);
+    assert_eq!(add_sized_note(&client_binding, account_id, 300).status(), Status::Ok);
 
-    let req_body = super::note_api::UpdateNote {
-        note_content: "Today we covered respiratory physiology. Here are the key points:\n\n* Oxygen and carbon dioxide exchange occurs in the alveoli of the lungs.\n* The respiratory system is controlled by the medulla oblongata in the brainstem.\n* The diaphragm and intercostal muscles are responsible for breathing.".to_string(),
-        note_id: 0,
-        note_title: "Lecture Notes".to_string(),
-        note_public: false,
+    let notes = fetch_previews(&client_binding, account_id, &token, Some(true));
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].byte_size, 300);
+    let preview = notes[0].preview.as_deref().unwrap();
+    assert_eq!(preview, format!("{}…", "q".repeat(super::note_api::PREVIEW_LENGTH)));
+}
+
+#[test]
+fn test_fetch_notes_no_preview_flag_size_only_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_quota_account(1_000_000);
+    let token = crate::tests::create_session(account_id);
+    assert_eq!(add_sized_note(&client_binding, account_id, 300).status(), Status::Ok);
+
+    for preview in [None, Some(false)] {
+        let notes = fetch_previews(&client_binding, account_id, &token, preview);
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].byte_size, 300);
+        assert!(notes[0].preview.is_none());
+    }
+}
+
+#[test]
+fn test_preview_short_content_unchanged() {
+    assert_eq!(super::note_api::preview("Ward round at 8"), "Ward round at 8");
+
+    let content = format!("{}   trailing", "é".repeat(super::note_api::PREVIEW_LENGTH - 3));
+    assert_eq!(
+        super::note_api::preview(&content),
+        format!("{}…", "é".repeat(super::note_api::PREVIEW_LENGTH - 3))
+    );
+}
+
+#[test]
+fn test_fetch_notes_session_cookie_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .cookie(rocket::http::Cookie::new("session_token", crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_notes_existing_account_no_notes_empty_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_notes(3, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    assert!(notes.is_empty());
+}
+
+#[test]
+fn test_fetch_notes_envelope_total_and_page_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get("/notes/1?limit=1&offset=1&envelope=true")
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let page = response
+        .into_json::<crate::endpoints::params::Envelope<super::note_api::NoteResponse>>()
+        .unwrap();
+    assert_eq!(page.data.len(), 1);
+    assert_eq!(page.limit, 1);
+    assert_eq!(page.offset, 1);
+    assert!(page.total >= 2);
+}
+
+#[test]
+fn test_fetch_notes_no_envelope_bare_array_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get("/notes/1?limit=1")
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    assert_eq!(notes.len(), 1);
+}
+
+#[test]
+fn test_fetch_notes_no_limit_or_envelope_every_note_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_quota_account(1_000_000);
+    let token = crate::tests::create_session(account_id);
+
+    // One more than the default page size
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            for number in 0..101 {
+                sqlx::query("INSERT INTO tblNotes (account_id, title, content) VALUES (?, ?, '')")
+                    .bind(account_id)
+                    .bind(format!("Rocket Unpaged Note {}", number))
+                    .execute(&mut *conn)
+                    .await
+                    .unwrap();
+            }
+        })
+    });
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(account_id, _, _, _, _, _)))
+        .header(crate::tests::bearer(&token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    assert_eq!(notes.len(), 101);
+
+    let response = client_binding
+        .get(format!("/notes/{}?envelope=true", account_id))
+        .header(crate::tests::bearer(&token))
+        .dispatch();
+    let page = response
+        .into_json::<crate::endpoints::params::Envelope<super::note_api::NoteResponse>>()
+        .unwrap();
+    assert_eq!(page.data.len(), 100);
+    assert_eq!(page.total, 101);
+}
+
+#[test]
+fn test_fetch_notes_limit_over_max_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get("/notes/1?limit=501")
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.into_json::<crate::endpoints::errors::ErrorBody>().unwrap();
+    assert_eq!(body.code, crate::endpoints::errors::ErrorCode::BadRequest);
+    assert_eq!(body.message, "Limit may not be more than 500");
+}
+
+#[test]
+fn test_download_note_owner_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::download_note(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(rocket::http::ContentType::Markdown));
+    assert!(response.into_string().unwrap().contains("respiratory physiology"));
+}
+
+#[test]
+fn test_download_note_matching_etag_not_modified() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket ETag Test Note", "Poll me");
+    let download = |etag: Option<&str>| {
+        let request = client_binding
+            .get(uri!(super::download_note(note_id)))
+            .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN));
+        match etag {
+            Some(val) => request.header(rocket::http::Header::new("If-None-Match", val.to_string())),
+            None => request,
+        }
+        .dispatch()
     };
 
+    let response = download(None);
+    assert_eq!(response.status(), Status::Ok);
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+
+    let response = download(Some(&etag));
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(response.headers().get_one("ETag"), Some(etag.as_str()));
+    assert!(response.into_string().unwrap_or_default().is_empty());
+
+    let response = download(Some("\"stale\""));
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "Poll me");
+}
+
+#[test]
+fn test_download_note_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
     let response = client_binding
-        .put(uri!(super::update_note))
-        .body(serde_json::to_string(&req_body).unwrap())
+        .get(uri!(super::download_note(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
         .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
 
+#[test]
+fn test_download_note_nonexisting_note_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::download_note(0)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
     assert_eq!(response.status(), Status::NotFound);
     assert!(response.body().is_some());
 }
+
+#[test]
+fn test_head_note_download_headers_empty_body_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let content = "Sized in bytes: café";
+    let note_id = add_test_note(&client_binding, "Rocket HEAD Test Note", content);
+
+    let download = client_binding
+        .get(uri!(super::download_note(note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(download.status(), Status::Ok);
+
+    let head = client_binding
+        .head(uri!(super::head_note(note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(head.status(), Status::Ok);
+    assert_eq!(head.content_type(), Some(rocket::http::ContentType::Markdown));
+    assert_eq!(head.body().preset_size(), Some(content.len()));
+    for header in ["ETag", "Last-Modified"] {
+        assert!(head.headers().get_one(header).is_some());
+        assert_eq!(head.headers().get_one(header), download.headers().get_one(header));
+    }
+    assert!(head.into_bytes().unwrap_or_default().is_empty());
+}
+
+#[test]
+fn test_head_note_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .head(uri!(super::head_note(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+#[test]
+fn test_download_note_no_session_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get(uri!(super::download_note(1))).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
+
+/// Tag a note as the seeded admin account, which owns notes 1 and 2
+fn tag_note<'c>(
+    client: &'c rocket::local::blocking::Client,
+    note_id: i32,
+    tag: &str,
+) -> rocket::local::blocking::LocalResponse<'c> {
+    let body = super::note_api::NewTag { tag: tag.to_string() };
+    client
+        .post(uri!(super::add_note_tag(note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch()
+}
+
+#[test]
+fn test_add_note_tag_normalized_and_deduplicated_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let response = tag_note(&client_binding, 1, "  Cardiology ");
+    assert_eq!(response.status(), Status::Ok);
+    let tags = response.into_json::<Vec<String>>().unwrap();
+    assert!(tags.contains(&"cardiology".to_string()));
+
+    let response = tag_note(&client_binding, 1, "CARDIOLOGY");
+    assert_eq!(response.status(), Status::Ok);
+    let tags = response.into_json::<Vec<String>>().unwrap();
+    assert_eq!(tags.iter().filter(|tag| *tag == "cardiology").count(), 1);
+}
+
+#[test]
+fn test_add_note_tag_empty_tag_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = tag_note(&client_binding, 1, "   ");
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_add_note_tag_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let body = super::note_api::NewTag { tag: "not mine".to_string() };
+    let response = client_binding
+        .post(uri!(super::add_note_tag(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_notes_tag_filter_untagged_excluded_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = tag_note(&client_binding, 1, "respiratory");
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, Some("Respiratory"), _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    let note_ids: Vec<i32> = notes.iter().map(|note| note.note_id).collect();
+    assert_eq!(note_ids, vec![1]);
+}
+
+#[test]
+fn test_fetch_notes_unused_tag_empty_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, Some("no note has this tag"), _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "[]");
+}
+
+#[test]
+fn test_remove_note_tag_tag_removed_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = tag_note(&client_binding, 2, "exam prep");
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client_binding
+        .delete(uri!(super::remove_note_tag(2, "Exam Prep")))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client_binding
+        .delete(uri!(super::remove_note_tag(2, "exam prep")))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_content_hash_sha256_hex() {
+    assert_eq!(
+        super::note_api::content_hash("abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
+/// Add a note for the seeded admin account, returning its id
+fn add_test_note(client: &rocket::local::blocking::Client, title: &str, content: &str) -> i32 {
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: title.to_string(),
+        note_content: content.to_string(),
+        note_public: false,
+    };
+    let response = client
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json::<super::note_api::NoteResponse>().unwrap().note_id
+}
+
+/// Check a note's integrity as the seeded admin account
+fn verify(client: &rocket::local::blocking::Client, note_id: i32) -> super::note_api::NoteIntegrity {
+    let response = client
+        .get(uri!(super::verify_note(note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json::<super::note_api::NoteIntegrity>().unwrap()
+}
+
+#[test]
+fn test_verify_note_untouched_note_matches() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket Integrity Test Note", "Intact");
+
+    let integrity = verify(&client_binding, note_id);
+    assert!(integrity.matches);
+    assert_eq!(integrity.stored_hash, Some(integrity.computed_hash));
+}
+
+#[test]
+fn test_verify_note_tampered_content_mismatch() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket Tamper Test Note", "Original content");
+
+    // Change the content behind the API's back, like a bad disk or a stray query would
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query("UPDATE tblNotes SET content = 'Original conten' WHERE note_id = ?")
+                .bind(note_id)
+                .execute(conn)
+                .await
+                .unwrap();
+        })
+    });
+
+    let integrity = verify(&client_binding, note_id);
+    assert!(!integrity.matches);
+    assert_ne!(integrity.stored_hash, Some(integrity.computed_hash));
+}
+
+#[test]
+fn test_verify_note_regular_user_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::verify_note(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_transfer_note_existing_target_owner_changed() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket Transfer Test Note", "Moving house");
+
+    let response = client_binding
+        .put(uri!(super::transfer_note(note_id, 2)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // The new owner can read it, the old one no longer can
+    let new_owner_token = crate::tests::create_session(2);
+    let response = client_binding
+        .get(uri!(super::download_note(note_id)))
+        .header(crate::tests::bearer(&new_owner_token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "Moving house");
+
+    let response = client_binding
+        .get(uri!(super::download_note(note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+#[test]
+fn test_transfer_note_nonexisting_target_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .put(uri!(super::transfer_note(1, 0)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.into_string().unwrap().contains("Target account not found"));
+}
+
+#[test]
+fn test_transfer_note_regular_user_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .put(uri!(super::transfer_note(1, 3)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+/// Names of the files in a ZIP archive, read from its local file headers
+fn zip_entry_names(archive: &[u8]) -> Vec<String> {
+    let read_u16 = |at: usize| u16::from_le_bytes([archive[at], archive[at + 1]]) as usize;
+    let read_u32 = |at: usize| {
+        u32::from_le_bytes([archive[at], archive[at + 1], archive[at + 2], archive[at + 3]]) as usize
+    };
+
+    let mut names = Vec::new();
+    let mut at = 0;
+    while read_u32(at) == 0x0403_4B50 {
+        let size = read_u32(at + 18);
+        let name_length = read_u16(at + 26);
+        let extra_length = read_u16(at + 28);
+        names.push(String::from_utf8(archive[at + 30..at + 30 + name_length].to_vec()).unwrap());
+        at += 30 + name_length + extra_length + size;
+    }
+
+    names
+}
+
+#[test]
+fn test_export_notes_two_notes_both_in_archive_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let new_account = serde_json::json!({
+        "email": "4040404@students.wits.ac.za",
+        "hashed_password": "password_1",
+        "username": "notes_export_account",
+        "cell_number": null,
+    });
+    let response = client_binding
+        .post(uri!(crate::endpoints::account::create_account))
+        .body(new_account.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let account_id = response.into_json::<serde_json::Value>().unwrap()["account_id"]
+        .as_i64()
+        .unwrap() as i32;
+
+    let token = crate::tests::create_session(account_id);
+    for title in ["Ward Rounds", "ward rounds"] {
+        let req_body = super::note_api::NewNote {
+            account_id,
+            note_title: title.to_string(),
+            note_content: format!("# {}", title),
+            note_public: false,
+        };
+        let response = client_binding
+            .post(uri!(super::add_note(_, _)))
+            .header(crate::tests::bearer(&token))
+            .body(serde_json::to_string(&req_body).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    let response = client_binding
+        .get(uri!(super::export_notes(account_id)))
+        .header(crate::tests::bearer(&crate::tests::create_session(account_id)))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(rocket::http::ContentType::ZIP));
+
+    let archive = response.into_bytes().unwrap();
+    assert_eq!(
+        zip_entry_names(&archive),
+        vec!["Ward Rounds.md", "ward rounds (2).md", "manifest.json"]
+    );
+}
+
+#[test]
+fn test_export_notes_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::export_notes(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_render_markdown_heading_rendered() {
+    let html = super::note_api::render_markdown("# Cardiology\n\nSome *notes*");
+    assert!(html.contains("<h1>Cardiology</h1>"));
+    assert!(html.contains("<em>notes</em>"));
+}
+
+#[test]
+fn test_render_markdown_script_escaped() {
+    let html = super::note_api::render_markdown("Hello\n\n<script>alert('xss')</script>\n\nInline <script>alert(1)</script>");
+    assert!(!html.contains("<script>"));
+    assert!(html.contains("&lt;script&gt;"));
+}
+
+#[test]
+fn test_render_markdown_javascript_link_removed() {
+    let html = super::note_api::render_markdown("[click me](javascript:alert(1))");
+    assert!(!html.contains("javascript:"));
+}
+
+#[test]
+fn test_fetch_note_owner_content_inline_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_note(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let note = response.into_json::<super::note_api::NoteResponse>().unwrap();
+    assert_eq!(note.note_id, 1);
+    assert_eq!(note.note_title, "Lecture Notes");
+    assert!(note.note_content.contains("respiratory physiology"));
+}
+
+#[test]
+fn test_fetch_note_public_note_no_session_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get(uri!(super::fetch_note(2))).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_json::<super::note_api::NoteResponse>().unwrap().note_id, 2);
+}
+
+#[test]
+fn test_fetch_note_private_note_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_note(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_note_nonexisting_note_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_note(0)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_notes_by_ids_only_owned_notes_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let owned_id = add_test_note(&client_binding, "Rocket Batch Test Note", "Batched");
+    let (_, other_note_ids, _) = create_reorder_account(&client_binding);
+
+    let response = client_binding
+        .post(uri!(super::fetch_notes_by_ids))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&vec![other_note_ids[0], owned_id, 0, owned_id]).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].note_id, owned_id);
+    assert_eq!(notes[0].note_content, "Batched");
+}
+
+#[test]
+fn test_fetch_notes_by_ids_too_many_ids_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_ids: Vec<i32> = (1..=super::MAX_BATCH_NOTE_IDS as i32 + 1).collect();
+
+    let response = client_binding
+        .post(uri!(super::fetch_notes_by_ids))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&note_ids).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_fetch_notes_by_ids_no_session_unauthorized() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let response = client_binding
+        .post(uri!(super::fetch_notes_by_ids))
+        .body("[1]")
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn test_render_note_public_note_html_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get(uri!(super::render_note(2))).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(rocket::http::ContentType::HTML));
+    assert!(response.into_string().unwrap().contains("<li>Endocrine system</li>"));
+}
+
+#[test]
+fn test_render_note_private_note_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::render_note(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_notes_search_exact_title_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_notes_search(1, "Study Group")))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.into_string().unwrap().contains("Study Group"));
+}
+
+#[test]
+fn test_fetch_notes_search_partial_title_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_notes_search(1, "sTuDy")))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.into_string().unwrap().contains("Study Group"));
+}
+
+#[test]
+fn test_fetch_notes_search_no_match_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_notes_search(1, "Cardiology Rotation")))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_notes_search_wildcard_literal_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_notes_search(1, "%")))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_notes_search_more_relevant_ranked_first() {
+    let client_binding = CLIENT.lock().unwrap();
+    let weaker = add_test_note(&client_binding, "Rocket Sepsis Notes", "Ranking test");
+    let stronger = add_test_note(&client_binding, "Rocket Sepsis Bundle", "Ranking test");
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes_search(1, "sepsis bundle")))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let results = response.into_json::<Vec<super::note_api::NoteSearchResult>>().unwrap();
+    let position = |note_id| results.iter().position(|val| val.note.note_id == note_id).unwrap();
+    assert!(position(stronger) < position(weaker));
+    assert!(results[position(stronger)].score > results[position(weaker)].score);
+}
+
+#[test]
+fn test_fetch_notes_search_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_notes_search(1, "Study")))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_notes_search_no_session_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_notes_search(1, "Study")))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_add_note_existing_account_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket Test Note".to_string(),
+        note_content: "".to_string(),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let new_note = response.into_json::<super::note_api::NoteResponse>().unwrap();
+    assert_eq!(new_note.note_title, req_body.note_title);
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    let fetched_note = notes.iter().find(|note| note.note_id == new_note.note_id).unwrap();
+    assert_eq!(fetched_note.note_title, new_note.note_title);
+    assert_eq!(fetched_note.note_content, new_note.note_content);
+    assert_eq!(fetched_note.note_public, new_note.note_public);
+}
+
+/// Add a note titled `title` to the seeded admin account, handling a
+/// duplicate title as asked
+fn add_titled_note<'c>(
+    client: &'c rocket::local::blocking::Client,
+    title: &str,
+    on_duplicate: Option<&str>,
+) -> rocket::local::blocking::LocalResponse<'c> {
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: title.to_string(),
+        note_content: "Duplicate title test".to_string(),
+        note_public: false,
+    };
+    client
+        .post(uri!(super::add_note(on_duplicate, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch()
+}
+
+#[test]
+fn test_add_note_duplicate_title_reject_conflict() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let response = add_titled_note(&client_binding, "Rocket Rejected Title", Some("reject"));
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = add_titled_note(&client_binding, "rocket rejected title", Some("reject"));
+    assert_eq!(response.status(), Status::Conflict);
+
+    let body = response.into_json::<crate::endpoints::errors::ErrorBody>().unwrap();
+    assert_eq!(body.code, crate::endpoints::errors::ErrorCode::NoteTitleExists);
+}
+
+#[test]
+fn test_add_note_duplicate_title_suffix_numbered_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let titles: Vec<String> = (0..3)
+        .map(|_| {
+            let response = add_titled_note(&client_binding, "Rocket Suffixed Title", Some("suffix"));
+            assert_eq!(response.status(), Status::Ok);
+            response.into_json::<super::note_api::NoteResponse>().unwrap().note_title
+        })
+        .collect();
+
+    assert_eq!(
+        titles,
+        vec!["Rocket Suffixed Title", "Rocket Suffixed Title (2)", "Rocket Suffixed Title (3)"]
+    );
+}
+
+#[test]
+fn test_add_note_duplicate_title_allowed_by_default_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    for _ in 0..2 {
+        let response = add_titled_note(&client_binding, "Rocket Allowed Title", None);
+        assert_eq!(response.status(), Status::Ok);
+        let note = response.into_json::<super::note_api::NoteResponse>().unwrap();
+        assert_eq!(note.note_title, "Rocket Allowed Title");
+    }
+}
+
+/// Add a note titled `title` to an account, sending the given Idempotency-Key
+fn add_idempotent_note(
+    client: &rocket::local::blocking::Client,
+    account_id: i32,
+    title: &str,
+    key: &str,
+) -> i32 {
+    let req_body = super::note_api::NewNote {
+        account_id,
+        note_title: title.to_string(),
+        note_content: "Idempotency key test".to_string(),
+        note_public: false,
+    };
+    let token = crate::tests::create_session(account_id);
+    let response = client
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(&token))
+        .header(rocket::http::Header::new("Idempotency-Key", key.to_string()))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json::<super::note_api::NoteResponse>().unwrap().note_id
+}
+
+/// Number of notes with the given title, across every account
+fn count_titled_notes(title: &'static str) -> i64 {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar("SELECT COUNT(*) FROM tblNotes WHERE title = ?")
+                .bind(title)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    })
+}
+
+#[test]
+fn test_add_note_repeated_idempotency_key_single_note() {
+    let client_binding = CLIENT.lock().unwrap();
+    let title = "Rocket Idempotent Note";
+
+    let first = add_idempotent_note(&client_binding, 1, title, "rocket-retry-1");
+    let second = add_idempotent_note(&client_binding, 1, title, "rocket-retry-1");
+
+    assert_eq!(first, second);
+    assert_eq!(count_titled_notes(title), 1);
+}
+
+#[test]
+fn test_add_note_idempotency_key_scoped_per_account() {
+    let client_binding = CLIENT.lock().unwrap();
+    let title = "Rocket Idempotent Scoped Note";
+
+    let admin_note = add_idempotent_note(&client_binding, 1, title, "rocket-retry-2");
+    let other_note = add_idempotent_note(&client_binding, 2, title, "rocket-retry-2");
+
+    assert_ne!(admin_note, other_note);
+    assert_eq!(count_titled_notes(title), 2);
+}
+
+#[test]
+fn test_add_note_idempotency_key_after_remove_conflict() {
+    let client_binding = CLIENT.lock().unwrap();
+    let title = "Rocket Idempotent Removed Note";
+
+    let note_id = add_idempotent_note(&client_binding, 1, title, "rocket-retry-3");
+    let response = client_binding
+        .delete(uri!(super::remove_note(1, note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: title.to_string(),
+        note_content: "Idempotency key test".to_string(),
+        note_public: false,
+    };
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .header(rocket::http::Header::new("Idempotency-Key", "rocket-retry-3"))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+    assert!(response.body().is_some());
+    // Only the removed note, nothing was added in its place
+    assert_eq!(count_titled_notes(title), 1);
+}
+
+#[test]
+fn test_add_note_blank_idempotency_key_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket Blank Key Note".to_string(),
+        note_content: "Idempotency key test".to_string(),
+        note_public: false,
+    };
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .header(rocket::http::Header::new("Idempotency-Key", "  "))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_add_note_unknown_duplicate_option_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = add_titled_note(&client_binding, "Rocket Unknown Option", Some("overwrite"));
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_validate_note_title_valid_title_ok() {
+    assert!(super::validate_note_title("Week 3: Cardiology (ward rounds)").is_ok());
+}
+
+#[test]
+fn test_validate_note_title_over_long_bad_request() {
+    let title = "a".repeat(super::MAX_TITLE_LENGTH + 1);
+    assert!(matches!(
+        super::validate_note_title(&title),
+        Err(super::ApiErrors::BadRequest(_))
+    ));
+}
+
+#[test]
+fn test_validate_note_title_newline_bad_request() {
+    assert!(matches!(
+        super::validate_note_title("Ward\nRounds"),
+        Err(super::ApiErrors::BadRequest(_))
+    ));
+}
+
+#[test]
+fn test_validate_note_title_path_separator_bad_request() {
+    assert!(super::validate_note_title("../../etc/passwd").is_err());
+    assert!(super::validate_note_title("C:\\notes").is_err());
+}
+
+#[test]
+fn test_add_note_title_with_newline_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = add_titled_note(&client_binding, "Rocket\nBroken Title", None);
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_suffixed_title_first_free_number() {
+    let taken = vec!["Untitled".to_string(), "untitled (2)".to_string(), "Untitled (4)".to_string()];
+    assert_eq!(super::suffixed_title("Untitled", &taken), "Untitled (3)");
+}
+
+#[test]
+fn test_add_note_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let notes_before = account_note_count(3);
+
+    let req_body = super::note_api::NewNote {
+        account_id: 3,
+        note_title: "Rocket Test Note".to_string(),
+        note_content: "".to_string(),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+    assert_eq!(account_note_count(3), notes_before);
+}
+
+#[test]
+fn test_add_note_no_session_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket Test Note".to_string(),
+        note_content: "".to_string(),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_add_note_content_under_limit_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket Test Note".to_string(),
+        note_content: "a".repeat(super::DEFAULT_MAX_NOTE_BYTES as usize),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn test_add_note_content_over_limit_payload_too_large() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket Test Note".to_string(),
+        note_content: "a".repeat(super::DEFAULT_MAX_NOTE_BYTES as usize + 1),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_add_note_body_over_json_limit_payload_too_large() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    // Rejected by Rocket while reading the body, before add_note runs
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket Test Note".to_string(),
+        note_content: "a".repeat(2 * 1024 * 1024),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+}
+
+#[test]
+fn test_add_note_markdown_content_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket Test Note".to_string(),
+        note_content: "# Heading\n\n* Point one\n\t* Nested point\r\n".to_string(),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn test_add_note_binary_content_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket Binary Test Note".to_string(),
+        // The first bytes of a PNG image
+        note_content: "\u{89}PNG\r\n\u{1a}\n\u{0}\u{0}\u{0}\rIHDR".to_string(),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert!(!response.into_string().unwrap().contains("Rocket Binary Test Note"));
+}
+
+#[test]
+fn test_add_note_jpeg_content_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    // A JPEG header read as text, the way a client mislabelling an image
+    // upload as a note would send it
+    let jpeg_header = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F', 0x00, 0x01];
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket JPEG Test Note".to_string(),
+        note_content: String::from_utf8_lossy(&jpeg_header).into_owned(),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(count_titled_notes("Rocket JPEG Test Note"), 0);
+}
+
+/// Timestamps stored against a note, straight from the database
+fn note_timestamps(note_id: i32) -> (chrono::NaiveDateTime, chrono::NaiveDateTime) {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_as::<_, (chrono::NaiveDateTime, chrono::NaiveDateTime)>(
+                "SELECT created_at, updated_at FROM tblNotes WHERE note_id = ?",
+            )
+            .bind(note_id)
+            .fetch_one(conn)
+            .await
+            .unwrap()
+        })
+    })
+}
+
+/// Version currently stored against a note
+fn note_version(note_id: i32) -> i32 {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_as::<_, (i32,)>("SELECT version FROM tblNotes WHERE note_id = ?")
+                .bind(note_id)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+                .0
+        })
+    })
+}
+
+/// Header telling an update which version of the note it was based on
+fn if_match(version: i32) -> rocket::http::Header<'static> {
+    rocket::http::Header::new("If-Match", version.to_string())
+}
+
+#[test]
+fn test_update_note_updated_at_advances_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket Timestamp Test Note".to_string(),
+        note_content: "First draft".to_string(),
+        note_public: false,
+    };
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    let new_note = response.into_json::<super::note_api::NoteResponse>().unwrap();
+    assert_eq!(new_note.created_at, new_note.updated_at);
+
+    // Pretend the note was written a while ago
+    let note_id = new_note.note_id;
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query("UPDATE tblNotes SET created_at = '2023-01-01', updated_at = '2023-01-01' WHERE note_id = ?")
+                .bind(note_id)
+                .execute(conn)
+                .await
+                .unwrap();
+        })
+    });
+    let (created_at, old_updated_at) = note_timestamps(note_id);
+
+    // Reading the note leaves it alone
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(note_timestamps(note_id).1, old_updated_at);
+
+    let req_body = super::note_api::UpdateNote {
+        note_id,
+        note_title: "Rocket Timestamp Test Note".to_string(),
+        note_content: "Second draft".to_string(),
+        note_public: false,
+    };
+    let response = client_binding
+        .put(uri!(super::update_note(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .header(if_match(new_note.note_version))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let (new_created_at, new_updated_at) = note_timestamps(note_id);
+    assert_eq!(new_created_at, created_at);
+    assert!(new_updated_at > old_updated_at);
+}
+
+#[test]
+fn test_update_note_public_only_updated_at_unchanged() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket Visibility Test Note".to_string(),
+        note_content: "Unchanged".to_string(),
+        note_public: false,
+    };
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    let note_id = response.into_json::<super::note_api::NoteResponse>().unwrap().note_id;
+
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query("UPDATE tblNotes SET updated_at = '2023-01-01' WHERE note_id = ?")
+                .bind(note_id)
+                .execute(conn)
+                .await
+                .unwrap();
+        })
+    });
+
+    let req_body = super::note_api::UpdateNote {
+        note_id,
+        note_title: "Rocket Visibility Test Note".to_string(),
+        note_content: "Unchanged".to_string(),
+        note_public: true,
+    };
+    let response = client_binding
+        .put(uri!(super::update_note(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .header(if_match(note_version(note_id)))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_eq!(
+        note_timestamps(note_id).1,
+        chrono::NaiveDate::from_ymd_opt(2023, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()
+    );
+}
+
+#[test]
+fn test_update_note_existing_note_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::UpdateNote {
+        note_content: "Today we covered respiratory physiology. Here are the key points:\n\n* Oxygen and carbon dioxide exchange occurs in the alveoli of the lungs.\n* The respiratory system is controlled by the medulla oblongata in the brainstem.\n* The diaphragm and intercostal muscles are responsible for breathing.".to_string(),
+        note_id: 1,
+        note_title: "Lecture Notes".to_string(),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .put(uri!(super::update_note(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .header(if_match(note_version(1)))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.body().is_none());
+}
+
+#[test]
+fn test_update_note_nonexisting_note_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::UpdateNote {
+        note_content: "Today we covered respiratory physiology. Here are the key points:\n\n* Oxygen and carbon dioxide exchange occurs in the alveoli of the lungs.\n* The respiratory system is controlled by the medulla oblongata in the brainstem.\n* The diaphragm and intercostal muscles are responsible for breathing.".to_string(),
+        note_id: 0,
+        note_title: "Lecture Notes".to_string(),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .put(uri!(super::update_note(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_update_note_stale_version_conflict() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket Concurrency Test Note", "Original");
+    let read_version = note_version(note_id);
+
+    let update = |content: &str| super::note_api::UpdateNote {
+        note_id,
+        note_title: "Rocket Concurrency Test Note".to_string(),
+        note_content: content.to_string(),
+        note_public: false,
+    };
+
+    // Both clients read the same version, the first to save wins
+    let response = client_binding
+        .put(uri!(super::update_note(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .header(if_match(read_version))
+        .body(serde_json::to_string(&update("First edit")).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(note_version(note_id), read_version + 1);
+
+    let response = client_binding
+        .put(uri!(super::update_note(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .header(if_match(read_version))
+        .body(serde_json::to_string(&update("Second edit")).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+    assert!(response.body().is_some());
+
+    let response = client_binding
+        .get(uri!(super::download_note(note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.into_string().unwrap(), "First edit");
+}
+
+#[test]
+fn test_update_note_missing_version_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket Unversioned Test Note", "Original");
+
+    let req_body = super::note_api::UpdateNote {
+        note_id,
+        note_title: "Rocket Unversioned Test Note".to_string(),
+        note_content: "Edited".to_string(),
+        note_public: false,
+    };
+    let response = client_binding
+        .put(uri!(super::update_note(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+/// Rename a note as the given session, based on the given version
+fn rename<'c>(
+    client: &'c rocket::local::blocking::Client,
+    token: &str,
+    note_id: i32,
+    version: i32,
+    title: &str,
+) -> rocket::local::blocking::LocalResponse<'c> {
+    let body = super::note_api::NoteTitle { note_title: title.to_string() };
+    client
+        .patch(uri!(super::rename_note(note_id)))
+        .header(crate::tests::bearer(token))
+        .header(if_match(version))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch()
+}
+
+#[test]
+fn test_rename_note_title_changed_content_untouched_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket Rename Test Note", "Keep this content");
+
+    let version = note_version(note_id);
+    let response = rename(&client_binding, crate::tests::ADMIN_TOKEN, note_id, version, "Rocket Renamed Test Note");
+    assert_eq!(response.status(), Status::Ok);
+
+    let (title, content) = crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_as::<_, (String, String)>("SELECT title, content FROM tblNotes WHERE note_id = ?")
+                .bind(note_id)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    });
+    assert_eq!(title, "Rocket Renamed Test Note");
+    assert_eq!(content, "Keep this content");
+    assert!(verify(&client_binding, note_id).matches);
+
+    // Renaming again from the version read before the first rename is stale
+    let response = rename(&client_binding, crate::tests::ADMIN_TOKEN, note_id, version, "Rocket Stale Test Note");
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+#[test]
+fn test_rename_note_nonexisting_note_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = rename(&client_binding, crate::tests::ADMIN_TOKEN, 0, 1, "Lecture Notes");
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_rename_note_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = rename(&client_binding, crate::tests::USER_TOKEN, 1, 1, "Not My Notes");
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_rename_note_invalid_title_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = rename(&client_binding, crate::tests::ADMIN_TOKEN, 1, 1, "Lecture\nNotes");
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_remove_note_soft_delete_and_restore_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::NewNote {
+        account_id: 1,
+        note_title: "Rocket Trash Test Note".to_string(),
+        note_content: "".to_string(),
+        note_public: false,
+    };
+    let response = client_binding
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    let new_note = response.into_json::<super::note_api::NoteResponse>().unwrap();
+
+    let response = client_binding
+        .delete(uri!(super::remove_note(1, new_note.note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.body().is_none());
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    assert!(notes.iter().all(|note| note.note_id != new_note.note_id));
+
+    let response = client_binding
+        .post(uri!(super::restore_note(1, new_note.note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.body().is_none());
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    assert!(notes.iter().any(|note| note.note_id == new_note.note_id));
+}
+
+#[test]
+fn test_remove_note_nonexisting_note_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .delete(uri!(super::remove_note(1, 0)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_restore_note_not_deleted_not_found() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .post(uri!(super::restore_note(1, 2)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_remove_notes_bulk_with_missing_id_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let mut note_ids = vec![];
+    for _ in 0..2 {
+        let req_body = super::note_api::NewNote {
+            account_id: 1,
+            note_title: "Rocket Bulk Test Note".to_string(),
+            note_content: "".to_string(),
+            note_public: false,
+        };
+        let response = client_binding
+            .post(uri!(super::add_note(_, _)))
+            .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+            .body(serde_json::to_string(&req_body).unwrap())
+            .dispatch();
+        note_ids.push(response.into_json::<super::note_api::NoteResponse>().unwrap().note_id);
+    }
+    // Note 0 doesn't exist
+    note_ids.push(0);
+
+    let response = client_binding
+        .delete(uri!(super::remove_notes_bulk(1)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .body(serde_json::to_string(&note_ids).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let summary = response.into_json::<super::note_api::BulkRemoveResponse>().unwrap();
+    assert_eq!(summary.removed, note_ids[..2].to_vec());
+    assert_eq!(summary.not_found, vec![0]);
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    assert!(notes.iter().all(|note| !note_ids.contains(&note.note_id)));
+}
+
+#[test]
+fn test_remove_notes_bulk_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .delete(uri!(super::remove_notes_bulk(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .body(serde_json::to_string(&vec![2]).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    assert!(notes.iter().any(|note| note.note_id == 2));
+}
+
+#[test]
+fn test_update_note_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::UpdateNote {
+        note_content: "Overwritten by another account".to_string(),
+        note_id: 2,
+        note_title: "Overwritten".to_string(),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .put(uri!(super::update_note(3)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+
+    // Naming the owner in the path doesn't get around the session
+    let response = client_binding
+        .put(uri!(super::update_note(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    let note = notes.iter().find(|note| note.note_id == 2).unwrap();
+    assert_eq!(note.note_title, "Study Group");
+}
+
+#[test]
+fn test_remove_note_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let response = client_binding
+        .delete(uri!(super::remove_note(3, 2)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+
+    let response = client_binding
+        .delete(uri!(super::remove_note(1, 2)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    assert!(notes.iter().any(|note| note.note_id == 2));
+}
+
+#[test]
+fn test_remove_note_no_session_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.delete(uri!(super::remove_note(1, 2))).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_update_note_no_session_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+
+    let req_body = super::note_api::UpdateNote {
+        note_content: "Overwritten without a session".to_string(),
+        note_id: 2,
+        note_title: "Overwritten".to_string(),
+        note_public: false,
+    };
+
+    let response = client_binding
+        .put(uri!(super::update_note(1)))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_restore_note_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Restored by another account", "Still removed");
+
+    let response = client_binding
+        .delete(uri!(super::remove_note(1, note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client_binding
+        .post(uri!(super::restore_note(3, note_id)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(1, _, _, _, _, _)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    assert!(notes.iter().all(|note| note.note_id != note_id));
+}
+
+#[test]
+fn test_add_protocol_regular_user_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let body = super::protocol_api::ProtocolRequest {
+        title: "Not allowed".to_string(),
+        content: None,
+        category: None,
+    };
+
+    let response = client_binding
+        .post(uri!(super::add_protocol))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .body(serde_json::to_string(&body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_remove_protocol_no_session_unauth() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.delete(uri!(super::remove_protocol(1))).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // Still there for everyone else
+    let response = client_binding.get(uri!(super::fetch_protocol(1))).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+/// Smallest file that passes the PNG signature check
+const TEST_PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0x00];
+
+/// Attach a file to a note as the seeded admin account
+fn attach_file<'c>(
+    client: &'c rocket::local::blocking::Client,
+    note_id: i32,
+    file_name: &str,
+    content_type: rocket::http::ContentType,
+    data: &[u8],
+) -> rocket::local::blocking::LocalResponse<'c> {
+    client
+        .post(uri!(super::upload_note_attachment(note_id, file_name)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .header(content_type)
+        .body(data)
+        .dispatch()
+}
+
+/// Attachments listed for a note, as the seeded admin account
+fn list_attachments(
+    client: &rocket::local::blocking::Client,
+    note_id: i32,
+) -> (Status, Vec<super::note_api::AttachmentResponse>) {
+    let response = client
+        .get(uri!(super::fetch_note_attachments(note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    let status = response.status();
+
+    (status, response.into_json().unwrap_or_default())
+}
+
+#[test]
+fn test_upload_note_attachment_listed_and_downloaded_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket Attachment Note", "See the ECG");
+
+    let response = attach_file(
+        &client_binding,
+        note_id,
+        "C:\\Users\\intern\\ecg.png",
+        rocket::http::ContentType::PNG,
+        TEST_PNG,
+    );
+    assert_eq!(response.status(), Status::Ok);
+    let attachment = response.into_json::<super::note_api::AttachmentResponse>().unwrap();
+    assert_eq!(attachment.note_id, note_id);
+    assert_eq!(attachment.file_name, "ecg.png");
+    assert_eq!(attachment.content_type, "image/png");
+    assert_eq!(attachment.size, TEST_PNG.len() as i32);
+
+    let (status, attachments) = list_attachments(&client_binding, note_id);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].attachment_id, attachment.attachment_id);
+
+    let response = client_binding
+        .get(uri!(super::download_note_attachment(note_id, attachment.attachment_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(rocket::http::ContentType::PNG));
+    assert_eq!(response.into_bytes().unwrap(), TEST_PNG);
+}
+
+#[test]
+fn test_upload_note_attachment_unsupported_type_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = attach_file(
+        &client_binding,
+        1,
+        "notes.txt",
+        rocket::http::ContentType::Plain,
+        b"plain text",
+    );
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // Claiming to be a PDF isn't enough, the file has to look like one
+    let response = attach_file(
+        &client_binding,
+        1,
+        "scan.pdf",
+        rocket::http::ContentType::PDF,
+        TEST_PNG,
+    );
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_upload_note_attachment_too_large_payload_too_large() {
+    let client_binding = CLIENT.lock().unwrap();
+    let mut data = b"%PDF-".to_vec();
+    data.resize(5 * 1024 * 1024 + 1, 0);
+
+    let response = attach_file(
+        &client_binding,
+        1,
+        "huge.pdf",
+        rocket::http::ContentType::PDF,
+        &data,
+    );
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+}
+
+#[test]
+fn test_upload_note_attachment_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .post(uri!(super::upload_note_attachment(1, "ecg.png")))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .header(rocket::http::ContentType::PNG)
+        .body(TEST_PNG)
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_note_attachments_private_note_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get(uri!(super::fetch_note_attachments(1)))
+        .header(crate::tests::bearer(crate::tests::USER_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+#[test]
+fn test_remove_note_attachment_removed_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket Attachment Removal", "Scan attached");
+    let response = attach_file(
+        &client_binding,
+        note_id,
+        "scan.pdf",
+        rocket::http::ContentType::PDF,
+        b"%PDF-1.7",
+    );
+    let attachment = response.into_json::<super::note_api::AttachmentResponse>().unwrap();
+
+    let response = client_binding
+        .delete(uri!(super::remove_note_attachment(note_id, attachment.attachment_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let (_, attachments) = list_attachments(&client_binding, note_id);
+    assert!(attachments.is_empty());
+
+    let response = client_binding
+        .delete(uri!(super::remove_note_attachment(note_id, attachment.attachment_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_remove_note_attachments_follow_note_to_trash() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket Trashed Attachment", "X-ray attached");
+    let response = attach_file(
+        &client_binding,
+        note_id,
+        "xray.png",
+        rocket::http::ContentType::PNG,
+        TEST_PNG,
+    );
+    let attachment = response.into_json::<super::note_api::AttachmentResponse>().unwrap();
+
+    let response = client_binding
+        .delete(uri!(super::remove_note(1, note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let (status, _) = list_attachments(&client_binding, note_id);
+    assert_eq!(status, Status::NotFound);
+    let response = client_binding
+        .get(uri!(super::download_note_attachment(note_id, attachment.attachment_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    // Restoring the note brings its attachments back with it
+    let response = client_binding
+        .post(uri!(super::restore_note(1, note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let (status, attachments) = list_attachments(&client_binding, note_id);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(attachments.len(), 1);
+}
+
+/// Create an account with its own storage quota, returning its id
+fn create_quota_account(quota_bytes: i64) -> i32 {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query(
+                "INSERT INTO tblAccount (email, hashed_password, username, storage_quota_bytes) \
+                 VALUES (?, 'password_1', 'quota_test', ?)",
+            )
+            .bind(format!("{}@students.wits.ac.za", uuid::Uuid::new_v4().simple()))
+            .bind(quota_bytes)
+            .execute(conn)
+            .await
+            .unwrap()
+            .last_insert_id() as i32
+        })
+    })
+}
+
+/// Storage an account is recorded as using
+fn bytes_used(account_id: i32) -> i64 {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar("SELECT bytes_used FROM tblAccount WHERE account_id = ?")
+                .bind(account_id)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    })
+}
+
+/// Add a note of `size` bytes to an account
+fn add_sized_note<'c>(
+    client: &'c rocket::local::blocking::Client,
+    account_id: i32,
+    size: usize,
+) -> rocket::local::blocking::LocalResponse<'c> {
+    let req_body = super::note_api::NewNote {
+        account_id,
+        note_title: "Rocket Quota Note".to_string(),
+        note_content: "q".repeat(size),
+        note_public: false,
+    };
+    let token = crate::tests::create_session(account_id);
+    client
+        .post(uri!(super::add_note(_, _)))
+        .header(crate::tests::bearer(&token))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch()
+}
+
+#[test]
+fn test_add_note_quota_full_payload_too_large() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_quota_account(1000);
+
+    let response = add_sized_note(&client_binding, account_id, 600);
+    assert_eq!(response.status(), Status::Ok);
+    let response = add_sized_note(&client_binding, account_id, 400);
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(bytes_used(account_id), 1000);
+
+    let response = add_sized_note(&client_binding, account_id, 1);
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+    assert!(response.body().is_some());
+    assert_eq!(bytes_used(account_id), 1000);
+}
+
+#[test]
+fn test_remove_note_quota_space_freed() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_quota_account(1000);
+
+    let response = add_sized_note(&client_binding, account_id, 800);
+    let note_id = response.into_json::<super::note_api::NoteResponse>().unwrap().note_id;
+    let response = add_sized_note(&client_binding, account_id, 800);
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    let token = crate::tests::create_session(account_id);
+    let response = client_binding
+        .delete(uri!(super::remove_note(account_id, note_id)))
+        .header(crate::tests::bearer(&token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(bytes_used(account_id), 0);
+
+    let response = add_sized_note(&client_binding, account_id, 800);
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(bytes_used(account_id), 800);
+
+    // Restoring is allowed over quota, and counts the note again
+    let response = client_binding
+        .post(uri!(super::restore_note(account_id, note_id)))
+        .header(crate::tests::bearer(&token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(bytes_used(account_id), 1600);
+}
+
+/// Validate a note for an account without adding it
+fn validate_note(
+    client: &rocket::local::blocking::Client,
+    account_id: i32,
+    title: &str,
+    content: &str,
+    on_duplicate: Option<&str>,
+) -> super::note_api::NoteValidation {
+    let req_body = super::note_api::NewNote {
+        account_id,
+        note_title: title.to_string(),
+        note_content: content.to_string(),
+        note_public: false,
+    };
+    let token = crate::tests::create_session(account_id);
+    let response = client
+        .post(uri!(super::add_note(on_duplicate, Some(true))))
+        .header(crate::tests::bearer(&token))
+        .header(rocket::http::Header::new("Idempotency-Key", "rocket-dry-run"))
+        .body(serde_json::to_string(&req_body).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json().unwrap()
+}
+
+#[test]
+fn test_add_note_validate_only_nothing_saved() {
+    let client_binding = CLIENT.lock().unwrap();
+    let title = "Rocket Dry Run Note";
+    let used_before = bytes_used(1);
+
+    let report = validate_note(&client_binding, 1, title, "Checked, never saved", None);
+    assert!(report.valid);
+    assert!(report.errors.is_empty());
+    assert_eq!(report.note_title, title);
+
+    assert_eq!(count_titled_notes(title), 0);
+    assert_eq!(bytes_used(1), used_before);
+    let keys: i64 = crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar("SELECT COUNT(*) FROM tblIdempotencyKeys WHERE idempotency_key = ?")
+                .bind("rocket-dry-run")
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    });
+    assert_eq!(keys, 0);
+}
+
+#[test]
+fn test_add_note_validate_only_every_problem_reported() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_quota_account(10);
+
+    let report = validate_note(
+        &client_binding,
+        account_id,
+        "Ward 4/5",
+        "More than ten bytes of notes",
+        Some("bogus"),
+    );
+    assert!(!report.valid);
+
+    let codes: Vec<crate::endpoints::errors::ErrorCode> =
+        report.errors.iter().map(|error| error.code).collect();
+    assert_eq!(
+        codes,
+        vec![
+            crate::endpoints::errors::ErrorCode::BadRequest,
+            crate::endpoints::errors::ErrorCode::BadRequest,
+            crate::endpoints::errors::ErrorCode::PayloadTooLarge,
+        ]
+    );
+    assert_eq!(bytes_used(account_id), 0);
+}
+
+#[test]
+fn test_add_note_validate_only_suffixed_title_reported() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = add_titled_note(&client_binding, "Rocket Dry Run Copy", None);
+    assert_eq!(response.status(), Status::Ok);
+
+    let report = validate_note(&client_binding, 1, "Rocket Dry Run Copy", "A copy", Some("suffix"));
+    assert!(report.valid);
+    assert_eq!(report.note_title, "Rocket Dry Run Copy (2)");
+    assert_eq!(count_titled_notes("Rocket Dry Run Copy (2)"), 0);
+}
+
+/// Create an account with three notes and a session, for reordering its notes
+/// without disturbing the seeded accounts. Gives back the account, its note IDs
+/// in the order they were added and the session token
+fn create_reorder_account(client: &rocket::local::blocking::Client) -> (i32, Vec<i32>, String) {
+    let account_id = create_quota_account(1_000_000);
+    let token = crate::tests::create_session(account_id);
+    let note_ids = ["First", "Second", "Third"]
+        .iter()
+        .map(|title| {
+            let req_body = super::note_api::NewNote {
+                account_id,
+                note_title: format!("Rocket Reorder {}", title),
+                note_content: "Reorder test".to_string(),
+                note_public: false,
+            };
+            let response = client
+                .post(uri!(super::add_note(_, _)))
+                .header(crate::tests::bearer(&token))
+                .body(serde_json::to_string(&req_body).unwrap())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+
+            response.into_json::<super::note_api::NoteResponse>().unwrap().note_id
+        })
+        .collect();
+
+    (account_id, note_ids, token)
+}
+
+#[test]
+fn test_reorder_notes_fetch_in_new_order_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let (account_id, note_ids, token) = create_reorder_account(&client_binding);
+    let new_order = vec![note_ids[2], note_ids[0], note_ids[1]];
+
+    let response = client_binding
+        .put(uri!(super::reorder_notes(account_id)))
+        .header(crate::tests::bearer(&token))
+        .body(serde_json::to_string(&new_order).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(account_id, _, _, _, _, _)))
+        .header(crate::tests::bearer(&token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    let fetched: Vec<i32> = notes.iter().map(|note| note.note_id).collect();
+    assert_eq!(fetched, new_order);
+}
+
+#[test]
+fn test_reorder_notes_other_account_note_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let (account_id, note_ids, token) = create_reorder_account(&client_binding);
+
+    // Note 1 belongs to the seeded admin account
+    let response = client_binding
+        .put(uri!(super::reorder_notes(account_id)))
+        .header(crate::tests::bearer(&token))
+        .body(serde_json::to_string(&vec![note_ids[1], 1]).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client_binding
+        .get(uri!(super::fetch_notes(account_id, _, _, _, _, _)))
+        .header(crate::tests::bearer(&token))
+        .dispatch();
+    let notes = response.into_json::<Vec<super::note_api::NoteResponse>>().unwrap();
+    let fetched: Vec<i32> = notes.iter().map(|note| note.note_id).collect();
+    assert_eq!(fetched, note_ids);
+}
+
+#[test]
+fn test_reorder_notes_duplicate_id_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let (account_id, note_ids, token) = create_reorder_account(&client_binding);
+
+    let response = client_binding
+        .put(uri!(super::reorder_notes(account_id)))
+        .header(crate::tests::bearer(&token))
+        .body(serde_json::to_string(&vec![note_ids[0], note_ids[0]]).unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+/// Notes an account has outside the trash, straight from the database
+fn account_note_count(account_id: i32) -> i64 {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar("SELECT COUNT(*) FROM tblNotes WHERE account_id = ? AND deleted_at IS NULL")
+                .bind(account_id)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    })
+}
+
+/// Run `test` with one setting overridden, putting the settings back after
+fn with_setting<T>(key: &str, value: i64, test: impl FnOnce() -> T) -> T {
+    let previous = crate::SETTINGS.blocking_read().clone();
+    *crate::SETTINGS.blocking_write() = config::Config::builder()
+        .add_source(previous.clone())
+        .set_override(key, value)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let result = test();
+    *crate::SETTINGS.blocking_write() = previous;
+    result
+}
+
+#[test]
+fn test_add_note_note_limit_reached_conflict() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_quota_account(1_000_000);
+
+    let statuses: Vec<Status> = with_setting("max_notes_per_account", 2, || {
+        (0..3)
+            .map(|_| add_sized_note(&client_binding, account_id, 10).status())
+            .collect()
+    });
+
+    assert_eq!(statuses, vec![Status::Ok, Status::Ok, Status::Conflict]);
+    assert_eq!(account_note_count(account_id), 2);
+}
+
+#[test]
+fn test_add_note_no_note_limit_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_quota_account(1_000_000);
+
+    for _ in 0..3 {
+        let response = add_sized_note(&client_binding, account_id, 10);
+        assert_eq!(response.status(), Status::Ok);
+    }
+    assert_eq!(account_note_count(account_id), 3);
+}
+
+/// Fetch the protocols, giving back the Last-Modified header sent
+fn protocols_last_modified(client: &rocket::local::blocking::Client) -> String {
+    let response = client.get(uri!(super::fetch_protocols(_, _, _, _))).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Cache-Control"), Some("public, no-cache"));
+
+    response.headers().get_one("Last-Modified").unwrap().to_string()
+}
+
+#[test]
+fn test_fetch_protocols_unchanged_since_not_modified() {
+    let client_binding = CLIENT.lock().unwrap();
+    let last_modified = protocols_last_modified(&client_binding);
+
+    let response = client_binding
+        .get(uri!(super::fetch_protocols(_, _, _, _)))
+        .header(rocket::http::Header::new("If-Modified-Since", last_modified.clone()))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(response.headers().get_one("Last-Modified"), Some(last_modified.as_str()));
+    assert!(response.body().is_none());
+}
+
+/// When a protocol was last updated, as stored
+fn protocol_updated_at(protocol_id: i32) -> chrono::NaiveDateTime {
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar("SELECT updated_at FROM tblProtocol WHERE protocol_id = ?")
+                .bind(protocol_id)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    })
+}
+
+#[test]
+fn test_remove_protocol_others_left_untouched() {
+    let client_binding = CLIENT.lock().unwrap();
+    let kept = add_categorised_protocol(&client_binding, "Rocket Kept Protocol", "fire");
+    let removed = add_categorised_protocol(&client_binding, "Rocket Removed Protocol", "fire");
+    crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query("UPDATE tblProtocol SET updated_at = '2024-01-01 00:00:00' WHERE protocol_id = ?")
+                .bind(kept.protocol_id)
+                .execute(conn)
+                .await
+                .unwrap()
+        })
+    });
+
+    remove_test_protocol(&client_binding, removed.protocol_id);
+
+    let expected = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    assert_eq!(protocol_updated_at(kept.protocol_id), expected);
+
+    // The removal is still recorded as a change for Last-Modified
+    let recorded: bool = crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar("SELECT COUNT(*) = 1 FROM tblProtocolRemovals WHERE protocol_id = ?")
+                .bind(removed.protocol_id)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    });
+    assert!(recorded);
+
+    remove_test_protocol(&client_binding, kept.protocol_id);
+}
+
+#[test]
+fn test_fetch_protocols_changed_since_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    protocols_last_modified(&client_binding);
+
+    let response = client_binding
+        .get(uri!(super::fetch_protocols(_, _, _, _)))
+        .header(rocket::http::Header::new("If-Modified-Since", "Sun, 01 Jan 2023 00:00:00 GMT"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.headers().get_one("Last-Modified").is_some());
+    assert!(response.into_string().unwrap().contains("HHHH"));
+}
+
+#[test]
+fn test_text_patch_apply_lines_replaced() {
+    let text_patch = super::note_api::TextPatch {
+        start: 1,
+        end: 3,
+        replacement: "* Renal system\n".to_string(),
+    };
+    let content = "# Exam prep\n* Endocrine system\n* Hematology\n---\n";
+
+    assert_eq!(
+        text_patch.apply(content).unwrap(),
+        "# Exam prep\n* Renal system\n---\n"
+    );
+}
+
+#[test]
+fn test_text_patch_apply_insert_and_append() {
+    let content = "first\nsecond";
+    let insert = super::note_api::TextPatch { start: 0, end: 0, replacement: "zeroth\n".to_string() };
+    let append = super::note_api::TextPatch { start: 2, end: 2, replacement: "\nthird".to_string() };
+
+    assert_eq!(insert.apply(content).unwrap(), "zeroth\nfirst\nsecond");
+    assert_eq!(append.apply(content).unwrap(), "first\nsecond\nthird");
+}
+
+/// Patch a note as the seeded admin account
+fn patch<'c>(
+    client: &'c rocket::local::blocking::Client,
+    note_id: i32,
+    version: i32,
+    text_patch: &super::note_api::TextPatch,
+) -> rocket::local::blocking::LocalResponse<'c> {
+    client
+        .patch(uri!(super::patch_note(note_id)))
+        .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+        .header(if_match(version))
+        .body(serde_json::to_string(text_patch).unwrap())
+        .dispatch()
+}
+
+#[test]
+fn test_patch_note_in_range_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket Patch Test Note", "# Rounds\nWard 4\nWard 5\n");
+
+    let text_patch = super::note_api::TextPatch {
+        start: 2,
+        end: 3,
+        replacement: "Ward 6\nWard 7\n".to_string(),
+    };
+    let response = patch(&client_binding, note_id, note_version(note_id), &text_patch);
+    assert_eq!(response.status(), Status::Ok);
+
+    let note = response.into_json::<super::note_api::NoteResponse>().unwrap();
+    assert_eq!(note.note_content, "# Rounds\nWard 4\nWard 6\nWard 7\n");
+    assert_eq!(note.note_version, note_version(note_id));
+    assert!(verify(&client_binding, note_id).matches);
+}
+
+#[test]
+fn test_patch_note_out_of_range_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let note_id = add_test_note(&client_binding, "Rocket Patch Range Test Note", "One line\n");
+    let version = note_version(note_id);
+
+    let text_patch = super::note_api::TextPatch {
+        start: 1,
+        end: 4,
+        replacement: "Too far\n".to_string(),
+    };
+    let response = patch(&client_binding, note_id, version, &text_patch);
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+
+    // Nothing was changed
+    assert_eq!(note_version(note_id), version);
+}
+
+/// Duplicate a note as the account owning it
+fn duplicate<'c>(
+    client: &'c rocket::local::blocking::Client,
+    note_id: i32,
+    on_duplicate: Option<&str>,
+    token: &str,
+) -> rocket::local::blocking::LocalResponse<'c> {
+    client
+        .post(uri!(super::duplicate_note(note_id, on_duplicate)))
+        .header(crate::tests::bearer(token))
+        .dispatch()
+}
+
+#[test]
+fn test_duplicate_note_copy_same_content_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let (account_id, note_ids, token) = create_reorder_account(&client_binding);
+
+    let response = duplicate(&client_binding, note_ids[0], None, &token);
+    assert_eq!(response.status(), Status::Ok);
+    let copy = response.into_json::<super::note_api::NoteResponse>().unwrap();
+    assert!(!note_ids.contains(&copy.note_id));
+    assert_eq!(copy.note_title, "Rocket Reorder First (copy)");
+    assert_eq!(copy.note_version, 1);
+
+    let response = client_binding
+        .get(uri!(super::fetch_note(copy.note_id)))
+        .header(crate::tests::bearer(&token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let stored = response.into_json::<super::note_api::NoteResponse>().unwrap();
+    assert_eq!(stored.note_content, "Reorder test");
+    assert!(!stored.note_public);
+    assert_eq!(account_note_count(account_id), 4);
+}
+
+#[test]
+fn test_duplicate_note_title_taken_policy_applied() {
+    let client_binding = CLIENT.lock().unwrap();
+    let (_, note_ids, token) = create_reorder_account(&client_binding);
+    assert_eq!(duplicate(&client_binding, note_ids[1], None, &token).status(), Status::Ok);
+
+    let response = duplicate(&client_binding, note_ids[1], Some("reject"), &token);
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = duplicate(&client_binding, note_ids[1], Some("suffix"), &token);
+    assert_eq!(response.status(), Status::Ok);
+    let copy = response.into_json::<super::note_api::NoteResponse>().unwrap();
+    assert_eq!(copy.note_title, "Rocket Reorder Second (copy) (2)");
+}
+
+#[test]
+fn test_duplicate_note_other_account_forbidden() {
+    let client_binding = CLIENT.lock().unwrap();
+    let (_, note_ids, _) = create_reorder_account(&client_binding);
+
+    let response = duplicate(&client_binding, note_ids[0], None, crate::tests::ADMIN_TOKEN);
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+/// Stream a note upload to the session's account
+fn stream<'c>(
+    client: &'c rocket::local::blocking::Client,
+    title: &str,
+    content: Vec<u8>,
+    token: &str,
+) -> rocket::local::blocking::LocalResponse<'c> {
+    client
+        .post(uri!(super::stream_note(title, Some(false), None::<String>)))
+        .header(crate::tests::bearer(token))
+        .header(rocket::http::ContentType::Markdown)
+        .body(content)
+        .dispatch()
+}
+
+#[test]
+fn test_stream_note_added_with_hash_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_quota_account(1_000_000);
+    let token = crate::tests::create_session(account_id);
+    // Spans several chunks
+    let content = "# Ward round\n".repeat(2000);
+
+    let response = stream(&client_binding, "Rocket Streamed Note", content.clone().into_bytes(), &token);
+    assert_eq!(response.status(), Status::Ok);
+    let note = response.into_json::<super::note_api::NoteResponse>().unwrap();
+    assert_eq!(note.note_title, "Rocket Streamed Note");
+    assert_eq!(note.note_content, content);
+    assert_eq!(bytes_used(account_id), content.len() as i64);
+
+    let hash_matches: bool = crate::tests::with_db(|conn| {
+        Box::pin(async move {
+            sqlx::query_scalar("SELECT content_hash = SHA2(content, 256) FROM tblNotes WHERE note_id = ?")
+                .bind(note.note_id)
+                .fetch_one(conn)
+                .await
+                .unwrap()
+        })
+    });
+    assert!(hash_matches);
+}
+
+#[test]
+fn test_stream_note_over_limit_payload_too_large_nothing_saved() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_quota_account(1_000_000);
+    let token = crate::tests::create_session(account_id);
+
+    let response = with_setting("max_note_bytes", 16 * 1024, || {
+        stream(&client_binding, "Rocket Streamed Note", vec![b'q'; 100 * 1024], &token)
+    });
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    // Nothing of the partial upload is left behind
+    assert_eq!(account_note_count(account_id), 0);
+    assert_eq!(bytes_used(account_id), 0);
+}
+
+#[test]
+fn test_stream_note_not_utf8_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let account_id = create_quota_account(1_000_000);
+    let token = crate::tests::create_session(account_id);
+
+    let response = stream(&client_binding, "Rocket Streamed Note", vec![0xff, 0xfe, 0x00], &token);
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(account_note_count(account_id), 0);
+}
@@ -0,0 +1,258 @@
+//! # Query Parameter Helpers
+//! Parsing and validation of query parameters shared between endpoints, the
+//! list responses they shape, and the pieces shared by the search endpoints
+
+use serde::{Deserialize, Serialize};
+
+use crate::endpoints::errors::{ApiErrors, ApiResult};
+
+/// Number of records returned when no limit is given
+pub const DEFAULT_LIMIT: u32 = 100;
+/// Largest page a client is allowed to ask for
+pub const MAX_LIMIT: u32 = 500;
+
+/// Direction records are sorted in
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// ## Parse a date query parameter
+///
+/// Parses an ISO-8601 calendar date, e.g. 2023-01-31
+pub fn parse_date(date: &str) -> ApiResult<chrono::NaiveDate> {
+    match chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(val) => Ok(val),
+        Err(_) => Err(ApiErrors::BadRequest(format!(
+            "Invalid date provided: {}",
+            date
+        ))),
+    }
+}
+
+/// ## Parse a from / to date window
+///
+/// Returns the start of the from date and the start of the day after the to
+/// date, so that a record is in the window when it ends on or after the first
+/// value and starts strictly before the second. Either end can be left open.
+pub fn parse_date_window(
+    from: Option<String>,
+    to: Option<String>,
+) -> ApiResult<(Option<chrono::NaiveDateTime>, Option<chrono::NaiveDateTime>)> {
+    let from = match from {
+        Some(val) => Some(parse_date(&val)?),
+        None => None,
+    };
+    let to = match to {
+        Some(val) => Some(parse_date(&val)?),
+        None => None,
+    };
+
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err(ApiErrors::BadRequest(
+                "The from date must not be after the to date".to_string(),
+            ));
+        }
+    }
+
+    let window_start = from.and_then(|date| date.and_hms_opt(0, 0, 0));
+    let window_end = to
+        .and_then(|date| date.succ_opt())
+        .and_then(|date| date.and_hms_opt(0, 0, 0));
+
+    Ok((window_start, window_end))
+}
+
+/// ## Parse a limit query parameter
+///
+/// Falls back to the default page size, anything over the maximum is rejected
+pub fn parse_limit(limit: Option<u32>) -> ApiResult<u32> {
+    match limit {
+        None => Ok(DEFAULT_LIMIT),
+        Some(val) if val > MAX_LIMIT => Err(ApiErrors::BadRequest(format!(
+            "Limit may not be more than {}",
+            MAX_LIMIT
+        ))),
+        Some(val) => Ok(val),
+    }
+}
+
+/// ## Parse an order query parameter
+///
+/// Accepts asc or desc in any case, defaulting to ascending
+pub fn parse_order(order: Option<String>) -> ApiResult<SortOrder> {
+    match order.map(|val| val.to_lowercase()).as_deref() {
+        None | Some("asc") => Ok(SortOrder::Asc),
+        Some("desc") => Ok(SortOrder::Desc),
+        Some(val) => Err(ApiErrors::BadRequest(format!(
+            "Invalid order provided: {}",
+            val
+        ))),
+    }
+}
+
+/// ## Escape a LIKE pattern
+///
+/// Escapes the wildcard characters in user input so that they are
+/// matched literally by a `LIKE` query.
+pub fn escape_like(query: &str) -> String {
+    query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// MySQL error numbers for a table without a usable FULLTEXT index
+const FULLTEXT_UNAVAILABLE_ERRORS: [u16; 2] = [
+    1191, // no FULLTEXT index matching the column list
+    1214, // the table's storage engine doesn't support FULLTEXT indexes
+];
+
+/// ## Check for a missing full-text index
+///
+/// Search endpoints rank results with `MATCH ... AGAINST`, which fails on a
+/// database without the FULLTEXT indexes. Those searches fall back to `LIKE`
+pub fn fulltext_unavailable(error: &sqlx::Error) -> bool {
+    error
+        .as_database_error()
+        .and_then(|val| val.try_downcast_ref::<sqlx::mysql::MySqlDatabaseError>())
+        .map_or(false, |val| FULLTEXT_UNAVAILABLE_ERRORS.contains(&val.number()))
+}
+
+/// One page of records along with how many there are in total
+#[derive(Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub data: Vec<T>,
+    pub total: i64,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+/// ## List response
+///
+/// A bare array of records, unless the client asked for `envelope=true`.
+/// Bare arrays are kept as the default so existing clients are unaffected
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum Listing<T> {
+    Bare(Vec<T>),
+    Enveloped(Envelope<T>),
+}
+
+impl<T> Listing<T> {
+    /// Wrap a page of records the way the client asked for. `total` is only
+    /// called for an envelope, so bare responses skip the count query
+    pub async fn new<F>(
+        data: Vec<T>,
+        envelope: Option<bool>,
+        limit: u32,
+        offset: u32,
+        total: F,
+    ) -> ApiResult<Listing<T>>
+    where
+        F: std::future::Future<Output = ApiResult<i64>>,
+    {
+        match envelope.unwrap_or(false) {
+            true => Ok(Listing::Enveloped(Envelope {
+                data,
+                total: total.await?,
+                limit,
+                offset,
+            })),
+            false => Ok(Listing::Bare(data)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_parse_date_valid_date_ok() {
+        let date = super::parse_date("2023-01-31").unwrap();
+        assert_eq!(date, chrono::NaiveDate::from_ymd_opt(2023, 1, 31).unwrap());
+    }
+
+    #[test]
+    fn test_parse_date_invalid_date_err() {
+        assert!(super::parse_date("2023-02-30").is_err());
+        assert!(super::parse_date("31/01/2023").is_err());
+    }
+
+    #[test]
+    fn test_parse_date_window_inclusive_bounds() {
+        let (start, end) = super::parse_date_window(
+            Some("2023-01-01".to_string()),
+            Some("2023-01-01".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(start.unwrap().to_string(), "2023-01-01 00:00:00");
+        assert_eq!(end.unwrap().to_string(), "2023-01-02 00:00:00");
+    }
+
+    #[test]
+    fn test_parse_date_window_open_ended_ok() {
+        let (start, end) = super::parse_date_window(None, None).unwrap();
+        assert!(start.is_none());
+        assert!(end.is_none());
+    }
+
+    #[test]
+    fn test_parse_date_window_from_after_to_err() {
+        assert!(super::parse_date_window(
+            Some("2023-01-02".to_string()),
+            Some("2023-01-01".to_string()),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_limit_defaults_and_bounds() {
+        assert_eq!(super::parse_limit(None).unwrap(), super::DEFAULT_LIMIT);
+        assert_eq!(super::parse_limit(Some(super::MAX_LIMIT)).unwrap(), super::MAX_LIMIT);
+        assert!(super::parse_limit(Some(super::MAX_LIMIT + 1)).is_err());
+    }
+
+    #[test]
+    fn test_parse_order_values() {
+        assert_eq!(super::parse_order(None).unwrap(), super::SortOrder::Asc);
+        assert_eq!(
+            super::parse_order(Some("DESC".to_string())).unwrap(),
+            super::SortOrder::Desc
+        );
+        assert!(super::parse_order(Some("sideways".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_fulltext_unavailable_other_errors_false() {
+        assert!(!super::fulltext_unavailable(&sqlx::Error::RowNotFound));
+        assert!(!super::fulltext_unavailable(&sqlx::Error::PoolTimedOut));
+    }
+
+    #[test]
+    fn test_escape_like_wildcards_escaped() {
+        assert_eq!(super::escape_like("100%_done\\"), "100\\%\\_done\\\\");
+        assert_eq!(super::escape_like("Lecture Notes"), "Lecture Notes");
+    }
+
+    #[rocket::async_test]
+    async fn test_listing_envelope_shape() {
+        let listing = super::Listing::new(vec![1, 2], Some(true), 2, 4, async { Ok(7) })
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&listing).unwrap(),
+            serde_json::json!({ "data": [1, 2], "total": 7, "limit": 2, "offset": 4 })
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_listing_no_envelope_bare_array() {
+        let listing = super::Listing::new(vec![1, 2], None, 2, 4, async { Ok(7) })
+            .await
+            .unwrap();
+        assert_eq!(serde_json::to_value(&listing).unwrap(), serde_json::json!([1, 2]));
+    }
+}
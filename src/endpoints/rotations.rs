@@ -5,24 +5,53 @@ mod tests;
 use rocket::serde::json::Json;
 use rocket_db_pools::{sqlx, Connection};
 
-use crate::db::{self, SPS};
-use crate::endpoints::errors::{ApiErrors, ApiResult};
+use crate::db::{self, retry_transient, SPS};
+use crate::endpoints::errors::{ApiErrors, ApiResult, ErrorCode};
+use crate::endpoints::params;
 
 /// ## Fetch rotations for an account
 ///
+/// Optionally only the rotations overlapping the dates from and to (both inclusive).
+/// Rotations are sorted by start date and returned a page at a time.
+/// When any query parameter is given, no matching rotations is an empty list rather than a 404
+///
 /// ### Arguments
 ///
 /// * account id
+/// * from (optional query, YYYY-MM-DD)
+/// * to (optional query, YYYY-MM-DD)
+/// * limit (optional query, defaults to 100, at most 500)
+/// * offset (optional query, defaults to 0)
+/// * order (optional query, asc or desc, defaults to asc)
+/// * envelope (optional query, wraps the page with the total count when true)
 ///
 /// ### Possible Responses
 ///
 /// * 200 Ok
+/// * 400 Bad Request
 /// * 404 Not Found
-#[get("/rotations/<account_id>")]
+#[get("/rotations/<account_id>?<from>&<to>&<limit>&<offset>&<order>&<envelope>")]
 pub async fn fetch_rotations(
     account_id: i32,
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    order: Option<String>,
+    envelope: Option<bool>,
     mut db_conn: Connection<SPS>,
-) -> ApiResult<Json<Vec<rotation_api::RotationResponse>>> {
+) -> ApiResult<Json<params::Listing<rotation_api::RotationResponse>>> {
+    let has_query = from.is_some()
+        || to.is_some()
+        || limit.is_some()
+        || offset.is_some()
+        || order.is_some()
+        || envelope.is_some();
+    let (window_start, window_end) = params::parse_date_window(from, to)?;
+    let limit = params::parse_limit(limit)?;
+    let offset = offset.unwrap_or(0);
+    let descending = params::parse_order(order)? == params::SortOrder::Desc;
+
     // Checking the user account actually exists
     match sqlx::query!(
         "SELECT account_id FROM tblAccount WHERE account_id = ?",
@@ -32,14 +61,30 @@ pub async fn fetch_rotations(
     .await
     {
         Ok(_) => (),
-        Err(_) => return Err(ApiErrors::NotFound("User account not found".to_string())),
+        Err(_) => {
+            return Err(ApiErrors::NotFound("User account not found".to_string())
+                .with_code(ErrorCode::AccountNotFound))
+        }
     }
 
-    let db_rotations = match sqlx::query_as!(
-        db::Rotation,
-        "SELECT * FROM tblEvents JOIN tblRotation USING (event_id) JOIN tblHospital USING (hospital_id) JOIN tblDiscipline USING (discipline_id) WHERE tblEvents.account_id = ?",
-        account_id
-    ).fetch_all(&mut *db_conn).await {
+    let db_rotations = match db::timed(async {
+        retry_transient!(
+            sqlx::query_as!(
+                db::Rotation,
+                "SELECT * FROM tblEvents JOIN tblRotation USING (event_id) JOIN tblHospital USING (hospital_id) JOIN tblDiscipline USING (discipline_id) WHERE tblEvents.account_id = ? AND (? IS NULL OR end_date >= ?) AND (? IS NULL OR start_date < ?) ORDER BY CASE WHEN ? THEN start_date END DESC, start_date ASC, event_id ASC LIMIT ? OFFSET ?",
+                account_id,
+                window_start,
+                window_start,
+                window_end,
+                window_end,
+                descending,
+                limit,
+                offset
+            ).fetch_all(&mut *db_conn)
+        )
+    })
+    .await?
+    {
         Ok(val) => val,
         #[cfg(not(tarpaulin_include))]
         Err(_) => {
@@ -47,7 +92,7 @@ pub async fn fetch_rotations(
         }
     };
 
-    if db_rotations.len() == 0 {
+    if db_rotations.len() == 0 && !has_query {
         return Err(ApiErrors::NotFound("No rotations where found".to_string()));
     }
 
@@ -56,5 +101,27 @@ pub async fn fetch_rotations(
         .map(|rotation| rotation.into())
         .collect();
 
-    Ok(Json(rotations))
+    let total = async {
+        match sqlx::query!(
+            "SELECT COUNT(*) AS total FROM tblEvents JOIN tblRotation USING (event_id) WHERE tblEvents.account_id = ? AND (? IS NULL OR end_date >= ?) AND (? IS NULL OR start_date < ?)",
+            account_id,
+            window_start,
+            window_start,
+            window_end,
+            window_end
+        )
+        .fetch_one(&mut *db_conn)
+        .await
+        {
+            Ok(val) => Ok(val.total),
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => Err(ApiErrors::InternalError(
+                "Failed to count rotations".to_string(),
+            )),
+        }
+    };
+
+    Ok(Json(
+        params::Listing::new(rotations, envelope, limit, offset, total).await?,
+    ))
 }
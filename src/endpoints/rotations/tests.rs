@@ -7,7 +7,7 @@ use crate::tests::CLIENT;
 fn test_fetch_rotations_existing_account_with_rotations_ok() {
     let client_binding = CLIENT.lock().unwrap();
     let response = client_binding
-        .get(uri!(super::fetch_rotations(1)))
+        .get(uri!(super::fetch_rotations(1, _, _, _, _, _, _)))
         .dispatch();
     assert_eq!(response.status(), Status::Ok);
     assert!(response.body().is_some());
@@ -17,7 +17,7 @@ fn test_fetch_rotations_existing_account_with_rotations_ok() {
 fn test_fetch_rotations_exisiting_account_without_rotations_notfound() {
     let client_binding = CLIENT.lock().unwrap();
     let response = client_binding
-        .get(uri!(super::fetch_rotations(2)))
+        .get(uri!(super::fetch_rotations(2, _, _, _, _, _, _)))
         .dispatch();
     assert_eq!(response.status(), Status::NotFound);
     assert!(response.body().is_some());
@@ -27,8 +27,164 @@ fn test_fetch_rotations_exisiting_account_without_rotations_notfound() {
 fn test_fetch_rotations_nonexisting_account_not_found() {
     let client_binding = CLIENT.lock().unwrap();
     let response = client_binding
-        .get(uri!(super::fetch_rotations(0)))
+        .get(uri!(super::fetch_rotations(0, _, _, _, _, _, _)))
         .dispatch();
     assert_eq!(response.status(), Status::NotFound);
     assert!(response.body().is_some());
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_fetch_rotations_envelope_total_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get("/rotations/1?limit=1&envelope=true")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let page = response
+        .into_json::<crate::endpoints::params::Envelope<super::rotation_api::RotationResponse>>()
+        .unwrap();
+    assert_eq!(page.data.len(), 1);
+    assert_eq!(page.limit, 1);
+    assert_eq!(page.offset, 0);
+    assert!(page.total >= 1);
+}
+
+#[test]
+fn test_fetch_rotations_envelope_no_rotations_empty_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get("/rotations/2?envelope=true")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let page = response
+        .into_json::<crate::endpoints::params::Envelope<super::rotation_api::RotationResponse>>()
+        .unwrap();
+    assert!(page.data.is_empty());
+    assert_eq!(page.total, 0);
+}
+
+#[test]
+fn test_fetch_rotations_no_envelope_bare_array_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get("/rotations/1?limit=1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let rotations = response
+        .into_json::<Vec<super::rotation_api::RotationResponse>>()
+        .unwrap();
+    assert_eq!(rotations.len(), 1);
+}
+
+#[test]
+fn test_fetch_rotations_date_window_match_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get("/rotations/1?from=2022-02-10&to=2022-02-28")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let rotations = response
+        .into_json::<Vec<super::rotation_api::RotationResponse>>()
+        .unwrap();
+    assert_eq!(rotations.len(), 1);
+    assert_eq!(rotations[0].event_name, "Rotation 1");
+}
+
+#[test]
+fn test_fetch_rotations_from_only_no_match_empty_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get("/rotations/1?from=2022-05-01").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "[]");
+}
+
+#[test]
+fn test_fetch_rotations_to_only_match_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get("/rotations/1?to=2022-02-01").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let rotations = response
+        .into_json::<Vec<super::rotation_api::RotationResponse>>()
+        .unwrap();
+    assert_eq!(rotations.len(), 1);
+}
+
+#[test]
+fn test_fetch_rotations_filtered_account_without_rotations_empty_ok() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding
+        .get("/rotations/2?from=2022-01-01&to=2022-12-31")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "[]");
+}
+
+#[test]
+fn test_fetch_rotations_malformed_date_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get("/rotations/1?to=tomorrow").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+/// Names of the rotations returned for a request, in the order they came back
+fn rotation_names(path: &str) -> Vec<String> {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get(path).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response
+        .into_json::<Vec<super::rotation_api::RotationResponse>>()
+        .unwrap()
+        .into_iter()
+        .map(|rotation| rotation.event_name)
+        .collect()
+}
+
+#[test]
+fn test_fetch_rotations_default_order_ascending_by_date() {
+    assert_eq!(
+        rotation_names("/rotations/1"),
+        vec!["Rotation 1", "Rotation 2", "Rotation 3"]
+    );
+}
+
+#[test]
+fn test_fetch_rotations_descending_order_ok() {
+    assert_eq!(
+        rotation_names("/rotations/1?order=desc"),
+        vec!["Rotation 3", "Rotation 2", "Rotation 1"]
+    );
+}
+
+#[test]
+fn test_fetch_rotations_page_boundaries_ok() {
+    assert_eq!(
+        rotation_names("/rotations/1?limit=2"),
+        vec!["Rotation 1", "Rotation 2"]
+    );
+    assert_eq!(
+        rotation_names("/rotations/1?limit=2&offset=2"),
+        vec!["Rotation 3"]
+    );
+    assert!(rotation_names("/rotations/1?limit=2&offset=4").is_empty());
+}
+
+#[test]
+fn test_fetch_rotations_limit_over_max_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get("/rotations/1?limit=501").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
+
+#[test]
+fn test_fetch_rotations_invalid_order_bad_request() {
+    let client_binding = CLIENT.lock().unwrap();
+    let response = client_binding.get("/rotations/1?order=random").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.body().is_some());
+}
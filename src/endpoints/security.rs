@@ -5,7 +5,7 @@ use rocket::serde::json::Json;
 use rocket_db_pools::Connection;
 
 use crate::db;
-use crate::db::SPS;
+use crate::db::{retry_transient, SPS};
 use crate::endpoints::errors::{ApiErrors, ApiResult};
 
 /// ## Fetch all security questions in the database
@@ -20,10 +20,16 @@ use crate::endpoints::errors::{ApiErrors, ApiResult};
 /// * 404 Not Found
 #[get("/security/questions")]
 pub async fn fetch_all_security_questions(mut db_conn: Connection<SPS>) -> ApiResult<Json<Vec<db::SecurityQuestion>>> {
-    let db_questions = match sqlx::query_as!(
-        db::SecurityQuestion,
-        "SELECT * FROM tblSecurityQuestions"
-    ).fetch_all(&mut *db_conn).await {
+    let db_questions = match db::timed(async {
+        retry_transient!(
+            sqlx::query_as!(
+                db::SecurityQuestion,
+                "SELECT * FROM tblSecurityQuestions"
+            ).fetch_all(&mut *db_conn)
+        )
+    })
+    .await?
+    {
         Ok(val) => val,
         #[cfg(not(tarpaulin_include))]
         Err(_) => {
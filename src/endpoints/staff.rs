@@ -4,7 +4,7 @@ mod tests;
 use rocket::serde::json::Json;
 use rocket_db_pools::{sqlx, Connection};
 
-use crate::db::{self, SPS};
+use crate::db::{self, retry_transient, SPS};
 use crate::endpoints::errors::{ApiErrors, ApiResult};
 
 /// ## Fetch Staff details
@@ -21,12 +21,17 @@ use crate::endpoints::errors::{ApiErrors, ApiResult};
 /// * 404 Not Found
 #[get("/staff")]
 pub async fn fetch_staff(mut db_conn: Connection<SPS>) -> ApiResult<Json<Vec<db::Staff>>> {
-    let db_staff = match sqlx::query_as!(
-        db::Staff,
-        "SELECT * FROM tblStaff",
-    )
-    .fetch_all(&mut *db_conn)
-    .await {
+    let db_staff = match db::timed(async {
+        retry_transient!(
+            sqlx::query_as!(
+                db::Staff,
+                "SELECT * FROM tblStaff",
+            )
+            .fetch_all(&mut *db_conn)
+        )
+    })
+    .await?
+    {
         Ok(val) => val,
         #[cfg(not(tarpaulin_include))]
         Err(_) => {
@@ -0,0 +1,9 @@
+//! # Fairings
+//! Request and response hooks attached to the Rocket instance
+
+pub mod compression;
+pub mod cors;
+pub mod metrics;
+pub mod read_only;
+pub mod request_id;
+pub mod shutdown;
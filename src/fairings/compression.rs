@@ -0,0 +1,280 @@
+use std::io::{Cursor, Write};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header};
+use rocket::{Request, Response};
+
+/// Smallest body worth compressing when `compression_min_bytes` is not set in config.toml
+const DEFAULT_COMPRESSION_MIN_BYTES: i64 = 1024;
+
+/// Encodings the API can compress responses with
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// Name of the encoding in `Accept-Encoding` and `Content-Encoding`
+    fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+/// ## Pick a response encoding
+///
+/// Chooses the encoding the client rates highest in its `Accept-Encoding`
+/// header, preferring brotli when they're rated the same. Encodings given a
+/// quality of 0 are refused by the client
+fn choose_encoding(accept_encoding: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+
+    for part in accept_encoding.split(',') {
+        let mut pieces = part.split(';');
+        let name = pieces.next().unwrap_or("").trim().to_lowercase();
+        let quality = pieces
+            .find_map(|piece| piece.trim().strip_prefix("q="))
+            .and_then(|val| val.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        let encodings = match name.as_str() {
+            "br" => vec![Encoding::Brotli],
+            "gzip" => vec![Encoding::Gzip],
+            "*" => vec![Encoding::Brotli, Encoding::Gzip],
+            _ => vec![],
+        };
+
+        for encoding in encodings {
+            let better = match best {
+                Some((current, current_quality)) => {
+                    quality > current_quality
+                        || (quality == current_quality
+                            && encoding == Encoding::Brotli
+                            && current != Encoding::Brotli)
+                }
+                None => true,
+            };
+
+            if quality > 0.0 && better {
+                best = Some((encoding, quality));
+            }
+        }
+    }
+
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Whether a response of this type is text that compresses well. Images,
+/// archives and other binary types are already compressed, so are left alone
+fn is_compressible(content_type: &ContentType) -> bool {
+    let sub = content_type.sub().as_str();
+
+    content_type.top() == "text"
+        || ["json", "javascript", "xml"].contains(&sub)
+        || sub.ends_with("+json")
+        || sub.ends_with("+xml")
+}
+
+/// Compress a body with the given encoding
+fn compress(encoding: Encoding, body: &[u8]) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Brotli => {
+            let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+            encoder.write_all(body)?;
+            Ok(encoder.into_inner())
+        }
+    }
+}
+
+/// ## Compression Fairing
+///
+/// Compresses text and JSON responses with gzip or brotli when the client
+/// accepts it and the body is at least `compression_min_bytes` long. Streamed
+/// responses, whose size isn't known up front, are sent as they are rather
+/// than read into memory.
+pub struct Compression;
+
+#[rocket::async_trait]
+impl Fairing for Compression {
+    fn info(&self) -> Info {
+        Info {
+            name: "Response Compression",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let encoding = match request
+            .headers()
+            .get_one("Accept-Encoding")
+            .and_then(choose_encoding)
+        {
+            Some(val) => val,
+            None => return,
+        };
+
+        if response.headers().contains("Content-Encoding") {
+            return;
+        }
+
+        match response.content_type() {
+            Some(content_type) if is_compressible(&content_type) => (),
+            _ => return,
+        }
+
+        let min_bytes = crate::SETTINGS
+            .read()
+            .await
+            .get_int("compression_min_bytes")
+            .unwrap_or(DEFAULT_COMPRESSION_MIN_BYTES);
+
+        match response.body().preset_size() {
+            Some(size) if size as i64 >= min_bytes => (),
+            _ => return,
+        }
+
+        let body = match response.body_mut().to_bytes().await {
+            Ok(val) => val,
+            #[cfg(not(tarpaulin_include))]
+            Err(e) => {
+                log::error!("Unable to read response body to compress: {}", e);
+                return;
+            }
+        };
+
+        let compressed = match compress(encoding, &body) {
+            Ok(val) => val,
+            #[cfg(not(tarpaulin_include))]
+            Err(e) => {
+                log::error!("Unable to compress response: {}", e);
+                response.set_sized_body(body.len(), Cursor::new(body));
+                return;
+            }
+        };
+
+        response.set_header(Header::new("Content-Encoding", encoding.name()));
+        response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+        response.set_sized_body(compressed.len(), Cursor::new(compressed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::{json, Json, Value};
+
+    use super::Encoding;
+
+    #[get("/large")]
+    fn large_json() -> Json<Value> {
+        Json(json!({ "notes": vec!["Cardiology ward round notes"; 200] }))
+    }
+
+    #[get("/small")]
+    fn small_json() -> Json<Value> {
+        Json(json!({ "status": "ok" }))
+    }
+
+    #[get("/archive")]
+    fn archive() -> (ContentType, Vec<u8>) {
+        (ContentType::ZIP, vec![0; 4096])
+    }
+
+    fn client() -> Client {
+        let rocket = rocket::build()
+            .mount("/", routes![large_json, small_json, archive])
+            .attach(super::Compression);
+        Client::untracked(rocket).unwrap()
+    }
+
+    #[test]
+    fn test_choose_encoding_preferences() {
+        assert_eq!(super::choose_encoding("gzip, deflate"), Some(Encoding::Gzip));
+        assert_eq!(super::choose_encoding("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(super::choose_encoding("br;q=0.5, gzip;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(super::choose_encoding("*"), Some(Encoding::Brotli));
+        assert_eq!(super::choose_encoding("gzip;q=0, identity"), None);
+        assert_eq!(super::choose_encoding("deflate"), None);
+    }
+
+    #[test]
+    fn test_compression_large_json_gzip_encoded() {
+        let client = client();
+        let response = client
+            .get("/large")
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+        assert!(response
+            .headers()
+            .get("Vary")
+            .any(|val| val == "Accept-Encoding"));
+
+        let mut body = String::new();
+        flate2::read::GzDecoder::new(&response.into_bytes().unwrap()[..])
+            .read_to_string(&mut body)
+            .unwrap();
+        assert!(body.contains("Cardiology ward round notes"));
+    }
+
+    #[test]
+    fn test_compression_large_json_brotli_encoded() {
+        let client = client();
+        let response = client
+            .get("/large")
+            .header(Header::new("Accept-Encoding", "gzip, br"))
+            .dispatch();
+
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("br"));
+
+        let mut body = String::new();
+        brotli::Decompressor::new(&response.into_bytes().unwrap()[..], 4096)
+            .read_to_string(&mut body)
+            .unwrap();
+        assert!(body.contains("Cardiology ward round notes"));
+    }
+
+    #[test]
+    fn test_compression_small_json_not_encoded() {
+        let client = client();
+        let response = client
+            .get("/small")
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .dispatch();
+
+        assert!(response.headers().get_one("Content-Encoding").is_none());
+        assert_eq!(response.into_string().unwrap(), r#"{"status":"ok"}"#);
+    }
+
+    #[test]
+    fn test_compression_zip_not_encoded() {
+        let client = client();
+        let response = client
+            .get("/archive")
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .dispatch();
+
+        assert!(response.headers().get_one("Content-Encoding").is_none());
+    }
+
+    #[test]
+    fn test_compression_no_accept_encoding_not_encoded() {
+        let client = client();
+        let response = client.get("/large").dispatch();
+
+        assert!(response.headers().get_one("Content-Encoding").is_none());
+    }
+}
@@ -0,0 +1,115 @@
+use std::io::Cursor;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+use rocket::{Request, Response};
+
+/// ## CORS Fairing
+///
+/// Adds the CORS headers to responses for requests coming from one of the
+/// origins listed in the `cors_allowed_origins` setting. Requests from any other
+/// origin get no CORS headers, so browsers will block them.
+///
+/// Preflight `OPTIONS` requests are answered with a 204 No Content.
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS Headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        // No route handles OPTIONS, so preflight requests would otherwise be a 404
+        if request.method() == Method::Options {
+            response.set_status(Status::NoContent);
+            response.set_sized_body(0, Cursor::new(""));
+        }
+
+        let origin = match request.headers().get_one("Origin") {
+            Some(val) => val,
+            None => return,
+        };
+
+        let allowed_origins: Vec<String> = crate::SETTINGS
+            .read()
+            .await
+            .get("cors_allowed_origins")
+            .unwrap_or_default();
+
+        if !allowed_origins.iter().any(|allowed| allowed == origin) {
+            return;
+        }
+
+        response.set_header(Header::new("Access-Control-Allow-Origin", origin.to_string()));
+        response.set_header(Header::new(
+            "Access-Control-Allow-Methods",
+            "GET, POST, PUT, PATCH, DELETE, OPTIONS",
+        ));
+        response.set_header(Header::new(
+            "Access-Control-Allow-Headers",
+            "Content-Type, Authorization, If-Match, If-None-Match, Idempotency-Key",
+        ));
+        response.set_header(Header::new("Access-Control-Expose-Headers", "ETag"));
+        response.set_header(Header::new("Vary", "Origin"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Header, Status};
+
+    use crate::tests::CLIENT;
+
+    #[test]
+    fn test_cors_preflight_allowed_origin_no_content() {
+        let client_binding = CLIENT.lock().unwrap();
+        let response = client_binding
+            .options("/notes")
+            .header(Header::new("Origin", "http://localhost:3000"))
+            .header(Header::new("Access-Control-Request-Method", "POST"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::NoContent);
+        assert_eq!(
+            response.headers().get_one("Access-Control-Allow-Origin"),
+            Some("http://localhost:3000")
+        );
+        assert!(response
+            .headers()
+            .get_one("Access-Control-Allow-Methods")
+            .unwrap()
+            .contains("POST"));
+        assert!(response.headers().get_one("Access-Control-Allow-Headers").is_some());
+    }
+
+    #[test]
+    fn test_cors_get_allowed_origin_headers() {
+        let client_binding = CLIENT.lock().unwrap();
+        let response = client_binding
+            .get("/notes/protocols")
+            .header(Header::new("Origin", "http://localhost:3000"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Access-Control-Allow-Origin"),
+            Some("http://localhost:3000")
+        );
+    }
+
+    #[test]
+    fn test_cors_get_unknown_origin_no_headers() {
+        let client_binding = CLIENT.lock().unwrap();
+        let response = client_binding
+            .get("/notes/protocols")
+            .header(Header::new("Origin", "http://evil.example.com"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.headers().get_one("Access-Control-Allow-Origin").is_none());
+    }
+}
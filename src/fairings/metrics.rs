@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Route name used for requests that didn't match any route
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+lazy_static! {
+    /// Request metrics for every route, shown at /metrics
+    pub static ref METRICS: Registry = Registry::default();
+}
+
+/// Everything recorded for one route and method
+#[derive(Default)]
+struct RouteMetrics {
+    /// Requests handled, by status code
+    statuses: BTreeMap<u16, u64>,
+    /// Requests that took at most each of `LATENCY_BUCKETS`
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+    count: u64,
+}
+
+/// ## Metrics registry
+///
+/// Counts requests and their latencies per route, and writes them out in the
+/// Prometheus text format
+#[derive(Default)]
+pub struct Registry {
+    routes: Mutex<BTreeMap<(String, String), RouteMetrics>>,
+}
+
+impl Registry {
+    /// Record one handled request
+    pub fn record(&self, route: &str, method: &str, status: u16, seconds: f64) {
+        let mut routes = self.routes.lock().unwrap();
+        let metrics = routes
+            .entry((route.to_string(), method.to_string()))
+            .or_default();
+
+        *metrics.statuses.entry(status).or_insert(0) += 1;
+        for (bucket, upper_bound) in metrics.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= upper_bound {
+                *bucket += 1;
+            }
+        }
+        metrics.latency_sum += seconds;
+        metrics.count += 1;
+    }
+
+    /// Every metric in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let routes = self.routes.lock().unwrap();
+        let mut out = String::new();
+
+        // Writing to a String can't fail, so the results are ignored
+        let _ = writeln!(
+            out,
+            "# HELP sps_http_requests_total Requests handled, by route and status."
+        );
+        let _ = writeln!(out, "# TYPE sps_http_requests_total counter");
+        for ((route, method), metrics) in routes.iter() {
+            for (status, count) in &metrics.statuses {
+                let _ = writeln!(
+                    out,
+                    "sps_http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                    route, method, status, count
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP sps_http_request_duration_seconds Time taken to handle requests, by route."
+        );
+        let _ = writeln!(out, "# TYPE sps_http_request_duration_seconds histogram");
+        for ((route, method), metrics) in routes.iter() {
+            let labels = format!("route=\"{}\",method=\"{}\"", route, method);
+            for (count, upper_bound) in metrics.buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "sps_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, upper_bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "sps_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, metrics.count
+            );
+            let _ = writeln!(
+                out,
+                "sps_http_request_duration_seconds_sum{{{}}} {}",
+                labels, metrics.latency_sum
+            );
+            let _ = writeln!(
+                out,
+                "sps_http_request_duration_seconds_count{{{}}} {}",
+                labels, metrics.count
+            );
+        }
+
+        out
+    }
+}
+
+/// When a request was received, kept in request-local state
+struct RequestStart(Instant);
+
+/// ## Metrics Fairing
+///
+/// Times every request and records it in `METRICS` under the name of the
+/// route that handled it, such as `fetch_notes` or `add_note`.
+pub struct Metrics;
+
+#[rocket::async_trait]
+impl Fairing for Metrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let start = request.local_cache(|| RequestStart(Instant::now()));
+        let route = request
+            .route()
+            .and_then(|route| route.name.as_deref())
+            .unwrap_or(UNMATCHED_ROUTE);
+
+        METRICS.record(
+            route,
+            request.method().as_str(),
+            response.status().code,
+            start.0.elapsed().as_secs_f64(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_registry_render_counts_and_buckets() {
+        let registry = super::Registry::default();
+        registry.record("fetch_notes", "GET", 200, 0.003);
+        registry.record("fetch_notes", "GET", 200, 0.2);
+        registry.record("fetch_notes", "GET", 404, 0.02);
+        registry.record("add_note", "POST", 200, 0.04);
+
+        let rendered = registry.render();
+        assert!(rendered.contains(
+            "sps_http_requests_total{route=\"fetch_notes\",method=\"GET\",status=\"200\"} 2"
+        ));
+        assert!(rendered.contains(
+            "sps_http_requests_total{route=\"fetch_notes\",method=\"GET\",status=\"404\"} 1"
+        ));
+        assert!(rendered.contains(
+            "sps_http_requests_total{route=\"add_note\",method=\"POST\",status=\"200\"} 1"
+        ));
+        assert!(rendered.contains("sps_http_request_duration_seconds_bucket{route=\"fetch_notes\",method=\"GET\",le=\"0.005\"} 1"));
+        assert!(rendered.contains("sps_http_request_duration_seconds_bucket{route=\"fetch_notes\",method=\"GET\",le=\"0.25\"} 3"));
+        assert!(rendered.contains("sps_http_request_duration_seconds_bucket{route=\"fetch_notes\",method=\"GET\",le=\"+Inf\"} 3"));
+        assert!(rendered.contains(
+            "sps_http_request_duration_seconds_count{route=\"fetch_notes\",method=\"GET\"} 3"
+        ));
+    }
+}
@@ -0,0 +1,153 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{Method, Status};
+use rocket::response::Responder;
+use rocket::{Data, Request, Response};
+
+use crate::endpoints::errors::ApiErrors;
+
+/// Put in front of the path of a blocked request so that no route matches it
+/// and its handler never runs
+const BLOCKED_PREFIX: &str = "/read-only";
+
+/// Writes still let through in read only mode, so it can be switched back off
+const ALWAYS_WRITABLE: [&str; 1] = ["/config/reload"];
+
+/// Marks a request refused for being a write, kept in request-local state so
+/// the response can be replaced with the error
+struct Blocked(bool);
+
+/// Requests that change something, other than the ones that are always let through
+fn is_blocked_write(request: &Request<'_>) -> bool {
+    matches!(
+        request.method(),
+        Method::Post | Method::Put | Method::Patch | Method::Delete
+    ) && !ALWAYS_WRITABLE.contains(&request.uri().path().as_str())
+}
+
+/// ## Read Only Fairing
+///
+/// While the `read_only` setting is on, every write is answered with a 503
+/// Service Unavailable and reads carry on as normal. The setting is checked
+/// on every request, so it can be switched with a settings reload
+pub struct ReadOnly;
+
+#[rocket::async_trait]
+impl Fairing for ReadOnly {
+    fn info(&self) -> Info {
+        Info {
+            name: "Read Only Mode",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        if !is_blocked_write(request) {
+            return;
+        }
+
+        let read_only = crate::SETTINGS
+            .read()
+            .await
+            .get_bool("read_only")
+            .unwrap_or(false);
+        if !read_only {
+            return;
+        }
+
+        request.local_cache(|| Blocked(true));
+        if let Ok(uri) = Origin::parse_owned(format!("{}{}", BLOCKED_PREFIX, request.uri())) {
+            request.set_uri(uri);
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if !request.local_cache(|| Blocked(false)).0 {
+            return;
+        }
+
+        let error = ApiErrors::ServiceUnavailable(
+            "The API is read only for maintenance, try again later".to_string(),
+            None,
+        );
+        match error.respond_to(request) {
+            Ok(val) => *response = val,
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => response.set_status(Status::ServiceUnavailable),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+
+    use crate::endpoints::errors::{ErrorBody, ErrorCode};
+    use crate::tests::CLIENT;
+
+    /// Run a test with read only mode switched on, putting the settings back after
+    fn with_read_only<T>(test: impl FnOnce() -> T) -> T {
+        let previous = crate::SETTINGS.blocking_read().clone();
+        *crate::SETTINGS.blocking_write() = config::Config::builder()
+            .add_source(previous.clone())
+            .set_override("read_only", true)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let result = test();
+        *crate::SETTINGS.blocking_write() = previous;
+        result
+    }
+
+    #[test]
+    fn test_read_only_get_passes_through_ok() {
+        let client_binding = CLIENT.lock().unwrap();
+
+        let status = with_read_only(|| client_binding.get("/notes/protocols").dispatch().status());
+        assert_eq!(status, Status::Ok);
+    }
+
+    #[test]
+    fn test_read_only_post_service_unavailable() {
+        let client_binding = CLIENT.lock().unwrap();
+        let title = format!("Rocket Read Only Note {}", uuid::Uuid::new_v4().simple());
+        let body = serde_json::json!({
+            "account_id": 1,
+            "note_title": title,
+            "note_content": "Blocked",
+            "note_public": false,
+        })
+        .to_string();
+
+        let (status, error) = with_read_only(|| {
+            let response = client_binding
+                .post("/notes")
+                .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+                .body(body.clone())
+                .dispatch();
+            (response.status(), response.into_json::<ErrorBody>().unwrap())
+        });
+        assert_eq!(status, Status::ServiceUnavailable);
+        assert_eq!(error.code, ErrorCode::ServiceUnavailable);
+
+        // Once switched off the same write goes through
+        let response = client_binding
+            .post("/notes")
+            .header(crate::tests::bearer(crate::tests::ADMIN_TOKEN))
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_is_blocked_write_writes_except_reload() {
+        let client = Client::untracked(rocket::build()).unwrap();
+
+        assert!(super::is_blocked_write(client.post("/notes").inner()));
+        assert!(super::is_blocked_write(client.delete("/notes/1/2").inner()));
+        assert!(!super::is_blocked_write(client.get("/notes/1").inner()));
+        assert!(!super::is_blocked_write(client.post("/config/reload").inner()));
+    }
+}
@@ -0,0 +1,151 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request, Response};
+
+/// Header the request ID is read from and sent back in
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest request ID accepted from a client
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// ID tying a request to the log lines it produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+/// Only short IDs of plain characters are taken from clients, so they can't
+/// be used to inject anything into headers or logs
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// ## Get the ID of a request
+///
+/// Uses the client's `X-Request-Id` when it sent a valid one, otherwise makes
+/// a new UUID. The ID is cached on the request, so it stays the same however
+/// many times it is asked for
+pub fn request_id<'r>(request: &'r Request<'_>) -> &'r str {
+    &request
+        .local_cache(|| {
+            match request
+                .headers()
+                .get_one(REQUEST_ID_HEADER)
+                .filter(|id| is_valid_request_id(id))
+            {
+                Some(id) => RequestId(id.to_string()),
+                None => RequestId(uuid::Uuid::new_v4().to_string()),
+            }
+        })
+        .0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RequestId(request_id(request).to_string()))
+    }
+}
+
+/// ## Request ID Fairing
+///
+/// Gives every request an ID, taken from the client's `X-Request-Id` header
+/// when it sends one, and echoes it back in the response's `X-Request-Id`.
+/// Error logs include the same ID, so a failed request can be matched to its
+/// log line.
+pub struct RequestIds;
+
+#[rocket::async_trait]
+impl Fairing for RequestIds {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request IDs",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request_id(request);
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        response.set_header(Header::new(REQUEST_ID_HEADER, request_id(request).to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+
+    use super::{RequestId, REQUEST_ID_HEADER};
+
+    #[get("/request_id")]
+    fn echo_request_id(request_id: RequestId) -> String {
+        request_id.0
+    }
+
+    fn client() -> Client {
+        let rocket = rocket::build()
+            .mount("/", routes![echo_request_id])
+            .attach(super::RequestIds);
+        Client::untracked(rocket).unwrap()
+    }
+
+    #[test]
+    fn test_request_id_generated_and_stable() {
+        let client = client();
+        let response = client.get("/request_id").dispatch();
+
+        let header = response.headers().get_one(REQUEST_ID_HEADER).unwrap().to_string();
+        assert!(uuid::Uuid::parse_str(&header).is_ok());
+        // The handler saw the same ID that was sent back
+        assert_eq!(response.into_string().unwrap(), header);
+    }
+
+    #[test]
+    fn test_request_id_unique_per_request() {
+        let client = client();
+        let first = client.get("/request_id").dispatch().into_string().unwrap();
+        let second = client.get("/request_id").dispatch().into_string().unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_request_id_client_id_honored() {
+        let client = client();
+        let response = client
+            .get("/request_id")
+            .header(Header::new(REQUEST_ID_HEADER, "frontend-1234"))
+            .dispatch();
+
+        assert_eq!(response.headers().get_one(REQUEST_ID_HEADER), Some("frontend-1234"));
+        assert_eq!(response.into_string().unwrap(), "frontend-1234");
+    }
+
+    #[test]
+    fn test_request_id_invalid_client_id_replaced() {
+        let client = client();
+        let response = client
+            .get("/request_id")
+            .header(Header::new(REQUEST_ID_HEADER, "bad id\r\ninjected"))
+            .dispatch();
+
+        let header = response.headers().get_one(REQUEST_ID_HEADER).unwrap();
+        assert!(uuid::Uuid::parse_str(header).is_ok());
+    }
+
+    #[test]
+    fn test_request_id_real_routes_header_present() {
+        let client_binding = crate::tests::CLIENT.lock().unwrap();
+        let response = client_binding.get("/health").dispatch();
+
+        assert!(response.headers().get_one(REQUEST_ID_HEADER).is_some());
+    }
+}
@@ -0,0 +1,178 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Method;
+use rocket::{Data, Orbit, Request, Response, Rocket};
+
+use crate::settings::TEMP_FILE_PREFIX;
+
+/// Seconds to wait on note writes still running at shutdown
+const DEFAULT_SHUTDOWN_TIMEOUT_SECONDS: i64 = 10;
+
+/// How often to check whether the note writes have finished
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Marks a request counted as an in-flight note write, kept in request-local
+/// state so the count is only taken back off for requests that added to it
+struct InFlight(bool);
+
+/// Requests that change notes, as opposed to only reading them
+fn is_note_mutation(request: &Request<'_>) -> bool {
+    matches!(
+        request.method(),
+        Method::Post | Method::Put | Method::Patch | Method::Delete
+    ) && request.uri().path().starts_with("/notes")
+}
+
+/// Wait until nothing is in flight or the timeout passes, giving back how
+/// many requests were still running
+async fn wait_for_in_flight(in_flight: &AtomicUsize, timeout: Duration) -> usize {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let running = in_flight.load(Ordering::SeqCst);
+        if running == 0 || Instant::now() >= deadline {
+            return running;
+        }
+        rocket::tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Remove temporary files left in a directory by writes that never finished,
+/// giving back how many were removed
+pub fn remove_temp_files(directory: &Path) -> std::io::Result<usize> {
+    let mut removed = 0;
+
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let is_temp_file = entry.file_name().to_string_lossy().starts_with(TEMP_FILE_PREFIX);
+        if is_temp_file && entry.file_type()?.is_file() {
+            std::fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// ## Graceful Shutdown Fairing
+///
+/// Keeps count of the note writes being handled. At shutdown it waits up to
+/// `shutdown_timeout_seconds` for them to finish, then clears out any
+/// temporary files left in `static_file_directory`
+#[derive(Default)]
+pub struct GracefulShutdown {
+    in_flight: AtomicUsize,
+}
+
+#[rocket::async_trait]
+impl Fairing for GracefulShutdown {
+    fn info(&self) -> Info {
+        Info {
+            name: "Graceful Shutdown",
+            kind: Kind::Request | Kind::Response | Kind::Shutdown,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        if is_note_mutation(request) {
+            self.in_flight.fetch_add(1, Ordering::SeqCst);
+            request.local_cache(|| InFlight(true));
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, _: &mut Response<'r>) {
+        if request.local_cache(|| InFlight(false)).0 {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        let (timeout_seconds, directory) = {
+            let settings = crate::SETTINGS.read().await;
+            (
+                settings
+                    .get_int("shutdown_timeout_seconds")
+                    .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECONDS),
+                settings.get_string("static_file_directory"),
+            )
+        };
+
+        let running = wait_for_in_flight(
+            &self.in_flight,
+            Duration::from_secs(timeout_seconds.max(0) as u64),
+        )
+        .await;
+        if running > 0 {
+            log::warn!(
+                "Shutting down with {} note writes still running after {} seconds",
+                running,
+                timeout_seconds
+            );
+        }
+
+        let directory = match directory {
+            Ok(val) => val,
+            #[cfg(not(tarpaulin_include))]
+            Err(_) => return,
+        };
+        match remove_temp_files(Path::new(&directory)) {
+            Ok(0) => (),
+            Ok(removed) => log::info!("Removed {} leftover temporary files from {}", removed, directory),
+            #[cfg(not(tarpaulin_include))]
+            Err(e) => log::error!("Unable to clean up temporary files in {}: {}", directory, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    use rocket::local::blocking::Client;
+
+    use crate::settings::TEMP_FILE_PREFIX;
+
+    #[test]
+    fn test_remove_temp_files_only_temp_files_removed() {
+        let directory = std::env::temp_dir().join(format!("sps-shutdown-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let temp_file = directory.join(format!("{}leftover", TEMP_FILE_PREFIX));
+        let kept_file = directory.join("note.md");
+        std::fs::write(&temp_file, b"").unwrap();
+        std::fs::write(&kept_file, b"# Ward round").unwrap();
+
+        assert_eq!(super::remove_temp_files(&directory).unwrap(), 1);
+        assert!(!temp_file.exists());
+        assert!(kept_file.exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn test_wait_for_in_flight_nothing_running_returns_at_once() {
+        let in_flight = AtomicUsize::new(0);
+        let running = super::wait_for_in_flight(&in_flight, Duration::from_secs(5)).await;
+        assert_eq!(running, 0);
+    }
+
+    #[rocket::async_test]
+    async fn test_wait_for_in_flight_still_running_times_out() {
+        let in_flight = AtomicUsize::new(2);
+        let running = super::wait_for_in_flight(&in_flight, Duration::from_millis(100)).await;
+        assert_eq!(running, 2);
+    }
+
+    #[test]
+    fn test_is_note_mutation_writes_only() {
+        let client = Client::untracked(rocket::build()).unwrap();
+
+        assert!(super::is_note_mutation(client.post("/notes").inner()));
+        assert!(super::is_note_mutation(client.delete("/notes/1/2").inner()));
+        assert!(!super::is_note_mutation(client.get("/notes/1").inner()));
+        assert!(!super::is_note_mutation(client.post("/account").inner()));
+    }
+}
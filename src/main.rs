@@ -1,24 +1,89 @@
 mod db;
 mod endpoints;
+mod fairings;
+mod settings;
 
 // Any errors croping up on the next 3 lines can just be ignored
 #[macro_use]
 extern crate rocket;
 extern crate lazy_static;
 
+use config::Config;
+use lazy_static::lazy_static;
 use rocket_db_pools::Database;
 use simple_logger::SimpleLogger;
+use tokio::sync::RwLock;
+
+lazy_static! {
+    /// Application settings read from config.toml in the working directory
+    pub static ref SETTINGS: RwLock<Config> =
+        RwLock::new(settings::load(settings::CONFIG_FILE).unwrap_or_default());
+}
 
 #[cfg(test)]
 pub mod tests {
     use lazy_static::lazy_static;
+    use rocket::http::Header;
     use rocket::local::blocking::Client;
     use std::sync::Mutex;
 
+    /// Session token of the seeded admin account
+    pub const ADMIN_TOKEN: &str = "admin_session_token";
+    /// Session token of the seeded regular user account
+    pub const USER_TOKEN: &str = "user_session_token";
+
     lazy_static! {
         pub static ref CLIENT: Mutex<Client> =
             Mutex::new(Client::tracked(super::rocket()).expect("valid rocket instance"));
     }
+
+    /// Authorization header sending a session token
+    pub fn bearer(token: &str) -> Header<'static> {
+        Header::new("Authorization", format!("Bearer {}", token))
+    }
+
+    /// Start a fresh session for an account, returning its token
+    pub fn create_session(account_id: i32) -> String {
+        let token = uuid::Uuid::new_v4().to_string();
+        let session_token = token.clone();
+        with_db(|conn| {
+            Box::pin(async move {
+                sqlx::query(
+                    "INSERT INTO tblSessionToken (account_id, token, created_at, expires_at, last_active) \
+                     VALUES (?, ?, NOW(), DATE_ADD(NOW(), INTERVAL 1 DAY), NOW())",
+                )
+                .bind(account_id)
+                .bind(session_token)
+                .execute(conn)
+                .await
+                .unwrap();
+            })
+        });
+        token
+    }
+
+    /// Run a query straight against the test database, for checking state the
+    /// endpoints don't expose
+    pub fn with_db<T>(
+        query: impl for<'c> FnOnce(
+            &'c mut sqlx::MySqlConnection,
+        ) -> futures::future::BoxFuture<'c, T>,
+    ) -> T {
+        use sqlx::Connection;
+
+        rocket::tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let mut conn = sqlx::MySqlConnection::connect(
+                    &std::env::var("DATABASE_URL").expect("DATABASE_URL must be set"),
+                )
+                .await
+                .unwrap();
+                query(&mut conn).await
+            })
+    }
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -30,34 +95,144 @@ fn rocket() -> _ {
         _ => (),
     }
 
+    // Body limits and the database pool are set up by Rocket, so have to be set
+    // before it is built. Nothing else holds the settings yet, so the read
+    // can't fail
+    let (limits, database_pool) = match SETTINGS.try_read() {
+        Ok(settings) => (
+            settings::limits(&settings),
+            settings::database_pool(&settings),
+        ),
+        #[cfg(not(tarpaulin_include))]
+        Err(_) => (rocket::data::Limits::default(), Vec::new()),
+    };
+    let figment = database_pool.into_iter().fold(
+        rocket::Config::figment().merge(("limits", limits)),
+        // Global, as rocket_db_pools fills in its own defaults over the default profile
+        |figment, (key, val)| {
+            figment.merge(rocket::figment::providers::Serialized::global(&key, val))
+        },
+    );
+
     // Rocket HTTP server creation routine
-    rocket::build()
+    rocket::custom(figment)
+        .register(
+            "/",
+            catchers![
+                endpoints::errors::bad_request,
+                endpoints::errors::not_found,
+                endpoints::errors::unprocessable_entity,
+                endpoints::errors::internal_error,
+            ],
+        )
         .mount(
             "/",
             routes![
                 endpoints::index,
+                endpoints::health,
+                endpoints::reload_config,
+                endpoints::metrics,
                 endpoints::auth::auth_credentials,
                 endpoints::auth::auth_session,
                 endpoints::auth::remove_session,
+                endpoints::auth::remove_all_sessions,
+                endpoints::auth::list_sessions,
                 endpoints::auth::auth_security_questions,
+                endpoints::auth::enroll_totp,
+                endpoints::auth::confirm_totp,
+                endpoints::account::request_password_reset,
                 endpoints::account::account_reset_password,
                 endpoints::notes::fetch_protocols,
+                endpoints::notes::fetch_protocol_categories,
+                endpoints::notes::fetch_protocol,
+                endpoints::notes::add_protocol,
+                endpoints::notes::update_protocol,
+                endpoints::notes::remove_protocol,
                 endpoints::notes::fetch_notes,
+                endpoints::notes::reorder_notes,
+                endpoints::notes::fetch_notes_by_ids,
+                endpoints::notes::duplicate_note,
+                endpoints::notes::download_note,
+                endpoints::notes::head_note,
+                endpoints::notes::fetch_note,
+                endpoints::notes::render_note,
+                endpoints::notes::verify_note,
+                endpoints::notes::transfer_note,
+                endpoints::notes::export_notes,
+                endpoints::notes::add_note_tag,
+                endpoints::notes::remove_note_tag,
+                endpoints::notes::upload_note_attachment,
+                endpoints::notes::fetch_note_attachments,
+                endpoints::notes::download_note_attachment,
+                endpoints::notes::remove_note_attachment,
                 endpoints::notes::add_note,
+                endpoints::notes::stream_note,
                 endpoints::notes::remove_note,
+                endpoints::notes::remove_notes_bulk,
                 endpoints::notes::update_note,
+                endpoints::notes::rename_note,
+                endpoints::notes::patch_note,
+                endpoints::notes::restore_note,
                 endpoints::events::fetch_events,
+                endpoints::events::events_ics,
                 endpoints::rotations::fetch_rotations,
                 endpoints::account::fetch_account,
+                endpoints::account::search_accounts,
                 endpoints::account::update_account,
+                endpoints::account::create_account,
+                endpoints::account::confirm_email,
+                endpoints::account::upload_avatar,
+                endpoints::account::delete_account,
                 endpoints::events::add_event,
                 endpoints::events::update_event,
                 endpoints::events::remove_event,
                 endpoints::security::fetch_all_security_questions,
                 endpoints::account::add_questions,
+                endpoints::account::fetch_account_security_questions,
                 endpoints::staff::fetch_staff,
                 endpoints::notes::fetch_public_notes,
+                endpoints::notes::fetch_notes_search,
+                endpoints::audit::fetch_audit_log,
             ],
         )
+        .attach(rocket::fairing::AdHoc::try_on_ignite(
+            "Validate Settings",
+            |rocket| async {
+                match settings::validate(&*SETTINGS.read().await) {
+                    Ok(_) => Ok(rocket),
+                    Err(e) => {
+                        log::error!("Invalid settings in {}: {e}", settings::CONFIG_FILE);
+                        Err(rocket)
+                    }
+                }
+            },
+        ))
+        .attach(fairings::request_id::RequestIds)
+        .attach(fairings::metrics::Metrics)
+        .attach(fairings::read_only::ReadOnly)
         .attach(db::SPS::init())
+        .attach(rocket::fairing::AdHoc::try_on_ignite(
+            "Database Migrations",
+            db::run_migrations,
+        ))
+        .attach(fairings::cors::Cors)
+        // After CORS, so its Vary header is added to rather than replaced
+        .attach(fairings::compression::Compression)
+        .attach(fairings::shutdown::GracefulShutdown::default())
+        .attach(rocket::fairing::AdHoc::on_liftoff("Startup Summary", |rocket| {
+            Box::pin(async move {
+                let database_url = rocket
+                    .figment()
+                    .extract_inner::<String>(&format!("databases.{}.url", settings::DATABASE))
+                    .ok();
+                log::info!(
+                    "{}",
+                    settings::startup_summary(
+                        &*SETTINGS.read().await,
+                        database_url.as_deref(),
+                        rocket.routes().count()
+                    )
+                );
+            })
+        }))
 }